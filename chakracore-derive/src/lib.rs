@@ -0,0 +1,484 @@
+//! Attribute macros for exporting Rust functions and types to JavaScript.
+//!
+//! These are re-exported by `chakracore` with the `derive` feature, and
+//! generate the glue otherwise written using `Function::typed` and
+//! `ClassBuilder`. The generated code refers to the `chakracore` crate by
+//! name, so it must not be renamed (e.g using `extern crate ... as js`).
+//!
+//! Arguments are converted using `FromJs`, and results using `ToJs`. A
+//! function may either return a value directly, or a `chakracore` result (any
+//! return type named `Result`), in which case an error throws an exception.
+//! If the first parameter is a `&ContextGuard`, the guard of the call is
+//! passed to it.
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::spanned::Spanned;
+
+/// The maximum number of arguments, limited by `FromArguments`.
+const MAX_ARGUMENTS: usize = 6;
+
+/// Exports a function to JavaScript.
+///
+/// The function is kept as is, and a sibling function with a `_function`
+/// suffix is generated, which creates a named host function in the guard's
+/// context. The JavaScript name defaults to the Rust name, and can be changed
+/// using `#[js_function(name = "...")]`.
+///
+/// ```rust
+/// extern crate chakracore;
+/// use chakracore::{js_function, script, Context, Property, Runtime};
+/// use chakracore::error::Result;
+///
+/// #[js_function(name = "repeat")]
+/// fn repeat_text(text: String, count: i32) -> Result<String> {
+///     if count < 0 { Err("count must be positive".into()) } else { Ok(text.repeat(count as usize)) }
+/// }
+///
+/// # fn main() {
+/// # let runtime = Runtime::new().unwrap();
+/// # let context = Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let function = repeat_text_function(&guard);
+/// guard.global().set(&guard, &Property::new(&guard, "repeat"), &function);
+///
+/// let result = script::eval(&guard, "repeat('ab', 2)").unwrap();
+/// assert_eq!(result.to_string(&guard), "abab");
+/// assert!(script::eval(&guard, "repeat('ab')").is_err());
+/// # }
+/// ```
+#[proc_macro_attribute]
+pub fn js_function(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let arguments = syn::parse_macro_input!(attribute as syn::AttributeArgs);
+    let function = syn::parse_macro_input!(item as syn::ItemFn);
+    expand_function(arguments, function)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/// Exports a type to JavaScript as a class, from an `impl` block.
+///
+/// This generates an associated `js_class` function, which creates the
+/// class' constructor using `ClassBuilder`. The class name defaults to the
+/// type's name, and can be changed using `#[js_class(name = "...")]`.
+///
+/// Methods are exported according to their attributes:
+///
+/// - `#[js(constructor)]` marks the function called by `new` (required).
+/// - `#[js(getter)]` exports a `&self` method as a read-only property.
+/// - `#[js(setter)]` adds a setter to a property, named after the method
+///   without its `set_` prefix.
+/// - `#[js(skip)]` excludes a method.
+/// - `#[js(name = "...")]` renames a method or property.
+///
+/// Any other method with a `self` receiver is exported as a method, while
+/// other associated functions are ignored.
+///
+/// ```rust
+/// extern crate chakracore;
+/// use chakracore::{js_class, script, Context, Property, Runtime};
+///
+/// struct Counter(i32);
+///
+/// #[js_class]
+/// impl Counter {
+///     #[js(constructor)]
+///     fn new(start: Option<i32>) -> Self {
+///         Counter(start.unwrap_or(0))
+///     }
+///
+///     fn increment(&mut self, step: i32) -> i32 {
+///         self.0 += step;
+///         self.0
+///     }
+///
+///     #[js(getter)]
+///     fn value(&self) -> i32 {
+///         self.0
+///     }
+///
+///     #[js(setter)]
+///     fn set_value(&mut self, value: i32) {
+///         self.0 = value;
+///     }
+/// }
+///
+/// # fn main() {
+/// # let runtime = Runtime::new().unwrap();
+/// # let context = Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let class = Counter::js_class(&guard).unwrap();
+/// guard.global().set(&guard, &Property::new(&guard, "Counter"), &class);
+///
+/// let result = script::eval(&guard, "var c = new Counter(5); c.increment(2); c.value += 1; c.value").unwrap();
+/// assert_eq!(result.to_integer(&guard), 8);
+/// # }
+/// ```
+#[proc_macro_attribute]
+pub fn js_class(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let arguments = syn::parse_macro_input!(attribute as syn::AttributeArgs);
+    let block = syn::parse_macro_input!(item as syn::ItemImpl);
+    expand_class(arguments, block)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+fn expand_function(arguments: syn::AttributeArgs, function: syn::ItemFn) -> syn::Result<TokenStream2> {
+    let js_name = parse_name(&arguments)?;
+
+    let signature = &function.sig;
+    if !signature.generics.params.is_empty() {
+        return Err(syn::Error::new(signature.generics.span(), "exported functions cannot be generic"));
+    }
+
+    let ident = &signature.ident;
+    let js_name = js_name.unwrap_or_else(|| ident.to_string());
+    let constructor = syn::Ident::new(&format!("{}_function", ident), ident.span());
+    let visibility = &function.vis;
+    let documentation = format!("Creates the JavaScript function of `{}`.", ident);
+
+    let parameters = Parameters::parse(signature.inputs.iter(), false)?;
+    let (patterns, types, call) = parameters.expand(quote!(#ident));
+    let result = wrap_result(&signature.output, call);
+
+    Ok(quote! {
+        #function
+
+        #[doc = #documentation]
+        #visibility fn #constructor(guard: &::chakracore::context::ContextGuard) -> ::chakracore::value::Function {
+            ::chakracore::value::Function::typed(guard, #js_name, |__guard, (#(#patterns,)*): (#(#types,)*)| {
+                #result
+            })
+        }
+    })
+}
+
+/// The kind of an exported class member.
+enum Member {
+    Constructor,
+    Method,
+    Getter,
+    Setter,
+    Skip,
+}
+
+fn expand_class(arguments: syn::AttributeArgs, mut block: syn::ItemImpl) -> syn::Result<TokenStream2> {
+    if block.trait_.is_some() || !block.generics.params.is_empty() {
+        return Err(syn::Error::new(block.span(), "classes must be exported from a non-generic, inherent impl"));
+    }
+
+    let self_type = block.self_ty.clone();
+    let js_name = parse_name(&arguments)?;
+
+    let js_name = match js_name {
+        Some(name) => name,
+        None => match *self_type {
+            syn::Type::Path(ref path) => path.path.segments.last().map(|segment| segment.ident.to_string()).unwrap(),
+            _ => return Err(syn::Error::new(self_type.span(), "expected a type name")),
+        },
+    };
+
+    let mut constructor = None;
+    let mut methods = Vec::new();
+    let mut getters = Vec::new();
+    let mut setters = Vec::new();
+
+    for item in &mut block.items {
+        let method = match *item {
+            syn::ImplItem::Method(ref mut method) => method,
+            _ => continue,
+        };
+
+        let (member, name) = take_attributes(method)?;
+        let ident = method.sig.ident.clone();
+        let has_receiver = match method.sig.inputs.first() {
+            Some(&syn::FnArg::Receiver(_)) => true,
+            _ => false,
+        };
+
+        let member = match member {
+            Some(member) => member,
+            None if has_receiver => Member::Method,
+            None => Member::Skip,
+        };
+
+        if !method.sig.generics.params.is_empty() {
+            match member {
+                Member::Skip => continue,
+                _ => return Err(syn::Error::new(method.sig.generics.span(), "exported methods cannot be generic")),
+            }
+        }
+
+        match member {
+            Member::Constructor => {
+                if has_receiver {
+                    return Err(syn::Error::new(method.sig.span(), "a constructor cannot take `self`"));
+                }
+                if constructor.is_some() {
+                    return Err(syn::Error::new(method.sig.span(), "a class can only have one constructor"));
+                }
+
+                let parameters = Parameters::parse(method.sig.inputs.iter(), false)?;
+                let (patterns, types, call) = parameters.expand(quote!(<#self_type>::#ident));
+                let result = wrap_result(&method.sig.output, call);
+                constructor = Some(quote! {
+                    Box::new(|__guard: &::chakracore::context::ContextGuard,
+                              __info: &::chakracore::value::function::CallbackInfo|
+                              -> ::std::result::Result<#self_type, ::chakracore::value::Value> {
+                        let (#(#patterns,)*): (#(#types,)*) =
+                            ::chakracore::value::function::convert_arguments(__guard, &__info.arguments)?;
+                        let __result: ::chakracore::error::Result<#self_type> = #result;
+                        __result.map_err(|error| ::chakracore::value::Error::new(__guard, &error.to_string()).into())
+                    })
+                });
+            },
+            Member::Method => {
+                let parameters = receiver_parameters(method)?;
+                let (patterns, types, call) = parameters.expand(quote!(__this.#ident));
+                let result = wrap_result(&method.sig.output, call);
+                let name = name.unwrap_or_else(|| ident.to_string());
+                methods.push(quote! {
+                    .method(#name, Box::new(|__guard: &::chakracore::context::ContextGuard,
+                                             __this: &mut #self_type,
+                                             __info: ::chakracore::value::function::CallbackInfo| {
+                        let (#(#patterns,)*): (#(#types,)*) =
+                            ::chakracore::value::function::convert_arguments(__guard, &__info.arguments)?;
+                        ::chakracore::value::function::convert_result(__guard, #result)
+                    }))
+                });
+            },
+            Member::Getter => {
+                let parameters = receiver_parameters(method)?;
+                if !parameters.types.is_empty() {
+                    return Err(syn::Error::new(method.sig.inputs.span(), "a getter cannot take arguments"));
+                }
+
+                let (_, _, call) = parameters.expand(quote!(__this.#ident));
+                let result = wrap_result(&method.sig.output, call);
+                let name = name.unwrap_or_else(|| ident.to_string());
+                getters.push((name, quote! {
+                    Box::new(|__guard: &::chakracore::context::ContextGuard, __this: &#self_type| {
+                        ::chakracore::value::function::convert_result(__guard, #result)
+                    })
+                }));
+            },
+            Member::Setter => {
+                let parameters = receiver_parameters(method)?;
+                if parameters.types.len() != 1 {
+                    return Err(syn::Error::new(method.sig.inputs.span(), "a setter must take exactly one argument"));
+                }
+
+                let (patterns, types, call) = parameters.expand(quote!(__this.#ident));
+                let result = wrap_result(&method.sig.output, call);
+                let name = name.unwrap_or_else(|| {
+                    let ident = ident.to_string();
+                    ident.trim_start_matches("set_").to_string()
+                });
+                setters.push((name, method.sig.span(), quote! {
+                    Box::new(|__guard: &::chakracore::context::ContextGuard,
+                              __this: &mut #self_type,
+                              __value: ::chakracore::value::Value| {
+                        let (#(#patterns,)*): (#(#types,)*) =
+                            ::chakracore::value::function::convert_arguments(__guard, &[__value])?;
+                        ::chakracore::value::function::convert_result(__guard, #result).map(|_| ())
+                    })
+                }));
+            },
+            Member::Skip => (),
+        }
+    }
+
+    let constructor = constructor.ok_or_else(|| {
+        syn::Error::new(block.self_ty.span(), "a class requires a `#[js(constructor)]` function")
+    })?;
+
+    let mut accessors = Vec::new();
+    for (name, getter) in getters {
+        let setter = match setters.iter().position(|setter| setter.0 == name) {
+            Some(index) => {
+                let setter = setters.remove(index).2;
+                quote!(Some(#setter))
+            },
+            None => quote!(None),
+        };
+        accessors.push(quote!(.accessor(#name, #getter, #setter)));
+    }
+
+    if let Some(&(ref name, span, _)) = setters.first() {
+        return Err(syn::Error::new(span, format!("the setter of `{}` has no getter", name)));
+    }
+
+    Ok(quote! {
+        #block
+
+        impl #self_type {
+            /// Creates the class' constructor in the guard's context.
+            pub fn js_class(guard: &::chakracore::context::ContextGuard)
+                    -> ::chakracore::error::Result<::chakracore::value::Function> {
+                ::chakracore::class::ClassBuilder::new(#js_name, #constructor)
+                    #(#methods)*
+                    #(#accessors)*
+                    .build(guard)
+            }
+        }
+    })
+}
+
+/// Removes the `#[js(...)]` attributes of a method, returning its kind and
+/// JavaScript name (if specified).
+fn take_attributes(method: &mut syn::ImplItemMethod) -> syn::Result<(Option<Member>, Option<String>)> {
+    let mut member = None;
+    let mut name = None;
+    let mut error = None;
+
+    method.attrs.retain(|attribute| {
+        if !attribute.path.is_ident("js") {
+            return true;
+        }
+
+        let result = attribute.parse_meta().and_then(|meta| {
+            let list = match meta {
+                syn::Meta::List(list) => list,
+                meta => return Err(syn::Error::new(meta.span(), "expected `#[js(...)]`")),
+            };
+
+            for nested in list.nested.iter() {
+                match *nested {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(ref pair)) if pair.path.is_ident("name") => {
+                        name = Some(string_literal(&pair.lit)?);
+                    },
+                    syn::NestedMeta::Meta(syn::Meta::Path(ref path)) => {
+                        member = Some(match path.get_ident().map(|ident| ident.to_string()) {
+                            Some(ref kind) if kind == "constructor" => Member::Constructor,
+                            Some(ref kind) if kind == "getter" => Member::Getter,
+                            Some(ref kind) if kind == "setter" => Member::Setter,
+                            Some(ref kind) if kind == "skip" => Member::Skip,
+                            _ => return Err(syn::Error::new(path.span(), "unknown member kind")),
+                        });
+                    },
+                    _ => return Err(syn::Error::new(nested.span(), "unknown attribute")),
+                }
+            }
+            Ok(())
+        });
+
+        if let Err(result) = result {
+            error.get_or_insert(result);
+        }
+        false
+    });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok((member, name)),
+    }
+}
+
+/// The parameters of an exported function, excluding any receiver.
+struct Parameters {
+    /// Whether the first parameter is a `&ContextGuard`.
+    guard: bool,
+    types: Vec<syn::Type>,
+}
+
+impl Parameters {
+    fn parse<'a, I: Iterator<Item = &'a syn::FnArg>>(inputs: I, has_receiver: bool) -> syn::Result<Self> {
+        let mut guard = false;
+        let mut types = Vec::new();
+
+        for (index, input) in inputs.skip(has_receiver as usize).enumerate() {
+            let input = match *input {
+                syn::FnArg::Typed(ref input) => input,
+                syn::FnArg::Receiver(ref receiver) => {
+                    return Err(syn::Error::new(receiver.span(), "unexpected `self`"));
+                },
+            };
+
+            if index == 0 && is_guard(&input.ty) {
+                guard = true;
+            } else {
+                types.push((*input.ty).clone());
+            }
+        }
+
+        if types.len() > MAX_ARGUMENTS {
+            let message = format!("exported functions can take at most {} arguments", MAX_ARGUMENTS);
+            return Err(syn::Error::new(types[MAX_ARGUMENTS].span(), message));
+        }
+
+        Ok(Parameters { guard: guard, types: types })
+    }
+
+    /// Returns the argument patterns and types, and the call of a function
+    /// with them.
+    fn expand(&self, function: TokenStream2) -> (Vec<syn::Ident>, Vec<syn::Type>, TokenStream2) {
+        let patterns = (0..self.types.len())
+            .map(|index| syn::Ident::new(&format!("__argument{}", index), Span::call_site()))
+            .collect::<Vec<_>>();
+        let call = if self.guard {
+            quote!(#function(__guard, #(#patterns),*))
+        } else {
+            quote!(#function(#(#patterns),*))
+        };
+        (patterns, self.types.clone(), call)
+    }
+}
+
+/// Parses the parameters of a method, which must have a receiver.
+fn receiver_parameters(method: &syn::ImplItemMethod) -> syn::Result<Parameters> {
+    match method.sig.inputs.first() {
+        Some(&syn::FnArg::Receiver(_)) => Parameters::parse(method.sig.inputs.iter(), true),
+        _ => Err(syn::Error::new(method.sig.span(), "expected a `self` receiver")),
+    }
+}
+
+/// Returns whether a type is a reference to a `ContextGuard`.
+fn is_guard(ty: &syn::Type) -> bool {
+    match *ty {
+        syn::Type::Reference(ref reference) => match *reference.elem {
+            syn::Type::Path(ref path) => path.path.segments.last()
+                .map_or(false, |segment| segment.ident == "ContextGuard"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Wraps a call in `Ok`, unless the function returns a `Result`.
+fn wrap_result(output: &syn::ReturnType, call: TokenStream2) -> TokenStream2 {
+    let is_result = match *output {
+        syn::ReturnType::Type(_, ref ty) => match **ty {
+            syn::Type::Path(ref path) => path.path.segments.last().map_or(false, |segment| segment.ident == "Result"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
+    };
+
+    if is_result { call } else { quote!(Ok(#call)) }
+}
+
+/// Parses the arguments of an attribute, which may only specify a name.
+fn parse_name(arguments: &syn::AttributeArgs) -> syn::Result<Option<String>> {
+    let mut name = None;
+    for argument in arguments {
+        match *argument {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(ref pair)) if pair.path.is_ident("name") => {
+                name = Some(string_literal(&pair.lit)?);
+            },
+            _ => return Err(syn::Error::new(argument.span(), "expected `name = \"...\"`")),
+        }
+    }
+    Ok(name)
+}
+
+fn string_literal(literal: &syn::Lit) -> syn::Result<String> {
+    match *literal {
+        syn::Lit::Str(ref string) => Ok(string.value()),
+        _ => Err(syn::Error::new(literal.span(), "expected a string literal")),
+    }
+}
@@ -0,0 +1,26 @@
+//! Handles "requests" with asynchronous JavaScript handlers, driven by tokio.
+//!
+//! Values are bound to their runtime's thread, so the evaluations are driven
+//! by a single-threaded tokio runtime on the same thread.
+extern crate chakracore as js;
+extern crate tokio;
+
+fn main() {
+    let runtime = js::Runtime::new().unwrap();
+    let context = js::Context::new(&runtime).unwrap();
+    let guard = context.make_current().unwrap();
+
+    js::script::eval(&guard, "
+        function handle(request) {
+            return Promise.resolve(request)
+                .then(request => request.path.split('/').filter(Boolean))
+                .then(segments => ({ status: 200, body: segments.join(' > ') }));
+        }").unwrap();
+
+    let executor = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    for path in &["/", "/users/42", "/files/docs/readme"] {
+        let code = format!("handle({{ path: {:?} }})", path);
+        let response = executor.block_on(js::script::eval_async(&guard, &code)).unwrap();
+        println!("{} => {}", path, response.to_json(&guard).unwrap());
+    }
+}
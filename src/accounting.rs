@@ -0,0 +1,341 @@
+//! Per-tenant accounting of resource usage.
+//!
+//! A `ResourceAccountant` aggregates the resources used by contexts into
+//! fixed time windows, keyed by a label (e.g a tenant identifier). Script
+//! executions are accounted by using the accountant as an audit sink, with
+//! the context's audit name as its label. Memory allocations are accounted
+//! per runtime, by installing the accountant's memory callback.
+//!
+//! Completed windows are retrieved by polling, e.g by a billing exporter,
+//! whereas the usage of the current window (or a recent period) can be read
+//! at any time, e.g to throttle a tenant.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use std::time::Duration;
+//! # use js::accounting::ResourceAccountant;
+//! let accountant = ResourceAccountant::new(Duration::from_secs(60), 60);
+//! let runtime = js::Runtime::builder()
+//!     .memory_callback(accountant.memory_callback("tenant"))
+//!     .build()
+//!     .unwrap();
+//! let context = js::Context::new(&runtime).unwrap();
+//! js::audit::enable(&context, "tenant", Box::new(accountant.clone()));
+//!
+//! let guard = context.make_current().unwrap();
+//! js::script::eval(&guard, "[1, 2, 3].map(x => x * 2)").unwrap();
+//! assert_eq!(accountant.current("tenant").executions, 1);
+//! ```
+use std::collections::BTreeMap;
+use std::mem;
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use audit::{AuditRecord, AuditSink, AuditStatus};
+use error::*;
+use runtime::{MemoryCallback, MemoryEvent};
+
+/// Resources used during a period.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    /// The number of script executions.
+    pub executions: u64,
+    /// The number of executions which did not complete successfully.
+    pub failures: u64,
+    /// The total duration of the executions.
+    pub execution_time: Duration,
+    /// The net change of memory usage caused by the executions, in bytes.
+    pub memory_delta: i64,
+    /// The number of bytes allocated by the runtime.
+    pub allocated: u64,
+    /// The number of failed allocations.
+    pub allocation_failures: u64,
+}
+
+impl<'a> AddAssign<&'a Usage> for Usage {
+    fn add_assign(&mut self, other: &'a Usage) {
+        self.executions += other.executions;
+        self.failures += other.failures;
+        self.execution_time += other.execution_time;
+        self.memory_delta += other.memory_delta;
+        self.allocated += other.allocated;
+        self.allocation_failures += other.allocation_failures;
+    }
+}
+
+/// The usage of a label during a completed time window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageWindow {
+    /// The label the usage is accounted to.
+    pub label: String,
+    /// The start of the window.
+    pub start: SystemTime,
+    /// The length of the window.
+    pub length: Duration,
+    /// The resources used during the window.
+    pub usage: Usage,
+}
+
+/// The aggregated windows of an accountant.
+struct Ledger {
+    /// The windows of each label, keyed by their index.
+    windows: BTreeMap<String, BTreeMap<u64, Usage>>,
+    /// The index of the earliest window not yet returned by `poll`.
+    unpolled: u64,
+}
+
+impl Ledger {
+    /// Accounts usage to a label's window.
+    fn account<F: FnOnce(&mut Usage)>(&mut self, label: &str, index: u64, retention: u64, update: F) {
+        if !self.windows.contains_key(label) {
+            self.windows.insert(label.to_string(), BTreeMap::new());
+        }
+
+        let windows = self.windows.get_mut(label).expect("retrieving label windows");
+        if !windows.contains_key(&index) {
+            windows.insert(index, Usage::default());
+
+            // Discard windows which are no longer retained
+            let last = *windows.keys().next_back().expect("retrieving latest window");
+            let first = last.saturating_sub(retention - 1);
+            *windows = windows.split_off(&first);
+        }
+
+        if let Some(usage) = windows.get_mut(&index) {
+            update(usage);
+        }
+    }
+}
+
+/// Allocations counted by a memory callback, not yet accounted to the ledger.
+#[derive(Default)]
+struct Allocations {
+    index: u64,
+    allocated: u64,
+    failures: u64,
+}
+
+impl Allocations {
+    /// Takes the counted allocations, leaving the window unchanged.
+    fn take(&mut self) -> Allocations {
+        let index = self.index;
+        mem::replace(self, Allocations { index: index, ..Default::default() })
+    }
+}
+
+/// An aggregator of resource usage per label and time window.
+///
+/// Clones share the same data.
+#[derive(Clone)]
+pub struct ResourceAccountant {
+    ledger: Arc<Mutex<Ledger>>,
+    buffers: Arc<Mutex<Vec<(String, Arc<Mutex<Allocations>>)>>>,
+    window: Duration,
+    retention: u64,
+    epoch: Instant,
+    system_epoch: SystemTime,
+}
+
+impl ResourceAccountant {
+    /// Creates an accountant with a window length, retaining a number of
+    /// windows per label.
+    pub fn new(window: Duration, retention: usize) -> Self {
+        assert!(window > Duration::from_secs(0), "window length must be positive");
+        ResourceAccountant {
+            ledger: Arc::new(Mutex::new(Ledger { windows: BTreeMap::new(), unpolled: 0 })),
+            buffers: Arc::new(Mutex::new(Vec::new())),
+            window: window,
+            retention: ::std::cmp::max(retention, 1) as u64,
+            epoch: Instant::now(),
+            system_epoch: SystemTime::now(),
+        }
+    }
+
+    /// Returns a memory callback accounting a runtime's allocations to a label.
+    ///
+    /// The callback never rejects allocations. Allocations are counted by the
+    /// callback itself, and only accounted to the shared windows once a window
+    /// completes or the usage is read.
+    pub fn memory_callback(&self, label: &str) -> Box<MemoryCallback> {
+        let accountant = self.clone();
+        let label = label.to_string();
+        let buffer = Arc::new(Mutex::new(Allocations::default()));
+        self.buffers.lock().unwrap().push((label.clone(), buffer.clone()));
+
+        Box::new(move |event, size| {
+            if let MemoryEvent::Free = event {
+                return true;
+            }
+
+            let index = accountant.index(Instant::now());
+            let mut allocations = buffer.lock().unwrap();
+            if allocations.index != index {
+                let completed = mem::replace(&mut *allocations, Allocations { index: index, ..Default::default() });
+                accountant.account_allocations(&label, completed);
+            }
+
+            match event {
+                MemoryEvent::Allocate => allocations.allocated += size as u64,
+                MemoryEvent::Failure => allocations.failures += 1,
+                MemoryEvent::Free => (),
+            }
+            true
+        })
+    }
+
+    /// Returns the usage of a label during the current window.
+    pub fn current(&self, label: &str) -> Usage {
+        self.flush();
+        let index = self.index(Instant::now());
+        let ledger = self.ledger.lock().unwrap();
+        ledger.windows.get(label).and_then(|windows| windows.get(&index)).cloned().unwrap_or_default()
+    }
+
+    /// Returns the usage of a label during the retained windows overlapping
+    /// a recent period (including the current window).
+    pub fn recent(&self, label: &str, period: Duration) -> Usage {
+        self.flush();
+        let now = Instant::now();
+        let first = self.index(if now - self.epoch > period { now - period } else { self.epoch });
+
+        let mut total = Usage::default();
+        if let Some(windows) = self.ledger.lock().unwrap().windows.get(label) {
+            for usage in windows.range(first..).map(|(_, usage)| usage) {
+                total += usage;
+            }
+        }
+        total
+    }
+
+    /// Returns the windows completed since the previous poll, for all labels.
+    ///
+    /// The windows are ordered by their start, and then by label. Windows are
+    /// only returned once, but remain available to `recent` until they are
+    /// no longer retained.
+    pub fn poll(&self) -> Vec<UsageWindow> {
+        self.flush();
+        let current = self.index(Instant::now());
+        let mut ledger = self.ledger.lock().unwrap();
+        let unpolled = ledger.unpolled;
+        ledger.unpolled = current;
+
+        let mut completed = ledger.windows.iter()
+            .flat_map(|(label, windows)| windows.range(unpolled..current).map(move |(&index, usage)| {
+                (index, label.clone(), usage.clone())
+            }))
+            .collect::<Vec<_>>();
+        completed.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        completed.into_iter().map(|(index, label, usage)| UsageWindow {
+            label: label,
+            start: self.start(index),
+            length: self.window,
+            usage: usage,
+        }).collect()
+    }
+
+    /// Accounts usage to a label's current window.
+    fn account<F: FnOnce(&mut Usage)>(&self, label: &str, update: F) {
+        let index = self.index(Instant::now());
+        self.ledger.lock().unwrap().account(label, index, self.retention, update);
+    }
+
+    /// Accounts allocations counted by a memory callback to their window.
+    fn account_allocations(&self, label: &str, allocations: Allocations) {
+        if allocations.allocated > 0 || allocations.failures > 0 {
+            self.ledger.lock().unwrap().account(label, allocations.index, self.retention, |usage| {
+                usage.allocated += allocations.allocated;
+                usage.allocation_failures += allocations.failures;
+            });
+        }
+    }
+
+    /// Accounts the allocations counted by all memory callbacks, and forgets
+    /// the callbacks which have been dropped.
+    fn flush(&self) {
+        let mut counted = Vec::new();
+        self.buffers.lock().unwrap().retain(|&(ref label, ref buffer)| {
+            counted.push((label.clone(), buffer.lock().unwrap().take()));
+            Arc::strong_count(buffer) > 1
+        });
+
+        for (label, allocations) in counted {
+            self.account_allocations(&label, allocations);
+        }
+    }
+
+    /// Returns the index of the window containing an instant.
+    fn index(&self, instant: Instant) -> u64 {
+        (nanos(instant - self.epoch) / nanos(self.window)) as u64
+    }
+
+    /// Returns the start of a window.
+    fn start(&self, index: u64) -> SystemTime {
+        let offset = nanos(self.window) * index as u128;
+        self.system_epoch + Duration::new((offset / 1_000_000_000) as u64, (offset % 1_000_000_000) as u32)
+    }
+}
+
+/// Returns the number of nanoseconds of a duration.
+fn nanos(duration: Duration) -> u128 {
+    duration.as_secs() as u128 * 1_000_000_000 + duration.subsec_nanos() as u128
+}
+
+impl AuditSink for ResourceAccountant {
+    fn append(&self, record: &AuditRecord) -> Result<()> {
+        self.account(&record.context, |usage| {
+            usage.executions += 1;
+            usage.execution_time += record.duration;
+            usage.memory_delta += record.memory_delta;
+            if record.status != AuditStatus::Success {
+                usage.failures += 1;
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use {audit, test, script};
+    use super::ResourceAccountant;
+
+    #[test]
+    fn accounting() {
+        let accountant = ResourceAccountant::new(Duration::from_millis(500), 10);
+        let (_runtime, context) = test::setup_env();
+        audit::enable(&context, "tenant", Box::new(accountant.clone()));
+
+        context.exec_with(|guard| {
+            script::eval(guard, "1 + 1").unwrap();
+            assert!(script::eval(guard, "throw new Error()").is_err());
+        }).unwrap();
+
+        let allocate = accountant.memory_callback("other");
+        assert!(allocate(::runtime::MemoryEvent::Allocate, 64));
+
+        let usage = accountant.current("tenant");
+        assert_eq!((usage.executions, usage.failures), (2, 1));
+        assert_eq!(accountant.current("other").allocated, 64);
+        assert!(accountant.poll().is_empty());
+
+        thread::sleep(Duration::from_millis(550));
+        assert_eq!(accountant.current("tenant").executions, 0);
+        assert_eq!(accountant.recent("tenant", Duration::from_secs(1)).executions, 2);
+
+        let windows = accountant.poll();
+        assert_eq!(windows.iter().map(|window| window.label.as_str()).collect::<Vec<_>>(), ["other", "tenant"]);
+        assert_eq!(windows[1].usage.executions, 2);
+        assert!(accountant.poll().is_empty());
+    }
+
+    #[test]
+    fn window_start() {
+        let accountant = ResourceAccountant::new(Duration::from_secs(60), 1);
+        let index = u32::max_value() as u64 + 2;
+        let offset = accountant.start(index).duration_since(accountant.system_epoch).unwrap();
+        assert_eq!(offset, Duration::from_secs(60 * index));
+    }
+}
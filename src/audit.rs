@@ -0,0 +1,343 @@
+//! An append-only audit trail of script executions.
+//!
+//! Once enabled for a context, every script executed through the `script`
+//! module (including compiled scripts) is recorded to an `AuditSink`. Each
+//! record contains a SHA-256 hash of the executed source, and is chained to
+//! its predecessor by including the previous record's hash in its own. Any
+//! modification, removal or reordering of records can therefore be detected
+//! using `verify`.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::audit::{self, MemorySink};
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let sink = MemorySink::new();
+//! audit::enable(&context, "tenant", Box::new(sink.clone()));
+//!
+//! js::script::eval_with_name(&guard, "job.js", "1 + 1").unwrap();
+//! let records = sink.records();
+//! assert_eq!(records[0].origin, "job.js");
+//! assert!(audit::verify(&records));
+//! ```
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chakracore_sys::*;
+use context::{Context, ContextGuard};
+use error::*;
+use value;
+
+/// A destination for audit records.
+pub trait AuditSink: Send {
+    /// Appends a record to the audit trail.
+    ///
+    /// If this fails, the audited execution returns the error instead of its
+    /// result.
+    fn append(&self, record: &AuditRecord) -> Result<()>;
+}
+
+/// The outcome of an audited execution.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditStatus {
+    /// The script completed successfully.
+    Success,
+    /// The script threw an exception, described by its stack trace (as
+    /// rendered by the context's formatter) if it has one.
+    Exception(String),
+    /// The script could not be compiled.
+    Compile(String),
+    /// The script was terminated.
+    Terminated,
+    /// The execution failed for any other reason.
+    Error(String),
+}
+
+/// A record of a single script execution.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// The position of the record within the context's trail.
+    pub sequence: u64,
+    /// The SHA-256 hash (hex encoded) of the executed source.
+    pub source_hash: String,
+    /// The name of the executed script.
+    pub origin: String,
+    /// The name of the context, as specified when the trail was enabled.
+    pub context: String,
+    /// The duration of the execution.
+    pub duration: Duration,
+    /// The change of the runtime's memory usage, in bytes.
+    pub memory_delta: i64,
+    /// The outcome of the execution.
+    pub status: AuditStatus,
+    /// The hash of the previous record (zeroes for the first record).
+    pub previous: String,
+    /// The hash of this record, including the previous record's hash.
+    pub hash: String,
+}
+
+/// An audit sink storing records in memory.
+///
+/// Clones share the same records.
+#[derive(Clone, Default)]
+pub struct MemorySink(Arc<Mutex<Vec<AuditRecord>>>);
+
+/// The audit state of a context.
+struct Auditor {
+    name: String,
+    sink: Box<AuditSink>,
+    sequence: u64,
+    previous: String,
+}
+
+impl AuditRecord {
+    /// Computes the hash of the record's contents.
+    ///
+    /// This does not include the `hash` field itself.
+    pub fn digest(&self) -> String {
+        let contents = format!("{}\n{}\n{:?}\n{:?}\n{}.{:09}\n{}\n{:?}\n{}",
+                               self.sequence,
+                               self.source_hash,
+                               self.origin,
+                               self.context,
+                               self.duration.as_secs(),
+                               self.duration.subsec_nanos(),
+                               self.memory_delta,
+                               self.status,
+                               self.previous);
+        hex(&sha256(contents.as_bytes()))
+    }
+}
+
+impl fmt::Display for AuditStatus {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuditStatus::Success => write!(formatter, "success"),
+            AuditStatus::Exception(ref message) => write!(formatter, "exception: {}", message),
+            AuditStatus::Compile(ref message) => write!(formatter, "compile error: {}", message),
+            AuditStatus::Terminated => write!(formatter, "terminated"),
+            AuditStatus::Error(ref message) => write!(formatter, "error: {}", message),
+        }
+    }
+}
+
+impl MemorySink {
+    /// Creates an empty sink.
+    pub fn new() -> Self {
+        MemorySink::default()
+    }
+
+    /// Returns a copy of all appended records.
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn append(&self, record: &AuditRecord) -> Result<()> {
+        self.0.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+/// Enables the audit trail of a context.
+///
+/// Any previously enabled trail is replaced, and its sink is returned.
+pub fn enable(context: &Context, name: &str, sink: Box<AuditSink>) -> Option<Box<AuditSink>> {
+    context.insert_user_data(Auditor {
+        name: name.to_string(),
+        sink: sink,
+        sequence: 0,
+        previous: hex(&[0; 32]),
+    }).map(|auditor| auditor.sink)
+}
+
+/// Disables the audit trail of a context, returning its sink.
+pub fn disable(context: &Context) -> Option<Box<AuditSink>> {
+    context.remove_user_data::<Auditor>().map(|auditor| auditor.sink)
+}
+
+/// Verifies the integrity of a sequence of records.
+///
+/// Returns false if any record has been modified, or if the records are not
+/// consecutive links of the same chain.
+pub fn verify(records: &[AuditRecord]) -> bool {
+    records.iter().all(|record| record.digest() == record.hash) &&
+        records.windows(2).all(|pair| {
+            pair[1].previous == pair[0].hash && pair[1].sequence == pair[0].sequence + 1
+        })
+}
+
+/// Executes a script, recording it if the context has an audit trail.
+pub(crate) fn audited<F>(guard: &ContextGuard,
+                         origin: &str,
+                         source: &str,
+                         execute: F) -> Result<value::Value>
+        where F: FnOnce() -> Result<value::Value> {
+    let context = guard.context();
+    if context.get_user_data::<Auditor>().is_none() {
+        return execute();
+    }
+
+    let runtime = context.runtime_handle();
+    let memory = memory_usage(runtime);
+    let start = Instant::now();
+    let result = execute();
+    let duration = start.elapsed();
+    let memory_delta = memory_usage(runtime) as i64 - memory as i64;
+
+    // The trail may have been disabled by the script itself
+    let auditor = match context.get_user_data_mut::<Auditor>() {
+        Some(auditor) => auditor,
+        None => return result,
+    };
+
+    let status = match result {
+        Ok(_) => AuditStatus::Success,
+        Err(Error(ErrorKind::ScriptException(ref error), _)) => {
+            AuditStatus::Exception(error.stack.clone().unwrap_or_else(|| error.to_string()))
+        },
+        Err(Error(ErrorKind::ScriptCompile(ref error), _)) => AuditStatus::Compile(error.to_string()),
+        Err(Error(ErrorKind::ScriptTerminated, _)) => AuditStatus::Terminated,
+        Err(ref error) => AuditStatus::Error(error.to_string()),
+    };
+
+    let mut record = AuditRecord {
+        sequence: auditor.sequence,
+        source_hash: hex(&sha256(source.as_bytes())),
+        origin: origin.to_string(),
+        context: auditor.name.clone(),
+        duration: duration,
+        memory_delta: memory_delta,
+        status: status,
+        previous: auditor.previous.clone(),
+        hash: String::new(),
+    };
+    record.hash = record.digest();
+
+    auditor.sink.append(&record).chain_err(|| "appending audit record")?;
+    auditor.sequence += 1;
+    auditor.previous = record.hash;
+    result
+}
+
+/// Returns the memory usage of a runtime.
+pub(crate) fn memory_usage(runtime: JsRuntimeHandle) -> usize {
+    let mut usage = 0;
+    jsassert!(unsafe { JsGetRuntimeMemoryUsage(runtime, &mut usage) });
+    usage
+}
+
+/// Encodes bytes as lowercase hexadecimal.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Computes the SHA-256 hash of data.
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    // Pad the message to a multiple of 64 bytes, ending with its bit length
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    message.extend((0..8).rev().map(|index| (bits >> (index * 8)) as u8));
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (index, word) in chunk.chunks(4).enumerate() {
+            w[index] = (word[0] as u32) << 24 | (word[1] as u32) << 16 | (word[2] as u32) << 8 | word[3] as u32;
+        }
+        for index in 16..64 {
+            let s0 = w[index - 15].rotate_right(7) ^ w[index - 15].rotate_right(18) ^ (w[index - 15] >> 3);
+            let s1 = w[index - 2].rotate_right(17) ^ w[index - 2].rotate_right(19) ^ (w[index - 2] >> 10);
+            w[index] = w[index - 16].wrapping_add(s0).wrapping_add(w[index - 7]).wrapping_add(s1);
+        }
+
+        let mut h = state;
+        for index in 0..64 {
+            let s1 = h[4].rotate_right(6) ^ h[4].rotate_right(11) ^ h[4].rotate_right(25);
+            let choice = (h[4] & h[5]) ^ (!h[4] & h[6]);
+            let temp1 = h[7].wrapping_add(s1).wrapping_add(choice).wrapping_add(K[index]).wrapping_add(w[index]);
+            let s0 = h[0].rotate_right(2) ^ h[0].rotate_right(13) ^ h[0].rotate_right(22);
+            let majority = (h[0] & h[1]) ^ (h[0] & h[2]) ^ (h[1] & h[2]);
+            let temp2 = s0.wrapping_add(majority);
+
+            h = [temp1.wrapping_add(temp2), h[0], h[1], h[2], h[3].wrapping_add(temp1), h[4], h[5], h[6]];
+        }
+
+        for (value, add) in state.iter_mut().zip(h.iter()) {
+            *value = value.wrapping_add(*add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (index, value) in state.iter().enumerate() {
+        for byte in 0..4 {
+            digest[index * 4 + byte] = (value >> (24 - byte * 8)) as u8;
+        }
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use super::{AuditStatus, MemorySink};
+
+    #[test]
+    fn sha256() {
+        assert_eq!(super::hex(&super::sha256(b"abc")),
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(super::hex(&super::sha256(&[b'a'; 64])),
+                   "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb");
+    }
+
+    #[test]
+    fn audit_trail() {
+        test::run_with_context(|guard| {
+            let sink = MemorySink::new();
+            super::enable(&guard.context(), "tenant", Box::new(sink.clone()));
+
+            script::eval_with_name(guard, "first.js", "var list = new Array(1000).fill(0)").unwrap();
+            assert!(script::eval_with_name(guard, "second.js", "throw new Error('foo')").is_err());
+            assert!(script::eval(guard, "(").is_err());
+
+            let mut records = sink.records();
+            assert_eq!(records.len(), 3);
+            assert_eq!(records[0].context, "tenant");
+            assert_eq!(records[0].status, AuditStatus::Success);
+            assert_eq!(records[0].source_hash, super::hex(&super::sha256(b"var list = new Array(1000).fill(0)")));
+            assert_eq!(records[1].origin, "second.js");
+            assert!(matches!(records[1].status, AuditStatus::Exception(_)));
+            assert!(matches!(records[2].status, AuditStatus::Compile(_)));
+            assert!(super::verify(&records));
+
+            records[1].origin = "other.js".into();
+            assert!(!super::verify(&records));
+            records.remove(1);
+            assert!(!super::verify(&records));
+
+            assert!(super::disable(&guard.context()).is_some());
+            script::eval(guard, "1").unwrap();
+            assert_eq!(sink.records().len(), 3);
+        });
+    }
+}
@@ -0,0 +1,146 @@
+//! Utilities for benchmarking scripts.
+//!
+//! In contrast to the built-in benchmarks, these are available on stable, and
+//! are intended for comparing script variants within a host.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let result = js::bench::measure_eval(&guard, "[1, 2, 3].map(x => x * 2)", 100).unwrap();
+//! println!("median: {:?}, p99: {:?}", result.median(), result.percentile(99.0));
+//! ```
+use std::time::{Duration, Instant};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use util::jstry;
+use script;
+
+/// Options used when measuring.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The number of measured iterations.
+    pub iterations: usize,
+    /// The number of iterations executed, but not measured, beforehand.
+    pub warmup: usize,
+    /// Whether a full garbage collection is performed before each iteration.
+    pub collect_garbage: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            iterations: 100,
+            warmup: 10,
+            collect_garbage: false,
+        }
+    }
+}
+
+/// The result of a measurement.
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    samples: Vec<Duration>,
+}
+
+impl Measurement {
+    /// Returns all samples, sorted from fastest to slowest.
+    pub fn samples(&self) -> &[Duration] {
+        &self.samples
+    }
+
+    /// Returns the fastest sample.
+    pub fn min(&self) -> Duration {
+        self.percentile(0.0)
+    }
+
+    /// Returns the slowest sample.
+    pub fn max(&self) -> Duration {
+        self.percentile(100.0)
+    }
+
+    /// Returns the median sample.
+    pub fn median(&self) -> Duration {
+        self.percentile(50.0)
+    }
+
+    /// Returns the mean of all samples.
+    pub fn mean(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::from_secs(0);
+        }
+
+        let total = self.samples.iter().fold(Duration::from_secs(0), |acc, &sample| acc + sample);
+        total / self.samples.len() as u32
+    }
+
+    /// Returns a percentile (0 - 100) using the nearest-rank method.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::from_secs(0);
+        }
+
+        let percentile = percentile.max(0.0).min(100.0);
+        let rank = (percentile / 100.0 * self.samples.len() as f64).ceil() as usize;
+        self.samples[rank.max(1) - 1]
+    }
+}
+
+/// Measures the execution of a script.
+///
+/// The source is parsed once, therefore only its execution is measured.
+pub fn measure_eval(guard: &ContextGuard, code: &str, iterations: usize) -> Result<Measurement> {
+    measure_eval_with(guard, code, &Options { iterations: iterations, ..Options::default() })
+}
+
+/// Measures the execution of a script, with options.
+pub fn measure_eval_with(guard: &ContextGuard, code: &str, options: &Options) -> Result<Measurement> {
+    let function = script::parse(guard, code)?;
+    measure(guard, options, || function.call(guard, &[]).map(|_| ()))
+}
+
+/// Measures a closure.
+pub fn measure<T>(guard: &ContextGuard, options: &Options, mut callback: T) -> Result<Measurement>
+        where T: FnMut() -> Result<()> {
+    let runtime = guard.context().runtime_handle();
+    let collect = || if options.collect_garbage {
+        jstry(unsafe { JsCollectGarbage(runtime) })
+    } else {
+        Ok(())
+    };
+
+    for _ in 0..options.warmup {
+        callback()?;
+    }
+
+    let mut samples = Vec::with_capacity(options.iterations);
+    for _ in 0..options.iterations {
+        collect()?;
+        let start = Instant::now();
+        callback()?;
+        samples.push(start.elapsed());
+    }
+
+    samples.sort();
+    Ok(Measurement { samples: samples })
+}
+
+#[cfg(test)]
+mod tests {
+    use test;
+    use super::Options;
+
+    #[test]
+    fn measure_eval() {
+        test::run_with_context(|guard| {
+            let options = Options { iterations: 20, warmup: 2, collect_garbage: true };
+            let result = super::measure_eval_with(guard, "[1, 2, 3].map(x => x * 2)", &options).unwrap();
+
+            assert_eq!(result.samples().len(), 20);
+            assert!(result.min() <= result.median() && result.median() <= result.max());
+            assert!(super::measure_eval(guard, "null[0]", 1).is_err());
+        });
+    }
+}
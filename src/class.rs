@@ -0,0 +1,298 @@
+//! Native classes, exposing Rust types to JavaScript.
+//!
+//! A class is a JavaScript constructor backed by a Rust type. Each instance
+//! created from script (using `new`) is an external object holding a value of
+//! the type, and inherits methods and accessors from the class' prototype.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::class::ClassBuilder;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! struct Counter(i32);
+//!
+//! let class = ClassBuilder::new("Counter", Box::new(|guard, info| {
+//!         Ok(Counter(info.arguments.get(0).map_or(0, |value| value.to_integer(guard))))
+//!     }))
+//!     .method("increment", Box::new(|guard, counter: &mut Counter, _| {
+//!         counter.0 += 1;
+//!         Ok(js::value::Number::new(guard, counter.0).into())
+//!     }))
+//!     .build(&guard)
+//!     .unwrap();
+//!
+//! guard.global().set(&guard, &js::Property::new(&guard, "Counter"), &class);
+//! let result = js::script::eval(&guard, "new Counter(5).increment()").unwrap();
+//! assert_eq!(result.to_integer(&guard), 6);
+//! ```
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use libc::c_void;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use value::{self, Value};
+use value::function::{CallbackInfo, CallbackResult};
+use Property;
+
+/// Callback type for class constructors.
+pub type Constructor<T> =
+    Fn(&ContextGuard, &CallbackInfo) -> ::std::result::Result<T, Value> + Send;
+
+/// Callback type for class methods.
+pub type Method<T> = Fn(&ContextGuard, &mut T, CallbackInfo) -> CallbackResult + Send;
+
+/// Callback type for class property getters.
+pub type Getter<T> = Fn(&ContextGuard, &T) -> CallbackResult + Send;
+
+/// Callback type for class property setters.
+pub type Setter<T> = Fn(&ContextGuard, &mut T, Value) -> ::std::result::Result<(), Value> + Send;
+
+/// A builder for native classes.
+pub struct ClassBuilder<T> {
+    name: String,
+    constructor: Box<Constructor<T>>,
+    methods: Vec<(String, Box<Method<T>>)>,
+    accessors: Vec<(String, Box<Getter<T>>, Option<Box<Setter<T>>>)>,
+    parent: Option<value::Function>,
+}
+
+/// The state shared between a class and its instances.
+struct ClassState {
+    /// The addresses of all live instances.
+    instances: Mutex<HashSet<usize>>,
+}
+
+/// The external data of a class instance.
+struct Instance<T> {
+    class: Arc<ClassState>,
+    data: RefCell<T>,
+}
+
+impl<T: 'static> ClassBuilder<T> {
+    /// Creates a new class builder.
+    ///
+    /// The constructor is invoked whenever the class is instantiated from
+    /// script. Calling the class without `new` results in a `TypeError`.
+    pub fn new(name: &str, constructor: Box<Constructor<T>>) -> Self {
+        ClassBuilder {
+            name: name.to_string(),
+            constructor: constructor,
+            methods: Vec::new(),
+            accessors: Vec::new(),
+            parent: None,
+        }
+    }
+
+    /// Adds a method to the class' prototype.
+    pub fn method(mut self, name: &str, method: Box<Method<T>>) -> Self {
+        self.methods.push((name.to_string(), method));
+        self
+    }
+
+    /// Adds an accessor property to the class' prototype.
+    ///
+    /// If no setter is specified, the property is read-only.
+    pub fn accessor(mut self,
+                    name: &str,
+                    getter: Box<Getter<T>>,
+                    setter: Option<Box<Setter<T>>>) -> Self {
+        self.accessors.push((name.to_string(), getter, setter));
+        self
+    }
+
+    /// Inherits from another constructor.
+    ///
+    /// The class' prototype will inherit from the parent's prototype. Native
+    /// methods of a parent class can only be used with instances of that
+    /// specific class.
+    pub fn extends(mut self, parent: &value::Function) -> Self {
+        self.parent = Some(parent.clone());
+        self
+    }
+
+    /// Creates the class' constructor in the guard's context.
+    pub fn build(self, guard: &ContextGuard) -> Result<value::Function> {
+        let state = Arc::new(ClassState { instances: Mutex::new(HashSet::new()) });
+        let prototype = match self.parent {
+            Some(ref parent) => {
+                let parent_prototype = parent.get(guard, &Property::new(guard, "prototype"));
+                value::Object::with_prototype(guard, &parent_prototype)?
+            },
+            None => value::Object::new(guard),
+        };
+
+        for (name, method) in self.methods {
+            let state = state.clone();
+            let function = value::Function::with_name(guard, &name, Box::new(move |guard, info| {
+                let this = info.this.clone();
+                with_instance(guard, &state, &this, |instance: &Instance<T>| {
+                    let mut data = borrow_mut(guard, instance)?;
+                    method(guard, &mut *data, info)
+                })
+            }));
+            prototype.set(guard, &Property::new(guard, &name), &function);
+        }
+
+        for (name, getter, setter) in self.accessors {
+            let getter_state = state.clone();
+            let getter = value::Function::new(guard, Box::new(move |guard, info| {
+                with_instance(guard, &getter_state, &info.this, |instance: &Instance<T>| {
+                    let data = instance.data.try_borrow().map_err(|_| borrow_error(guard))?;
+                    getter(guard, &*data)
+                })
+            }));
+            let mut descriptor = value::PropertyDescriptor::new().getter(getter).configurable(true);
+
+            if let Some(setter) = setter {
+                let setter_state = state.clone();
+                let setter = value::Function::new(guard, Box::new(move |guard, info| {
+                    let value = info.arguments.get(0).cloned().unwrap_or_else(|| value::undefined(guard));
+                    with_instance(guard, &setter_state, &info.this, |instance: &Instance<T>| {
+                        let mut data = borrow_mut(guard, instance)?;
+                        setter(guard, &mut *data, value).map(|_| value::undefined(guard))
+                    })
+                }));
+                descriptor = descriptor.setter(setter);
+            }
+
+            prototype.define(guard, &Property::new(guard, &name), &descriptor)?;
+        }
+
+        let name = self.name.clone();
+        let constructor = self.constructor;
+        let function = value::Function::with_name(guard, &self.name, Box::new(move |guard, info| {
+            if !info.is_construct_call {
+                let message = format!("Class constructor {} cannot be invoked without 'new'", name);
+                return Err(value::Error::type_error(guard, &message).into());
+            }
+
+            let data = constructor(guard, &info)?;
+            let instance = Box::into_raw(Box::new(Instance {
+                class: state.clone(),
+                data: RefCell::new(data),
+            }));
+            state.instances.lock().unwrap().insert(instance as usize);
+
+            let prototype = info.callee.clone()
+                .into_object()
+                .expect("converting class constructor to object")
+                .get(guard, &Property::new(guard, "prototype"));
+
+            unsafe {
+                let mut reference = JsValueRef::new();
+                jsassert!(JsCreateExternalObject(instance as *mut _,
+                                                 Some(finalize::<T>),
+                                                 &mut reference));
+                let object = value::Object::from_raw(reference);
+                object.set_prototype(guard, &prototype).map_err(|_| {
+                    Value::from(value::Error::type_error(guard, "Class prototype is invalid"))
+                })?;
+                Ok(object.into())
+            }
+        }));
+
+        if let Some(ref parent) = self.parent {
+            function.set_prototype(guard, parent)?;
+        }
+
+        prototype.set(guard, &Property::new(guard, "constructor"), &function);
+        function.set(guard, &Property::new(guard, "prototype"), &prototype);
+        Ok(function)
+    }
+}
+
+impl<T> Drop for Instance<T> {
+    fn drop(&mut self) {
+        let address = self as *const _ as usize;
+        self.class.instances.lock().unwrap().remove(&address);
+    }
+}
+
+/// Executes a closure with the instance of a class, throwing a `TypeError` if
+/// the value is not an instance of the class.
+fn with_instance<T, F>(guard: &ContextGuard, state: &ClassState, this: &Value, callback: F) -> CallbackResult
+        where F: FnOnce(&Instance<T>) -> CallbackResult {
+    let address = this.clone().into_external().map(|external| unsafe {
+        let mut data = ptr::null_mut();
+        jsassert!(JsGetExternalData(external.as_raw(), &mut data));
+        data as usize
+    });
+
+    match address {
+        Some(address) if state.instances.lock().unwrap().contains(&address) => {
+            callback(unsafe { &*(address as *const Instance<T>) })
+        },
+        _ => Err(value::Error::type_error(guard, "Illegal invocation").into()),
+    }
+}
+
+/// Mutably borrows an instance's data, throwing if it's already borrowed.
+fn borrow_mut<'a, T>(guard: &ContextGuard,
+                     instance: &'a Instance<T>)
+                     -> ::std::result::Result<::std::cell::RefMut<'a, T>, Value> {
+    instance.data.try_borrow_mut().map_err(|_| borrow_error(guard))
+}
+
+/// Returns an error for re-entrant use of an instance.
+fn borrow_error(guard: &ContextGuard) -> Value {
+    value::Error::type_error(guard, "Native instance is already in use").into()
+}
+
+/// A finalizer callback, triggered before an instance is collected.
+unsafe extern "system" fn finalize<T>(data: *mut c_void) {
+    Box::from_raw(data as *mut Instance<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, value, script, Property};
+    use super::ClassBuilder;
+
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    #[test]
+    fn class() {
+        test::run_with_context(|guard| {
+            let class = ClassBuilder::new("Point", Box::new(|guard, info| {
+                    Ok(Point {
+                        x: info.arguments[0].to_double(guard),
+                        y: info.arguments[1].to_double(guard),
+                    })
+                }))
+                .method("length", Box::new(|guard, point: &mut Point, _| {
+                    let length = (point.x * point.x + point.y * point.y).sqrt();
+                    Ok(value::Number::from_double(guard, length).into())
+                }))
+                .accessor("x", Box::new(|guard, point: &Point| {
+                    Ok(value::Number::from_double(guard, point.x).into())
+                }), Some(Box::new(|guard, point: &mut Point, value| {
+                    point.x = value.to_double(guard);
+                    Ok(())
+                })))
+                .build(guard)
+                .unwrap();
+            guard.global().set(guard, &Property::new(guard, "Point"), &class);
+
+            let result = script::eval(guard, "
+                var point = new Point(3, 4);
+                var length = point.length();
+                point.x = 6;
+                [length, point.x, point instanceof Point]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), "[5,6,true]");
+
+            let result = script::eval(guard, "
+                var errors = [];
+                try { Point(1, 2); } catch (ex) { errors.push(ex instanceof TypeError); }
+                try { Point.prototype.length.call({}); } catch (ex) { errors.push(ex instanceof TypeError); }
+                errors").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), "[true,true]");
+        });
+    }
+}
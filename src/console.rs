@@ -0,0 +1,263 @@
+//! The `console` global, forwarding messages to a sink.
+//!
+//! The arguments are formatted similar to browsers: a leading string may
+//! contain substitutions (`%s`, `%d`, `%i`, `%f`, `%o`, `%O`, `%j` and `%%`),
+//! and the remaining arguments are appended, separated by spaces. Values
+//! are formatted like Node's `util.inspect` (see `Value::inspect`), except
+//! for `%j`, which uses the engine's `JSON.stringify`.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use std::sync::{Arc, Mutex};
+//! # use js::console::Level;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let messages = Arc::new(Mutex::new(Vec::new()));
+//! let sink = messages.clone();
+//! js::console::install(&guard, Box::new(move |level: Level, message: &str| {
+//!     sink.lock().unwrap().push((level, message.to_string()));
+//! })).unwrap();
+//!
+//! js::script::eval(&guard, "console.warn('%s has %d items', 'cart', 3, [1, 2])").unwrap();
+//! assert_eq!(messages.lock().unwrap()[0], (Level::Warn, "cart has 3 items [ 1, 2 ]".to_string()));
+//! ```
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use context::ContextGuard;
+use error::*;
+use value::{self, Value};
+use Property;
+
+/// The severity of a console message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Log,
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Log => "log",
+            Level::Debug => "debug",
+        })
+    }
+}
+
+/// A destination of console messages.
+pub trait Sink: Send + Sync {
+    /// Writes a formatted message.
+    fn write(&self, level: Level, message: &str);
+}
+
+impl<F: Fn(Level, &str) + Send + Sync> Sink for F {
+    fn write(&self, level: Level, message: &str) {
+        self(level, message)
+    }
+}
+
+/// A sink writing messages as lines to a writer.
+///
+/// Messages other than `console.log` are prefixed with their level. Errors
+/// of the writer are ignored.
+pub struct WriteSink<W: Write + Send>(Mutex<W>);
+
+impl<W: Write + Send> WriteSink<W> {
+    /// Creates a sink using a writer.
+    pub fn new(writer: W) -> Self {
+        WriteSink(Mutex::new(writer))
+    }
+}
+
+impl<W: Write + Send> Sink for WriteSink<W> {
+    fn write(&self, level: Level, message: &str) {
+        let mut writer = self.0.lock().unwrap();
+        let _ = match level {
+            Level::Log => writeln!(writer, "{}", message),
+            _ => writeln!(writer, "{}: {}", level, message),
+        };
+    }
+}
+
+/// A sink writing errors and warnings to the standard error, and any other
+/// messages to the standard output.
+pub struct Stdio;
+
+impl Sink for Stdio {
+    fn write(&self, level: Level, message: &str) {
+        let _ = match level {
+            Level::Error | Level::Warn => writeln!(io::stderr(), "{}", message),
+            _ => writeln!(io::stdout(), "{}", message),
+        };
+    }
+}
+
+/// A sink forwarding messages to the `log` crate.
+///
+/// `console.log` messages are logged at the info level.
+#[cfg(feature = "log")]
+pub struct LogSink {
+    target: String,
+}
+
+#[cfg(feature = "log")]
+impl LogSink {
+    /// Creates a sink logging messages with a target.
+    pub fn new(target: &str) -> Self {
+        LogSink { target: target.to_string() }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Sink for LogSink {
+    fn write(&self, level: Level, message: &str) {
+        let level = match level {
+            Level::Error => ::log_lib::Level::Error,
+            Level::Warn => ::log_lib::Level::Warn,
+            Level::Info | Level::Log => ::log_lib::Level::Info,
+            Level::Debug => ::log_lib::Level::Debug,
+        };
+        log!(target: &self.target, level, "{}", message);
+    }
+}
+
+/// Installs the global `console` object in the guard's context, with the
+/// `log`, `info`, `warn`, `error` and `debug` functions.
+pub fn install(guard: &ContextGuard, sink: Box<Sink>) -> Result<()> {
+    let sink: Arc<Box<Sink>> = Arc::new(sink);
+    let console = value::Object::new(guard);
+
+    for &(name, level) in &[("log", Level::Log),
+                            ("info", Level::Info),
+                            ("warn", Level::Warn),
+                            ("error", Level::Error),
+                            ("debug", Level::Debug)] {
+        let sink = sink.clone();
+        let function = value::Function::with_name(guard, name, Box::new(move |guard, info| {
+            sink.write(level, &format(guard, &info.arguments));
+            Ok(value::undefined(guard))
+        }));
+        console.set(guard, &Property::new(guard, name), &function);
+    }
+
+    guard.global().set(guard, &Property::new(guard, "console"), &console);
+    Ok(())
+}
+
+/// Formats console arguments into a message.
+pub fn format(guard: &ContextGuard, arguments: &[Value]) -> String {
+    let mut rest = arguments.iter();
+    let mut parts = Vec::new();
+
+    if let Some(template) = arguments.first().and_then(|first| first.clone().into_string()) {
+        rest.next();
+        let template = template.value();
+        let mut message = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(character) = chars.next() {
+            if character != '%' {
+                message.push(character);
+                continue;
+            }
+
+            let substitution = match chars.peek().cloned() {
+                Some('%') => Some("%".to_string()),
+                Some('s') => rest.next().map(|value| inspect(guard, value)),
+                Some('d') | Some('i') => rest.next().map(|value| number(guard, value).trunc().to_string()),
+                Some('f') => rest.next().map(|value| number(guard, value).to_string()),
+                Some('o') | Some('O') => rest.next().map(|value| value.inspect(guard, &value::InspectOptions::default())),
+                Some('j') => rest.next().map(|value| guard.json_stringify(value).unwrap_or_else(|_| "[Circular]".to_string())),
+                _ => None,
+            };
+
+            // Unknown substitutions, or ones without an argument, are kept
+            match substitution {
+                Some(substitution) => {
+                    chars.next();
+                    message.push_str(&substitution);
+                },
+                None => message.push('%'),
+            }
+        }
+        parts.push(message);
+    }
+
+    parts.extend(rest.map(|value| inspect(guard, value)));
+    parts.join(" ")
+}
+
+/// Converts a value to a number, which is `NaN` if the conversion throws
+/// (e.g for symbols).
+fn number(guard: &ContextGuard, value: &Value) -> f64 {
+    value.try_to_double(guard).unwrap_or(::std::f64::NAN)
+}
+
+/// Returns the representation of a value, with strings left unquoted.
+///
+/// Other values are rendered like Node's `util.inspect`, so messages match
+/// the host's own `Value::inspect` output.
+fn inspect(guard: &ContextGuard, value: &Value) -> String {
+    match value.clone().into_string() {
+        Some(string) => string.value(),
+        None => value.inspect(guard, &value::InspectOptions::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::io::Write;
+    use {test, script};
+    use super::{Level, Sink, WriteSink};
+
+    #[test]
+    fn format() {
+        test::run_with_context(|guard| {
+            let messages = Arc::new(Mutex::new(Vec::new()));
+            let sink = messages.clone();
+            super::install(guard, Box::new(move |level: Level, message: &str| {
+                sink.lock().unwrap().push(format!("{}: {}", level, message));
+            })).unwrap();
+
+            script::eval(guard, "
+                console.log('plain', 1, true, null, undefined, { a: [1] });
+                console.info('%s=%d%% (%f) %j %x %s', 'rate', 42.9, 1.5, 'quoted');
+                console.error(Symbol('id'), function named() {});
+                var cycle = {}; cycle.self = cycle;
+                console.debug(cycle);
+                console.log('%d %f', Symbol(), { valueOf() { throw 1; } })").unwrap();
+
+            assert_eq!(*messages.lock().unwrap(), [
+                "log: plain 1 true null undefined { a: [ 1 ] }",
+                r#"info: rate=42% (1.5) "quoted" %x %s"#,
+                "error: Symbol(id) [Function: named]",
+                "debug: <ref *1> { self: [Circular *1] }",
+                "log: NaN NaN",
+            ]);
+        });
+    }
+
+    #[test]
+    fn write_sink() {
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buffer: &[u8]) -> ::std::io::Result<usize> { self.0.lock().unwrap().write(buffer) }
+            fn flush(&mut self) -> ::std::io::Result<()> { Ok(()) }
+        }
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let sink = WriteSink::new(Shared(output.clone()));
+        sink.write(Level::Log, "foo");
+        sink.write(Level::Warn, "bar");
+        assert_eq!(&*output.lock().unwrap(), b"foo\nwarn: bar\n");
+    }
+}
@@ -1,19 +1,74 @@
 //! Execution contexts and sandboxing.
+use std::collections::{HashMap, VecDeque};
 use std::marker::PhantomData;
-use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::{mem, ptr};
+use std::time::{Duration, Instant};
 use boolinator::Boolinator;
 use anymap::AnyMap;
 use chakracore_sys::*;
+use deadline::{Scheduler, Watchdog};
 use error::*;
-use util::jstry;
-use {value, Runtime};
+use event_loop::{ErrorPolicy, EventLoop, Policy};
+use snapshot::{self, Snapshot};
+use util::{self, jstry};
+use value::Builtins;
+use value::function::Middleware;
+use property::PropertyCache;
+use runtime::{Event, Events};
+use policy::Policy as RuntimePolicy;
+use {script, value, Property, Runtime};
+
+/// Callback type for exceptions thrown by microtasks.
+pub type MicrotaskErrorCallback = Fn(&ContextGuard, &Error) + Send;
+
+/// Callback type for rendering stack traces.
+pub type StackFormatter = Fn(&str) -> String;
 
 /// Used for holding context instance data.
 struct ContextData {
-    promise_queue: Vec<value::Function>,
+    promise_queue: VecDeque<value::Function>,
+    microtask_error: Option<Box<MicrotaskErrorCallback>>,
+    middleware: Rc<Vec<Rc<Box<Middleware>>>>,
+    stack_trace_limit: Option<u32>,
+    stack_formatter: Option<Rc<Box<StackFormatter>>>,
+    scheduler: Option<Scheduler>,
+    shutting_down: bool,
+    timers: Vec<Timer>,
+    timer_id: u32,
+    pass: u64,
+    intrinsics: Option<Property>,
+    external_data: Option<Property>,
+    thrown: HashMap<usize, value::Value>,
+    orphaned_thrown: Arc<Mutex<Vec<usize>>>,
+    events: Events,
+    policy: Option<Arc<RuntimePolicy>>,
+    collections: Arc<AtomicUsize>,
     user_data: AnyMap,
 }
 
+/// A callback scheduled to be executed by `execute_tasks`.
+struct Timer {
+    id: u32,
+    deadline: Instant,
+    callback: value::Function,
+    arguments: Vec<value::Value>,
+    interval: Option<Duration>,
+    /// The task pass during which the timer was scheduled, if it is deferred.
+    pass: u64,
+    /// Whether the timer was scheduled by a script, and can be cancelled by it.
+    script: bool,
+}
+
+/// The engine's built-in `JSON.parse` and `JSON.stringify` functions.
+#[derive(Clone)]
+pub(crate) struct JsonFunctions {
+    pub parse: value::Function,
+    pub stringify: value::Function,
+}
+
 /// A sandboxed execution context with its own set of built-in objects and
 /// functions.
 ///
@@ -26,29 +81,186 @@ struct ContextData {
 #[derive(Debug, PartialEq)]
 pub struct Context(JsContextRef);
 
+/// A snapshot of a context's global bindings, created by `Context::snapshot`.
+///
+/// The snapshot is independent of its context, but native functions share
+/// their callbacks with the original bindings, so it cannot be sent to other
+/// threads.
+pub struct ContextSnapshot(Snapshot);
+
 // TODO: Should context lifetime explicitly depend on runtime?
 impl Context {
     /// Creates a new context and returns a handle to it.
     pub fn new(runtime: &Runtime) -> Result<Context> {
-        let mut reference = JsContextRef::new();
         unsafe {
-            jstry!(JsCreateContext(runtime.as_raw(), &mut reference));
-            jstry!(JsSetObjectBeforeCollectCallback(reference, ptr::null_mut(), Some(Self::collect)));
-
-            let context = Self::from_raw(reference);
-            context.set_data(Box::new(ContextData {
-                promise_queue: Vec::new(),
-                user_data: AnyMap::new(),
-            }))?;
-
-            // Promise continuation callback requires an active context
-            context.exec_with(|_| {
-                let data = context.get_data() as *mut _ as *mut _;
-                jstry(JsSetPromiseContinuationCallback(Some(Self::promise_handler), data))
-            })
-            .expect("activating promise continuation callback")
-            .map(|_| context)
+            Self::create(runtime.as_raw(), runtime.events().clone(), runtime.policy(), runtime.collections())
+        }
+    }
+
+    /// Creates a new context, and customizes its global object before any
+    /// script is evaluated.
+    ///
+    /// The callback receives the context's global object, e.g to install host
+    /// bindings or to remove built-ins. If it fails, the context is discarded.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// let context = js::Context::new_with_globals(&runtime, |guard, global| {
+    ///     global.set(guard, &js::Property::new(guard, "version"), &js::value::String::new(guard, "1.0"));
+    ///     global.delete(guard, &js::Property::new(guard, "Date"));
+    ///     Ok(())
+    /// }).unwrap();
+    ///
+    /// let guard = context.make_current().unwrap();
+    /// let result = js::script::eval(&guard, "version + ' ' + typeof Date").unwrap();
+    /// assert_eq!(result.to_string(&guard), "1.0 undefined");
+    /// ```
+    pub fn new_with_globals<T>(runtime: &Runtime, initialize: T) -> Result<Context>
+            where T: FnOnce(&ContextGuard, &value::Object) -> Result<()> {
+        let context = Self::new(runtime)?;
+        context.exec_with(|guard| initialize(guard, &guard.global()))??;
+        Ok(context)
+    }
+
+    /// Creates a new context which cannot generate code from strings, and
+    /// customizes its global object (see `new_with_globals`).
+    ///
+    /// The global `eval` and `Function` are removed, and the constructors of
+    /// functions, generators and async functions (which remain reachable from
+    /// their prototypes) throw an `EvalError` instead. In contrast to the
+    /// runtime's `disable_eval`, this only affects the context's scripts; the
+    /// host can still evaluate code using the `script` module.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// let context = js::Context::new_sandboxed(&runtime, |_, _| Ok(())).unwrap();
+    /// let guard = context.make_current().unwrap();
+    ///
+    /// assert_eq!(js::script::eval(&guard, "typeof eval").unwrap().to_string(&guard), "undefined");
+    /// assert!(js::script::eval(&guard, "(() => {}).constructor('return this')").is_err());
+    /// ```
+    pub fn new_sandboxed<T>(runtime: &Runtime, initialize: T) -> Result<Context>
+            where T: FnOnce(&ContextGuard, &value::Object) -> Result<()> {
+        Self::new_with_globals(runtime, |guard, global| {
+            Self::disable_code_generation(guard, global)?;
+            initialize(guard, global)
+        })
+    }
+
+    /// Removes the functions generating code from strings.
+    fn disable_code_generation(guard: &ContextGuard, global: &value::Object) -> Result<()> {
+        let constructor = value::Function::with_name(guard, "Function", Box::new(|guard, _| {
+            let message = "Code generation from strings is disallowed in this context";
+            Err(value::Error::custom(guard, "EvalError", message).into())
+        }));
+
+        let prototypes = script::eval(guard, "[
+            Function.prototype,
+            Object.getPrototypeOf(function* () {}),
+            Object.getPrototypeOf(async function () {}),
+        ]")?.into_array().expect("retrieving function prototypes");
+
+        let descriptor = value::PropertyDescriptor::new()
+            .value(constructor)
+            .writable(false)
+            .configurable(false);
+        for prototype in prototypes.iter(guard) {
+            let prototype = prototype.into_object().expect("converting function prototype");
+            prototype.define(guard, &Property::new(guard, "constructor"), &descriptor)?;
+        }
+
+        for name in &["eval", "Function"] {
+            global.delete(guard, &Property::new(guard, name));
         }
+        Ok(())
+    }
+
+    /// Creates a new context using a raw runtime handle, and the runtime's
+    /// lifecycle events, policy and collection counter.
+    unsafe fn create(runtime: JsRuntimeHandle,
+                     events: Events,
+                     policy: Option<Arc<RuntimePolicy>>,
+                     collections: Arc<AtomicUsize>) -> Result<Context> {
+        let mut reference = JsContextRef::new();
+        jstry!(JsCreateContext(runtime, &mut reference));
+        jstry!(JsSetObjectBeforeCollectCallback(reference, ptr::null_mut(), Some(Self::collect)));
+
+        let context = Self::from_raw(reference);
+        context.set_data(Box::new(ContextData {
+            promise_queue: VecDeque::new(),
+            microtask_error: None,
+            middleware: Rc::new(Vec::new()),
+            stack_trace_limit: None,
+            stack_formatter: None,
+            scheduler: None,
+            shutting_down: false,
+            timers: Vec::new(),
+            timer_id: 0,
+            pass: 0,
+            intrinsics: None,
+            external_data: None,
+            thrown: HashMap::new(),
+            orphaned_thrown: Arc::new(Mutex::new(Vec::new())),
+            events: events.clone(),
+            policy: policy.clone(),
+            collections: collections,
+            user_data: AnyMap::new(),
+        }))?;
+
+        // Promise continuation callback requires an active context
+        context.exec_with(|guard| {
+            let data = context.get_data() as *mut _ as *mut _;
+            jstry(JsSetPromiseContinuationCallback(Some(Self::promise_handler), data))?;
+
+            // The built-ins are retrieved before any global can be modified
+            let intrinsics = value::Object::new(guard);
+            intrinsics.set(guard, &Property::new(guard, "builtins"), &Builtins::capture(guard));
+            for name in &["JSON.parse", "JSON.stringify"] {
+                if let Some(function) = util::jsfunc(guard, name) {
+                    intrinsics.set(guard, &Property::new(guard, name), &function);
+                }
+            }
+            Self::install_intrinsics(guard, &intrinsics)?;
+            if let Some(ref policy) = policy {
+                policy.restrict_globals(guard);
+            }
+            Ok(())
+        })
+        .expect("activating promise continuation callback")
+        .map(|_| {
+            events.emit(Event::ContextCreated);
+            context
+        })
+    }
+
+    /// Stores the values the crate looks up in a context, e.g the built-ins
+    /// as they were before any script was evaluated.
+    ///
+    /// Rooting these values would keep the context alive, so they are held by
+    /// the global object instead, as a hidden, read-only property. The values
+    /// and their containers are frozen, since scripts can still find them
+    /// through the property's symbol.
+    unsafe fn install_intrinsics(guard: &ContextGuard, intrinsics: &value::Object) -> Result<()> {
+        for key in snapshot::own_property_names(guard, intrinsics) {
+            let value = intrinsics.get(guard, &Property::new(guard, &key));
+            if !value.is_function() {
+                if let Some(object) = value.into_object() {
+                    object.freeze(guard)?;
+                }
+            }
+        }
+        intrinsics.freeze(guard)?;
+
+        let key = Property::from_symbol(guard, &value::Symbol::new(guard, "intrinsics"));
+        let descriptor = value::PropertyDescriptor::new()
+            .value(intrinsics.clone())
+            .writable(false)
+            .configurable(false);
+        guard.global().define(guard, &key, &descriptor)?;
+        guard.context().get_data().intrinsics = Some(key);
+        Ok(())
     }
 
     /// Binds the context to the current scope.
@@ -152,6 +364,211 @@ impl Context {
         unsafe { self.get_data().user_data.get_mut::<T>() }
     }
 
+    /// Sets a callback for exceptions thrown by microtasks.
+    ///
+    /// Microtasks are run by `execute_tasks`, and an exception thrown by one
+    /// (or by an expired timer) does not prevent the remaining tasks from
+    /// running. Event loops with a reporting policy use it as well. Without a
+    /// callback, these exceptions are discarded. Promise reactions are not
+    /// affected, since their exceptions reject the derived promise instead.
+    pub fn on_microtask_error(&self, callback: Box<MicrotaskErrorCallback>) {
+        unsafe { self.get_data().microtask_error = Some(callback) };
+    }
+
+    /// Adds middleware wrapping every host function called in the context.
+    ///
+    /// Host functions are the named native functions (i.e those created with
+    /// `Function::with_name`), which includes the functions installed by this
+    /// crate's modules. Middleware is called in the order it was added, so the
+    /// first one is the outermost, and it applies to functions created before
+    /// it was added as well.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// context.add_middleware(Box::new(|guard, function, info, next| {
+    ///     if function == "setTimeout" {
+    ///         return Err(js::value::Error::new(guard, "Permission denied").into());
+    ///     }
+    ///     next.call(guard, info)
+    /// }));
+    ///
+    /// let guard = context.make_current().unwrap();
+    /// js::timers::install(&guard).unwrap();
+    /// assert!(js::script::eval(&guard, "setTimeout(() => {})").is_err());
+    /// assert!(js::script::eval(&guard, "clearTimeout(0)").is_ok());
+    /// ```
+    pub fn add_middleware(&self, middleware: Box<Middleware>) {
+        // The chain is only copied if it is being called
+        let data = unsafe { self.get_data() };
+        Rc::make_mut(&mut data.middleware).push(Rc::new(middleware));
+    }
+
+    /// Sets the maximum number of frames captured by stack traces.
+    ///
+    /// This sets the context's `Error.stackTraceLimit` (which defaults to
+    /// 10), and limits the frames returned by a paused debugger as well.
+    pub fn set_stack_trace_limit(&self, limit: u32) -> Result<()> {
+        self.exec_with(|guard| {
+            let error = util::jsfunc(guard, "Error").ok_or("Error constructor is unavailable")?;
+            error.set(guard, &::Property::new(guard, "stackTraceLimit"), &value::Number::from_double(guard, limit as f64));
+            unsafe { self.get_data().stack_trace_limit = Some(limit) };
+            Ok(())
+        })?
+    }
+
+    /// Sets a formatter for the stack traces rendered by the crate.
+    ///
+    /// It is applied to the stack of every `ScriptError` (and therefore
+    /// audit records), and to errors logged by the console. The formatter
+    /// receives the stack as provided by the engine, and may trim or enrich
+    /// it, e.g by hiding frames of bootstrap code.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # use js::error::{ErrorKind, Frame};
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// context.set_stack_formatter(Box::new(|stack| {
+    ///     stack.lines()
+    ///         .filter(|line| Frame::parse(line).map_or(true, |frame| !frame.url.starts_with("lib:")))
+    ///         .collect::<Vec<_>>()
+    ///         .join("\n")
+    /// }));
+    ///
+    /// let guard = context.make_current().unwrap();
+    /// js::script::eval_with_name(&guard, "lib:bootstrap.js", "function run(f) { f(); }").unwrap();
+    /// let error = js::script::eval_with_name(&guard, "app.js", "run(() => { throw new Error('foo'); })").unwrap_err();
+    /// match *error.kind() {
+    ///     ErrorKind::ScriptException(ref error) => assert!(!error.stack.as_ref().unwrap().contains("lib:")),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn set_stack_formatter(&self, formatter: Box<StackFormatter>) {
+        unsafe { self.get_data().stack_formatter = Some(Rc::new(formatter)) };
+    }
+
+    /// Sets the scheduler driving the context's timers and timeouts.
+    ///
+    /// By default, the global (real-time) scheduler is used. See the
+    /// `deadline` module for details.
+    pub fn set_scheduler(&self, scheduler: Scheduler) {
+        unsafe { self.get_data().scheduler = Some(scheduler) };
+    }
+
+    /// Creates a context with a copy of the global bindings of the guard's
+    /// context.
+    ///
+    /// This allows a prepared context to spawn isolated children, without
+    /// evaluating the preparation code again. The child belongs to the same
+    /// runtime. All bindings which are not built-in are copied deeply (shared
+    /// and cyclic references are preserved):
+    ///
+    /// - Native functions are re-created with the same callback (so any state
+    ///   captured by the callback is shared with the parent).
+    /// - Script functions are re-evaluated from their source, so they lose
+    ///   any variables captured by closures. Forking fails if a function's
+    ///   source cannot be evaluated (e.g if its `toString` was replaced).
+    /// - Objects are copied as plain objects (i.e prototypes, property
+    ///   attributes and accessors are not preserved).
+    /// - Values which cannot be copied, such as external objects, are omitted.
+    ///
+    /// User data and scheduled tasks are not copied.
+    pub fn fork(guard: &ContextGuard) -> Result<Context> {
+        let runtime = guard.context().runtime_handle();
+        let context = unsafe { Self::create(runtime, guard.events(), guard.policy(), guard.collections())? };
+        let snapshot = ContextSnapshot::read(guard, &context)?;
+        context.exec_with(|child| snapshot.0.write(child))??;
+        Ok(context)
+    }
+
+    /// Takes a snapshot of the global bindings of the guard's context.
+    ///
+    /// Unlike `fork`, the snapshot is only taken once, and can then be
+    /// restored in any number of contexts (of any runtime on this thread)
+    /// using `from_snapshot`. The bindings are copied as described by `fork`.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// let runtime = js::Runtime::new().unwrap();
+    /// let template = js::Context::new(&runtime).unwrap();
+    /// let snapshot = template.exec_with(|guard| {
+    ///     js::script::eval(&guard, "var config = { limit: 10 };").unwrap();
+    ///     js::Context::snapshot(&guard)
+    /// }).unwrap().unwrap();
+    ///
+    /// for _ in 0..3 {
+    ///     let sandbox = js::Context::from_snapshot(&runtime, &snapshot).unwrap();
+    ///     let guard = sandbox.make_current().unwrap();
+    ///     assert_eq!(js::script::eval(&guard, "config.limit++").unwrap().to_integer(&guard), 10);
+    /// }
+    /// ```
+    pub fn snapshot(guard: &ContextGuard) -> Result<ContextSnapshot> {
+        let runtime = guard.context().runtime_handle();
+        let pristine = unsafe { Self::create(runtime, guard.events(), guard.policy(), guard.collections())? };
+        ContextSnapshot::read(guard, &pristine)
+    }
+
+    /// Creates a new context with the global bindings of a snapshot.
+    pub fn from_snapshot(runtime: &Runtime, snapshot: &ContextSnapshot) -> Result<Context> {
+        let context = Self::new(runtime)?;
+        context.exec_with(|guard| snapshot.0.write(guard))??;
+        Ok(context)
+    }
+
+    /// Shuts down the guard's context, allowing pending work to finish.
+    ///
+    /// Once called, `queueMicrotask` (see `timers::install`) throws and host
+    /// callbacks are no longer scheduled, but promise reactions still run.
+    /// Queued tasks and scheduled callbacks are executed until none remain, or
+    /// the grace period expires.
+    /// If time remains, the global `onbeforeexit` function is then called (if
+    /// defined), and any work it queues is drained as well.
+    ///
+    /// Anything still pending after the grace period is discarded, and a
+    /// script running at that time is interrupted (this requires the runtime
+    /// to have been created with `enable_script_interrupt`). Returns true if
+    /// all work completed within the grace period.
+    pub fn shutdown(guard: &ContextGuard, grace: Duration) -> Result<bool> {
+        let scheduler = guard.scheduler();
+        let deadline = scheduler.now() + grace;
+        let watchdog = Watchdog::start(&scheduler, guard.context().runtime_handle(), guard.events(), grace);
+
+        let data = unsafe { guard.current.get_data() };
+        data.shutting_down = true;
+
+        // Failing tasks are reported, so they do not prevent the others from finishing
+        let policy = Policy { errors: ErrorPolicy::Report, ..Policy::default() };
+        let mut event_loop = EventLoop::with_policy(guard, policy);
+        let mut result = event_loop.run_until(Some(deadline));
+        if result.is_ok() && scheduler.now() < deadline {
+            let hook = guard.global().get(guard, &::Property::new(guard, "onbeforeexit")).into_function();
+            if let Some(hook) = hook {
+                result = hook.call(guard, &[]).map(|_| ()).and_then(|_| event_loop.run_until(Some(deadline)));
+            }
+        }
+
+        let completed = data.promise_queue.is_empty() && data.timers.is_empty();
+        data.promise_queue.clear();
+        data.timers.clear();
+
+        let interrupted = watchdog.stop()?;
+
+        match result {
+            Ok(_) => Ok(completed && !interrupted),
+            Err(Error(ErrorKind::ScriptTerminated, _)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the handle of the runtime that owns the context.
+    pub(crate) fn runtime_handle(&self) -> JsRuntimeHandle {
+        let mut runtime = JsRuntimeHandle::new();
+        jsassert!(unsafe { JsGetRuntime(self.as_raw(), &mut runtime) });
+        runtime
+    }
+
     /// Returns a recyclable value's associated context.
     ///
     /// This is unreliable, because types that have an associated context is
@@ -198,13 +615,20 @@ impl Context {
     /// A promise handler, triggered whenever a promise method is used.
     unsafe extern "system" fn promise_handler(task: JsValueRef, data: *mut ::libc::c_void) {
         let data = (data as *mut ContextData).as_mut().expect("retrieving promise handler stack");
-        data.promise_queue.push(value::Function::from_raw(task));
+        data.promise_queue.push_back(value::Function::from_raw(task));
     }
 
     /// A collect callback, triggered before the context is destroyed.
     unsafe extern "system" fn collect(context: JsContextRef, _: *mut ::libc::c_void) {
         let context = Self::from_raw(context);
-        Box::from_raw(context.get_data());
+        let mut data = Box::from_raw(context.get_data());
+        data.events.emit(Event::ContextCollected);
+
+        // Any remaining values are unreachable, and releasing them would call
+        // into JSRT from within the collection
+        mem::forget(mem::replace(&mut data.promise_queue, VecDeque::new()));
+        mem::forget(mem::replace(&mut data.timers, Vec::new()));
+        mem::forget(mem::replace(&mut data.thrown, HashMap::new()));
     }
 }
 
@@ -220,6 +644,15 @@ pub struct ContextGuard<'a> {
     drop: bool,
 }
 
+impl ContextSnapshot {
+    /// Reads the bindings of the guard's context, which are not defined by a
+    /// pristine context (i.e the built-ins).
+    fn read(guard: &ContextGuard, pristine: &Context) -> Result<Self> {
+        let builtins = pristine.exec_with(|child| snapshot::own_property_names(child, &child.global()))?;
+        Snapshot::read(guard, &builtins).map(ContextSnapshot)
+    }
+}
+
 impl<'a> ContextGuard<'a> {
     /// Returns the guard's associated context.
     pub fn context(&self) -> Context {
@@ -235,12 +668,327 @@ impl<'a> ContextGuard<'a> {
         }
     }
 
-    /// Executes all the context's queued promise tasks.
+    /// Returns whether the runtime has a pending exception.
+    ///
+    /// Native calls fail while an exception is pending (e.g after `throw`),
+    /// until it is taken using `take_exception`.
+    pub fn has_exception(&self) -> bool {
+        let mut result = false;
+        jsassert!(unsafe { JsHasException(&mut result) });
+        result
+    }
+
+    /// Takes the runtime's pending exception, if there is one.
+    pub fn take_exception(&self) -> Option<value::Value> {
+        if !self.has_exception() {
+            return None;
+        }
+
+        let mut exception = JsValueRef::new();
+        unsafe {
+            jsassert!(JsGetAndClearException(&mut exception));
+            Some(value::Value::from_raw(exception))
+        }
+    }
+
+    /// Sets the runtime's pending exception.
+    ///
+    /// Within a native callback, the exception is thrown once the callback
+    /// returns (regardless of its result). Otherwise, the exception is
+    /// pending until it is taken, replacing any previous exception.
+    pub fn throw(&self, exception: &value::Value) {
+        jsassert!(unsafe { JsSetException(exception.as_raw()) });
+    }
+
+    /// Evaluates code in an isolated context and returns a clone of the result.
+    ///
+    /// The code is executed in a new context, sharing the same runtime, with
+    /// its own set of built-in objects. Therefore the code cannot access nor
+    /// modify any bindings of this context. The result is transferred using
+    /// structured clone semantics, so only plain data (primitives, arrays and
+    /// objects) can be returned.
+    pub fn isolated_eval(&self, code: &str) -> Result<value::Value> {
+        let runtime = self.current.runtime_handle();
+        let context = unsafe { Context::create(runtime, self.events(), self.policy(), self.collections())? };
+        let result = context.exec_with(|guard| {
+            script::eval(guard, code).and_then(|value| value.export(guard))
+        })??;
+        Ok(result.write(self))
+    }
+
+    /// Parses JSON using the engine's built-in `JSON.parse`.
+    ///
+    /// The built-in functions are retrieved when the context is created, so
+    /// they are unaffected by scripts replacing the global `JSON` object.
+    pub fn json_parse(&self, json: &str) -> Result<value::Value> {
+        let json = value::String::new(self, json);
+        self.json()?.parse.call(self, &[&json])
+    }
+
+    /// Converts a value to JSON using the engine's built-in `JSON.stringify`.
+    ///
+    /// Values without a JSON representation (e.g `undefined` or functions)
+    /// result in an error.
+    pub fn json_stringify(&self, value: &value::Value) -> Result<String> {
+        let json = self.json()?.stringify.call(self, &[value])?;
+        match json.into_string() {
+            Some(json) => Ok(json.value()),
+            None => Err("value has no JSON representation".into()),
+        }
+    }
+
+    /// Returns a property identifier, reusing the context's cached one.
+    ///
+    /// In contrast to `Property::new`, the identifier is only created once per
+    /// context and name. This is intended for the names used by hot paths,
+    /// since cached identifiers live as long as the context.
+    pub fn property(&self, name: &str) -> Property {
+        let data = unsafe { self.current.get_data() };
+        data.user_data.entry::<PropertyCache>().or_insert_with(PropertyCache::default).get(self, name)
+    }
+
+    /// Returns the scheduler driving the context's timers and timeouts.
+    pub fn scheduler(&self) -> Scheduler {
+        let data = unsafe { self.current.get_data() };
+        data.scheduler.clone().unwrap_or_else(Scheduler::global)
+    }
+
+    /// Returns the lifecycle events of the context's runtime.
+    pub(crate) fn events(&self) -> Events {
+        unsafe { self.current.get_data().events.clone() }
+    }
+
+    /// Returns the policy of the context's runtime, if any.
+    pub(crate) fn policy(&self) -> Option<Arc<RuntimePolicy>> {
+        unsafe { self.current.get_data().policy.clone() }
+    }
+
+    /// Returns the collection counter of the context's runtime.
+    pub(crate) fn collections(&self) -> Arc<AtomicUsize> {
+        unsafe { self.current.get_data().collections.clone() }
+    }
+
+    /// Returns the context's host function middleware.
+    ///
+    /// The chain is shared, so middleware added during a call only applies to
+    /// subsequent calls.
+    pub(crate) fn middleware(&self) -> Rc<Vec<Rc<Box<Middleware>>>> {
+        unsafe { self.current.get_data().middleware.clone() }
+    }
+
+    /// Returns the context's stack trace limit, if it has been set.
+    pub(crate) fn stack_trace_limit(&self) -> Option<u32> {
+        unsafe { self.current.get_data().stack_trace_limit }
+    }
+
+    /// Renders a stack trace using the context's formatter.
+    pub(crate) fn format_stack(&self, stack: &str) -> String {
+        // The formatter may be replaced while it is running
+        let formatter = unsafe { self.current.get_data().stack_formatter.clone() };
+        formatter.map_or_else(|| stack.to_string(), |formatter| formatter(stack))
+    }
+
+    /// Returns one of the context's intrinsics (see `install_intrinsics`).
+    fn intrinsic(&self, name: &str) -> Option<value::Value> {
+        let data = unsafe { self.current.get_data() };
+        let intrinsics = data.intrinsics.as_ref().and_then(|key| self.global().get(self, key).into_object())?;
+        let value = intrinsics.get(self, &self.property(name));
+        if value.is_undefined() { None } else { Some(value) }
+    }
+
+    /// Returns the context's built-in functions (retrieved when the context
+    /// was created).
+    pub(crate) fn builtins<'b>(&'b self) -> Builtins<'b> {
+        Builtins::new(self, self.intrinsic("builtins").and_then(|functions| functions.into_object()))
+    }
+
+    /// Returns the key of the hidden property holding objects' external data.
+    ///
+    /// Only the symbol's property identifier is kept, since it belongs to the
+    /// runtime (unlike the symbol, which would keep the context alive).
+    pub(crate) fn external_data_key(&self) -> Property {
+        let data = unsafe { self.current.get_data() };
+        data.external_data.get_or_insert_with(|| {
+            Property::from_symbol(self, &value::Symbol::new(self, "external data"))
+        }).clone()
+    }
+
+    /// Stores a value thrown using `error::Thrown`, returning the list of
+    /// errors dropped without access to the context.
+    pub(crate) fn store_thrown(&self, id: usize, value: value::Value) -> Arc<Mutex<Vec<usize>>> {
+        let data = unsafe { self.current.get_data() };
+        for orphan in data.orphaned_thrown.lock().unwrap().drain(..) {
+            data.thrown.remove(&orphan);
+        }
+
+        data.thrown.insert(id, value);
+        data.orphaned_thrown.clone()
+    }
+
+    /// Removes a value thrown using `error::Thrown`.
+    pub(crate) fn take_thrown(&self, id: usize) -> Option<value::Value> {
+        unsafe { self.current.get_data().thrown.remove(&id) }
+    }
+
+    /// Returns the context's JSON functions (retrieved when the context was
+    /// created).
+    pub(crate) fn json(&self) -> Result<JsonFunctions> {
+        let function = |name| self.intrinsic(name).and_then(|function| function.into_function());
+        match (function("JSON.parse"), function("JSON.stringify")) {
+            (Some(parse), Some(stringify)) => Ok(JsonFunctions { parse: parse, stringify: stringify }),
+            _ => Err("JSON is not available in this context".into()),
+        }
+    }
+
+    /// Executes all the context's queued promise tasks and microtasks.
+    ///
+    /// The tasks are executed in the order they were queued. Any scheduled
+    /// host callbacks (e.g promise timeouts) whose deadline has passed are
+    /// executed as well, once the promise queue is empty.
+    ///
+    /// This executes a single turn of an `EventLoop`, which reports the errors
+    /// of failing tasks to the context's microtask error callback. If the
+    /// script execution is terminated, the remaining tasks stay queued. Use an
+    /// `EventLoop` directly to receive these errors instead.
     pub fn execute_tasks(&self) {
+        let policy = Policy { errors: ErrorPolicy::Report, ..Policy::default() };
+        let _ = EventLoop::with_policy(self, policy).turn();
+    }
+
+    /// Executes tasks and timers, waiting for pending timers, until none remain.
+    ///
+    /// Unlike `execute_tasks`, this blocks the thread while waiting for the
+    /// next timer to expire. An interval timer keeps this running until it is
+    /// cleared. An error is returned if a task fails, or if the script
+    /// execution is terminated.
+    pub fn run_timers_until_idle(&self) -> Result<()> {
+        EventLoop::new(self).run()
+    }
+
+    /// Starts a new task pass, making deferred timers eligible.
+    pub(crate) fn begin_task_pass(&self) {
+        unsafe { self.current.get_data().pass += 1 };
+    }
+
+    /// Returns whether any microtasks are queued.
+    pub(crate) fn has_microtasks(&self) -> bool {
+        unsafe { !self.current.get_data().promise_queue.is_empty() }
+    }
+
+    /// Queues a microtask, returning false if the context is shutting down.
+    pub(crate) fn queue_microtask(&self, callback: value::Function) -> bool {
         let data = unsafe { self.current.get_data() };
-        while let Some(task) = data.promise_queue.pop() {
-            task.call(self, &[]).expect("executing promise task");
+        if data.shutting_down {
+            return false;
         }
+        data.promise_queue.push_back(callback);
+        true
+    }
+
+    /// Removes the next queued microtask.
+    pub(crate) fn next_microtask(&self) -> Option<value::Function> {
+        unsafe { self.current.get_data().promise_queue.pop_front() }
+    }
+
+    /// Removes the earliest expired timer, returning its callback and
+    /// arguments. Interval timers are rescheduled.
+    ///
+    /// Deferred timers scheduled during the current pass wait for the next one.
+    pub(crate) fn next_timer(&self) -> Option<(value::Function, Vec<value::Value>)> {
+        let now = self.scheduler().now();
+        let data = unsafe { self.current.get_data() };
+        let pass = data.pass;
+        let index = data.timers.iter()
+            .enumerate()
+            .filter(|&(_, timer)| timer.deadline <= now && timer.pass < pass)
+            .min_by_key(|&(_, timer)| (timer.deadline, timer.id))
+            .map(|(index, _)| index)?;
+
+        let timer = data.timers.remove(index);
+        if let Some(interval) = timer.interval {
+            data.timers.push(Timer {
+                id: timer.id,
+                deadline: now + interval,
+                callback: timer.callback.clone(),
+                arguments: timer.arguments.clone(),
+                interval: timer.interval,
+                pass: pass,
+                script: timer.script,
+            });
+        }
+        Some((timer.callback, timer.arguments))
+    }
+
+    /// Returns the earliest deadline of the scheduled host callbacks.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let data = unsafe { self.current.get_data() };
+        data.timers.iter().map(|timer| timer.deadline).min()
+    }
+
+    /// Passes a task's exception to the context's error callback.
+    pub(crate) fn report_task_error(&self, error: &Error) {
+        // The callback is taken while running, in case it executes tasks itself
+        let callback = unsafe { self.current.get_data().microtask_error.take() };
+        if let Some(callback) = callback {
+            callback(self, error);
+            let data = unsafe { self.current.get_data() };
+            if data.microtask_error.is_none() {
+                data.microtask_error = Some(callback);
+            }
+        }
+    }
+
+    /// Schedules a callback to be executed by `execute_tasks` after a delay.
+    pub(crate) fn schedule(&self, delay: Duration, callback: value::Function) {
+        self.add_timer(delay, callback, Vec::new(), None, 0, false);
+    }
+
+    /// Schedules a script timer, returning its identifier.
+    ///
+    /// The timer is deferred, so a timer scheduled while tasks are executed
+    /// only runs during the next task pass.
+    pub(crate) fn schedule_timer(&self,
+                                 delay: Duration,
+                                 callback: value::Function,
+                                 arguments: Vec<value::Value>,
+                                 interval: Option<Duration>) -> u32 {
+        let pass = unsafe { self.current.get_data().pass };
+        self.add_timer(delay, callback, arguments, interval, pass, true)
+    }
+
+    /// Cancels a scheduled script timer, returning whether it was pending.
+    ///
+    /// Host callbacks share the identifiers, but cannot be cancelled.
+    pub(crate) fn cancel_timer(&self, id: u32) -> bool {
+        let data = unsafe { self.current.get_data() };
+        let count = data.timers.len();
+        data.timers.retain(|timer| !timer.script || timer.id != id);
+        data.timers.len() != count
+    }
+
+    /// Adds a timer, unless the context is shutting down.
+    fn add_timer(&self,
+                 delay: Duration,
+                 callback: value::Function,
+                 arguments: Vec<value::Value>,
+                 interval: Option<Duration>,
+                 pass: u64,
+                 script: bool) -> u32 {
+        let now = self.scheduler().now();
+        let data = unsafe { self.current.get_data() };
+        data.timer_id = data.timer_id.wrapping_add(1).max(1);
+        if !data.shutting_down {
+            data.timers.push(Timer {
+                id: data.timer_id,
+                deadline: now + delay,
+                callback: callback,
+                arguments: arguments,
+                interval: interval,
+                pass: pass,
+                script: script,
+            });
+        }
+        data.timer_id
     }
 }
 
@@ -331,6 +1079,233 @@ mod tests {
         });
     }
 
+    #[test]
+    fn microtasks() {
+        use std::sync::{Arc, Mutex};
+        test::run_with_context(|guard| {
+            let errors = Arc::new(Mutex::new(Vec::new()));
+            let reported = errors.clone();
+            guard.context().on_microtask_error(Box::new(move |_, error| {
+                reported.lock().unwrap().push(error.to_string());
+            }));
+
+            ::timers::install(guard).unwrap();
+            let result = script::eval(guard, "
+                var order = [];
+                queueMicrotask(() => order.push(1));
+                Promise.resolve().then(() => order.push(2));
+                queueMicrotask(() => { throw new Error('foo'); });
+                queueMicrotask(() => queueMicrotask(() => order.push(4)));
+                queueMicrotask(() => order.push(3));
+                order").unwrap();
+            assert!(script::eval(guard, "queueMicrotask(1)").is_err());
+
+            guard.execute_tasks();
+            assert_eq!(result.to_json(guard).unwrap(), "[1,2,3,4]");
+
+            let errors = errors.lock().unwrap();
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].contains("foo"));
+        });
+    }
+
+    #[test]
+    fn shutdown() {
+        use std::time::Duration;
+        use Runtime;
+
+        let runtime = Runtime::builder().enable_script_interrupt().build().unwrap();
+        let context = Context::new(&runtime).unwrap();
+        context.exec_with(|guard| {
+            ::timers::install(guard).unwrap();
+            let result = script::eval(guard, "
+                var order = [];
+                queueMicrotask(() => order.push(1));
+                onbeforeexit = () => {
+                    order.push(2);
+                    Promise.resolve().then(() => order.push(3));
+                    try { queueMicrotask(() => order.push(4)); } catch (ex) { order.push('rejected'); }
+                };
+                order").unwrap();
+            assert!(Context::shutdown(guard, Duration::from_secs(5)).unwrap());
+            assert_eq!(result.to_json(guard).unwrap(), r#"[1,2,"rejected",3]"#);
+        }).unwrap();
+
+        let context = Context::new(&runtime).unwrap();
+        context.exec_with(|guard| {
+            ::timers::install(guard).unwrap();
+            script::eval(guard, "queueMicrotask(() => { while (true) {} })").unwrap();
+            assert!(!Context::shutdown(guard, Duration::from_millis(50)).unwrap());
+        }).unwrap();
+        assert!(!runtime.is_execution_disabled());
+    }
+
+    #[test]
+    fn fork() {
+        test::run_with_context(|guard| {
+            let add = value::Function::with_name(guard, "add", Box::new(|guard, info| {
+                let sum = info.arguments.iter().map(|value| value.to_integer(guard)).sum();
+                Ok(value::Number::new(guard, sum).into())
+            }));
+            guard.global().set(guard, &Property::new(guard, "add"), &add);
+            script::eval(guard, "
+                var config = { name: 'parent', list: [1, 2], bytes: new Uint8Array([3]) };
+                config.self = config;
+                var methods = { triple(x) { return x * 3; } };
+                function double(x) { return add(x, x); }").unwrap();
+
+            let child = Context::fork(guard).unwrap();
+            child.exec_with(|guard| {
+                let result = script::eval(guard, "
+                    config.name = 'child';
+                    [config.self === config, config.list, config.bytes[0], double(21), methods.triple(3), typeof queueMicrotask]").unwrap();
+                assert_eq!(result.to_json(guard).unwrap(), r#"[true,[1,2],3,42,9,"undefined"]"#);
+            }).unwrap();
+
+            assert_eq!(script::eval(guard, "config.name").unwrap().to_string(guard), "parent");
+
+            script::eval(guard, "methods.broken = function() {}; methods.broken.toString = () => '{';").unwrap();
+            let error = Context::fork(guard).err().unwrap();
+            assert!(error.to_string().contains("`methods.broken`"));
+        });
+    }
+
+    #[test]
+    fn snapshot() {
+        let snapshot = {
+            let (_runtime, context) = test::setup_env();
+            context.exec_with(|guard| {
+                let answer = value::Function::with_name(guard, "answer", Box::new(|guard, _| {
+                    Ok(value::Number::new(guard, 42).into())
+                }));
+                guard.global().set(guard, &Property::new(guard, "answer"), &answer);
+                script::eval(guard, "var state = { calls: [] };").unwrap();
+                Context::snapshot(guard).unwrap()
+            }).unwrap()
+        };
+
+        // The snapshot outlives its context, and is restored in another runtime
+        let (runtime, _) = test::setup_env();
+        for _ in 0..2 {
+            let context = Context::from_snapshot(&runtime, &snapshot).unwrap();
+            context.exec_with(|guard| {
+                let result = script::eval(guard, "state.calls.push(answer()); [state.calls, typeof queueMicrotask]").unwrap();
+                assert_eq!(result.to_json(guard).unwrap(), r#"[[42],"undefined"]"#);
+            }).unwrap();
+        }
+    }
+
+    #[test]
+    fn sandboxed() {
+        let (runtime, _) = test::setup_env();
+        let context = Context::new_sandboxed(&runtime, |guard, global| {
+            global.set(guard, &Property::new(guard, "answer"), &value::Number::new(guard, 42));
+            Ok(())
+        }).unwrap();
+
+        context.exec_with(|guard| {
+            let result = script::eval(guard, "[typeof eval, typeof Function, answer, (function () {}).constructor.name]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"["undefined","undefined",42,"Function"]"#);
+
+            for code in &["(function () {}).constructor('return 1')",
+                          "(function* () {}).constructor('yield 1')",
+                          "(async function () {}).constructor('return 1')",
+                          "Function.prototype.constructor = null; (() => {}).constructor('')"] {
+                assert!(script::eval(guard, code).is_err(), "{}", code);
+            }
+        }).unwrap();
+
+        assert!(Context::new_with_globals(&runtime, |_, _| Err("failed".into())).is_err());
+    }
+
+    #[test]
+    fn collected() {
+        use runtime::{Event, Events};
+
+        let events = Events::new();
+        let receiver = events.subscribe();
+        let runtime = ::Runtime::builder().events(events).build().unwrap();
+        for _ in 0..3 {
+            let context = Context::new(&runtime).unwrap();
+            context.exec_with(|guard| {
+                let object = value::Object::new(guard);
+                object.set_external_data(guard, Box::new(5)).unwrap();
+                assert_eq!(guard.json_stringify(&object.into()).unwrap(), "{}");
+                assert_eq!(value::Map::new(guard).size(guard).unwrap(), 0);
+            }).unwrap();
+        }
+
+        // The contexts are collected while their runtime is still alive
+        runtime.collect_garbage().unwrap();
+        assert!(receiver.try_iter().any(|event| event == Event::ContextCollected));
+    }
+
+    #[test]
+    fn json() {
+        test::run_with_context(|guard| {
+            script::eval(guard, "JSON.stringify = () => 'replaced'").unwrap();
+            let value = guard.json_parse(r#"{"foo":[1,"bar",null]}"#).unwrap();
+            assert_eq!(guard.json_stringify(&value).unwrap(), r#"{"foo":[1,"bar",null]}"#);
+
+            script::eval(guard, "JSON = undefined").unwrap();
+            assert_eq!(guard.json_parse("[true]").unwrap().to_json(guard).unwrap(), "[true]");
+            assert!(guard.json_parse("{").is_err());
+            assert!(guard.json_stringify(&value::undefined(guard)).is_err());
+        });
+    }
+
+    #[test]
+    fn stack_trace() {
+        test::run_with_context(|guard| {
+            let context = guard.context();
+            context.set_stack_trace_limit(2).unwrap();
+            context.set_stack_formatter(Box::new(|stack| stack.replace("deep", "[hidden]")));
+            let error = script::eval(guard, "function deep(n) { if (n) deep(n - 1); else throw new Error('foo'); } deep(5)").unwrap_err();
+
+            let error = match *error.kind() {
+                ::error::ErrorKind::ScriptException(ref error) => error.clone(),
+                _ => panic!("expected script exception"),
+            };
+            let stack = error.stack.unwrap();
+            assert_eq!(stack.lines().filter(|line| line.trim().starts_with("at ")).count(), 2);
+            assert!(stack.contains("at [hidden]") && !stack.contains("deep"));
+            assert_eq!(error.line, Some(1));
+        });
+    }
+
+    #[test]
+    fn isolated_eval() {
+        test::run_with_context(|guard| {
+            let result = guard.isolated_eval("var leaked = 10; ({ foo: [1, 'bar'] })").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"{"foo":[1,"bar"]}"#);
+
+            let leaked = script::eval(guard, "typeof leaked").unwrap();
+            assert_eq!(leaked.to_string(guard), "undefined");
+            assert!(guard.isolated_eval("(function() {})").is_err());
+        });
+    }
+
+    #[test]
+    fn exceptions() {
+        test::run_with_context(|guard| {
+            assert!(!guard.has_exception());
+            assert!(guard.take_exception().is_none());
+
+            guard.throw(&value::Error::type_error(guard, "pending").into());
+            assert!(guard.has_exception());
+            assert_eq!(guard.take_exception().unwrap().to_string(guard), "TypeError: pending");
+            assert!(!guard.has_exception());
+
+            let function = value::Function::new(guard, Box::new(|guard, _| {
+                guard.throw(&value::Number::new(guard, 42).into());
+                Ok(value::undefined(guard))
+            }));
+            guard.global().set(guard, &Property::new(guard, "thrower"), &function);
+            let result = script::eval(guard, "try { thrower(); 0 } catch (e) { e }").unwrap();
+            assert_eq!(result.to_integer(guard), 42);
+        });
+    }
+
     #[test]
     fn shared_objects() {
         let (runtime, context) = test::setup_env();
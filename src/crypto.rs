@@ -0,0 +1,330 @@
+//! A minimal `crypto` global, backed by the host.
+//!
+//! The engine does not provide any cryptographic primitives. This extension
+//! installs `crypto.getRandomValues` (see the [random](../random/index.html)
+//! module) and a subset of `crypto.subtle`, delegating the actual algorithms
+//! to a host-provided `CryptoBackend`:
+//!
+//! - `digest(algorithm, data)`
+//! - `importKey("raw", keyData, { name: "HMAC", hash }, extractable, usages)`
+//! - `sign("HMAC", key, data)`
+//! - `verify("HMAC", key, signature, data)`
+//!
+//! Like their web counterparts, all methods return promises, and data is
+//! accepted as an `ArrayBuffer`, a typed array or a `DataView`. Imported keys
+//! cannot be exported.
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::{ptr, slice};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use random::{self, RandomSource};
+use util::{argument, named_error};
+use value::{self, Value};
+use value::function::CallbackResult;
+use Property;
+
+/// The cryptographic algorithms used by `crypto.subtle`.
+///
+/// Algorithm names are passed in upper case (e.g `SHA-256`). Any error is
+/// reported to scripts as a rejected promise, with an `OperationError`.
+pub trait CryptoBackend: Send {
+    /// Computes the digest of data.
+    fn digest(&self, algorithm: &str, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Computes the HMAC of data, using a digest algorithm.
+    fn hmac(&self, hash: &str, key: &[u8], data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The addresses of all live keys.
+type Keys = Arc<Mutex<HashSet<usize>>>;
+
+/// The external data of an imported key.
+struct KeyData {
+    keys: Keys,
+    hash: String,
+    usages: Vec<String>,
+    material: Vec<u8>,
+}
+
+/// The state shared by the `crypto.subtle` methods.
+struct Subtle {
+    backend: Mutex<Box<CryptoBackend>>,
+    keys: Keys,
+}
+
+impl Drop for KeyData {
+    fn drop(&mut self) {
+        let address = self as *const _ as usize;
+        self.keys.lock().unwrap().remove(&address);
+    }
+}
+
+/// Installs the `crypto` global in the guard's context.
+///
+/// If a `crypto` object already exists, its properties are replaced.
+pub fn install(guard: &ContextGuard,
+               random: Box<RandomSource>,
+               backend: Box<CryptoBackend>) -> Result<()> {
+    random::install(guard, random)?;
+
+    let crypto = guard.global()
+        .get(guard, &Property::new(guard, "crypto"))
+        .into_object()
+        .expect("retrieving crypto object");
+
+    let subtle = Arc::new(Subtle {
+        backend: Mutex::new(backend),
+        keys: Arc::new(Mutex::new(HashSet::new())),
+    });
+
+    let object = value::Object::new(guard);
+    method(guard, &object, "digest", &subtle, digest);
+    method(guard, &object, "importKey", &subtle, import_key);
+    method(guard, &object, "sign", &subtle, sign);
+    method(guard, &object, "verify", &subtle, verify);
+    crypto.set(guard, &Property::new(guard, "subtle"), &object);
+    Ok(())
+}
+
+/// The result of a `crypto.subtle` step, rejecting with a value.
+type Fallible<T> = ::std::result::Result<T, Value>;
+
+/// The outcome of a `crypto.subtle` operation.
+type Outcome = Fallible<Value>;
+
+/// Defines a method, which returns its outcome as a promise.
+fn method(guard: &ContextGuard,
+          object: &value::Object,
+          name: &str,
+          subtle: &Arc<Subtle>,
+          operation: fn(&ContextGuard, &Subtle, &[Value]) -> Outcome) {
+    let subtle = subtle.clone();
+    let function = value::Function::with_name(guard, name, Box::new(move |guard, info| -> CallbackResult {
+        let promise = match operation(guard, &subtle, &info.arguments) {
+            Ok(result) => value::Promise::resolved(guard, &result),
+            Err(reason) => value::Promise::rejected(guard, &reason),
+        };
+        promise.map(Value::from).map_err(|_| value::Error::new(guard, "Could not create promise").into())
+    }));
+    object.set(guard, &Property::new(guard, name), &function);
+}
+
+/// Implements `crypto.subtle.digest(algorithm, data)`.
+fn digest(guard: &ContextGuard, subtle: &Subtle, arguments: &[Value]) -> Outcome {
+    let algorithm = algorithm_name(guard, argument(guard, arguments, 0))?;
+    let data = buffer_source(guard, argument(guard, arguments, 1))?;
+    let digest = subtle.backend.lock().unwrap().digest(&algorithm, &data)
+        .map_err(|error| operation_error(guard, &error))?;
+    Ok(value::ArrayBuffer::with_data(guard, digest).into())
+}
+
+/// Implements `crypto.subtle.importKey(format, keyData, algorithm, extractable, usages)`.
+fn import_key(guard: &ContextGuard, subtle: &Subtle, arguments: &[Value]) -> Outcome {
+    if argument(guard, arguments, 0).to_string(guard) != "raw" {
+        return Err(not_supported(guard, "Only the 'raw' key format is supported"));
+    }
+
+    let material = buffer_source(guard, argument(guard, arguments, 1))?;
+    let algorithm = argument(guard, arguments, 2);
+    if algorithm_name(guard, algorithm.clone())? != "HMAC" {
+        return Err(not_supported(guard, "Only HMAC keys are supported"));
+    }
+
+    let hash = algorithm.clone().into_object()
+        .map(|algorithm| algorithm.get(guard, &Property::new(guard, "hash")))
+        .ok_or_else(|| type_error(guard, "HMAC parameters must specify a hash"))
+        .and_then(|hash| algorithm_name(guard, hash))?;
+    let extractable = argument(guard, arguments, 3).to_bool(guard);
+    let usages = argument(guard, arguments, 4).into_array()
+        .map(|usages| usages.iter(guard).map(|usage| usage.to_string(guard)).collect::<Vec<_>>())
+        .ok_or_else(|| type_error(guard, "Key usages must be an array"))?;
+
+    let data = KeyData {
+        keys: subtle.keys.clone(),
+        hash: hash.clone(),
+        usages: usages.clone(),
+        material: material,
+    };
+    let key = value::External::typed(guard, data);
+    let address = key.value_ref::<KeyData>(guard).expect("retrieving key data") as *const _ as usize;
+    subtle.keys.lock().unwrap().insert(address);
+
+    let set = |name: &str, value: &Value| key.set(guard, &Property::new(guard, name), value);
+    let description = value::Object::new(guard);
+    let hash_description = value::Object::new(guard);
+    hash_description.set(guard, &Property::new(guard, "name"), &value::String::new(guard, &hash));
+    description.set(guard, &Property::new(guard, "name"), &value::String::new(guard, "HMAC"));
+    description.set(guard, &Property::new(guard, "hash"), &hash_description);
+    set("type", &value::String::new(guard, "secret").into());
+    set("algorithm", &description.into());
+    set("extractable", &value::Boolean::new(guard, extractable).into());
+    set("usages", &value::ToJs::to_js(&usages, guard));
+    Ok(key.into())
+}
+
+/// Implements `crypto.subtle.sign(algorithm, key, data)`.
+fn sign(guard: &ContextGuard, subtle: &Subtle, arguments: &[Value]) -> Outcome {
+    let signature = hmac(guard, subtle, arguments, "sign")?;
+    Ok(value::ArrayBuffer::with_data(guard, signature).into())
+}
+
+/// Implements `crypto.subtle.verify(algorithm, key, signature, data)`.
+fn verify(guard: &ContextGuard, subtle: &Subtle, arguments: &[Value]) -> Outcome {
+    let signature = buffer_source(guard, argument(guard, arguments, 2))?;
+    let arguments = [
+        argument(guard, arguments, 0),
+        argument(guard, arguments, 1),
+        argument(guard, arguments, 3),
+    ];
+    let expected = hmac(guard, subtle, &arguments, "verify")?;
+
+    // Compare in constant time, to avoid leaking the expected signature
+    let difference = expected.iter().zip(signature.iter()).fold(0, |difference, (a, b)| difference | (a ^ b));
+    let valid = expected.len() == signature.len() && difference == 0;
+    Ok(value::Boolean::new(guard, valid).into())
+}
+
+/// Computes the HMAC of `[algorithm, key, data]` arguments.
+fn hmac(guard: &ContextGuard, subtle: &Subtle, arguments: &[Value], usage: &str) -> Fallible<Vec<u8>> {
+    if algorithm_name(guard, argument(guard, arguments, 0))? != "HMAC" {
+        return Err(not_supported(guard, "Only the HMAC algorithm is supported"));
+    }
+
+    let key = argument(guard, arguments, 1);
+    let data = buffer_source(guard, argument(guard, arguments, 2))?;
+    with_key(guard, subtle, &key, |key| {
+        if !key.usages.iter().any(|allowed| allowed == usage) {
+            let message = format!("Key does not support the '{}' operation", usage);
+            return Err(value::Error::new(guard, &message).into());
+        }
+
+        subtle.backend.lock().unwrap()
+            .hmac(&key.hash, &key.material, &data)
+            .map_err(|error| operation_error(guard, &error))
+    })
+}
+
+/// Executes a closure with the data of a key, failing if it is not a key.
+fn with_key<T, F>(guard: &ContextGuard, subtle: &Subtle, key: &Value, callback: F) -> Fallible<T>
+        where F: FnOnce(&KeyData) -> Fallible<T> {
+    let external = key.as_external();
+    match external.and_then(|external| external.value_ref::<KeyData>(guard)) {
+        Some(data) if subtle.keys.lock().unwrap().contains(&(data as *const _ as usize)) => callback(data),
+        _ => Err(type_error(guard, "Argument is not a CryptoKey")),
+    }
+}
+
+/// Returns the normalized name of an algorithm (either a string, or an
+/// object with a `name` property).
+fn algorithm_name(guard: &ContextGuard, algorithm: Value) -> Fallible<String> {
+    let name = match algorithm.clone().into_object() {
+        Some(object) => object.get(guard, &Property::new(guard, "name")),
+        None => algorithm,
+    };
+
+    if name.is_string() {
+        Ok(name.to_string(guard).to_uppercase())
+    } else {
+        Err(type_error(guard, "Algorithm must be a string or have a name"))
+    }
+}
+
+/// Returns the contents of an `ArrayBuffer`, typed array or `DataView`.
+fn buffer_source(guard: &ContextGuard, value: Value) -> Fallible<Vec<u8>> {
+    match value.get_type() {
+        JsValueType::ArrayBuffer => {
+            Ok(value.into_array_buffer().expect("converting value to array buffer").as_slice().to_vec())
+        },
+        JsValueType::DataView => {
+            Ok(value.into_data_view().expect("converting value to data view").as_slice().to_vec())
+        },
+        JsValueType::TypedArray => unsafe {
+            let mut data = ptr::null_mut();
+            let mut size = 0;
+            let mut kind = JsTypedArrayType::Int8;
+            let mut element_size = 0;
+            jsassert!(JsGetTypedArrayStorage(value.as_raw(), &mut data, &mut size, &mut kind, &mut element_size));
+            Ok(slice::from_raw_parts(data, size as usize).to_vec())
+        },
+        _ => Err(type_error(guard, "Data must be an ArrayBuffer or a view of one")),
+    }
+}
+
+/// Creates a `TypeError`.
+fn type_error(guard: &ContextGuard, message: &str) -> Value {
+    value::Error::type_error(guard, message).into()
+}
+
+/// Creates a `NotSupportedError`.
+fn not_supported(guard: &ContextGuard, message: &str) -> Value {
+    named_error(guard, "NotSupportedError", message)
+}
+
+/// Creates an `OperationError` from a backend error.
+fn operation_error(guard: &ContextGuard, error: &Error) -> Value {
+    named_error(guard, "OperationError", &error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, audit, Property};
+    use error::*;
+    use random::SeededRandom;
+    use super::CryptoBackend;
+
+    struct Backend;
+
+    impl CryptoBackend for Backend {
+        fn digest(&self, algorithm: &str, data: &[u8]) -> Result<Vec<u8>> {
+            match algorithm {
+                "SHA-256" => Ok(audit::sha256(data).to_vec()),
+                _ => Err(format!("unsupported digest '{}'", algorithm).into()),
+            }
+        }
+
+        fn hmac(&self, hash: &str, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+            let mut key = if key.len() > 64 { self.digest(hash, key)? } else { key.to_vec() };
+            key.resize(64, 0);
+
+            let mut inner = key.iter().map(|byte| byte ^ 0x36).collect::<Vec<_>>();
+            inner.extend_from_slice(data);
+            let mut outer = key.iter().map(|byte| byte ^ 0x5c).collect::<Vec<_>>();
+            outer.extend(self.digest(hash, &inner)?);
+            self.digest(hash, &outer)
+        }
+    }
+
+    #[test]
+    fn subtle() {
+        test::run_with_context(|guard| {
+            super::install(guard, Box::new(SeededRandom::new(1)), Box::new(Backend)).unwrap();
+            script::eval(guard, "
+                var result = {};
+                var bytes = (text) => new Uint8Array(text.split('').map((c) => c.charCodeAt(0)));
+                var hex = (buffer) => Array.from(new Uint8Array(buffer))
+                    .map((byte) => ('0' + byte.toString(16)).slice(-2)).join('');
+
+                crypto.subtle.digest('sha-256', bytes('abc')).then((digest) => result.digest = hex(digest));
+                crypto.subtle.digest('SHA-1', bytes('abc')).catch((error) => result.unsupported = error.name);
+                crypto.subtle.sign('HMAC', {}, bytes('abc')).catch((error) => result.forged = error.name);
+
+                crypto.subtle.importKey('raw', bytes('Jefe'), { name: 'HMAC', hash: 'SHA-256' }, false, ['sign', 'verify'])
+                    .then((key) => crypto.subtle.sign('HMAC', key, bytes('what do ya want for nothing?'))
+                        .then((signature) => {
+                            result.signature = hex(signature);
+                            return crypto.subtle.verify('HMAC', key, signature, bytes('what do ya want for nothing?'));
+                        }))
+                    .then((valid) => result.valid = valid);").unwrap();
+            guard.execute_tasks();
+
+            let result = guard.global().get(guard, &Property::new(guard, "result"));
+            assert_eq!(result.to_json(guard).unwrap(), concat!(
+                r#"{"digest":"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad","#,
+                r#""unsupported":"OperationError","forged":"TypeError","#,
+                r#""signature":"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843","#,
+                r#""valid":true}"#));
+        });
+    }
+}
@@ -0,0 +1,340 @@
+//! A scheduler of deadlines, shared by the crate's timeout-taking APIs.
+//!
+//! Script watchdogs (e.g of `script::eval_with_timeout` and
+//! `Context::shutdown`), context timers (including promise timeouts) and the
+//! wake-ups of asynchronous evaluations are all driven by a context's
+//! `Scheduler`. By default, this is the global scheduler, which follows real
+//! time and fires deadlines on a background thread.
+//!
+//! A manual scheduler only advances when it is told to, so tests can control
+//! time without sleeping. Its deadlines fire on the thread advancing it. When
+//! a host blocks waiting for a timer (e.g using `run_timers_until_idle`), a
+//! manual scheduler skips ahead to the timer's deadline instead.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use std::time::Duration;
+//! # use js::deadline::Scheduler;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! let scheduler = Scheduler::manual();
+//! context.set_scheduler(scheduler.clone());
+//!
+//! let guard = context.make_current().unwrap();
+//! js::timers::install(&guard).unwrap();
+//! js::script::eval(&guard, "var fired = false; setTimeout(() => fired = true, 60000)").unwrap();
+//! guard.execute_tasks();
+//! assert!(!js::script::eval(&guard, "fired").unwrap().to_bool(&guard));
+//!
+//! scheduler.advance(Duration::from_secs(60)).unwrap();
+//! guard.execute_tasks();
+//! assert!(js::script::eval(&guard, "fired").unwrap().to_bool(&guard));
+//! ```
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use chakracore_sys::*;
+use error::*;
+use runtime::{Event, Events};
+use util::jstry;
+
+/// Callback type for actions fired by a deadline.
+pub type Action = FnOnce() + Send;
+
+/// The key of a scheduled action, ordered by its deadline.
+type Key = (Instant, u64);
+
+/// The state of a scheduler.
+struct State {
+    /// The current time of a manual scheduler.
+    manual: Option<Instant>,
+    actions: BTreeMap<Key, Box<Action>>,
+    /// The identifier of the action being fired, if any.
+    running: Option<u64>,
+    next_id: u64,
+}
+
+/// The state shared by a scheduler and its deadlines.
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+/// A scheduler of deadlines.
+///
+/// Clones share the same deadlines (and clock).
+#[derive(Clone)]
+pub struct Scheduler(Arc<Shared>);
+
+/// The global real-time scheduler, created once it is first used.
+static GLOBAL: Mutex<Option<Scheduler>> = Mutex::new(None);
+
+impl Scheduler {
+    /// Returns the global scheduler, which follows real time.
+    pub fn global() -> Scheduler {
+        GLOBAL.lock().unwrap().get_or_insert_with(|| {
+            let scheduler = Self::create(None);
+            let shared = scheduler.0.clone();
+            thread::spawn(move || Self::run(&shared));
+            scheduler
+        }).clone()
+    }
+
+    /// Creates a scheduler whose clock only advances manually.
+    pub fn manual() -> Scheduler {
+        Self::create(Some(Instant::now()))
+    }
+
+    /// Returns whether the scheduler's clock advances manually.
+    pub fn is_manual(&self) -> bool {
+        self.state().manual.is_some()
+    }
+
+    /// Returns the scheduler's current time.
+    pub fn now(&self) -> Instant {
+        self.state().manual.unwrap_or_else(Instant::now)
+    }
+
+    /// Schedules an action to be fired after a timeout.
+    pub fn schedule(&self, timeout: Duration, action: Box<Action>) -> Deadline {
+        let instant = self.now() + timeout;
+        self.schedule_at(instant, action)
+    }
+
+    /// Schedules an action to be fired at an instant.
+    ///
+    /// The action is cancelled once the returned deadline is dropped, unless
+    /// it has been detached.
+    pub fn schedule_at(&self, instant: Instant, action: Box<Action>) -> Deadline {
+        let mut state = self.state();
+        state.next_id += 1;
+        let key = (instant, state.next_id);
+        state.actions.insert(key, action);
+        self.0.condvar.notify_all();
+        Deadline { shared: self.0.clone(), key: key, attached: true }
+    }
+
+    /// Returns the number of pending actions.
+    pub fn pending(&self) -> usize {
+        self.state().actions.len()
+    }
+
+    /// Advances the clock of a manual scheduler, firing the actions which
+    /// have expired, and returns their number.
+    ///
+    /// The actions are fired on the current thread, in order of their
+    /// deadlines. An error is returned if the scheduler follows real time.
+    pub fn advance(&self, duration: Duration) -> Result<usize> {
+        let now = self.state().manual.ok_or("a real-time scheduler cannot be advanced")?;
+        Ok(self.advance_to(now + duration))
+    }
+
+    /// Blocks until an instant has passed.
+    ///
+    /// A manual scheduler advances its clock to the instant instead.
+    pub(crate) fn sleep_until(&self, instant: Instant) {
+        if self.is_manual() {
+            self.advance_to(instant);
+        } else {
+            let now = Instant::now();
+            if instant > now {
+                thread::sleep(instant - now);
+            }
+        }
+    }
+
+    fn create(manual: Option<Instant>) -> Scheduler {
+        Scheduler(Arc::new(Shared {
+            state: Mutex::new(State { manual: manual, actions: BTreeMap::new(), running: None, next_id: 0 }),
+            condvar: Condvar::new(),
+        }))
+    }
+
+    fn state<'a>(&'a self) -> MutexGuard<'a, State> {
+        self.0.state.lock().unwrap()
+    }
+
+    /// Advances a manual clock, unless it is already past the instant.
+    fn advance_to(&self, instant: Instant) -> usize {
+        let mut state = self.state();
+        let now = state.manual.map_or(instant, |now| now.max(instant));
+        state.manual = Some(now);
+        Self::fire(&self.0, state, now).1
+    }
+
+    /// Fires the actions which have expired at an instant.
+    ///
+    /// Actions are fired without holding the lock, so they may schedule or
+    /// cancel other actions.
+    fn fire<'a>(shared: &'a Shared, mut state: MutexGuard<'a, State>, now: Instant) -> (MutexGuard<'a, State>, usize) {
+        let mut fired = 0;
+        while let Some(key) = state.actions.keys().next().cloned().filter(|key| key.0 <= now) {
+            let action = state.actions.remove(&key).expect("removing expired action");
+            state.running = Some(key.1);
+            drop(state);
+
+            action();
+            fired += 1;
+
+            state = shared.state.lock().unwrap();
+            state.running = None;
+            shared.condvar.notify_all();
+        }
+        (state, fired)
+    }
+
+    /// Fires the actions of a real-time scheduler as they expire.
+    fn run(shared: &Shared) {
+        let mut state = shared.state.lock().unwrap();
+        loop {
+            state = Self::fire(shared, state, Instant::now()).0;
+            state = match state.actions.keys().next().map(|key| key.0) {
+                Some(next) => {
+                    let timeout = next.saturating_duration_since(Instant::now());
+                    shared.condvar.wait_timeout(state, timeout).unwrap().0
+                },
+                None => shared.condvar.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+/// A scheduled action.
+///
+/// The action is cancelled once the deadline is dropped, unless it has been
+/// detached.
+pub struct Deadline {
+    shared: Arc<Shared>,
+    key: Key,
+    attached: bool,
+}
+
+impl Deadline {
+    /// Returns the instant at which the action is fired.
+    pub fn instant(&self) -> Instant {
+        self.key.0
+    }
+
+    /// Cancels the action, returning false if it has already been fired.
+    ///
+    /// If the action is being fired by another thread, this waits until it
+    /// has finished.
+    pub fn cancel(mut self) -> bool {
+        self.remove()
+    }
+
+    /// Detaches the deadline, so the action is fired even though the
+    /// deadline is dropped.
+    pub fn detach(mut self) {
+        self.attached = false;
+    }
+
+    fn remove(&mut self) -> bool {
+        self.attached = false;
+        let mut state = self.shared.state.lock().unwrap();
+        while state.running == Some(self.key.1) {
+            state = self.shared.condvar.wait(state).unwrap();
+        }
+        state.actions.remove(&self.key).is_some()
+    }
+}
+
+impl Drop for Deadline {
+    /// Cancels the action, unless the deadline has been detached.
+    fn drop(&mut self) {
+        if self.attached {
+            self.remove();
+        }
+    }
+}
+
+/// A watchdog interrupting a runtime's script execution once a timeout
+/// expires.
+pub(crate) struct Watchdog {
+    deadline: Deadline,
+    runtime: JsRuntimeHandle,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    /// Starts a watchdog for a runtime, emitting to its events once it
+    /// interrupts the runtime.
+    pub fn start(scheduler: &Scheduler, runtime: JsRuntimeHandle, events: Events, timeout: Duration) -> Self {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let flag = interrupted.clone();
+        let deadline = scheduler.schedule(timeout, Box::new(move || {
+            let disabled = unsafe { JsDisableRuntimeExecution(runtime) } == JsErrorCode::NoError;
+            flag.store(disabled, Ordering::SeqCst);
+            if disabled {
+                events.emit(Event::ExecutionDisabled);
+            }
+        }));
+
+        Watchdog { deadline: deadline, runtime: runtime, interrupted: interrupted }
+    }
+
+    /// Stops the watchdog, returning whether it interrupted the runtime.
+    ///
+    /// If it did, the runtime's execution is enabled again.
+    pub fn stop(self) -> Result<bool> {
+        self.deadline.cancel();
+        let interrupted = self.interrupted.load(Ordering::SeqCst);
+        if interrupted {
+            jstry(unsafe { JsEnableRuntimeExecution(self.runtime) })?;
+        }
+        Ok(interrupted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use {test, script};
+    use super::Scheduler;
+
+    #[test]
+    fn manual() {
+        let scheduler = Scheduler::manual();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let start = scheduler.now();
+
+        let schedule = |name: &'static str, seconds: u64| {
+            let fired = fired.clone();
+            scheduler.schedule(Duration::from_secs(seconds), Box::new(move || fired.lock().unwrap().push(name)))
+        };
+
+        schedule("late", 20).detach();
+        let early = schedule("early", 10);
+        let cancelled = schedule("cancelled", 15);
+        drop(schedule("dropped", 5));
+
+        assert_eq!(scheduler.advance(Duration::from_secs(10)).unwrap(), 1);
+        assert!(!early.cancel());
+        assert!(cancelled.cancel());
+        assert_eq!(scheduler.pending(), 1);
+
+        assert_eq!(scheduler.advance(Duration::from_secs(10)).unwrap(), 1);
+        assert_eq!(*fired.lock().unwrap(), ["early", "late"]);
+        assert_eq!(scheduler.now() - start, Duration::from_secs(20));
+        assert!(Scheduler::global().advance(Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn timers() {
+        test::run_with_context(|guard| {
+            let scheduler = Scheduler::manual();
+            let start = scheduler.now();
+            guard.context().set_scheduler(scheduler.clone());
+
+            ::timers::install(guard).unwrap();
+            script::eval(guard, "var order = []; setTimeout(() => order.push(2), 3600000); setTimeout(() => order.push(1), 1000)").unwrap();
+            guard.run_timers_until_idle().unwrap();
+
+            assert_eq!(script::eval(guard, "order").unwrap().to_json(guard).unwrap(), "[1,2]");
+            assert_eq!(scheduler.now() - start, Duration::from_secs(3600));
+        });
+    }
+}
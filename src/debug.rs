@@ -0,0 +1,524 @@
+//! Script debugging, using the engine's diagnostic APIs.
+//!
+//! Debugging is enabled per runtime by starting a `Debugger` with a
+//! `DebugHandler`. The handler is notified whenever a script is compiled, a
+//! breakpoint is hit, a step completes etc. While a handler is notified of a
+//! break, script execution is paused; the stack can be inspected, and
+//! expressions evaluated, using `Paused`. The handler's return value decides
+//! how execution is resumed.
+//!
+//! Only scripts compiled after debugging has started are visible to the
+//! debugger.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::debug::{Debugger, DebugHandler, DebugEvent, Paused, Step};
+//! struct Logger;
+//!
+//! impl DebugHandler for Logger {
+//!     fn on_event(&mut self, paused: &Paused, event: DebugEvent) -> Step {
+//!         if event == DebugEvent::DebuggerStatement {
+//!             let frames = paused.stack_trace().unwrap();
+//!             println!("paused at line {}", frames[0].line);
+//!         }
+//!         Step::Continue
+//!     }
+//! }
+//!
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let debugger = Debugger::start(&guard, Box::new(Logger)).unwrap();
+//! js::script::eval(&guard, "debugger;").unwrap();
+//! debugger.stop(&guard).unwrap();
+//! ```
+use std::ptr;
+use libc::c_void;
+use chakracore_sys::*;
+use context::{Context, ContextGuard};
+use error::*;
+use util::jstry;
+use value::{self, Value};
+use Property;
+
+/// An event reported to a debug handler.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugEvent {
+    /// A script has been compiled.
+    SourceCompile,
+    /// A script failed to compile.
+    CompileError,
+    /// A breakpoint was hit.
+    Breakpoint,
+    /// A step operation has completed.
+    StepComplete,
+    /// A `debugger` statement was executed.
+    DebuggerStatement,
+    /// Execution was paused by `Debugger::request_break`.
+    AsyncBreak,
+    /// An exception was thrown (see `Debugger::set_break_on_exception`).
+    RuntimeException,
+}
+
+/// How execution is resumed after an event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Continue until the next break.
+    Continue,
+    /// Step into the next function call.
+    Into,
+    /// Step out of the current function.
+    Out,
+    /// Step over the next statement.
+    Over,
+}
+
+/// When exceptions should pause execution.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BreakOnException {
+    /// Exceptions never pause execution.
+    Never,
+    /// Only exceptions that are not caught pause execution.
+    Uncaught,
+    /// All exceptions pause execution, as soon as they are thrown.
+    Always,
+}
+
+/// An exception reported by a `RuntimeException` event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThrownException {
+    /// Whether the exception is not caught by the script.
+    pub uncaught: bool,
+    /// The identifier of the script throwing the exception.
+    pub script_id: u32,
+    /// The line (0-based) of the throw site.
+    pub line: u32,
+    /// The column (0-based) of the throw site.
+    pub column: u32,
+    /// The exception's string representation.
+    pub display: String,
+}
+
+/// A handler of debug events.
+pub trait DebugHandler: Send {
+    /// Called for each debug event, returning how execution is resumed.
+    ///
+    /// The step is ignored for events that do not pause execution (i.e
+    /// `SourceCompile` and `CompileError`).
+    fn on_event(&mut self, paused: &Paused, event: DebugEvent) -> Step;
+}
+
+/// A breakpoint within a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// The breakpoint's identifier.
+    pub id: u32,
+    /// The identifier of the breakpoint's script.
+    pub script_id: u32,
+    /// The line (0-based) of the breakpoint.
+    pub line: u32,
+    /// The column (0-based) of the breakpoint.
+    pub column: u32,
+}
+
+/// A script known to the debugger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptInfo {
+    /// The script's identifier.
+    pub id: u32,
+    /// The name the script was evaluated with, if any.
+    pub file_name: Option<String>,
+    /// The number of lines in the script.
+    pub line_count: u32,
+}
+
+/// A frame of a paused script's stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    /// The index of the frame (0 being the innermost).
+    pub index: u32,
+    /// The identifier of the frame's script.
+    pub script_id: u32,
+    /// The line (0-based) being executed.
+    pub line: u32,
+    /// The column (0-based) being executed.
+    pub column: u32,
+    /// The source of the statement being executed.
+    pub source: String,
+    /// The name of the frame's function (as reported by the engine, e.g
+    /// `Global code` for top-level code).
+    pub function_name: String,
+}
+
+/// A debugging session of a runtime.
+///
+/// The session lasts until it is stopped, or dropped. Dropping a debugger
+/// requires the runtime to be active on the current thread; otherwise the
+/// session cannot be stopped, and the handler is leaked.
+pub struct Debugger {
+    runtime: JsRuntimeHandle,
+    handler: *mut Box<DebugHandler>,
+}
+
+/// The state of a paused script, provided to a debug handler.
+pub struct Paused<'a> {
+    guard: &'a ContextGuard<'a>,
+    data: Value,
+}
+
+impl Debugger {
+    /// Starts debugging the runtime of a context.
+    pub fn start(guard: &ContextGuard, handler: Box<DebugHandler>) -> Result<Debugger> {
+        let runtime = guard.context().runtime_handle();
+        let handler = Box::into_raw(Box::new(handler));
+        match jstry(unsafe { JsDiagStartDebugging(runtime, Some(handle_event), handler as *mut c_void) }) {
+            Ok(_) => Ok(Debugger { runtime: runtime, handler: handler }),
+            Err(error) => {
+                unsafe { Box::from_raw(handler) };
+                Err(error)
+            },
+        }
+    }
+
+    /// Stops debugging, returning the handler.
+    pub fn stop(mut self, _guard: &ContextGuard) -> Result<Box<DebugHandler>> {
+        let handler = self.detach()?;
+        Ok(*unsafe { Box::from_raw(handler) })
+    }
+
+    /// Sets a breakpoint at a position of a script.
+    ///
+    /// The position is adjusted by the engine to the nearest statement.
+    pub fn set_breakpoint(&self,
+                          guard: &ContextGuard,
+                          script_id: u32,
+                          line: u32,
+                          column: u32) -> Result<Breakpoint> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagSetBreakpoint(script_id, line, column, &mut result));
+            Ok(breakpoint(guard, &Value::from_raw(result)))
+        }
+    }
+
+    /// Removes a breakpoint.
+    pub fn remove_breakpoint(&self, _guard: &ContextGuard, id: u32) -> Result<()> {
+        jstry(unsafe { JsDiagRemoveBreakpoint(id) })
+    }
+
+    /// Returns all active breakpoints.
+    pub fn breakpoints(&self, guard: &ContextGuard) -> Result<Vec<Breakpoint>> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagGetBreakpoints(&mut result));
+            Ok(elements(guard, result).iter().map(|value| breakpoint(guard, value)).collect())
+        }
+    }
+
+    /// Sets when exceptions should pause execution.
+    ///
+    /// With `Always`, execution is paused at the throw site of first-chance
+    /// exceptions, before the stack is unwound (i.e even if the exception is
+    /// caught later on).
+    pub fn set_break_on_exception(&self, _guard: &ContextGuard, mode: BreakOnException) -> Result<()> {
+        let attributes = match mode {
+            BreakOnException::Never => JsDiagBreakOnExceptionAttributeNone,
+            BreakOnException::Uncaught => JsDiagBreakOnExceptionAttributeUncaught,
+            BreakOnException::Always => JsDiagBreakOnExceptionAttributeFirstChance,
+        };
+        jstry(unsafe { JsDiagSetBreakOnException(self.runtime, attributes) })
+    }
+
+    /// Returns when exceptions pause execution.
+    pub fn break_on_exception(&self, _guard: &ContextGuard) -> Result<BreakOnException> {
+        let mut attributes = JsDiagBreakOnExceptionAttributeNone;
+        jstry!(unsafe { JsDiagGetBreakOnException(self.runtime, &mut attributes) });
+        Ok(if attributes == JsDiagBreakOnExceptionAttributeNone {
+            BreakOnException::Never
+        } else if attributes == JsDiagBreakOnExceptionAttributeUncaught {
+            BreakOnException::Uncaught
+        } else {
+            BreakOnException::Always
+        })
+    }
+
+    /// Requests execution to be paused, as soon as possible.
+    ///
+    /// The handler is notified with an `AsyncBreak` event.
+    pub fn request_break(&self) -> Result<()> {
+        jstry(unsafe { JsDiagRequestAsyncBreak(self.runtime) })
+    }
+
+    /// Returns all scripts compiled since debugging started.
+    pub fn scripts(&self, guard: &ContextGuard) -> Result<Vec<ScriptInfo>> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagGetScripts(&mut result));
+            Ok(elements(guard, result).iter().map(|script| {
+                let file_name = property(guard, script, "fileName");
+                ScriptInfo {
+                    id: integer(guard, script, "scriptId"),
+                    file_name: if file_name.is_undefined() { None } else { Some(file_name.to_string(guard)) },
+                    line_count: integer(guard, script, "lineCount"),
+                }
+            }).collect())
+        }
+    }
+
+    /// Returns the source code of a script.
+    pub fn source(&self, guard: &ContextGuard, script_id: u32) -> Result<String> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagGetSource(script_id, &mut result));
+            Ok(property(guard, &Value::from_raw(result), "source").to_string(guard))
+        }
+    }
+
+    /// Stops the debugging session, returning the handler's pointer.
+    fn detach(&mut self) -> Result<*mut Box<DebugHandler>> {
+        let handler = self.handler;
+        if handler.is_null() {
+            return Err("debugger has already been stopped".into());
+        }
+
+        let mut state = ptr::null_mut();
+        jstry!(unsafe { JsDiagStopDebugging(self.runtime, &mut state) });
+        self.handler = ptr::null_mut();
+        Ok(handler)
+    }
+}
+
+impl Drop for Debugger {
+    fn drop(&mut self) {
+        if let Ok(handler) = self.detach() {
+            unsafe { Box::from_raw(handler) };
+        }
+    }
+}
+
+impl<'a> Paused<'a> {
+    /// Returns the guard of the paused script's context.
+    pub fn guard(&self) -> &ContextGuard<'a> {
+        self.guard
+    }
+
+    /// Returns the event's data, as provided by the engine.
+    ///
+    /// This is an object describing the event, e.g the `scriptId` of a
+    /// compiled script, or the `exception` of a runtime exception.
+    pub fn data(&self) -> &Value {
+        &self.data
+    }
+
+    /// Returns the exception of a `RuntimeException` event.
+    pub fn exception(&self) -> Option<ThrownException> {
+        let guard = self.guard;
+        let exception = property(guard, &self.data, "exception");
+        if exception.is_undefined() {
+            return None;
+        }
+
+        Some(ThrownException {
+            uncaught: property(guard, &self.data, "uncaught").to_bool(guard),
+            script_id: integer(guard, &self.data, "scriptId"),
+            line: integer(guard, &self.data, "line"),
+            column: integer(guard, &self.data, "column"),
+            display: property(guard, &exception, "display").to_string(guard),
+        })
+    }
+
+    /// Returns the frames of the paused script's stack.
+    ///
+    /// The frames are limited by the context's stack trace limit, if it has
+    /// been set.
+    pub fn stack_trace(&self) -> Result<Vec<StackFrame>> {
+        let guard = self.guard;
+        let limit = guard.stack_trace_limit().map_or(usize::max_value(), |limit| limit as usize);
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagGetStackTrace(&mut result));
+            Ok(elements(guard, result).iter().take(limit).map(|frame| StackFrame {
+                index: integer(guard, frame, "index"),
+                script_id: integer(guard, frame, "scriptId"),
+                line: integer(guard, frame, "line"),
+                column: integer(guard, frame, "column"),
+                source: property(guard, frame, "sourceText").to_string(guard),
+                function_name: function_name(guard, integer(guard, frame, "functionHandle")),
+            }).collect())
+        }
+    }
+
+    /// Returns the properties (e.g locals and arguments) of a stack frame.
+    ///
+    /// The result is an object as provided by the engine, containing arrays
+    /// such as `locals` and `arguments`.
+    pub fn frame_properties(&self, frame: u32) -> Result<Value> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagGetStackProperties(frame, &mut result));
+            Ok(Value::from_raw(result))
+        }
+    }
+
+    /// Evaluates an expression within a stack frame.
+    ///
+    /// The result is an object describing the value, with properties such as
+    /// `type` and `display` (the value's string representation).
+    pub fn evaluate(&self, expression: &str, frame: u32) -> Result<Value> {
+        let expression = value::String::new(self.guard, expression);
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry!(JsDiagEvaluate(expression.as_raw(),
+                                  frame,
+                                  JsParseScriptAttributeNone,
+                                  false,
+                                  &mut result));
+            Ok(Value::from_raw(result))
+        }
+    }
+}
+
+/// Returns a property of a diagnostic object.
+fn property(guard: &ContextGuard, object: &Value, name: &str) -> Value {
+    object.clone()
+        .into_object()
+        .map_or_else(|| value::undefined(guard), |object| object.get(guard, &Property::new(guard, name)))
+}
+
+/// Returns an integer property of a diagnostic object.
+fn integer(guard: &ContextGuard, object: &Value, name: &str) -> u32 {
+    property(guard, object, name).to_integer(guard) as u32
+}
+
+/// Returns the name of a function, using its diagnostic handle.
+fn function_name(guard: &ContextGuard, handle: u32) -> String {
+    let mut result = JsValueRef::new();
+    if unsafe { JsDiagGetObjectFromHandle(handle, &mut result) } != JsErrorCode::NoError {
+        return String::new();
+    }
+
+    let name = property(guard, &unsafe { Value::from_raw(result) }, "name");
+    if name.is_undefined() { String::new() } else { name.to_string(guard) }
+}
+
+/// Returns the elements of a diagnostic array.
+unsafe fn elements(guard: &ContextGuard, array: JsValueRef) -> Vec<Value> {
+    Value::from_raw(array).into_array().map_or_else(Vec::new, |array| array.to_vec(guard))
+}
+
+/// Converts a diagnostic object to a breakpoint.
+fn breakpoint(guard: &ContextGuard, object: &Value) -> Breakpoint {
+    Breakpoint {
+        id: integer(guard, object, "breakpointId"),
+        script_id: integer(guard, object, "scriptId"),
+        line: integer(guard, object, "line"),
+        column: integer(guard, object, "column"),
+    }
+}
+
+/// A debug event callback, triggered by the engine.
+unsafe extern "system" fn handle_event(event: JsDiagDebugEvent, data: JsValueRef, state: *mut c_void) {
+    let handler = (state as *mut Box<DebugHandler>).as_mut().expect("retrieving debug handler");
+    let event = match event {
+        JsDiagDebugEvent::SourceCompile => DebugEvent::SourceCompile,
+        JsDiagDebugEvent::CompileError => DebugEvent::CompileError,
+        JsDiagDebugEvent::Breakpoint => DebugEvent::Breakpoint,
+        JsDiagDebugEvent::StepComplete => DebugEvent::StepComplete,
+        JsDiagDebugEvent::DebuggerStatement => DebugEvent::DebuggerStatement,
+        JsDiagDebugEvent::AsyncBreak => DebugEvent::AsyncBreak,
+        JsDiagDebugEvent::RuntimeException => DebugEvent::RuntimeException,
+    };
+
+    Context::exec_with_current(|guard| {
+        let paused = Paused { guard: guard, data: Value::from_raw(data) };
+        let step = handler.on_event(&paused, event);
+
+        if event != DebugEvent::SourceCompile && event != DebugEvent::CompileError {
+            let step = match step {
+                Step::Continue => JsDiagStepType::Continue,
+                Step::Into => JsDiagStepType::StepIn,
+                Step::Out => JsDiagStepType::StepOut,
+                Step::Over => JsDiagStepType::StepOver,
+            };
+            jsassert!(JsDiagSetStepType(step));
+        }
+    }).expect("executing debug event callback")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use {test, script, Property};
+    use super::{BreakOnException, Debugger, DebugHandler, DebugEvent, Paused, Step, ThrownException};
+
+    struct Handler(Arc<Mutex<Vec<(DebugEvent, u32, String)>>>);
+
+    impl DebugHandler for Handler {
+        fn on_event(&mut self, paused: &Paused, event: DebugEvent) -> Step {
+            if event == DebugEvent::DebuggerStatement {
+                let frames = paused.stack_trace().unwrap();
+                let result = paused.evaluate("x * 2", 0).unwrap();
+                let display = result.into_object()
+                    .unwrap()
+                    .get(paused.guard(), &Property::new(paused.guard(), "display"))
+                    .to_string(paused.guard());
+                self.0.lock().unwrap().push((event, frames[0].line, display));
+            }
+            Step::Continue
+        }
+    }
+
+    #[test]
+    fn debugger_statement() {
+        test::run_with_context(|guard| {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let debugger = Debugger::start(guard, Box::new(Handler(events.clone()))).unwrap();
+
+            let result = script::eval_with_name(guard, "debug.js", "var x = 5;\ndebugger;\nx").unwrap();
+            assert_eq!(result.to_integer(guard), 5);
+            assert_eq!(*events.lock().unwrap(), [(DebugEvent::DebuggerStatement, 1, "10".to_string())]);
+
+            let scripts = debugger.scripts(guard).unwrap();
+            let script = scripts.iter()
+                .find(|script| script.file_name.as_ref().map_or(false, |name| name.ends_with("debug.js")))
+                .unwrap();
+            assert_eq!(script.line_count, 3);
+            assert_eq!(debugger.source(guard, script.id).unwrap(), "var x = 5;\ndebugger;\nx");
+
+            assert!(debugger.stop(guard).is_ok());
+        });
+    }
+
+    struct Exceptions(Arc<Mutex<Vec<ThrownException>>>);
+
+    impl DebugHandler for Exceptions {
+        fn on_event(&mut self, paused: &Paused, event: DebugEvent) -> Step {
+            if event == DebugEvent::RuntimeException {
+                self.0.lock().unwrap().push(paused.exception().unwrap());
+            }
+            Step::Continue
+        }
+    }
+
+    #[test]
+    fn break_on_exception() {
+        test::run_with_context(|guard| {
+            let exceptions = Arc::new(Mutex::new(Vec::new()));
+            let debugger = Debugger::start(guard, Box::new(Exceptions(exceptions.clone()))).unwrap();
+            let code = "try {\n  throw new Error('caught');\n} catch (error) {}";
+
+            debugger.set_break_on_exception(guard, BreakOnException::Never).unwrap();
+            script::eval(guard, code).unwrap();
+            assert!(exceptions.lock().unwrap().is_empty());
+
+            debugger.set_break_on_exception(guard, BreakOnException::Always).unwrap();
+            assert_eq!(debugger.break_on_exception(guard).unwrap(), BreakOnException::Always);
+            script::eval(guard, code).unwrap();
+
+            let exceptions = exceptions.lock().unwrap();
+            assert_eq!(exceptions.len(), 1);
+            assert!(!exceptions[0].uncaught);
+            assert_eq!((exceptions[0].line, exceptions[0].column), (1, 2));
+        });
+    }
+}
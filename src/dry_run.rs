@@ -0,0 +1,158 @@
+//! Previewing scripts without performing side effects.
+//!
+//! In dry-run mode, calls to host functions are recorded instead of invoking
+//! their callbacks, and return canned values. This allows a platform to show
+//! which APIs a user script would call (and with what arguments) before it is
+//! actually executed.
+//!
+//! Host functions are the named native functions (i.e those created with
+//! `Function::with_name`), which includes the functions installed by this
+//! crate's modules. Anonymous native functions, which are used internally
+//! (e.g for promise callbacks), are not intercepted.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::dry_run::DryRun;
+//! # use js::value::ExportedValue;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let send = js::value::Function::with_name(&guard, "send", Box::new(|_, _| {
+//!     panic!("not called in dry-run mode");
+//! }));
+//! guard.global().set(&guard, &js::Property::new(&guard, "send"), &send);
+//!
+//! let dry_run = DryRun::new().returns("send", ExportedValue::Boolean(true));
+//! js::dry_run::enable(&context, &dry_run);
+//!
+//! let result = js::script::eval(&guard, "send('user@example.com', 42)").unwrap();
+//! assert!(result.to_bool(&guard));
+//!
+//! let calls = dry_run.calls();
+//! assert_eq!(calls[0].function, "send");
+//! assert_eq!(calls[0].arguments[1], Some(ExportedValue::Number(42.0)));
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use context::{Context, ContextGuard};
+use value::ExportedValue;
+use value::function::{CallbackInfo, CallbackResult};
+
+/// A recorded call of a host function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostCall {
+    /// The name of the function.
+    pub function: String,
+    /// The arguments, or `None` for arguments which cannot be exported (e.g
+    /// functions).
+    pub arguments: Vec<Option<ExportedValue>>,
+    /// Whether the function was called as a constructor.
+    pub is_construct_call: bool,
+}
+
+/// The recorded calls and canned values of a dry run.
+#[derive(Default)]
+struct Recording {
+    calls: Vec<HostCall>,
+    returns: HashMap<String, ExportedValue>,
+    fallback: Option<ExportedValue>,
+}
+
+/// A recorder of host function calls.
+///
+/// Clones share the same recording.
+#[derive(Clone, Default)]
+pub struct DryRun(Arc<Mutex<Recording>>);
+
+impl DryRun {
+    /// Creates a recorder, where all host functions return `undefined`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value returned by a host function.
+    pub fn returns(self, function: &str, value: ExportedValue) -> Self {
+        self.0.lock().unwrap().returns.insert(function.to_string(), value);
+        self
+    }
+
+    /// Sets the value returned by host functions without a canned value.
+    pub fn returns_by_default(self, value: ExportedValue) -> Self {
+        self.0.lock().unwrap().fallback = Some(value);
+        self
+    }
+
+    /// Returns the calls recorded so far, in the order they were made.
+    pub fn calls(&self) -> Vec<HostCall> {
+        self.0.lock().unwrap().calls.clone()
+    }
+
+    /// Returns and clears the calls recorded so far.
+    pub fn take_calls(&self) -> Vec<HostCall> {
+        ::std::mem::replace(&mut self.0.lock().unwrap().calls, Vec::new())
+    }
+}
+
+/// Enables dry-run mode for a context, recording its host function calls.
+///
+/// A previously enabled recorder is replaced.
+pub fn enable(context: &Context, dry_run: &DryRun) {
+    context.insert_user_data(dry_run.clone());
+}
+
+/// Disables dry-run mode for a context, returning whether it was enabled.
+pub fn disable(context: &Context) -> bool {
+    context.remove_user_data::<DryRun>().is_some()
+}
+
+/// Records a host function call, if dry-run mode is enabled for the guard's
+/// context, and returns its canned value.
+pub(crate) fn intercept(guard: &ContextGuard, function: &str, info: &CallbackInfo) -> Option<CallbackResult> {
+    let dry_run = guard.context().get_user_data::<DryRun>()?.clone();
+    let arguments = info.arguments.iter().map(|argument| argument.export(guard).ok()).collect();
+
+    let mut recording = dry_run.0.lock().unwrap();
+    recording.calls.push(HostCall {
+        function: function.to_string(),
+        arguments: arguments,
+        is_construct_call: info.is_construct_call,
+    });
+
+    let value = recording.returns.get(function).or(recording.fallback.as_ref());
+    Some(Ok(value.map_or_else(|| ::value::undefined(guard), |value| value.write(guard))))
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, timers};
+    use value::ExportedValue;
+    use super::DryRun;
+
+    #[test]
+    fn record() {
+        test::run_with_context(|guard| {
+            timers::install(guard).unwrap();
+            let dry_run = DryRun::new().returns_by_default(ExportedValue::Number(7.0));
+            super::enable(&guard.context(), &dry_run);
+
+            let result = script::eval(guard, "
+                var fired = false;
+                new setInterval();
+                setTimeout(() => fired = true, 0, { id: 1 })").unwrap();
+            guard.execute_tasks();
+            assert_eq!(result.to_integer(guard), 7);
+
+            let calls = dry_run.take_calls();
+            assert_eq!(calls.len(), 2);
+            assert!(calls[0].is_construct_call);
+            assert_eq!(calls[1].arguments[0], None);
+            assert_eq!(calls[1].arguments[2].as_ref().and_then(|id| id.get("id")), Some(&ExportedValue::Number(1.0)));
+            assert!(dry_run.calls().is_empty());
+
+            assert!(super::disable(&guard.context()));
+            script::eval(guard, "setTimeout(() => fired = true)").unwrap();
+            guard.execute_tasks();
+            assert!(script::eval(guard, "fired").unwrap().to_bool(guard));
+        });
+    }
+}
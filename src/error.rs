@@ -1,15 +1,435 @@
 //! Error types and utilities.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use context::{Context, ContextGuard};
+use value::{self, Value};
+use {source_map, Property};
+
 error_chain! {
     errors {
         /// A variant indicating that a runtime error has occured.
-        ScriptException(message: String) {
+        ScriptException(error: ScriptError) {
             description("JavaScript exception")
-            display("JavaScript exception: {}", message)
+            display("JavaScript exception: {}", error)
         }
         /// An error caused by incorrect code syntax.
-        ScriptCompile(message: String) {
+        ScriptCompile(error: ScriptError) {
             description("JavaScript compile error")
-            display("JavaScript compile error: {}", message)
+            display("JavaScript compile error: {}", error)
         }
+        /// A variant indicating that script execution was terminated.
+        ScriptTerminated {
+            description("JavaScript execution terminated")
+            display("JavaScript execution terminated")
+        }
+        /// A script was rejected, or interrupted, by its runtime's policy.
+        PolicyViolation(reason: String) {
+            description("runtime policy violation")
+            display("runtime policy violation: {}", reason)
+        }
+        /// A JavaScript value to be thrown by a typed host function.
+        Throw(thrown: Thrown) {
+            description("JavaScript value thrown")
+            display("JavaScript value thrown: {}", thrown)
+        }
+    }
+}
+
+/// The identifier of the next thrown value.
+static NEXT_THROWN: AtomicUsize = AtomicUsize::new(0);
+
+/// A JavaScript value thrown by a native callback.
+///
+/// Typed host functions throw the value itself, instead of a generic `Error`
+/// with the error's description, so custom error types are preserved. Since
+/// values are bound to their thread, the value is kept by its context (until
+/// it is thrown, or this is dropped), so errors can still be sent between
+/// threads. If the context's runtime is in use when an error is dropped (e.g
+/// on another thread), its value is discarded the next time the context
+/// keeps a thrown value.
+///
+/// A `value::Error` can be converted to an `Error` directly (e.g using `?`).
+///
+/// ```rust
+/// # use chakracore as js;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let parse = js::value::Function::typed(&guard, "parse", |guard, (text,): (String,)| {
+///     text.parse::<i32>().map_err(|_| js::value::Error::syntax_error(guard, "not a number").into())
+/// });
+///
+/// guard.global().set(&guard, &js::Property::new(&guard, "parse"), &parse);
+/// let result = js::script::eval(&guard, "try { parse('x') } catch (e) { e instanceof SyntaxError }").unwrap();
+/// assert!(result.to_bool(&guard));
+/// ```
+#[derive(Debug)]
+pub struct Thrown {
+    id: usize,
+    description: String,
+    context: Context,
+    orphaned: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Thrown {
+    /// Creates a thrown value, kept by the guard's context.
+    pub fn new(guard: &ContextGuard, value: &Value) -> Self {
+        let id = NEXT_THROWN.fetch_add(1, Ordering::Relaxed);
+        let orphaned = guard.store_thrown(id, value.clone());
+        let description = value.try_to_string(guard).unwrap_or_else(|_| "[object]".to_string());
+        Thrown { id: id, description: description, context: guard.context(), orphaned: orphaned }
+    }
+
+    /// Removes the value from its context, unless it has been taken already
+    /// (or the guard is of another context).
+    pub fn take(&self, guard: &ContextGuard) -> Option<Value> {
+        guard.take_thrown(self.id)
+    }
+}
+
+impl fmt::Display for Thrown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl Drop for Thrown {
+    fn drop(&mut self) {
+        let id = self.id;
+        if self.context.exec_with(|guard| guard.take_thrown(id)).is_err() {
+            self.orphaned.lock().unwrap().push(id);
+        }
+    }
+}
+
+impl From<value::Error> for Error {
+    /// Converts the error into a `Throw` error, using the active context.
+    ///
+    /// Without an active context, the error cannot be kept (nor described),
+    /// so a generic error is returned instead.
+    fn from(error: value::Error) -> Self {
+        let value: Value = error.into();
+        Context::exec_with_current(|guard| ErrorKind::Throw(Thrown::new(guard, &value)).into())
+            .unwrap_or_else(|| "JavaScript error converted without an active context".into())
+    }
+}
+
+/// Diagnostics of a JavaScript exception.
+///
+/// The fields are extracted from the thrown value when the exception occurs,
+/// so they remain accessible after the context has been destroyed. Any of the
+/// optional fields may be missing, e.g if a script throws a primitive value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScriptError {
+    /// The exception's string representation (e.g `TypeError: foo`).
+    pub exception: String,
+    /// The exception's `message` property.
+    pub message: Option<String>,
+    /// The exception's `stack` property.
+    pub stack: Option<String>,
+    /// The URL of the source where the exception occurred.
+    pub url: Option<String>,
+    /// The line (1-based) where the exception occurred.
+    pub line: Option<u32>,
+    /// The column (1-based) where the exception occurred.
+    pub column: Option<u32>,
+    /// The line of source code where the exception occurred.
+    pub source: Option<String>,
+}
+
+impl ScriptError {
+    /// Extracts the diagnostics of a thrown value.
+    ///
+    /// Properties which cannot be read or converted (e.g a throwing getter)
+    /// are left empty. Positions in transformed sources are mapped to their
+    /// original source (see `source_map`).
+    pub fn new(guard: &ContextGuard, exception: &Value) -> Self {
+        Self::extract(guard, exception).mapped(guard)
+    }
+
+    /// Extracts the diagnostics of a thrown value, without mapping them.
+    fn extract(guard: &ContextGuard, exception: &Value) -> Self {
+        let mut error = ScriptError {
+            exception: exception.try_to_string(guard).unwrap_or_else(|_| "[object]".to_string()),
+            ..ScriptError::default()
+        };
+
+        let object = match exception.clone().into_object() {
+            Some(object) => object,
+            None => return error,
+        };
+
+        let property = |name: &str| match object.try_get(guard, &Property::new(guard, name)) {
+            Ok(ref value) if value.is_undefined() || value.is_null() => None,
+            Ok(value) => Some(value),
+            Err(_) => None,
+        };
+        let string = |name: &str| property(name).and_then(|value| value.try_to_string(guard).ok());
+        let number = |name: &str| property(name).and_then(|value| value.into_number()).map(|value| value.value());
+
+        error.message = string("message");
+        let stack = string("stack");
+        error.stack = stack.as_ref().map(|stack| guard.format_stack(stack));
+
+        // Compile errors report a zero-based position of the syntax error
+        if let (Some(line), Some(column)) = (number("line"), number("column")) {
+            error.url = string("url");
+            error.line = Some(line as u32 + 1);
+            error.column = Some(column as u32 + 1);
+        } else if let Some(frame) = stack.as_ref().and_then(|stack| stack.lines().filter_map(Frame::parse).next()) {
+            // The location is read from the unformatted stack
+            error.url = Some(frame.url);
+            error.line = Some(frame.line);
+            error.column = Some(frame.column);
+        }
+
+        error
+    }
+
+    /// Extracts the diagnostics of an exception's metadata, as returned by
+    /// `JsGetAndClearExceptionWithMetadata`.
+    ///
+    /// The metadata's position takes precedence over the exception's own, and
+    /// includes the line of source code.
+    pub fn from_metadata(guard: &ContextGuard, metadata: &Value) -> Self {
+        let metadata = match metadata.clone().into_object() {
+            Some(metadata) => metadata,
+            None => return Self::new(guard, metadata),
+        };
+
+        let property = |name: &str| {
+            let value = metadata.get(guard, &Property::new(guard, name));
+            if value.is_undefined() || value.is_null() { None } else { Some(value) }
+        };
+
+        let exception = property("exception").unwrap_or_else(|| ::value::undefined(guard));
+        let mut error = Self::extract(guard, &exception);
+
+        // The metadata's position is zero-based
+        if let (Some(line), Some(column)) = (property("line"), property("column")) {
+            error.line = Some(line.to_integer(guard) as u32 + 1);
+            error.column = Some(column.to_integer(guard) as u32 + 1);
+            error.url = property("url").map(|value| value.to_string(guard)).or(error.url);
+            error.source = property("source").map(|value| value.to_string(guard));
+        }
+        error.mapped(guard)
+    }
+
+    /// Maps the position to the original source, if the source at the URL
+    /// was transformed.
+    ///
+    /// The line of source code is removed, since it is the transformed one.
+    fn mapped(mut self, guard: &ContextGuard) -> Self {
+        if let (&Some(ref url), Some(line), Some(column)) = (&self.url, self.line, self.column) {
+            let original = source_map::original_position(guard, url, line.saturating_sub(1), column.saturating_sub(1));
+            if let Some((url, line, column)) = original {
+                self.url = Some(url);
+                self.line = Some(line + 1);
+                self.column = Some(column + 1);
+                self.source = None;
+            }
+        }
+        self
+    }
+}
+
+/// Displays the exception and its location, followed by the line of source
+/// code with a caret below the column (if the source is available).
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.exception)?;
+        if let (&Some(ref url), Some(line), Some(column)) = (&self.url, self.line, self.column) {
+            write!(f, " ({}:{}:{})", url, line, column)?;
+        }
+
+        if let (&Some(ref source), Some(column)) = (&self.source, self.column) {
+            let source = source.trim_end();
+            // Preserve tabs, so the caret is aligned regardless of tab width
+            let indent = source.chars()
+                .take(column.saturating_sub(1) as usize)
+                .map(|character| if character == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            write!(f, "\n    {}\n    {}^", source, indent)?;
+        }
+        Ok(())
+    }
+}
+
+/// A frame of a script stack trace.
+///
+/// Frames are rendered as `at function (url:line:column)`, or as `at
+/// url:line:column` for anonymous code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The name of the frame's function, if any.
+    pub function: Option<String>,
+    /// The URL of the frame's source.
+    pub url: String,
+    /// The line (1-based) being executed.
+    pub line: u32,
+    /// The column (1-based) being executed.
+    pub column: u32,
+}
+
+impl Frame {
+    /// Parses a line of a stack trace, returning `None` if it is not a frame
+    /// (e.g the exception's message).
+    pub fn parse(line: &str) -> Option<Frame> {
+        let frame = line.trim();
+        if !frame.starts_with("at ") {
+            return None;
+        }
+
+        let (function, location) = match (frame.rfind(" ("), frame.rfind(')')) {
+            (Some(start), Some(end)) if start < end => (Some(&frame[3..start]), &frame[start + 2..end]),
+            _ => (None, &frame[3..]),
+        };
+
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let url = parts.next()?;
+        Some(Frame {
+            function: function.map(|function| function.to_string()),
+            url: url.to_string(),
+            line: line,
+            column: column,
+        })
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref function) => write!(f, "at {} ({}:{}:{})", function, self.url, self.line, self.column),
+            None => write!(f, "at {}:{}:{}", self.url, self.line, self.column),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use super::*;
+
+    #[test]
+    fn script_error() {
+        test::run_with_context(|guard| {
+            let error = script::eval(guard, "\n  throw new TypeError('foo');").unwrap_err();
+            let error = match *error.kind() {
+                ErrorKind::ScriptException(ref error) => error.clone(),
+                _ => panic!("expected script exception"),
+            };
+
+            assert_eq!(error.exception, "TypeError: foo");
+            assert_eq!(error.message, Some("foo".to_string()));
+            assert!(error.stack.unwrap().contains("TypeError: foo"));
+            assert_eq!((error.line, error.column), (Some(2), Some(3)));
+
+            let error = script::eval(guard, "throw 5").unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::ScriptException(ScriptError { line: None, .. }));
+
+            let error = script::eval(guard, "throw { get stack() { throw 1; }, toString() { throw 2; } }").unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::ScriptException(ScriptError { stack: None, message: None, .. }));
+        });
+    }
+
+    #[test]
+    fn source_mapped() {
+        test::run_with_context(|guard| {
+            let map = r#"{ "version": 3, "sources": ["in.ts"], "mappings": ";AAAA,EAAE" }"#;
+            ::source_map::register(&guard.context(), "out.js", map.to_string());
+
+            let error = script::eval_with_name(guard, "out.js", "\n  throw new TypeError('foo');").unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::ScriptException(ScriptError {
+                url: Some(ref url), line: Some(1), column: Some(3), source: None, ..
+            }) if url == "in.ts");
+        });
+    }
+
+    #[test]
+    fn source_excerpt() {
+        test::run_with_context(|guard| {
+            let error = script::eval_with_name(guard, "file.js", "var a = 1;\n\tundefinedFunction();").unwrap_err();
+            let error = match *error.kind() {
+                ErrorKind::ScriptException(ref error) => error.clone(),
+                _ => panic!("expected script exception"),
+            };
+
+            assert_eq!(error.source.as_ref().map(|source| source.trim()), Some("undefinedFunction();"));
+            assert_eq!((error.url.as_ref().map(|url| url.as_str()), error.line, error.column), (Some("file.js"), Some(2), Some(2)));
+            assert!(error.to_string().ends_with("(file.js:2:2)\n    \tundefinedFunction();\n    \t^"));
+        });
+    }
+
+    #[test]
+    fn thrown() {
+        test::run_with_context(|guard| {
+            let fail = ::value::Function::typed(guard, "fail", |guard, (custom,): (bool,)| -> Result<()> {
+                if custom {
+                    Err(::value::Error::custom(guard, "QuotaError", "quota exceeded").into())
+                } else {
+                    Err("plain".into())
+                }
+            });
+            guard.global().set(guard, &Property::new(guard, "fail"), &fail);
+
+            let result = script::eval(guard, "try { fail(true) } catch (e) { [e instanceof Error, e.name, e.message] }").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"[true,"QuotaError","quota exceeded"]"#);
+            let result = script::eval(guard, "try { fail(false) } catch (e) { e.message }").unwrap();
+            assert_eq!(result.to_string(guard), "plain");
+
+            // The value is released once the error is dropped
+            let error = Error::from(::value::Error::new(guard, "unused"));
+            let id = match *error.kind() {
+                ErrorKind::Throw(ref thrown) => thrown.id,
+                _ => panic!("expected thrown value"),
+            };
+            assert_eq!(error.to_string(), "JavaScript value thrown: Error: unused");
+            drop(error);
+            assert!(guard.take_thrown(id).is_none());
+
+            // Dropping the error while the runtime is in use on this thread
+            let error = Error::from(::value::Error::new(guard, "elsewhere"));
+            let id = match *error.kind() {
+                ErrorKind::Throw(ref thrown) => thrown.id,
+                _ => panic!("expected thrown value"),
+            };
+            ::std::thread::spawn(move || drop(error)).join().unwrap();
+            let _ = Error::from(::value::Error::new(guard, "next"));
+            assert!(guard.take_thrown(id).is_none());
+
+            let value = script::eval(guard, "({ toString() { throw 1; } })").unwrap();
+            assert_eq!(Thrown::new(guard, &value).to_string(), "[object]");
+        });
+    }
+
+    #[test]
+    fn thrown_outside_context() {
+        let (_runtime, context) = test::setup_env();
+        let (error, id) = context.exec_with(|guard| {
+            let error = Error::from(::value::Error::new(guard, "unused"));
+            let id = match *error.kind() {
+                ErrorKind::Throw(ref thrown) => thrown.id,
+                _ => panic!("expected thrown value"),
+            };
+            (error, id)
+        }).unwrap();
+
+        // The error is dropped without an active context
+        drop(error);
+        context.exec_with(|guard| assert!(guard.take_thrown(id).is_none())).unwrap();
+    }
+
+    #[test]
+    fn frame() {
+        let frame = Frame::parse("   at Global code (file.js:3:5)").unwrap();
+        assert_eq!(frame, Frame { function: Some("Global code".to_string()), url: "file.js".to_string(), line: 3, column: 5 });
+        assert_eq!(frame.to_string(), "at Global code (file.js:3:5)");
+
+        let frame = Frame::parse("   at C:\\file.js:1:2").unwrap();
+        assert_eq!((frame.function, frame.url, frame.line), (None, "C:\\file.js".to_string(), 1));
+        assert_eq!(Frame::parse("Error: foo"), None);
     }
 }
@@ -0,0 +1,278 @@
+//! An event loop interleaving microtasks, timers and host callbacks.
+//!
+//! ChakraCore does not run any tasks on its own. The `EventLoop` executes the
+//! tasks of a context in turns, similar to a browser's event loop:
+//!
+//! - *Microtasks* (promise reactions and `queueMicrotask` callbacks) are
+//!   drained at microtask checkpoints.
+//! - *Macrotasks* are expired timers (e.g `setTimeout` or promise timeouts),
+//!   and callbacks submitted to the loop by the host.
+//!
+//! Unlike `ContextGuard::execute_tasks`, failing tasks are returned as errors
+//! (unless the policy reports them to the context's error callback instead).
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::event_loop::EventLoop;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let result = js::script::eval(&guard, "var result = []; result").unwrap();
+//!
+//! let mut event_loop = EventLoop::new(&guard);
+//! event_loop.submit(Box::new(|guard| {
+//!     js::script::eval(guard, "Promise.resolve('foo').then(value => result.push(value))")?;
+//!     Ok(())
+//! }));
+//! event_loop.run().unwrap();
+//! assert_eq!(result.to_json(&guard).unwrap(), r#"["foo"]"#);
+//! ```
+use std::collections::VecDeque;
+use std::time::Instant;
+use context::ContextGuard;
+use error::*;
+use value;
+
+/// Callback type for host callbacks submitted to an event loop.
+pub type HostCallback = FnOnce(&ContextGuard) -> Result<()>;
+
+/// How the errors of failing tasks are handled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    /// The error is returned, and the remaining tasks stay queued.
+    Return,
+    /// The error is passed to the context's microtask error callback, and the
+    /// loop continues.
+    Report,
+}
+
+/// The scheduling policy of an event loop.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// Whether microtasks are drained after each macrotask. Otherwise they
+    /// are only drained at the start and the end of a turn.
+    pub microtask_checkpoints: bool,
+    /// The maximum number of macrotasks executed per turn.
+    pub macrotask_budget: Option<usize>,
+    /// Whether submitted callbacks take precedence over expired timers.
+    /// Otherwise timers are executed first.
+    pub callbacks_first: bool,
+    /// How the errors of failing tasks are handled. A terminated script
+    /// execution is always returned.
+    pub errors: ErrorPolicy,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            microtask_checkpoints: true,
+            macrotask_budget: None,
+            callbacks_first: false,
+            errors: ErrorPolicy::Return,
+        }
+    }
+}
+
+/// A macrotask selected for execution.
+enum Macrotask {
+    Timer(value::Function, Vec<value::Value>),
+    Callback(Box<HostCallback>),
+}
+
+/// An event loop executing the tasks of the guard's context.
+pub struct EventLoop<'a> {
+    guard: &'a ContextGuard<'a>,
+    policy: Policy,
+    callbacks: VecDeque<Box<HostCallback>>,
+}
+
+impl<'a> EventLoop<'a> {
+    /// Creates an event loop with the default policy.
+    pub fn new(guard: &'a ContextGuard<'a>) -> Self {
+        Self::with_policy(guard, Policy::default())
+    }
+
+    /// Creates an event loop with a scheduling policy.
+    pub fn with_policy(guard: &'a ContextGuard<'a>, policy: Policy) -> Self {
+        EventLoop {
+            guard: guard,
+            policy: policy,
+            callbacks: VecDeque::new(),
+        }
+    }
+
+    /// Submits a host callback, to be executed as a macrotask.
+    pub fn submit(&mut self, callback: Box<HostCallback>) {
+        self.callbacks.push_back(callback);
+    }
+
+    /// Returns whether any tasks, callbacks or timers (expired or not) are
+    /// pending.
+    pub fn has_pending(&self) -> bool {
+        !self.callbacks.is_empty() || self.guard.has_microtasks() || self.guard.next_deadline().is_some()
+    }
+
+    /// Executes a single turn, returning the number of tasks executed.
+    ///
+    /// A turn executes the queued microtasks, and the macrotasks which are
+    /// ready (within the policy's budget). Callbacks submitted, and timers
+    /// scheduled by scripts, during a turn are executed by the next one.
+    pub fn turn(&mut self) -> Result<usize> {
+        self.guard.begin_task_pass();
+        let mut executed = self.microtasks()?;
+        let mut callbacks = self.callbacks.len();
+        let mut budget = self.policy.macrotask_budget.unwrap_or(usize::max_value());
+
+        let guard = self.guard;
+        while budget > 0 {
+            let timer = || guard.next_timer().map(|(callback, arguments)| Macrotask::Timer(callback, arguments));
+            let task = if self.policy.callbacks_first && callbacks > 0 {
+                None
+            } else {
+                timer()
+            };
+
+            let task = match task {
+                Some(task) => task,
+                None if callbacks > 0 => {
+                    callbacks -= 1;
+                    Macrotask::Callback(self.callbacks.pop_front().expect("retrieving host callback"))
+                },
+                None => match timer() {
+                    Some(task) => task,
+                    None => break,
+                },
+            };
+
+            let result = match task {
+                Macrotask::Timer(callback, arguments) => {
+                    let arguments = arguments.iter().collect::<Vec<_>>();
+                    callback.call(self.guard, &arguments).map(|_| ())
+                },
+                Macrotask::Callback(callback) => callback(self.guard),
+            };
+
+            self.handle(result)?;
+            executed += 1;
+            budget -= 1;
+
+            if self.policy.microtask_checkpoints {
+                executed += self.microtasks()?;
+            }
+        }
+
+        if !self.policy.microtask_checkpoints {
+            executed += self.microtasks()?;
+        }
+        Ok(executed)
+    }
+
+    /// Executes turns until no tasks are ready, without waiting for timers.
+    ///
+    /// An interval timer with no delay keeps this running until it is cleared.
+    pub fn run_until_idle(&mut self) -> Result<()> {
+        while self.turn()? > 0 {}
+        Ok(())
+    }
+
+    /// Executes turns, waiting for pending timers, until no tasks remain.
+    ///
+    /// This blocks the thread while waiting for the next timer to expire.
+    pub fn run(&mut self) -> Result<()> {
+        self.run_until(None)
+    }
+
+    /// Executes turns, waiting for pending timers, until no tasks remain or
+    /// an optional deadline has passed.
+    pub(crate) fn run_until(&mut self, deadline: Option<Instant>) -> Result<()> {
+        loop {
+            self.turn()?;
+            let scheduler = self.guard.scheduler();
+            let now = scheduler.now();
+            if deadline.map_or(false, |deadline| now >= deadline) {
+                return Ok(());
+            } else if !self.callbacks.is_empty() || self.guard.has_microtasks() {
+                continue;
+            }
+
+            match self.guard.next_deadline() {
+                Some(next) => {
+                    let wake = deadline.map_or(next, |deadline| next.min(deadline));
+                    scheduler.sleep_until(wake);
+                },
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Drains the microtask queue, returning the number of tasks executed.
+    fn microtasks(&mut self) -> Result<usize> {
+        let mut executed = 0;
+        while let Some(task) = self.guard.next_microtask() {
+            let result = task.call(self.guard, &[]).map(|_| ());
+            self.handle(result)?;
+            executed += 1;
+        }
+        Ok(executed)
+    }
+
+    /// Handles the result of a task according to the policy.
+    fn handle(&self, result: Result<()>) -> Result<()> {
+        match result {
+            Err(Error(ErrorKind::ScriptTerminated, state)) => Err(Error(ErrorKind::ScriptTerminated, state)),
+            Err(error) => match self.policy.errors {
+                ErrorPolicy::Return => Err(error),
+                ErrorPolicy::Report => {
+                    self.guard.report_task_error(&error);
+                    Ok(())
+                },
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, timers};
+    use super::{ErrorPolicy, EventLoop, Policy};
+
+    #[test]
+    fn interleave() {
+        test::run_with_context(|guard| {
+            timers::install(guard).unwrap();
+            let result = script::eval(guard, "
+                var order = [];
+                setTimeout(() => {
+                    order.push('timer');
+                    Promise.resolve().then(() => order.push('timer microtask'));
+                });
+                order").unwrap();
+
+            let mut event_loop = EventLoop::with_policy(guard, Policy { callbacks_first: true, ..Policy::default() });
+            event_loop.submit(Box::new(|guard| script::eval(guard, "order.push('callback')").map(|_| ())));
+            event_loop.run().unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"["callback","timer","timer microtask"]"#);
+            assert!(!event_loop.has_pending());
+        });
+    }
+
+    #[test]
+    fn errors() {
+        test::run_with_context(|guard| {
+            ::timers::install(guard).unwrap();
+            script::eval(guard, "var count = 0; queueMicrotask(() => { throw new Error(); })").unwrap();
+            script::eval(guard, "queueMicrotask(() => count++)").unwrap();
+
+            let mut event_loop = EventLoop::new(guard);
+            assert!(event_loop.turn().is_err());
+            assert_eq!(event_loop.turn().unwrap(), 1);
+
+            // Reported errors do not stop the loop
+            script::eval(guard, "queueMicrotask(() => { throw new Error(); }); queueMicrotask(() => count++)").unwrap();
+            let mut event_loop = EventLoop::with_policy(guard, Policy { errors: ErrorPolicy::Report, ..Policy::default() });
+            assert_eq!(event_loop.turn().unwrap(), 2);
+            assert_eq!(script::eval(guard, "count").unwrap().to_integer(guard), 2);
+        });
+    }
+}
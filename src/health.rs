@@ -0,0 +1,245 @@
+//! Liveness checks for threads hosting a runtime.
+//!
+//! A thread dedicated to a runtime signals that it is alive using a
+//! `Heartbeat`, e.g whenever it finishes a job and periodically while it is
+//! waiting for one. A `Monitor` watches the heartbeats of such threads. If a
+//! thread has been silent for longer than the monitor's timeout (e.g due to a
+//! script stuck in a loop), its runtime is interrupted, and the thread is
+//! replaced using a host-provided callback.
+//!
+//! An interrupted thread is expected to exit, disposing its runtime. Alerts
+//! are raised for each wedged thread and replacement, so hosts can log them
+//! or notify operators.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use error::*;
+use runtime::InterruptHandle;
+
+/// Callback type for health alerts.
+pub type AlertCallback = Fn(&HealthEvent) + Send + Sync;
+
+/// Callback type for replacing a wedged thread.
+///
+/// This is called once the wedged thread's runtime has been interrupted, and
+/// returns the heartbeat and interrupt handle of its replacement.
+pub type ReplaceCallback = FnMut() -> Result<(Heartbeat, InterruptHandle)> + Send;
+
+/// A shared timestamp of a thread's last sign of life.
+#[derive(Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Heartbeat {
+    /// Creates a heartbeat, beating at the moment of creation.
+    pub fn new() -> Self {
+        Heartbeat(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Signals that the thread is alive.
+    pub fn beat(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns the time elapsed since the last beat.
+    pub fn elapsed(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat::new()
+    }
+}
+
+impl fmt::Debug for Heartbeat {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Heartbeat({:?})", self.elapsed())
+    }
+}
+
+/// An event raised by a health monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthEvent {
+    /// A thread has not signalled its heartbeat within the timeout.
+    Wedged { name: String, silence: Duration },
+    /// A wedged thread has been replaced.
+    Replaced { name: String },
+    /// A wedged thread could not be replaced (it is retried after a timeout).
+    ReplaceFailed { name: String, error: String },
+}
+
+/// A thread watched by a monitor.
+struct Watched {
+    name: String,
+    heartbeat: Heartbeat,
+    interrupt: InterruptHandle,
+    /// Shared, so it can be called without locking the watched threads.
+    replace: Arc<Mutex<Box<ReplaceCallback>>>,
+}
+
+/// A monitor of runtime threads, replacing the ones that become wedged.
+///
+/// The heartbeats are checked on a background thread, which is stopped once
+/// the monitor is dropped.
+pub struct Monitor {
+    watched: Arc<Mutex<Vec<Watched>>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Creates a monitor, considering threads wedged after a timeout.
+    pub fn new(timeout: Duration, alert: Option<Box<AlertCallback>>) -> Self {
+        let watched = Arc::new(Mutex::new(Vec::<Watched>::new()));
+        let (sender, receiver) = mpsc::channel::<()>();
+
+        let entries = watched.clone();
+        let thread = thread::spawn(move || {
+            let raise = |event: HealthEvent| if let Some(ref alert) = alert { alert(&event) };
+            while receiver.recv_timeout(timeout / 4) == Err(RecvTimeoutError::Timeout) {
+                // Callbacks are invoked without the lock, so they may (un)watch threads
+                let wedged = entries.lock().unwrap().iter()
+                    .filter(|entry| entry.heartbeat.elapsed() >= timeout)
+                    .map(|entry| {
+                        (entry.name.clone(), entry.heartbeat.elapsed(), entry.interrupt.clone(), entry.replace.clone())
+                    })
+                    .collect::<Vec<_>>();
+
+                for (name, silence, interrupt, replace) in wedged {
+                    raise(HealthEvent::Wedged { name: name.clone(), silence: silence });
+                    let _ = interrupt.interrupt();
+
+                    let result = (&mut *replace.lock().unwrap())();
+                    {
+                        // The thread may have been unwatched in the meantime
+                        let mut watched = entries.lock().unwrap();
+                        let entry = watched.iter_mut().find(|entry| Arc::ptr_eq(&entry.replace, &replace));
+                        match (entry, &result) {
+                            (Some(entry), &Ok((ref heartbeat, ref interrupt))) => {
+                                entry.heartbeat = heartbeat.clone();
+                                entry.interrupt = interrupt.clone();
+                            },
+                            (Some(entry), &Err(_)) => entry.heartbeat.beat(),
+                            (None, _) => (),
+                        }
+                    }
+
+                    match result {
+                        Ok(_) => raise(HealthEvent::Replaced { name: name }),
+                        Err(error) => raise(HealthEvent::ReplaceFailed { name: name, error: error.to_string() }),
+                    }
+                }
+            }
+        });
+
+        Monitor {
+            watched: watched,
+            stop: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    /// Starts watching a thread, identified by a name.
+    pub fn watch(&self,
+                 name: &str,
+                 heartbeat: Heartbeat,
+                 interrupt: InterruptHandle,
+                 replace: Box<ReplaceCallback>) {
+        self.watched.lock().unwrap().push(Watched {
+            name: name.to_string(),
+            heartbeat: heartbeat,
+            interrupt: interrupt,
+            replace: Arc::new(Mutex::new(replace)),
+        });
+    }
+
+    /// Stops watching a thread, returning whether it was watched.
+    pub fn unwatch(&self, name: &str) -> bool {
+        let mut watched = self.watched.lock().unwrap();
+        let count = watched.len();
+        watched.retain(|entry| entry.name != name);
+        watched.len() != count
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("joining health monitor");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use {error, script, Context, Runtime};
+    use super::*;
+
+    /// Spawns a runtime thread, which runs a script and then beats until stopped.
+    fn spawn(code: &'static str, stop: Arc<AtomicBool>) -> (Heartbeat, InterruptHandle, thread::JoinHandle<bool>) {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let runtime = Runtime::builder().enable_script_interrupt().build().unwrap();
+            let context = Context::new(&runtime).unwrap();
+            let heartbeat = Heartbeat::new();
+            sender.send((heartbeat.clone(), runtime.interrupt_handle())).unwrap();
+
+            let guard = context.make_current().unwrap();
+            if let Err(error) = script::eval(&guard, code) {
+                return match *error.kind() { error::ErrorKind::ScriptTerminated => true, _ => false };
+            }
+
+            while !stop.load(Ordering::SeqCst) {
+                heartbeat.beat();
+                thread::sleep(Duration::from_millis(10));
+            }
+            false
+        });
+
+        let (heartbeat, interrupt) = receiver.recv().unwrap();
+        (heartbeat, interrupt, thread)
+    }
+
+    #[test]
+    fn replace_wedged() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let alerts = events.clone();
+        let monitor = Monitor::new(Duration::from_millis(200), Some(Box::new(move |event: &HealthEvent| {
+            alerts.lock().unwrap().push(event.clone());
+        })));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (heartbeat, interrupt, wedged) = spawn("while (true) {}", stop.clone());
+
+        let replacement = Arc::new(Mutex::new(None));
+        let (replacement_stop, replaced) = (stop.clone(), replacement.clone());
+        monitor.watch("worker", heartbeat, interrupt, Box::new(move || {
+            let (heartbeat, interrupt, thread) = spawn("1 + 1", replacement_stop.clone());
+            *replaced.lock().unwrap() = Some(thread);
+            Ok((heartbeat, interrupt))
+        }));
+
+        // The wedged thread exits once its script has been interrupted
+        assert!(wedged.join().unwrap());
+        thread::sleep(Duration::from_millis(400));
+        assert!(monitor.unwatch("worker"));
+        drop(monitor);
+
+        stop.store(true, Ordering::SeqCst);
+        let thread = replacement.lock().unwrap().take().unwrap();
+        assert!(!thread.join().unwrap());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_matches!(events[0], HealthEvent::Wedged { .. });
+        assert_eq!(events[1], HealthEvent::Replaced { name: "worker".to_string() });
+    }
+}
@@ -0,0 +1,651 @@
+//! Heap snapshots, for tracking memory leaks.
+//!
+//! JSRT only provides a heap enumeration API on Windows, so snapshots are
+//! taken by walking the object graph instead, starting from a context's
+//! global object. Prototypes, own properties (including accessors and symbol
+//! keyed properties) and the buffers of typed arrays are followed, without
+//! invoking any getters (although the traps of proxies are). Values which are
+//! only reachable from closures or host data cannot be enumerated.
+//!
+//! The engine does not expose the size of its objects either, so sizes are
+//! estimated from the number of properties, the length of strings and the
+//! size of buffers; the runtime's actual memory usage is recorded alongside.
+//! Retained sizes are computed from the dominator tree of the graph, i.e an
+//! object retains the objects which are only reachable through it.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::heap::HeapSnapshot;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! js::script::eval(&guard, "
+//!     function Session(id) { this.id = id; }
+//!     var sessions = [];
+//!     for (var i = 0; i < 100; i++) sessions.push(new Session(i));").unwrap();
+//!
+//! let snapshot = HeapSnapshot::take(&guard).unwrap();
+//! let summary = snapshot.summary();
+//! let sessions = summary.iter().find(|summary| summary.name == "Session").unwrap();
+//! assert_eq!(sessions.count, 100);
+//!
+//! let json = snapshot.to_heapsnapshot();
+//! assert!(json.starts_with(r#"{"snapshot":"#));
+//! ```
+use std::collections::HashMap;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use stream::{JsonWriter, StreamSink};
+use util::jstry;
+use value::{self, Value};
+use Property;
+
+/// The estimated size of an object without properties.
+const OBJECT_SIZE: usize = 32;
+
+/// The estimated size of a function without properties.
+const FUNCTION_SIZE: usize = 64;
+
+/// The estimated size of each property of an object.
+const PROPERTY_SIZE: usize = 16;
+
+/// The estimated size of an empty string.
+const STRING_SIZE: usize = 24;
+
+/// A snapshot of the objects reachable from a context's global object.
+#[derive(Clone, Debug)]
+pub struct HeapSnapshot {
+    /// The nodes of the graph, starting with its synthetic root.
+    pub nodes: Vec<HeapNode>,
+    /// The runtime's memory usage when the snapshot was taken, in bytes.
+    pub memory_usage: usize,
+    /// The index of each node's immediate dominator.
+    dominators: Vec<usize>,
+}
+
+/// A value in a heap snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeapNode {
+    /// The node's identifier, unique within its snapshot.
+    pub id: u32,
+    /// The kind of value.
+    pub kind: NodeKind,
+    /// The name of the object's constructor, the name of a function, or the
+    /// contents of a string.
+    pub name: String,
+    /// The estimated size of the value itself, in bytes.
+    pub self_size: usize,
+    /// The estimated size of the value, and the values it retains, in bytes.
+    pub retained_size: usize,
+    /// The references to other nodes.
+    pub edges: Vec<HeapEdge>,
+}
+
+/// The kinds of values in a heap snapshot.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+    /// The root of the graph, which is not a value.
+    Synthetic,
+    /// An object (including arrays and buffers).
+    Object,
+    /// A function.
+    Closure,
+    /// A string.
+    String,
+    /// A regular expression.
+    RegExp,
+}
+
+/// A reference from a node to another one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeapEdge {
+    /// The kind of reference.
+    pub kind: EdgeKind,
+    /// The property's name (or index, for elements).
+    pub name: String,
+    /// The index of the referenced node.
+    pub to: usize,
+}
+
+/// The kinds of references between nodes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// A named property.
+    Property,
+    /// An array element.
+    Element,
+    /// A reference which is not a property (e.g a prototype or an accessor).
+    Internal,
+}
+
+/// The nodes of a snapshot with the same constructor (or kind).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstructorSummary {
+    /// The name of the constructor, or `(string)`, `(closure)` and `(regexp)`.
+    pub name: String,
+    /// The number of nodes.
+    pub count: usize,
+    /// The total estimated size of the nodes themselves, in bytes.
+    pub self_size: usize,
+    /// The estimated size retained by the nodes, in bytes.
+    pub retained_size: usize,
+}
+
+/// The state of a walk through the object graph.
+struct Walker<'a> {
+    guard: &'a ContextGuard<'a>,
+    nodes: Vec<HeapNode>,
+    indices: HashMap<usize, usize>,
+    constructors: HashMap<usize, String>,
+    pending: Vec<(usize, Value)>,
+}
+
+impl HeapSnapshot {
+    /// Takes a snapshot of the objects reachable from the guard's context.
+    pub fn take(guard: &ContextGuard) -> Result<HeapSnapshot> {
+        let mut walker = Walker {
+            guard: guard,
+            nodes: vec![HeapNode::new(0, NodeKind::Synthetic, "(root)", 0)],
+            indices: HashMap::new(),
+            constructors: HashMap::new(),
+            pending: Vec::new(),
+        };
+
+        let global = walker.visit(&guard.global().into()).ok_or("global object is not an object")?;
+        walker.nodes[0].edges.push(HeapEdge { kind: EdgeKind::Property, name: "global".into(), to: global });
+        while let Some((index, value)) = walker.pending.pop() {
+            walker.walk(index, &value);
+        }
+
+        let mut memory_usage = 0;
+        jstry(unsafe { JsGetRuntimeMemoryUsage(guard.context().runtime_handle(), &mut memory_usage) })?;
+
+        let mut snapshot = HeapSnapshot { nodes: walker.nodes, memory_usage: memory_usage, dominators: Vec::new() };
+        snapshot.compute_retained_sizes();
+        Ok(snapshot)
+    }
+
+    /// Returns the total estimated size of the nodes, in bytes.
+    pub fn total_size(&self) -> usize {
+        self.nodes[0].retained_size
+    }
+
+    /// Summarizes the nodes by constructor, sorted by decreasing retained size.
+    ///
+    /// The retained size of a constructor only includes the nodes which are not
+    /// retained by other nodes of the same constructor, so that nested objects
+    /// are not counted twice.
+    pub fn summary(&self) -> Vec<ConstructorSummary> {
+        let mut summaries: Vec<ConstructorSummary> = Vec::new();
+        let mut positions = HashMap::new();
+
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let class = node.class_name();
+            let position = *positions.entry(class).or_insert_with(|| {
+                summaries.push(ConstructorSummary {
+                    name: class.to_string(),
+                    count: 0,
+                    self_size: 0,
+                    retained_size: 0,
+                });
+                summaries.len() - 1
+            });
+
+            let summary = &mut summaries[position];
+            summary.count += 1;
+            summary.self_size += node.self_size;
+            if !self.is_retained_by_class(index, class) {
+                summary.retained_size += node.retained_size;
+            }
+        }
+
+        summaries.sort_by(|a, b| b.retained_size.cmp(&a.retained_size).then_with(|| a.name.cmp(&b.name)));
+        summaries
+    }
+
+    /// Serializes the snapshot in the Chrome heap snapshot (`.heapsnapshot`)
+    /// format, as loaded by DevTools.
+    pub fn to_heapsnapshot(&self) -> String {
+        let mut writer = JsonWriter::new(Vec::new());
+        self.write_heapsnapshot(&mut writer).expect("writing heap snapshot");
+        String::from_utf8(writer.into_inner()).expect("converting heap snapshot to string")
+    }
+
+    fn write_heapsnapshot(&self, sink: &mut StreamSink) -> Result<()> {
+        let mut strings = Vec::new();
+        let mut string_indices = HashMap::new();
+        let mut intern = |string: &str| -> usize {
+            if let Some(&index) = string_indices.get(string) {
+                return index;
+            }
+            strings.push(string.to_string());
+            string_indices.insert(string.to_string(), strings.len() - 1);
+            strings.len() - 1
+        };
+
+        let edge_count = self.nodes.iter().map(|node| node.edges.len()).sum::<usize>();
+        let mut nodes = Vec::with_capacity(self.nodes.len() * NODE_FIELDS.len());
+        let mut edges = Vec::with_capacity(edge_count * EDGE_FIELDS.len());
+        for node in &self.nodes {
+            nodes.extend_from_slice(&[
+                node.kind.type_index(),
+                intern(&node.name),
+                node.id as usize,
+                node.self_size,
+                node.edges.len(),
+                0,
+            ]);
+
+            for edge in &node.edges {
+                let name = match edge.kind {
+                    EdgeKind::Element => edge.name.parse().unwrap_or(0),
+                    _ => intern(&edge.name),
+                };
+                edges.extend_from_slice(&[edge.kind.type_index(), name, edge.to * NODE_FIELDS.len()]);
+            }
+        }
+
+        sink.begin_object()?;
+        sink.key("snapshot")?;
+        sink.begin_object()?;
+        sink.key("meta")?;
+        sink.begin_object()?;
+        write_strings(sink, "node_fields", NODE_FIELDS)?;
+        sink.key("node_types")?;
+        sink.begin_array(NODE_FIELDS.len())?;
+        write_strings(sink, "", NODE_TYPES)?;
+        for field in &["string", "number", "number", "number", "number"] {
+            sink.string(field)?;
+        }
+        sink.end_array()?;
+        write_strings(sink, "edge_fields", EDGE_FIELDS)?;
+        sink.key("edge_types")?;
+        sink.begin_array(EDGE_FIELDS.len())?;
+        write_strings(sink, "", EDGE_TYPES)?;
+        sink.string("string_or_number")?;
+        sink.string("node")?;
+        sink.end_array()?;
+        write_strings(sink, "trace_function_info_fields",
+                      &["function_id", "name", "script_name", "script_id", "line", "column"])?;
+        write_strings(sink, "trace_node_fields", &["id", "function_info_index", "count", "size", "children"])?;
+        write_strings(sink, "sample_fields", &["timestamp_us", "last_assigned_id"])?;
+        write_strings(sink, "location_fields", &["object_index", "script_id", "line", "column"])?;
+        sink.end_object()?;
+        sink.key("node_count")?;
+        sink.number(self.nodes.len() as f64)?;
+        sink.key("edge_count")?;
+        sink.number(edge_count as f64)?;
+        sink.key("trace_function_count")?;
+        sink.number(0.0)?;
+        sink.end_object()?;
+
+        write_numbers(sink, "nodes", &nodes)?;
+        write_numbers(sink, "edges", &edges)?;
+        for key in &["trace_function_infos", "trace_tree", "samples", "locations"] {
+            write_numbers(sink, key, &[])?;
+        }
+        let strings = strings.iter().map(|string| string.as_str()).collect::<Vec<_>>();
+        write_strings(sink, "strings", &strings)?;
+        sink.end_object()
+    }
+
+    /// Computes the dominator tree of the graph (using the algorithm of
+    /// Cooper, Harvey and Kennedy), and the retained size of each node.
+    fn compute_retained_sizes(&mut self) {
+        const UNDEFINED: usize = ::std::usize::MAX;
+        let count = self.nodes.len();
+
+        // All nodes are reachable from the root, since they were found from it
+        let mut postorder = Vec::with_capacity(count);
+        let mut visited = vec![false; count];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some(&(node, edge)) = stack.last() {
+            if let Some(to) = self.nodes[node].edges.get(edge).map(|edge| edge.to) {
+                stack.last_mut().unwrap().1 += 1;
+                if !visited[to] {
+                    visited[to] = true;
+                    stack.push((to, 0));
+                }
+            } else {
+                stack.pop();
+                postorder.push(node);
+            }
+        }
+
+        let mut rank = vec![0; count];
+        let mut predecessors = vec![Vec::new(); count];
+        for (position, &node) in postorder.iter().enumerate() {
+            rank[node] = position;
+        }
+        for (node, data) in self.nodes.iter().enumerate() {
+            for edge in &data.edges {
+                predecessors[edge.to].push(node);
+            }
+        }
+
+        let mut dominators = vec![UNDEFINED; count];
+        dominators[0] = 0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in postorder.iter().rev().skip(1) {
+                let mut dominator = UNDEFINED;
+                for &predecessor in &predecessors[node] {
+                    if dominators[predecessor] == UNDEFINED {
+                        continue;
+                    }
+
+                    dominator = if dominator == UNDEFINED {
+                        predecessor
+                    } else {
+                        let (mut a, mut b) = (predecessor, dominator);
+                        while a != b {
+                            while rank[a] < rank[b] { a = dominators[a]; }
+                            while rank[b] < rank[a] { b = dominators[b]; }
+                        }
+                        a
+                    };
+                }
+
+                if dominators[node] != dominator {
+                    dominators[node] = dominator;
+                    changed = true;
+                }
+            }
+        }
+
+        // Nodes are dominated by nodes which come later in postorder
+        for node in &mut self.nodes {
+            node.retained_size = node.self_size;
+        }
+        for &node in &postorder {
+            if node != 0 {
+                let size = self.nodes[node].retained_size;
+                self.nodes[dominators[node]].retained_size += size;
+            }
+        }
+        self.dominators = dominators;
+    }
+
+    /// Returns whether a node is dominated by another node of a class.
+    fn is_retained_by_class(&self, mut index: usize, class: &str) -> bool {
+        while index != 0 {
+            index = self.dominators[index];
+            if index != 0 && self.nodes[index].class_name() == class {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl HeapNode {
+    fn new(id: u32, kind: NodeKind, name: &str, self_size: usize) -> Self {
+        HeapNode {
+            id: id,
+            kind: kind,
+            name: name.to_string(),
+            self_size: self_size,
+            retained_size: self_size,
+            edges: Vec::new(),
+        }
+    }
+
+    /// Returns the name nodes are grouped by in summaries.
+    fn class_name(&self) -> &str {
+        match self.kind {
+            NodeKind::String => "(string)",
+            NodeKind::Closure => "(closure)",
+            NodeKind::RegExp => "(regexp)",
+            NodeKind::Object | NodeKind::Synthetic => &self.name,
+        }
+    }
+}
+
+impl NodeKind {
+    fn type_index(&self) -> usize {
+        match *self {
+            NodeKind::String => 2,
+            NodeKind::Object => 3,
+            NodeKind::Closure => 5,
+            NodeKind::RegExp => 6,
+            NodeKind::Synthetic => 9,
+        }
+    }
+}
+
+impl EdgeKind {
+    fn type_index(&self) -> usize {
+        match *self {
+            EdgeKind::Element => 1,
+            EdgeKind::Property => 2,
+            EdgeKind::Internal => 3,
+        }
+    }
+}
+
+impl<'a> Walker<'a> {
+    /// Returns the node of a value, creating it if it has not been visited.
+    /// Primitives other than strings are not represented by nodes.
+    fn visit(&mut self, value: &Value) -> Option<usize> {
+        let kind = match value.get_type() {
+            JsValueType::String => NodeKind::String,
+            JsValueType::Function => NodeKind::Closure,
+            JsValueType::Object if value::RegExp::is_same(self.guard, value) => NodeKind::RegExp,
+            JsValueType::Object | JsValueType::Error | JsValueType::Array | JsValueType::ArrayBuffer |
+            JsValueType::TypedArray | JsValueType::DataView => NodeKind::Object,
+            _ => return None,
+        };
+
+        let address = value.as_raw().0 as usize;
+        if let Some(&index) = self.indices.get(&address) {
+            return Some(index);
+        }
+
+        let index = self.nodes.len();
+        let node = match kind {
+            NodeKind::String => {
+                let string = value.to_string(self.guard);
+                let length = value.clone().into_string().map_or(0, |string| string.len());
+                HeapNode::new(0, kind, &string, STRING_SIZE + length * 2)
+            },
+            _ => {
+                let object = value.clone().into_object().expect("converting heap value to object");
+                let name = self.name(&object, kind);
+                HeapNode::new(0, kind, &name, 0)
+            },
+        };
+
+        self.nodes.push(HeapNode { id: index as u32 * 2 + 1, ..node });
+        self.indices.insert(address, index);
+        if kind != NodeKind::String {
+            self.pending.push((index, value.clone()));
+        }
+        Some(index)
+    }
+
+    /// Records the size and references of an object's node.
+    fn walk(&mut self, index: usize, value: &Value) {
+        let guard = self.guard;
+        let object = value.clone().into_object().expect("converting heap value to object");
+        let mut edges = Vec::new();
+        let mut size = if value.get_type() == JsValueType::Function { FUNCTION_SIZE } else { OBJECT_SIZE };
+
+        // Proxies with throwing traps are recorded without references
+        let prototype = object.try_get_prototype(guard).ok();
+        if let Some(prototype) = prototype.and_then(|prototype| self.visit(&prototype)) {
+            edges.push(HeapEdge { kind: EdgeKind::Internal, name: "__proto__".into(), to: prototype });
+        }
+
+        match value.get_type() {
+            // The elements of typed arrays are stored in their buffer
+            JsValueType::TypedArray => {
+                let mut buffer = JsValueRef::new();
+                let (mut offset, mut length) = (0, 0);
+                let code = unsafe {
+                    JsGetTypedArrayInfo(value.as_raw(), ::std::ptr::null_mut(), &mut buffer, &mut offset, &mut length)
+                };
+                if code == JsErrorCode::NoError {
+                    if let Some(to) = self.visit(&unsafe { Value::from_raw(buffer) }) {
+                        edges.push(HeapEdge { kind: EdgeKind::Internal, name: "buffer".into(), to: to });
+                    }
+                }
+                self.finish(index, size, edges);
+                return;
+            },
+            JsValueType::ArrayBuffer => {
+                let mut storage = ::std::ptr::null_mut();
+                let mut length = 0;
+                if unsafe { JsGetArrayBufferStorage(value.as_raw(), &mut storage, &mut length) } == JsErrorCode::NoError {
+                    size += length as usize;
+                }
+            },
+            _ => (),
+        }
+
+        let is_array = value.get_type() == JsValueType::Array;
+        for (key, descriptor) in object.try_get_own_property_descriptors(guard).unwrap_or_default() {
+            size += PROPERTY_SIZE;
+            let name = key.to_string(guard);
+            if let Some(to) = descriptor.get_value().and_then(|value| self.visit(value)) {
+                let kind = if is_array && name.parse::<u32>().is_ok() { EdgeKind::Element } else { EdgeKind::Property };
+                edges.push(HeapEdge { kind: kind, name: name.clone(), to: to });
+            }
+
+            let getter = descriptor.get_getter().map(|getter| ("get", getter.clone()));
+            let setter = descriptor.get_setter().map(|setter| ("set", setter.clone()));
+            for (prefix, accessor) in getter.into_iter().chain(setter) {
+                if let Some(to) = self.visit(&accessor.into()) {
+                    edges.push(HeapEdge { kind: EdgeKind::Internal, name: format!("{} {}", prefix, name), to: to });
+                }
+            }
+        }
+
+        self.finish(index, size, edges);
+    }
+
+    fn finish(&mut self, index: usize, size: usize, edges: Vec<HeapEdge>) {
+        let node = &mut self.nodes[index];
+        node.self_size = size;
+        node.retained_size = size;
+        node.edges = edges;
+    }
+
+    /// Returns the name of a function, or the constructor name of an object.
+    fn name(&mut self, object: &value::Object, kind: NodeKind) -> String {
+        let guard = self.guard;
+        if kind == NodeKind::Closure {
+            return own_string(guard, object, "name")
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "(anonymous)".to_string());
+        }
+
+        let prototype = match object.try_get_prototype(guard).ok().and_then(|prototype| prototype.into_object()) {
+            Some(prototype) => prototype,
+            None => return "Object".to_string(),
+        };
+
+        let address = prototype.as_raw().0 as usize;
+        if let Some(name) = self.constructors.get(&address) {
+            return name.clone();
+        }
+
+        // Descriptors are used so that no getters are invoked
+        let name = prototype.try_get_own_property_descriptor(guard, &Property::new(guard, "constructor"))
+            .ok()
+            .and_then(|descriptor| descriptor)
+            .and_then(|descriptor| descriptor.get_value().and_then(|value| value.clone().into_function()))
+            .and_then(|constructor| own_string(guard, &constructor, "name"))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "Object".to_string());
+        self.constructors.insert(address, name.clone());
+        name
+    }
+}
+
+/// The fields of each node in the Chrome format.
+const NODE_FIELDS: &[&str] = &["type", "name", "id", "self_size", "edge_count", "trace_node_id"];
+
+/// The node types of the Chrome format (in order).
+const NODE_TYPES: &[&str] = &[
+    "hidden", "array", "string", "object", "code", "closure", "regexp", "number", "native", "synthetic",
+    "concatenated string", "sliced string", "symbol", "bigint",
+];
+
+/// The fields of each edge in the Chrome format.
+const EDGE_FIELDS: &[&str] = &["type", "name_or_index", "to_node"];
+
+/// The edge types of the Chrome format (in order).
+const EDGE_TYPES: &[&str] = &["context", "element", "property", "internal", "hidden", "shortcut", "weak"];
+
+/// Returns the value of an own data property, if it is a string.
+fn own_string(guard: &ContextGuard, object: &value::Object, name: &str) -> Option<String> {
+    object.try_get_own_property_descriptor(guard, &Property::new(guard, name))
+        .ok()
+        .and_then(|descriptor| descriptor)
+        .and_then(|descriptor| descriptor.get_value().and_then(|value| value.clone().into_string()))
+        .map(|string| string.value())
+}
+
+/// Writes an array of strings, as the value of a key unless it is empty.
+fn write_strings(sink: &mut StreamSink, key: &str, strings: &[&str]) -> Result<()> {
+    if !key.is_empty() {
+        sink.key(key)?;
+    }
+    sink.begin_array(strings.len())?;
+    for string in strings {
+        sink.string(string)?;
+    }
+    sink.end_array()
+}
+
+/// Writes an array of numbers as the value of a key.
+fn write_numbers(sink: &mut StreamSink, key: &str, numbers: &[usize]) -> Result<()> {
+    sink.key(key)?;
+    sink.begin_array(numbers.len())?;
+    for &number in numbers {
+        sink.number(number as f64)?;
+    }
+    sink.end_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use {script, test, Property};
+    use super::{HeapSnapshot, NodeKind};
+
+    #[test]
+    fn snapshot() {
+        test::run_with_context(|guard| {
+            script::eval(guard, "
+                function Cache() { this.entries = []; }
+                var cache = new Cache();
+                for (var i = 0; i < 10; i++) cache.entries.push({ key: 'entry' + i });
+                var buffer = new Uint8Array(1024);").unwrap();
+
+            let snapshot = HeapSnapshot::take(guard).unwrap();
+            assert!(snapshot.memory_usage > 0);
+            assert!(snapshot.nodes.iter().any(|node| node.kind == NodeKind::String && node.name == "entry9"));
+
+            let summary = snapshot.summary();
+            let cache = summary.iter().find(|summary| summary.name == "Cache").unwrap();
+            assert_eq!(cache.count, 1);
+            assert!(cache.retained_size > cache.self_size + 10 * super::OBJECT_SIZE);
+            let buffer = summary.iter().find(|summary| summary.name == "ArrayBuffer").unwrap();
+            assert!(buffer.retained_size >= 1024);
+            assert!(snapshot.total_size() >= summary.iter().map(|summary| summary.self_size).sum::<usize>());
+
+            let json = guard.json_parse(&snapshot.to_heapsnapshot()).unwrap().into_object().unwrap();
+            let nodes = json.get(guard, &Property::new(guard, "nodes")).into_array().unwrap();
+            assert_eq!(nodes.len(guard), snapshot.nodes.len() * super::NODE_FIELDS.len());
+
+            // Proxies with throwing traps are recorded without their references
+            script::eval(guard, "var trap = () => { throw new Error(); };
+                var proxy = new Proxy({}, { ownKeys: trap, getPrototypeOf: trap, getOwnPropertyDescriptor: trap })")
+                .unwrap();
+            assert!(HeapSnapshot::take(guard).is_ok());
+        });
+    }
+}
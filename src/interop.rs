@@ -0,0 +1,198 @@
+//! Interoperability with numeric host data.
+//!
+//! A `Matrix` is represented in JavaScript as a `Float64Array` of its
+//! elements in row-major order, with `rows` and `cols` properties describing
+//! its shape. Matrices can also be read from nested arrays.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::interop::Matrix;
+//! # use js::value::{FromJs, ToJs};
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let matrix = Matrix::from_rows(&[vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
+//! let scale = js::script::eval(&guard, "
+//!     (function (m) {
+//!         var result = m.map(x => x * 10);
+//!         result.rows = m.rows;
+//!         result.cols = m.cols;
+//!         return result;
+//!     })").unwrap().into_function().unwrap();
+//!
+//! let result = scale.call(&guard, &[&matrix.to_js(&guard)]).unwrap();
+//! let result = Matrix::from_js(&guard, &result).unwrap();
+//! assert_eq!(result.to_rows(), [[10.0, 20.0], [30.0, 40.0]]);
+//! ```
+use std::ptr;
+use std::slice;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use value::{self, FromJs, ToJs, Value};
+use Property;
+
+/// A dense matrix of doubles, stored in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    /// Creates a matrix from its elements in row-major order.
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Result<Matrix> {
+        if rows.checked_mul(cols) != Some(data.len()) {
+            return Err(format!("{} elements do not fit a {}x{} matrix", data.len(), rows, cols).into());
+        }
+        Ok(Matrix { rows: rows, cols: cols, data: data })
+    }
+
+    /// Creates a matrix from its rows, which must have the same length.
+    pub fn from_rows(rows: &[Vec<f64>]) -> Result<Matrix> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err("matrix rows have different lengths".into());
+        }
+        Self::new(rows.len(), cols, rows.concat())
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns an element, unless it is out of bounds.
+    pub fn get(&self, row: usize, col: usize) -> Option<f64> {
+        if row < self.rows && col < self.cols { Some(self.data[row * self.cols + col]) } else { None }
+    }
+
+    /// Returns the elements in row-major order.
+    pub fn as_slice(&self) -> &[f64] {
+        &self.data
+    }
+
+    /// Returns the rows of the matrix.
+    pub fn to_rows(&self) -> Vec<Vec<f64>> {
+        if self.cols == 0 {
+            return vec![Vec::new(); self.rows];
+        }
+        self.data.chunks(self.cols).map(|row| row.to_vec()).collect()
+    }
+
+    /// Returns the elements in row-major order.
+    pub fn into_vec(self) -> Vec<f64> {
+        self.data
+    }
+
+    /// Converts the matrix to an `ndarray` array.
+    #[cfg(feature = "ndarray")]
+    pub fn into_ndarray(self) -> ::ndarray::Array2<f64> {
+        ::ndarray::Array2::from_shape_vec((self.rows, self.cols), self.data).expect("converting matrix to array")
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl From<::ndarray::Array2<f64>> for Matrix {
+    fn from(array: ::ndarray::Array2<f64>) -> Self {
+        let (rows, cols) = array.dim();
+        Matrix { rows: rows, cols: cols, data: array.iter().cloned().collect() }
+    }
+}
+
+impl ToJs for Matrix {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        let buffer = value::ArrayBuffer::with_data(guard, self.data.clone());
+        let array = unsafe {
+            let mut array = JsValueRef::new();
+            jsassert!(JsCreateTypedArray(JsTypedArrayType::Float64, buffer.as_raw(), 0, self.data.len() as _, &mut array));
+            value::Object::from_raw(array)
+        };
+
+        array.set(guard, &Property::new(guard, "rows"), &(self.rows as u32).to_js(guard));
+        array.set(guard, &Property::new(guard, "cols"), &(self.cols as u32).to_js(guard));
+        array.into()
+    }
+}
+
+impl FromJs for Matrix {
+    /// Reads a typed array with `rows` and `cols` properties (a typed array
+    /// without them is read as a single row), or an array of rows.
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        if value.get_type() != JsValueType::TypedArray {
+            return Self::from_rows(&Vec::<Vec<f64>>::from_js(guard, value)?);
+        }
+
+        let data = read_typed_array(guard, value);
+        let object = value.clone().into_object().expect("converting typed array to object");
+        let dimension = |name: &str| -> Result<Option<usize>> {
+            match Option::<f64>::from_js(guard, &object.get(guard, &Property::new(guard, name)))? {
+                Some(size) if size >= 0.0 && size.fract() == 0.0 => Ok(Some(size as usize)),
+                Some(size) => Err(format!("invalid matrix {}: {}", name, size).into()),
+                None => Ok(None),
+            }
+        };
+
+        match (dimension("rows")?, dimension("cols")?) {
+            (Some(rows), Some(cols)) => Self::new(rows, cols, data),
+            (Some(rows), None) if rows > 0 => Self::new(rows, data.len() / rows, data),
+            (None, Some(cols)) if cols > 0 => Self::new(data.len() / cols, cols, data),
+            _ => Self::new(if data.is_empty() { 0 } else { 1 }, data.len(), data),
+        }
+    }
+}
+
+/// Reads the elements of a typed array as doubles.
+fn read_typed_array(guard: &ContextGuard, value: &Value) -> Vec<f64> {
+    let mut data = ptr::null_mut();
+    let mut size = 0;
+    let mut kind = JsTypedArrayType::Float64;
+    let mut element_size = 0;
+    unsafe {
+        jsassert!(JsGetTypedArrayStorage(value.as_raw(), &mut data, &mut size, &mut kind, &mut element_size));
+        if kind == JsTypedArrayType::Float64 {
+            let bytes = slice::from_raw_parts(data, size as usize);
+            return bytes.chunks(8).map(|chunk| {
+                let mut bits = [0; 8];
+                bits.copy_from_slice(chunk);
+                f64::from_bits(u64::from_ne_bytes(bits))
+            }).collect();
+        }
+    }
+
+    // Other element types are converted by the engine
+    let object = value.clone().into_object().expect("converting typed array to object");
+    let length = size as u32 / element_size.max(1) as u32;
+    (0..length).map(|index| object.get_index(guard, index).to_double(guard)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use value::{FromJs, ToJs};
+    use super::Matrix;
+
+    #[test]
+    fn matrix() {
+        test::run_with_context(|guard| {
+            let matrix = Matrix::new(2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+            guard.global().set(guard, &::Property::new(guard, "m"), &matrix.to_js(guard));
+            let shape = script::eval(guard, "[m instanceof Float64Array, m.rows, m.cols, m[5]]").unwrap();
+            assert_eq!(shape.to_json(guard).unwrap(), "[true,2,3,6]");
+
+            let result = script::eval(guard, "var t = new Int32Array([1, 2, 3, 4]); t.cols = 2; t").unwrap();
+            assert_eq!(Matrix::from_js(guard, &result).unwrap().to_rows(), [[1.0, 2.0], [3.0, 4.0]]);
+
+            let nested = script::eval(guard, "[[1, 2], [3, 4]]").unwrap();
+            assert_eq!(Matrix::from_js(guard, &nested).unwrap().get(1, 0), Some(3.0));
+            assert!(Matrix::from_js(guard, &script::eval(guard, "[[1], [2, 3]]").unwrap()).is_err());
+            assert!(Matrix::new(2, 2, vec![1.0]).is_err());
+        });
+    }
+}
@@ -29,19 +29,65 @@ extern crate anymap;
 extern crate boolinator;
 extern crate chakracore_sys;
 extern crate libc;
+#[cfg(feature = "derive")]
+extern crate chakracore_derive;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log as log_lib;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde as serde_lib;
+#[cfg(feature = "url")]
+extern crate url as url_lib;
 
 pub use context::Context;
 pub use runtime::Runtime;
 pub use property::Property;
+#[cfg(feature = "derive")]
+pub use chakracore_derive::{js_class, js_function};
 
 #[macro_use]
 mod macros;
 mod property;
+mod snapshot;
 mod util;
 pub mod runtime;
+pub mod accounting;
+pub mod audit;
+pub mod bench;
+pub mod class;
+pub mod console;
 pub mod context;
+pub mod crypto;
+pub mod deadline;
+pub mod debug;
+pub mod dry_run;
 pub mod error;
+pub mod event_loop;
+pub mod health;
+pub mod heap;
+pub mod interop;
+pub mod loader;
+pub mod policy;
+pub mod profile;
+pub mod pool;
+pub mod random;
 pub mod script;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod source_map;
+pub mod stream;
+pub mod template;
+pub mod timers;
+pub mod url;
+pub mod web;
+#[macro_use]
+pub mod testing;
+#[macro_use]
 pub mod value;
 
 #[cfg(test)]
@@ -61,7 +107,7 @@ mod test {
 }
 
 #[cfg(all(feature = "unstable", test))]
-mod bench {
+mod benches {
     extern crate test;
     use self::test::Bencher;
     use super::*;
@@ -84,4 +130,50 @@ mod bench {
             (0..10000).fold(0, |acc, _| acc + object.get(&guard, &Property::new(&guard, "test")).to_integer(&guard));
         });
     }
+
+    #[bench]
+    fn property_cache_bench(bench: &mut Bencher) {
+        let (_runtime, context) = setup_env();
+
+        let guard = context.make_current().unwrap();
+        let object = value::Object::new(&guard);
+        object.set(&guard, &guard.property("test"), &value::Number::new(&guard, 10));
+
+        bench.iter(|| {
+            (0..10000).fold(0, |acc, _| acc + object.get(&guard, &guard.property("test")).to_integer(&guard));
+        });
+    }
+
+    #[bench]
+    fn object_set_bench(bench: &mut Bencher) {
+        let (_runtime, context) = setup_env();
+        let guard = context.make_current().unwrap();
+
+        bench.iter(|| {
+            let array = value::Array::new(&guard, 1000);
+            for index in 0..1000 {
+                let object = value::Object::new(&guard);
+                object.set(&guard, &Property::new(&guard, "id"), &value::Number::new(&guard, index));
+                object.set(&guard, &Property::new(&guard, "name"), &value::String::new(&guard, "name"));
+                array.set_index(&guard, index as u32, &object);
+            }
+            array
+        });
+    }
+
+    #[bench]
+    fn object_builder_bench(bench: &mut Bencher) {
+        let (_runtime, context) = setup_env();
+        let guard = context.make_current().unwrap();
+
+        bench.iter(|| {
+            let mut array = value::ArrayBuilder::with_capacity(1000);
+            for index in 0..1000 {
+                let mut object = value::ObjectBuilder::with_capacity(2);
+                object.set("id", index).set("name", "name");
+                array.push(object);
+            }
+            array.build(&guard)
+        });
+    }
 }
@@ -0,0 +1,773 @@
+//! ES module loading and evaluation.
+//!
+//! Modules are resolved and loaded using a user supplied `ModuleLoader`. The
+//! module graph is discovered breadth first; whenever a module has been
+//! parsed, all of its (not yet seen) dependencies are announced to the loader
+//! through `prefetch`, before any of their sources are requested. This allows
+//! a host to fetch every module of a level concurrently, instead of serially
+//! on demand.
+//!
+//! A loader may also provide a set of `SourceTransformer`s, which are applied
+//! to each module's source before it is parsed. Any source maps emitted are
+//! registered in the context's [source map registry](../source_map/index.html).
+//!
+//! Static imports may specify import attributes (e.g `with { type: "json" }`,
+//! or the older `assert` keyword). These are passed to the loader through
+//! `load_with_attributes`. Modules imported with the `json` type are parsed as
+//! JSON, and exposed as a module with the value as its default export.
+//!
+//! The loader is only borrowed while a graph is evaluated, so dynamic
+//! `import()` expressions requesting modules outside of the graph (or from
+//! classic scripts) are rejected.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::loader::ModuleLoader;
+//! # use js::error::*;
+//! struct Loader;
+//!
+//! impl ModuleLoader for Loader {
+//!     fn load(&self, name: &str) -> Result<String> {
+//!         match name {
+//!             "main.js" => Ok("import { foo } from 'foo.js'; foo;".into()),
+//!             "foo.js" => Ok("export const foo = 1337;".into()),
+//!             _ => Err(format!("module '{}' not found", name).into()),
+//!         }
+//!     }
+//! }
+//!
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! js::loader::evaluate(&guard, &Loader, "main.js").unwrap();
+//! ```
+use std::collections::HashMap;
+use std::{mem, ptr};
+use libc::c_void;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use util::jstry;
+use {policy, source_map, value};
+
+/// The attributes of an import declaration (e.g `type: "json"`).
+pub type ImportAttributes = HashMap<String, String>;
+
+/// A host implementation for resolving and loading modules.
+pub trait ModuleLoader {
+    /// Resolves a module specifier to a normalized module name.
+    ///
+    /// The `referrer` is the normalized name of the importing module. The
+    /// default implementation uses the specifier as is.
+    fn resolve(&self, specifier: &str, _referrer: &str) -> Result<String> {
+        Ok(specifier.to_string())
+    }
+
+    /// Returns the source code of a module, identified by its normalized name.
+    fn load(&self, name: &str) -> Result<String>;
+
+    /// Returns the source code of a module, imported with attributes.
+    ///
+    /// Modules without any import attributes (such as the root module) are
+    /// also loaded through this method. The default implementation ignores
+    /// the attributes and uses `load`.
+    fn load_with_attributes(&self, name: &str, _attributes: &ImportAttributes) -> Result<String> {
+        self.load(name)
+    }
+
+    /// Announces modules that are about to be loaded.
+    ///
+    /// This is called with the normalized names of all dependencies
+    /// discovered while parsing a level of the module graph, before any of
+    /// their sources are requested with `load`. The default implementation
+    /// does nothing.
+    fn prefetch(&self, _names: &[String]) { }
+
+    /// Returns the transformers applied to each module's source.
+    ///
+    /// The transformers are applied in order, before the source is parsed.
+    fn transformers(&self) -> &[Box<SourceTransformer>] {
+        &[]
+    }
+}
+
+/// The output of a source transformation.
+#[derive(Clone, Debug)]
+pub struct Transformed {
+    /// The transformed source code.
+    pub source: String,
+    /// An optional source map, mapping the output to its input.
+    pub source_map: Option<String>,
+}
+
+/// A transformation of source code (e.g stripping types or instrumentation).
+pub trait SourceTransformer {
+    /// Transforms the source of a module, identified by its normalized name.
+    fn transform(&self, name: &str, source: String) -> Result<Transformed>;
+}
+
+/// A module loader serving modules from memory.
+///
+/// The modules are keyed by their path (e.g `lib/foo.js`). Relative
+/// specifiers (those starting with `./` or `../`) are resolved against the
+/// importing module's directory, any other specifier is used as is.
+///
+/// A package can be created from a tar archive, which is useful for bundling
+/// the scripts of a host within its binary:
+///
+/// ```rust,ignore
+/// let package = MemoryPackage::from_tar(include_bytes!("scripts.tar"))?;
+/// js::loader::evaluate(&guard, &package, "main.js")?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryPackage {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryPackage {
+    /// Creates an empty package.
+    pub fn new() -> Self {
+        MemoryPackage::default()
+    }
+
+    /// Adds a module to the package, returning any previous source.
+    pub fn insert(&mut self, path: &str, source: String) -> Option<String> {
+        self.modules.insert(normalize_path(path), source)
+    }
+
+    /// Returns the source of a module.
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.modules.get(&normalize_path(path)).map(|source| source.as_str())
+    }
+
+    /// Creates a package from an uncompressed tar archive.
+    ///
+    /// Only regular files are included, and they must contain UTF-8 text.
+    pub fn from_tar(archive: &[u8]) -> Result<Self> {
+        const BLOCK: usize = 512;
+
+        let mut package = MemoryPackage::new();
+        let mut offset = 0;
+        while offset + BLOCK <= archive.len() {
+            let header = &archive[offset..offset + BLOCK];
+            if header.iter().all(|&byte| byte == 0) {
+                break;
+            }
+
+            let size = parse_octal(&header[124..136])
+                .ok_or_else(|| Error::from("invalid tar entry size"))?;
+            let start = offset + BLOCK;
+            let end = start.checked_add(size)
+                .filter(|&end| end <= archive.len())
+                .ok_or_else(|| Error::from("truncated tar archive"))?;
+
+            // Regular files have either no type, or type '0'
+            if header[156] == 0 || header[156] == b'0' {
+                let mut path = tar_string(&header[0..100]);
+                if &header[257..262] == b"ustar" {
+                    let prefix = tar_string(&header[345..500]);
+                    if !prefix.is_empty() {
+                        path = format!("{}/{}", prefix, path);
+                    }
+                }
+
+                let source = String::from_utf8(archive[start..end].to_vec())
+                    .map_err(|_| format!("tar entry '{}' is not valid UTF-8", path))?;
+                package.insert(&path, source);
+            }
+
+            offset = start + (size + BLOCK - 1) / BLOCK * BLOCK;
+        }
+        Ok(package)
+    }
+}
+
+impl ModuleLoader for MemoryPackage {
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String> {
+        if specifier.starts_with("./") || specifier.starts_with("../") {
+            let directory = referrer.rfind('/').map_or("", |index| &referrer[..index]);
+            Ok(normalize_path(&format!("{}/{}", directory, specifier)))
+        } else {
+            Ok(normalize_path(specifier))
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<String> {
+        self.get(name)
+            .map(|source| source.to_string())
+            .ok_or_else(|| format!("module '{}' not found in package", name).into())
+    }
+}
+
+/// Normalizes a path, removing any `.` and `..` components.
+fn normalize_path(path: &str) -> String {
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => { components.pop(); },
+            component => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+/// Parses a NUL (or space) terminated octal number of a tar header.
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let digits = tar_string(field);
+    usize::from_str_radix(digits.trim(), 8).ok()
+}
+
+/// Parses a NUL terminated string of a tar header.
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Loads a module and its dependencies, and evaluates it.
+///
+/// The name is passed as is to the loader (i.e it is not resolved).
+pub fn evaluate(guard: &ContextGuard, loader: &ModuleLoader, name: &str) -> Result<value::Value> {
+    let mut graph = Graph {
+        loader: loader,
+        records: HashMap::new(),
+        entries: Vec::new(),
+        pending: Vec::new(),
+        attributes: HashMap::new(),
+        sources: Vec::new(),
+        error: None,
+        exception: None,
+        ready: false,
+        loaded: false,
+    };
+
+    let root = graph.initialize(guard, JsModuleRecord::new(), name)?;
+    let source = graph.load(guard, name, &ImportAttributes::new())?;
+    graph.parse(guard, root, name, source)?;
+
+    loop {
+        let level = mem::replace(&mut graph.pending, Vec::new());
+        if level.is_empty() {
+            break;
+        }
+
+        let names = level.iter().map(|&(ref name, _, _)| name.clone()).collect::<Vec<_>>();
+        loader.prefetch(&names);
+
+        for (name, record, attributes) in level {
+            let source = graph.load(guard, &name, &attributes)?;
+            graph.parse(guard, record, &name, source)?;
+        }
+    }
+    graph.loaded = true;
+
+    if let Some(exception) = graph.exception.take() {
+        return Err(ErrorKind::ScriptCompile(ScriptError::new(guard, &exception)).into());
+    }
+
+    if !graph.ready {
+        return Err(format!("module graph of '{}' could not be instantiated", name).into());
+    }
+
+    policy::counted(guard, || unsafe {
+        let mut result = JsValueRef::new();
+        jstry(JsModuleEvaluation(root, &mut result)).map(|_| value::Value::from_raw(result))
+    })
+}
+
+/// Information associated with each module record.
+struct Entry {
+    graph: *mut Graph<'static>,
+    name: String,
+}
+
+/// The state of a module graph being loaded.
+struct Graph<'a> {
+    loader: &'a ModuleLoader,
+    records: HashMap<String, JsModuleRecord>,
+    entries: Vec<Box<Entry>>,
+    pending: Vec<(String, JsModuleRecord, ImportAttributes)>,
+    /// The import attributes of each module, keyed by specifier.
+    attributes: HashMap<String, HashMap<String, ImportAttributes>>,
+    sources: Vec<String>,
+    error: Option<Error>,
+    exception: Option<value::Value>,
+    ready: bool,
+    /// Whether the graph has been loaded, after which no more modules can be
+    /// fetched (e.g by dynamic imports).
+    loaded: bool,
+}
+
+impl<'a> Graph<'a> {
+    /// Creates a new module record and registers the host callbacks.
+    fn initialize(&mut self,
+                  guard: &ContextGuard,
+                  referrer: JsModuleRecord,
+                  name: &str) -> Result<JsModuleRecord> {
+        let specifier = value::String::new(guard, name);
+        let mut entry = Box::new(Entry {
+            graph: self as *mut Graph as *mut _,
+            name: name.to_string(),
+        });
+
+        let mut record = JsModuleRecord::new();
+        unsafe {
+            jstry!(JsInitializeModuleRecord(referrer, specifier.as_raw(), &mut record));
+            jstry!(JsSetModuleHostInfo(record,
+                                       JsModuleHostInfoKind::HostDefined,
+                                       &mut *entry as *mut Entry as *mut c_void));
+            jstry!(JsSetModuleHostInfo(record,
+                                       JsModuleHostInfoKind::FetchImportedModuleCallback,
+                                       fetch as *mut c_void));
+            jstry!(JsSetModuleHostInfo(record,
+                                       JsModuleHostInfoKind::FetchImportedModuleFromScriptCallback,
+                                       fetch_from_script as *mut c_void));
+            jstry!(JsSetModuleHostInfo(record,
+                                       JsModuleHostInfoKind::NotifyModuleReadyCallback,
+                                       ready as *mut c_void));
+        }
+
+        self.entries.push(entry);
+        self.records.insert(name.to_string(), record);
+        Ok(record)
+    }
+
+    /// Loads and transforms the source of a module.
+    fn load(&self,
+            guard: &ContextGuard,
+            name: &str,
+            attributes: &ImportAttributes) -> Result<String> {
+        let source = self.loader.load_with_attributes(name, attributes)?;
+        match attributes.get("type").map(|kind| kind.as_str()) {
+            None => (),
+            Some("json") => return json_module(guard, name, &source),
+            Some(kind) => return Err(format!("module '{}' has unsupported type '{}'", name, kind).into()),
+        }
+
+        // Maps of a previous evaluation of the module are replaced
+        source_map::remove(&guard.context(), name);
+        self.loader.transformers().iter().fold(Ok(source), |source, transformer| {
+            source.and_then(|source| transformer.transform(name, source)).map(|output| {
+                if let Some(map) = output.source_map {
+                    source_map::register(&guard.context(), name, map);
+                }
+                output.source
+            })
+        })
+    }
+
+    /// Parses the source of a module record.
+    fn parse(&mut self,
+             guard: &ContextGuard,
+             record: JsModuleRecord,
+             name: &str,
+             source: String) -> Result<()> {
+        let (source, attributes) = strip_attributes(&source)?;
+        self.attributes.insert(name.to_string(), attributes);
+        policy::consulted(guard, name, &source)?;
+
+        let context = self.sources.len() as JsSourceContext;
+        let mut exception = JsValueRef::new();
+        let code = unsafe {
+            JsParseModuleSource(record,
+                                context,
+                                source.as_ptr() as *mut _,
+                                source.len() as _,
+                                JsParseModuleSourceFlags::DataIsUTF8,
+                                &mut exception)
+        };
+
+        // The engine may refer to the source until the graph is evaluated
+        self.sources.push(source);
+
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        if code == JsErrorCode::NoError {
+            Ok(())
+        } else if exception.0.is_null() {
+            jstry(code)
+        } else {
+            let exception = unsafe { value::Value::from_raw(exception) };
+            Err(ErrorKind::ScriptCompile(ScriptError::new(guard, &exception)).into())
+        }
+    }
+
+    /// Resolves, and if required creates, a dependency of a module.
+    fn fetch(&mut self,
+             guard: &ContextGuard,
+             referrer: JsModuleRecord,
+             referrer_name: &str,
+             specifier: &str) -> Result<JsModuleRecord> {
+        let name = self.loader.resolve(specifier, referrer_name)?;
+        if let Some(record) = self.records.get(&name) {
+            return Ok(*record);
+        }
+
+        if self.loaded {
+            return Err(format!("module '{}' is not part of the module graph", name).into());
+        }
+
+        let attributes = self.attributes.get(referrer_name)
+            .and_then(|attributes| attributes.get(specifier))
+            .cloned()
+            .unwrap_or_default();
+        let record = self.initialize(guard, referrer, &name)?;
+        self.pending.push((name, record, attributes));
+        Ok(record)
+    }
+}
+
+impl<'a> Drop for Graph<'a> {
+    /// Detaches the graph from its module records, since the engine keeps
+    /// them (and their callbacks) alive for the rest of the context's life.
+    fn drop(&mut self) {
+        for record in self.records.values() {
+            unsafe { JsSetModuleHostInfo(*record, JsModuleHostInfoKind::HostDefined, ptr::null_mut()) };
+        }
+    }
+}
+
+/// Converts a JSON source to a module, with the value as its default export.
+fn json_module(guard: &ContextGuard, name: &str, source: &str) -> Result<String> {
+    let value = guard.json_parse(source)
+        .map_err(|error| format!("module '{}' is not valid JSON: {}", name, error))?;
+
+    // Line terminators are valid in JSON strings, but not in script literals
+    let json = guard.json_stringify(&value)?.replace('\u{2028}', "\\u2028").replace('\u{2029}', "\\u2029");
+    Ok(format!("export default {};", json))
+}
+
+/// Removes the import attributes of a module's static imports.
+///
+/// The engine does not support the attributes syntax, so each clause is
+/// replaced with whitespace (preserving source positions), and the
+/// attributes are returned keyed by their specifier.
+fn strip_attributes(source: &str) -> Result<(String, HashMap<String, ImportAttributes>)> {
+    let bytes = source.as_bytes();
+    let mut output = bytes.to_vec();
+    let mut attributes = HashMap::new();
+
+    let mut index = 0;
+    while index < bytes.len() {
+        match (bytes[index], bytes.get(index + 1)) {
+            (b'/', Some(&b'/')) => {
+                index = source[index..].find('\n').map_or(bytes.len(), |end| index + end);
+            },
+            (b'/', Some(&b'*')) => {
+                index = source[index + 2..].find("*/").map_or(bytes.len(), |end| index + end + 4);
+            },
+            (quote @ b'\'', _) | (quote @ b'"', _) | (quote @ b'`', _) => {
+                let end = string_end(bytes, index, quote);
+                if quote != b'`' && follows_import(&source[..index]) {
+                    if let Some((length, clause)) = parse_attributes(&source[end..])? {
+                        for byte in &mut output[end..end + length] {
+                            if *byte != b'\n' {
+                                *byte = b' ';
+                            }
+                        }
+                        attributes.insert(source[index + 1..end - 1].to_string(), clause);
+                    }
+                }
+                index = end;
+            },
+            _ => index += 1,
+        }
+    }
+
+    let output = String::from_utf8(output).expect("stripping import attributes");
+    Ok((output, attributes))
+}
+
+/// Returns the index after the end of a string literal.
+fn string_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            byte if byte == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Returns whether a string literal is the specifier of a static import.
+fn follows_import(prefix: &str) -> bool {
+    let prefix = prefix.trim_end();
+    ["from", "import"].iter().any(|keyword| {
+        prefix.ends_with(keyword) && prefix[..prefix.len() - keyword.len()]
+            .chars()
+            .next_back()
+            .map_or(true, |c| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+    })
+}
+
+/// Parses an attributes clause, returning its length and contents.
+fn parse_attributes(source: &str) -> Result<Option<(usize, ImportAttributes)>> {
+    let rest = source.trim_start();
+    let body = match ["with", "assert"].iter().find(|keyword| rest.starts_with(*keyword)) {
+        Some(keyword) => rest[keyword.len()..].trim_start(),
+        None => return Ok(None),
+    };
+
+    if !body.starts_with('{') {
+        return Ok(None);
+    }
+
+    let close = body.find('}').ok_or_else(|| Error::from("unterminated import attributes"))?;
+    let mut attributes = ImportAttributes::new();
+    for entry in body[1..close].split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let colon = entry.find(':')
+            .ok_or_else(|| format!("invalid import attribute '{}'", entry))?;
+        let key = entry[..colon].trim();
+        let value = unquote(entry[colon + 1..].trim())
+            .ok_or_else(|| format!("import attribute '{}' must be a string", key))?;
+        attributes.insert(unquote(key).unwrap_or(key).to_string(), value.to_string());
+    }
+    Ok(Some((source.len() - body.len() + close + 1, attributes)))
+}
+
+/// Removes the quotes of a string literal.
+fn unquote(literal: &str) -> Option<&str> {
+    let quoted = literal.len() >= 2 &&
+        ((literal.starts_with('"') && literal.ends_with('"')) ||
+         (literal.starts_with('\'') && literal.ends_with('\'')));
+    if quoted { Some(&literal[1..literal.len() - 1]) } else { None }
+}
+
+/// Returns the entry associated with a module record, unless its graph has
+/// already been evaluated.
+unsafe fn get_entry<'a>(record: JsModuleRecord) -> Option<&'a Entry> {
+    let mut data = ptr::null_mut();
+    if JsGetModuleHostInfo(record, JsModuleHostInfoKind::HostDefined, &mut data) != JsErrorCode::NoError {
+        return None;
+    }
+    (data as *const Entry).as_ref()
+}
+
+/// A fetch callback, triggered for each import of a parsed module.
+unsafe extern "system" fn fetch(referrer: JsModuleRecord,
+                                specifier: JsValueRef,
+                                record: *mut JsModuleRecord) -> JsErrorCode {
+    let entry = match get_entry(referrer) {
+        Some(entry) => entry,
+        None => return JsErrorCode::InvalidArgument,
+    };
+    let graph = &mut *entry.graph;
+
+    ::Context::exec_with_current(|guard| {
+        let specifier = value::Value::from_raw(specifier).to_string(guard);
+        match graph.fetch(guard, referrer, &entry.name, &specifier) {
+            Ok(dependency) => {
+                *record = dependency;
+                JsErrorCode::NoError
+            },
+            Err(error) => {
+                graph.error = Some(error);
+                JsErrorCode::InvalidArgument
+            },
+        }
+    }).unwrap_or(JsErrorCode::NoCurrentContext)
+}
+
+/// A fetch callback, triggered for dynamic imports of classic scripts, which
+/// are not supported.
+unsafe extern "system" fn fetch_from_script(_referrer: JsSourceContext,
+                                            _specifier: JsValueRef,
+                                            _record: *mut JsModuleRecord) -> JsErrorCode {
+    JsErrorCode::InvalidArgument
+}
+
+/// A ready callback, triggered once a module's dependencies are parsed.
+unsafe extern "system" fn ready(record: JsModuleRecord, exception: JsValueRef) -> JsErrorCode {
+    let graph = match get_entry(record) {
+        Some(entry) => &mut *entry.graph,
+        None => return JsErrorCode::NoError,
+    };
+    if exception.0.is_null() {
+        graph.ready = true;
+    } else {
+        let exception = value::Value::from_raw(exception);
+        if !exception.is_undefined() {
+            graph.exception = Some(exception);
+        } else {
+            graph.ready = true;
+        }
+    }
+    JsErrorCode::NoError
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use {test, script, source_map};
+    use error::*;
+    use super::{MemoryPackage, ModuleLoader, SourceTransformer, Transformed};
+
+    struct Loader {
+        prefetched: RefCell<Vec<Vec<String>>>,
+    }
+
+    impl ModuleLoader for Loader {
+        fn load(&self, name: &str) -> Result<String> {
+            match name {
+                "main.js" => Ok("import { foo } from 'foo.js';
+                                 import { bar } from 'bar.js';
+                                 result.value = foo + bar;".into()),
+                "foo.js" => Ok("import { bar } from 'bar.js'; export const foo = bar * 2;".into()),
+                "bar.js" => Ok("export const bar = 5;".into()),
+                _ => Err("module not found".into()),
+            }
+        }
+
+        fn prefetch(&self, names: &[String]) {
+            self.prefetched.borrow_mut().push(names.to_vec());
+        }
+    }
+
+    /// Creates a tar archive of regular files.
+    fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for &(name, contents) in files {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            let size = format!("{:011o}\0", contents.len());
+            header[124..136].copy_from_slice(size.as_bytes());
+            header[156] = b'0';
+
+            archive.extend_from_slice(&header);
+            archive.extend_from_slice(contents.as_bytes());
+            let padding = (512 - contents.len() % 512) % 512;
+            archive.extend(vec![0; padding]);
+        }
+        archive.extend(vec![0; 1024]);
+        archive
+    }
+
+    #[test]
+    fn memory_package() {
+        test::run_with_context(|guard| {
+            let archive = tar(&[
+                ("main.js", "import { foo } from './lib/foo.js'; result.value = foo;"),
+                ("lib/foo.js", "import { bar } from '../bar.js'; export const foo = bar + 1;"),
+                ("bar.js", "export const bar = 41;"),
+            ]);
+
+            let package = MemoryPackage::from_tar(&archive).unwrap();
+            assert_eq!(package.get("./lib/foo.js").map(|source| source.len()), Some(60));
+
+            script::eval(guard, "var result = {};").unwrap();
+            super::evaluate(guard, &package, "main.js").unwrap();
+            assert_eq!(script::eval(guard, "result.value").unwrap().to_integer(guard), 42);
+
+            assert!(MemoryPackage::from_tar(&archive[..520]).is_err());
+        });
+    }
+
+    #[test]
+    fn evaluate() {
+        test::run_with_context(|guard| {
+            script::eval(guard, "var result = {};").unwrap();
+            let loader = Loader { prefetched: RefCell::new(Vec::new()) };
+            super::evaluate(guard, &loader, "main.js").unwrap();
+
+            let result = script::eval(guard, "result.value").unwrap();
+            assert_eq!(result.to_integer(guard), 15);
+            assert_eq!(*loader.prefetched.borrow(), vec![vec!["foo.js", "bar.js"]]);
+        });
+    }
+
+    struct Rewrite;
+
+    impl SourceTransformer for Rewrite {
+        fn transform(&self, _name: &str, source: String) -> Result<Transformed> {
+            Ok(Transformed {
+                source: source.replace("bar = 5", "bar = 10"),
+                source_map: Some("{}".into()),
+            })
+        }
+    }
+
+    struct TransformingLoader {
+        inner: Loader,
+        transformers: Vec<Box<SourceTransformer>>,
+    }
+
+    impl ModuleLoader for TransformingLoader {
+        fn load(&self, name: &str) -> Result<String> {
+            self.inner.load(name)
+        }
+
+        fn transformers(&self) -> &[Box<SourceTransformer>] {
+            &self.transformers
+        }
+    }
+
+    #[test]
+    fn transformers() {
+        test::run_with_context(|guard| {
+            script::eval(guard, "var result = {};").unwrap();
+            let loader = TransformingLoader {
+                inner: Loader { prefetched: RefCell::new(Vec::new()) },
+                transformers: vec![Box::new(Rewrite)],
+            };
+            super::evaluate(guard, &loader, "main.js").unwrap();
+
+            let result = script::eval(guard, "result.value").unwrap();
+            assert_eq!(result.to_integer(guard), 30);
+            assert_eq!(source_map::get(&guard.context(), "bar.js").unwrap(), ["{}"]);
+        });
+    }
+
+    #[test]
+    fn import_attributes() {
+        test::run_with_context(|guard| {
+            let mut package = MemoryPackage::new();
+            package.insert("main.js", "
+                import data from './data.json' with { type: 'json' };
+                import { foo } from './foo.js' assert {};
+                result.value = [data.list.length, data.name, foo];".into());
+            package.insert("data.json", r#"{ "list": [1, 2, 3], "name": "data" }"#.into());
+            package.insert("foo.js", "export const foo = 'with { type: \"json\" }';".into());
+
+            script::eval(guard, "var result = {};").unwrap();
+            super::evaluate(guard, &package, "main.js").unwrap();
+            let result = script::eval(guard, "result.value").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"[3,"data","with { type: \"json\" }"]"#);
+
+            package.insert("main.js", "import data from './data.json' with { type: 'css' };".into());
+            assert!(super::evaluate(guard, &package, "main.js").is_err());
+        });
+    }
+
+    #[test]
+    fn missing_module() {
+        test::run_with_context(|guard| {
+            let loader = Loader { prefetched: RefCell::new(Vec::new()) };
+            assert!(super::evaluate(guard, &loader, "missing.js").is_err());
+        });
+    }
+
+    #[test]
+    fn dynamic_import() {
+        test::run_with_context(|guard| {
+            let mut package = MemoryPackage::new();
+            package.insert("main.js", "
+                export function later() { return import('./other.js'); }
+                result.later = later;".into());
+            package.insert("other.js", "export default 1;".into());
+
+            script::eval(guard, "var result = {};").unwrap();
+            super::evaluate(guard, &package, "main.js").unwrap();
+
+            // The graph has been released, so the imports must be rejected
+            script::eval(guard, "
+                result.later().catch(() => result.rejected = (result.rejected || 0) + 1);
+                import('./other.js').catch(() => result.rejected = (result.rejected || 0) + 1);").unwrap();
+            guard.execute_tasks();
+            assert_eq!(script::eval(guard, "result.rejected").unwrap().to_integer(guard), 2);
+        });
+    }
+}
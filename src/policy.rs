@@ -0,0 +1,270 @@
+//! Resource policies for untrusted scripts.
+//!
+//! A `Policy` is configured when a runtime is built, and applies to all of
+//! its contexts. It restricts the built-ins available to scripts, consults a
+//! callback before each script or module is compiled, and limits the number
+//! of statements executed each time the host enters script code. This complements other sandboxing
+//! measures (e.g `Context::new_sandboxed` and memory limits), as a defense in
+//! depth for running untrusted code.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::runtime::Policy;
+//! let policy = Policy::new()
+//!     .allow_globals(&["Array", "JSON", "Math", "Object"])
+//!     .max_statements(1000)
+//!     .eval_callback(Box::new(|_, url, _| {
+//!         if url.starts_with("plugin:") { Ok(()) } else { Err(format!("{} is not a plugin", url)) }
+//!     }));
+//!
+//! let runtime = js::Runtime::builder().policy(policy).build().unwrap();
+//! let context = js::Context::new(&runtime).unwrap();
+//! let guard = context.make_current().unwrap();
+//!
+//! let result = js::script::eval_with_name(&guard, "plugin:a", "typeof Date + ' ' + Math.max(1, 2)").unwrap();
+//! assert_eq!(result.to_string(&guard), "undefined 2");
+//! assert!(js::script::eval_with_name(&guard, "plugin:b", "while (true) {}").is_err());
+//! assert!(js::script::eval_with_name(&guard, "other", "1").is_err());
+//! ```
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use chakracore_sys::*;
+use context::{Context, ContextGuard};
+use debug::{DebugEvent, Debugger, DebugHandler, Paused, Step};
+use error::*;
+use util::jstry;
+use value;
+use Property;
+
+/// Callback type consulted before each top-level evaluation, receiving the
+/// script's URL and source. Returning an error rejects the script.
+pub type EvalCallback = Fn(&ContextGuard, &str, &str) -> ::std::result::Result<(), String> + Send + Sync;
+
+/// A policy restricting the scripts of a runtime.
+#[derive(Default)]
+pub struct Policy {
+    max_statements: Option<u64>,
+    allowed_globals: Option<Vec<String>>,
+    eval_callback: Option<Box<EvalCallback>>,
+}
+
+/// Marks a context whose statements are currently counted.
+struct Counting;
+
+/// A debug handler stepping through each statement, and interrupting the
+/// runtime once its budget is exhausted.
+struct StatementCounter {
+    remaining: u64,
+    runtime: JsRuntimeHandle,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl Policy {
+    /// Creates a policy without any restrictions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the number of statements executed by each top-level evaluation,
+    /// including the statements of functions it calls.
+    ///
+    /// The same limit applies separately to each function call made by the
+    /// host (e.g a parsed script, a timer or a promise reaction run by
+    /// `execute_tasks`) and to each module evaluation.
+    ///
+    /// Statements are counted by stepping through the script with a debugger,
+    /// which slows execution down considerably, and fails if the runtime is
+    /// already being debugged. Exceeding the limit interrupts the script, so
+    /// the runtime is built with `enable_script_interrupt`.
+    pub fn max_statements(mut self, limit: u64) -> Self {
+        self.max_statements = Some(limit);
+        self
+    }
+
+    /// Restricts the global bindings of new contexts to a list of names.
+    ///
+    /// All other built-ins are removed when a context is created, except the
+    /// ones which cannot be deleted (e.g `undefined` and `NaN`). Bindings
+    /// defined by the host afterwards (e.g using `timers::install`) are not
+    /// affected.
+    pub fn allow_globals(mut self, names: &[&str]) -> Self {
+        self.allowed_globals = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Sets a callback consulted before each top-level evaluation.
+    ///
+    /// The callback is also consulted when a script is parsed (without being
+    /// executed), and for each module of a module graph.
+    pub fn eval_callback(mut self, callback: Box<EvalCallback>) -> Self {
+        self.eval_callback = Some(callback);
+        self
+    }
+
+    /// Returns whether the policy counts statements.
+    pub(crate) fn counts_statements(&self) -> bool {
+        self.max_statements.is_some()
+    }
+
+    /// Removes the globals which are not allowed from the guard's context.
+    pub(crate) fn restrict_globals(&self, guard: &ContextGuard) {
+        if let Some(ref allowed) = self.allowed_globals {
+            let global = guard.global();
+            for name in ::snapshot::own_property_names(guard, &global) {
+                if !allowed.contains(&name) {
+                    global.delete(guard, &Property::new(guard, &name));
+                }
+            }
+        }
+    }
+}
+
+/// Executes a top-level script, enforcing the policy of its runtime.
+pub(crate) fn enforced<F>(guard: &ContextGuard, origin: &str, source: &str, execute: F) -> Result<value::Value>
+        where F: FnOnce() -> Result<value::Value> {
+    consulted(guard, origin, source)?;
+    counted(guard, execute)
+}
+
+/// Consults the policy's callback before a script is compiled.
+pub(crate) fn consulted(guard: &ContextGuard, origin: &str, source: &str) -> Result<()> {
+    let policy = match guard.policy() {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+
+    if let Some(ref callback) = policy.eval_callback {
+        callback(guard, origin, source).map_err(ErrorKind::PolicyViolation)?;
+    }
+    Ok(())
+}
+
+/// Executes script code entered from the host (e.g an evaluation, a function
+/// call or a task), counting its statements if the policy limits them.
+pub(crate) fn counted<T, F>(guard: &ContextGuard, execute: F) -> Result<T> where F: FnOnce() -> Result<T> {
+    let limit = match guard.policy().and_then(|policy| policy.max_statements) {
+        Some(limit) => limit,
+        None => return execute(),
+    };
+
+    // Code entered by host callbacks is counted by the outer evaluation
+    let context = guard.context();
+    if context.get_user_data::<Counting>().is_some() {
+        return execute();
+    }
+
+    let runtime = context.runtime_handle();
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let counter = StatementCounter { remaining: limit, runtime: runtime, exceeded: exceeded.clone() };
+    let debugger = Debugger::start(guard, Box::new(counter))?;
+    debugger.request_break()?;
+
+    context.insert_user_data(Counting);
+    let mut counting = CountingGuard { context: context, debugger: Some(debugger), exceeded: exceeded };
+    let result = execute();
+    let stopped = counting.stop(guard);
+
+    // A failure to stop the debugger is secondary to exceeding the limit
+    if counting.exceeded.swap(false, Ordering::SeqCst) {
+        jstry(unsafe { JsEnableRuntimeExecution(runtime) })?;
+        let message = format!("script exceeded the limit of {} statements", limit);
+        Err(ErrorKind::PolicyViolation(message).into())
+    } else {
+        stopped?;
+        result
+    }
+}
+
+/// Stops counting the statements of a context once dropped, even if the
+/// counted code panics.
+struct CountingGuard {
+    context: Context,
+    debugger: Option<Debugger>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl CountingGuard {
+    /// Removes the marker, and stops the debugger.
+    fn stop(&mut self, guard: &ContextGuard) -> Result<()> {
+        self.context.remove_user_data::<Counting>();
+        match self.debugger.take() {
+            Some(debugger) => debugger.stop(guard).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CountingGuard {
+    fn drop(&mut self) {
+        self.context.remove_user_data::<Counting>();
+        drop(self.debugger.take());
+        if self.exceeded.load(Ordering::SeqCst) {
+            unsafe { JsEnableRuntimeExecution(self.context.runtime_handle()) };
+        }
+    }
+}
+
+impl DebugHandler for StatementCounter {
+    fn on_event(&mut self, _paused: &Paused, event: DebugEvent) -> Step {
+        match event {
+            DebugEvent::SourceCompile | DebugEvent::CompileError => return Step::Continue,
+            _ => (),
+        }
+
+        if self.remaining == 0 {
+            if unsafe { JsDisableRuntimeExecution(self.runtime) } == JsErrorCode::NoError {
+                self.exceeded.store(true, Ordering::SeqCst);
+            }
+            return Step::Continue;
+        }
+
+        self.remaining -= 1;
+        Step::Into
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::ErrorKind;
+    use event_loop::EventLoop;
+    use {script, Context, Runtime};
+    use super::Policy;
+
+    #[test]
+    fn policy() {
+        let policy = Policy::new()
+            .allow_globals(&["Math"])
+            .max_statements(50)
+            .eval_callback(Box::new(|_, _, source| {
+                if source.contains("forbidden") { Err("forbidden".to_string()) } else { Ok(()) }
+            }));
+
+        let runtime = Runtime::builder().policy(policy).build().unwrap();
+        let context = Context::new(&runtime).unwrap();
+        context.exec_with(|guard| {
+            let result = script::eval(guard, "[typeof Math, typeof Object, typeof queueMicrotask, typeof undefined]").unwrap();
+            assert_eq!(result.to_string(guard), "object,undefined,undefined,undefined");
+            assert_eq!(script::eval(guard, "var total = 0; for (var i = 0; i < 5; i++) { total += i; } total")
+                .unwrap()
+                .to_integer(guard), 10);
+
+            let error = script::eval(guard, "for (;;) {}").unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::PolicyViolation(_));
+            assert!(!runtime.is_execution_disabled());
+
+            let error = script::eval(guard, "'forbidden'").unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::PolicyViolation(ref reason) if reason == "forbidden");
+            assert!(script::parse(guard, "'forbidden'").is_err());
+            assert_eq!(script::eval(guard, "1 + 1").unwrap().to_integer(guard), 2);
+
+            let function = script::parse(guard, "for (;;) {}").unwrap();
+            let error = function.call(guard, &[]).unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::PolicyViolation(_));
+
+            script::eval(guard, "Promise.resolve().then(() => { for (;;) {} })").unwrap();
+            let error = EventLoop::new(guard).turn().unwrap_err();
+            assert_matches!(error.kind(), &ErrorKind::PolicyViolation(_));
+            assert!(!runtime.is_execution_disabled());
+        }).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+//! A pool of runtimes on worker threads.
+//!
+//! A runtime can only be active on one thread at a time, so executing scripts
+//! concurrently requires several runtimes. A `Pool` manages a fixed number of
+//! worker threads, each owning a runtime, and distributes submitted jobs among
+//! them. Each job is executed in a new context, and its result is returned as
+//! an `ExportedValue`, since values cannot leave their runtime.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! let pool = js::runtime::Pool::new(2).unwrap();
+//! let jobs = (0..4).map(|index| pool.execute(&format!("{} * 2", index))).collect::<Vec<_>>();
+//! let results = jobs.into_iter().map(|job| job.wait().unwrap().as_f64().unwrap()).collect::<Vec<_>>();
+//! assert_eq!(results, [0.0, 2.0, 4.0, 6.0]);
+//! ```
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{self, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use error::*;
+use event_loop::{ErrorPolicy, EventLoop, Policy};
+use health::{AlertCallback, Heartbeat, Monitor};
+use runtime::InterruptHandle;
+use value::ExportedValue;
+use {script, Context, Runtime};
+
+/// How often idle workers signal their heartbeat.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Callback type for creating the runtime of a worker.
+pub type RuntimeFactory = Fn() -> Result<Runtime> + Send + Sync;
+
+/// A job, returning false if its script execution was interrupted.
+type Job = Box<FnOnce(&Runtime) -> bool + Send>;
+
+/// The queue of jobs, shared between the pool and its workers.
+struct Queue {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+/// The state shared between the pool and its workers.
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+    factory: Box<RuntimeFactory>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    /// Whether interrupted workers are replaced by a health monitor.
+    monitored: bool,
+}
+
+/// A builder for the pool type.
+pub struct Builder {
+    threads: usize,
+    factory: Option<Box<RuntimeFactory>>,
+    health_check: Option<(Duration, Option<Box<AlertCallback>>)>,
+}
+
+impl Builder {
+    /// Sets how the workers' runtimes are created.
+    ///
+    /// By default, runtimes are created with script interrupts enabled.
+    pub fn runtime(mut self, factory: Box<RuntimeFactory>) -> Self {
+        self.factory = Some(factory);
+        self
+    }
+
+    /// Replaces workers which are busy with a job for longer than a timeout.
+    ///
+    /// The wedged worker's runtime is interrupted (its job fails with a
+    /// `ScriptTerminated` error), and a new worker takes its place. This
+    /// requires runtimes with script interrupts enabled.
+    pub fn health_check(mut self, timeout: Duration, alert: Option<Box<AlertCallback>>) -> Self {
+        self.health_check = Some((timeout, alert));
+        self
+    }
+
+    /// Creates the pool, and starts its workers.
+    pub fn build(self) -> Result<Pool> {
+        if self.threads == 0 {
+            return Err("a pool requires at least one thread".into());
+        }
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { jobs: VecDeque::new(), closed: false }),
+            available: Condvar::new(),
+            factory: self.factory.unwrap_or_else(|| {
+                Box::new(|| Runtime::builder().enable_script_interrupt().build())
+            }),
+            threads: Mutex::new(Vec::new()),
+            monitored: self.health_check.is_some(),
+        });
+
+        let monitor = self.health_check.map(|(timeout, alert)| Monitor::new(timeout, alert));
+        let mut pool = Pool { shared: shared, monitor: None };
+        for index in 0..self.threads {
+            let (heartbeat, interrupt) = spawn_worker(&pool.shared)?;
+            if let Some(ref monitor) = monitor {
+                let shared = pool.shared.clone();
+                monitor.watch(&format!("worker-{}", index), heartbeat, interrupt, Box::new(move || {
+                    spawn_worker(&shared)
+                }));
+            }
+        }
+
+        pool.monitor = monitor;
+        Ok(pool)
+    }
+}
+
+/// A pool of runtimes, each on its own worker thread.
+///
+/// Once the pool is dropped, the workers finish the queued jobs and exit.
+pub struct Pool {
+    shared: Arc<Shared>,
+    monitor: Option<Monitor>,
+}
+
+impl Pool {
+    /// Creates a pool with a number of worker threads.
+    pub fn new(threads: usize) -> Result<Pool> {
+        Self::builder(threads).build()
+    }
+
+    /// Returns a pool builder.
+    pub fn builder(threads: usize) -> Builder {
+        Builder {
+            threads: threads,
+            factory: None,
+            health_check: None,
+        }
+    }
+
+    /// Evaluates code on a worker, and returns its eventual result.
+    ///
+    /// The promise queue is drained after evaluation, but the result is not
+    /// awaited if it is a promise. Values which cannot be exported (e.g
+    /// functions) result in an error.
+    pub fn execute(&self, code: &str) -> JobFuture {
+        let code = code.to_string();
+        self.submit(Box::new(move |runtime| {
+            let context = Context::new(runtime)?;
+            context.exec_with(|guard| {
+                let value = script::eval(guard, &code)?;
+                let policy = Policy { errors: ErrorPolicy::Report, ..Policy::default() };
+                EventLoop::with_policy(guard, policy).turn()?;
+                value.export(guard)
+            })?
+        }))
+    }
+
+    /// Queues a job, and returns a future of its result.
+    ///
+    /// A panicking job fails with an error, and its worker continues with the
+    /// next job.
+    fn submit(&self, job: Box<FnOnce(&Runtime) -> Result<ExportedValue> + Send>) -> JobFuture {
+        let completion = Arc::new(Completion::default());
+        let state = completion.clone();
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.jobs.push_back(Box::new(move |runtime: &Runtime| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| job(runtime)))
+                .unwrap_or_else(|_| Err("pool job panicked".into()));
+            let terminated = match result {
+                Err(Error(ErrorKind::ScriptTerminated, _)) => true,
+                _ => false,
+            };
+            state.complete(result);
+            !terminated
+        }));
+        self.shared.available.notify_one();
+        JobFuture(completion)
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // Replacements must not be spawned while the workers are exiting
+        drop(self.monitor.take());
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.available.notify_all();
+
+        let threads = ::std::mem::replace(&mut *self.shared.threads.lock().unwrap(), Vec::new());
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Spawns a worker, and returns its heartbeat and interrupt handle.
+fn spawn_worker(shared: &Arc<Shared>) -> Result<(Heartbeat, InterruptHandle)> {
+    let (sender, receiver) = mpsc::channel();
+    let worker = shared.clone();
+    let thread = thread::spawn(move || {
+        let runtime = match (worker.factory)() {
+            Ok(runtime) => runtime,
+            Err(error) => return sender.send(Err(error)).unwrap(),
+        };
+
+        let heartbeat = Heartbeat::new();
+        sender.send(Ok((heartbeat.clone(), runtime.interrupt_handle()))).unwrap();
+
+        loop {
+            let job = {
+                let mut queue = worker.queue.lock().unwrap();
+                loop {
+                    heartbeat.beat();
+                    if let Some(job) = queue.jobs.pop_front() {
+                        break Some(job);
+                    } else if queue.closed {
+                        break None;
+                    }
+                    queue = worker.available.wait_timeout(queue, HEARTBEAT_INTERVAL).unwrap().0;
+                }
+            };
+
+            match job {
+                Some(job) => if !job(&runtime) {
+                    // A health monitor has already replaced an interrupted
+                    // worker, so it exits (disposing its runtime)
+                    if worker.monitored {
+                        break;
+                    }
+
+                    // Otherwise the worker resumes, or is replaced inline
+                    if runtime.enable_execution().is_err() {
+                        let _ = spawn_worker(&worker);
+                        break;
+                    }
+                },
+                None => break,
+            }
+        }
+    });
+
+    let result = receiver.recv().chain_err(|| "starting pool worker")?;
+
+    // The threads of replaced workers are detached once they have exited
+    let mut threads = shared.threads.lock().unwrap();
+    threads.retain(|thread| !thread.is_finished());
+    threads.push(thread);
+    result
+}
+
+/// The eventual result of a job.
+#[derive(Default)]
+struct Completion {
+    state: Mutex<(Option<Result<ExportedValue>>, Option<Waker>)>,
+    done: Condvar,
+}
+
+impl Completion {
+    /// Stores the result, and wakes any waiting task.
+    fn complete(&self, result: Result<ExportedValue>) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = Some(result);
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+        self.done.notify_all();
+    }
+}
+
+/// A future resolving with the result of a job executed by a pool.
+pub struct JobFuture(Arc<Completion>);
+
+impl JobFuture {
+    /// Blocks the current thread until the job has finished.
+    pub fn wait(self) -> Result<ExportedValue> {
+        let mut state = self.0.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.0.take() {
+                return result;
+            }
+            state = self.0.done.wait(state).unwrap();
+        }
+    }
+}
+
+impl Future for JobFuture {
+    type Output = Result<ExportedValue>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut task::Context) -> Poll<Self::Output> {
+        let mut state = self.0.state.lock().unwrap();
+        match state.0.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.1 = Some(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use error;
+    use health::HealthEvent;
+    use value::ExportedValue;
+    use {script, Context};
+    use super::Pool;
+
+    #[test]
+    fn execute() {
+        let pool = Pool::new(3).unwrap();
+        let jobs = (0..6)
+            .map(|index| pool.execute(&format!("
+                var result = {{ index: {} }};
+                Promise.resolve().then(() => result.done = true);
+                result", index)))
+            .collect::<Vec<_>>();
+
+        for (index, job) in jobs.into_iter().enumerate() {
+            let result = job.wait().unwrap();
+            assert_eq!(result.get("index").and_then(|index| index.as_f64()), Some(index as f64));
+            assert_eq!(result.get("done").and_then(|done| done.as_bool()), Some(true));
+        }
+
+        assert!(pool.execute("(function () {})").wait().is_err());
+        assert!(Pool::new(0).is_err());
+
+        let pool = Pool::new(1).unwrap();
+        assert!(pool.submit(Box::new(|_| panic!("job panicked"))).wait().is_err());
+        assert_eq!(pool.execute("1 + 1").wait().unwrap().as_f64(), Some(2.0));
+    }
+
+    #[test]
+    fn terminated_without_monitor() {
+        let pool = Pool::new(1).unwrap();
+        let job = pool.submit(Box::new(|runtime| {
+            let context = Context::new(runtime)?;
+            runtime.interrupt_handle().interrupt()?;
+            context.exec_with(|guard| script::eval(guard, "1").map(|_| ExportedValue::Undefined))?
+        }));
+        assert_matches!(job.wait().unwrap_err().kind(), &error::ErrorKind::ScriptTerminated);
+        assert_eq!(pool.execute("1 + 1").wait().unwrap().as_f64(), Some(2.0));
+    }
+
+    #[test]
+    fn replace_wedged() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let alerts = events.clone();
+        let pool = Pool::builder(1)
+            .health_check(Duration::from_millis(200), Some(Box::new(move |event: &HealthEvent| {
+                alerts.lock().unwrap().push(event.clone());
+            })))
+            .build()
+            .unwrap();
+
+        let error = pool.execute("while (true) {}").wait().unwrap_err();
+        assert_matches!(error.kind(), &error::ErrorKind::ScriptTerminated);
+        assert_eq!(pool.execute("1 + 1").wait().unwrap().as_f64(), Some(2.0));
+
+        // Dropping the pool waits for the monitor to finish raising alerts
+        drop(pool);
+        assert_matches!(events.lock().unwrap()[1], HealthEvent::Replaced { .. });
+    }
+}
@@ -0,0 +1,351 @@
+//! CPU profiling, by sampling the stacks of running scripts.
+//!
+//! JSRT only provides a profiling API on Windows, so the profiler is built on
+//! the diagnostic APIs instead: a sampling thread requests the runtime to
+//! break at a regular interval, and each break records the script's stack.
+//! The samples are aggregated into a call tree, which can be exported in the
+//! Chrome CPU profile format (e.g for flame graphs or DevTools).
+//!
+//! Since the profiler is a debugger session, it cannot be used while the
+//! runtime is being debugged, and only scripts compiled after it has started
+//! are sampled. Durations are estimated from the number of samples.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use std::time::Duration;
+//! # use js::profile::Profiler;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let profiler = Profiler::start(&guard, Duration::from_millis(1)).unwrap();
+//! js::script::eval_with_name(&guard, "app.js", "
+//!     function spin(until) { while (Date.now() < until); }
+//!     spin(Date.now() + 50);").unwrap();
+//! let profile = profiler.stop(&guard).unwrap();
+//!
+//! assert!(profile.root.find("spin").map_or(false, |node| node.hit_count > 0));
+//! let json = profile.to_cpuprofile();
+//! assert!(json.contains(r#""functionName":"spin""#));
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use chakracore_sys::*;
+use context::ContextGuard;
+use debug::{DebugEvent, Debugger, DebugHandler, Paused, Step};
+use error::*;
+use stream::{JsonWriter, StreamSink};
+
+/// A running CPU profiler.
+pub struct Profiler {
+    debugger: Option<Debugger>,
+    samples: Arc<Mutex<Vec<Sample>>>,
+    running: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<()>>,
+    interval: Duration,
+    start: Instant,
+}
+
+/// A recorded CPU profile.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// The root of the call tree, named `(root)`.
+    pub root: ProfileNode,
+    /// The sampling interval.
+    pub interval: Duration,
+    /// The time between starting and stopping the profiler.
+    pub duration: Duration,
+    /// The node of each sample, and the time since the previous sample.
+    samples: Vec<(u32, Duration)>,
+}
+
+/// A function in a profile's call tree.
+///
+/// Each node represents a function called from a specific path of callers,
+/// so a function may be represented by several nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileNode {
+    /// The node's identifier, unique within its profile.
+    pub id: u32,
+    /// The name of the function.
+    pub function_name: String,
+    /// The URL (or file name) of the function's script, if any.
+    pub url: String,
+    /// The identifier of the function's script.
+    pub script_id: u32,
+    /// The first line (0-based) sampled within the function.
+    pub line: u32,
+    /// The number of samples in which the function was executing.
+    pub hit_count: u32,
+    /// The estimated time spent in the function itself.
+    pub self_time: Duration,
+    /// The estimated time spent in the function, and the functions it called.
+    pub total_time: Duration,
+    /// The functions called by the function.
+    pub children: Vec<ProfileNode>,
+}
+
+/// The stack of a sample, with the outermost frame first.
+struct Sample {
+    time: Instant,
+    frames: Vec<(u32, String, u32)>,
+}
+
+/// A debug handler recording the stack of each break.
+struct Sampler(Arc<Mutex<Vec<Sample>>>);
+
+impl Profiler {
+    /// Starts profiling the runtime of a context, sampling at an interval.
+    pub fn start(guard: &ContextGuard, interval: Duration) -> Result<Profiler> {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let debugger = Debugger::start(guard, Box::new(Sampler(samples.clone())))?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let runtime = guard.context().runtime_handle();
+        let flag = running.clone();
+        let sampler = thread::spawn(move || {
+            while flag.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                unsafe { JsDiagRequestAsyncBreak(runtime) };
+            }
+        });
+
+        Ok(Profiler {
+            debugger: Some(debugger),
+            samples: samples,
+            running: running,
+            sampler: Some(sampler),
+            interval: interval,
+            start: Instant::now(),
+        })
+    }
+
+    /// Stops profiling, and builds the profile of the recorded samples.
+    pub fn stop(mut self, guard: &ContextGuard) -> Result<Profile> {
+        let duration = self.start.elapsed();
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(sampler) = self.sampler.take() {
+            sampler.join().map_err(|_| "joining the sampling thread")?;
+        }
+
+        let debugger = self.debugger.take().expect("retrieving profiler's debugger");
+        let urls = debugger.scripts(guard)?
+            .into_iter()
+            .map(|script| (script.id, script.file_name.unwrap_or_default()))
+            .collect::<HashMap<_, _>>();
+
+        let samples = ::std::mem::replace(&mut *self.samples.lock().unwrap(), Vec::new());
+        let profile = Profile::build(self.start, self.interval, duration, &samples, &urls);
+        debugger.stop(guard)?;
+        Ok(profile)
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(sampler) = self.sampler.take() {
+            let _ = sampler.join();
+        }
+    }
+}
+
+impl Profile {
+    /// Aggregates samples into a call tree.
+    fn build(start: Instant,
+             interval: Duration,
+             duration: Duration,
+             samples: &[Sample],
+             urls: &HashMap<u32, String>) -> Profile {
+        let mut root = ProfileNode::new(1, "(root)", "", 0, 0);
+        let mut next_id = 2;
+        let mut previous = start;
+        let mut leaves = Vec::with_capacity(samples.len());
+
+        for sample in samples {
+            let mut node = &mut root;
+            for &(script_id, ref name, line) in &sample.frames {
+                let position = node.children.iter()
+                    .position(|child| child.script_id == script_id && &child.function_name == name);
+                let index = position.unwrap_or_else(|| {
+                    let url = urls.get(&script_id).map_or("", |url| url.as_str());
+                    node.children.push(ProfileNode::new(next_id, name, url, script_id, line));
+                    next_id += 1;
+                    node.children.len() - 1
+                });
+
+                node = &mut node.children[index];
+                node.line = node.line.min(line);
+            }
+
+            node.hit_count += 1;
+            leaves.push((node.id, sample.time.duration_since(previous)));
+            previous = sample.time;
+        }
+
+        root.compute_times(interval);
+        Profile { root: root, interval: interval, duration: duration, samples: leaves }
+    }
+
+    /// Returns the number of samples.
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Serializes the profile in the Chrome CPU profile (`.cpuprofile`)
+    /// format, as loaded by DevTools and flame graph tools.
+    pub fn to_cpuprofile(&self) -> String {
+        let mut writer = JsonWriter::new(Vec::new());
+        self.write_cpuprofile(&mut writer).expect("writing CPU profile");
+        String::from_utf8(writer.into_inner()).expect("converting CPU profile to string")
+    }
+
+    fn write_cpuprofile(&self, sink: &mut StreamSink) -> Result<()> {
+        let mut nodes = Vec::new();
+        self.root.flatten(&mut nodes);
+
+        sink.begin_object()?;
+        sink.key("nodes")?;
+        sink.begin_array(nodes.len())?;
+        for node in nodes {
+            sink.begin_object()?;
+            sink.key("id")?;
+            sink.number(node.id as f64)?;
+            sink.key("callFrame")?;
+            sink.begin_object()?;
+            sink.key("functionName")?;
+            sink.string(&node.function_name)?;
+            sink.key("scriptId")?;
+            sink.string(&node.script_id.to_string())?;
+            sink.key("url")?;
+            sink.string(&node.url)?;
+            sink.key("lineNumber")?;
+            sink.number(if node.id == self.root.id { -1.0 } else { node.line as f64 })?;
+            sink.key("columnNumber")?;
+            sink.number(-1.0)?;
+            sink.end_object()?;
+            sink.key("hitCount")?;
+            sink.number(node.hit_count as f64)?;
+            sink.key("children")?;
+            sink.begin_array(node.children.len())?;
+            for child in &node.children {
+                sink.number(child.id as f64)?;
+            }
+            sink.end_array()?;
+            sink.end_object()?;
+        }
+        sink.end_array()?;
+
+        sink.key("startTime")?;
+        sink.number(0.0)?;
+        sink.key("endTime")?;
+        sink.number(micros(self.duration))?;
+        sink.key("samples")?;
+        sink.begin_array(self.samples.len())?;
+        for &(id, _) in &self.samples {
+            sink.number(id as f64)?;
+        }
+        sink.end_array()?;
+        sink.key("timeDeltas")?;
+        sink.begin_array(self.samples.len())?;
+        for &(_, delta) in &self.samples {
+            sink.number(micros(delta))?;
+        }
+        sink.end_array()?;
+        sink.end_object()
+    }
+}
+
+impl ProfileNode {
+    fn new(id: u32, function_name: &str, url: &str, script_id: u32, line: u32) -> Self {
+        ProfileNode {
+            id: id,
+            function_name: function_name.to_string(),
+            url: url.to_string(),
+            script_id: script_id,
+            line: line,
+            hit_count: 0,
+            self_time: Duration::from_secs(0),
+            total_time: Duration::from_secs(0),
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the first node (depth-first) of a function, by name.
+    pub fn find(&self, function_name: &str) -> Option<&ProfileNode> {
+        if self.function_name == function_name {
+            return Some(self);
+        }
+        self.children.iter().filter_map(|child| child.find(function_name)).next()
+    }
+
+    /// Computes the estimated durations of the node and its descendants.
+    fn compute_times(&mut self, interval: Duration) {
+        self.self_time = interval * self.hit_count;
+        self.total_time = self.self_time;
+        for child in &mut self.children {
+            child.compute_times(interval);
+            self.total_time += child.total_time;
+        }
+    }
+
+    /// Collects the node and its descendants, depth-first.
+    fn flatten<'a>(&'a self, nodes: &mut Vec<&'a ProfileNode>) {
+        nodes.push(self);
+        for child in &self.children {
+            child.flatten(nodes);
+        }
+    }
+}
+
+impl DebugHandler for Sampler {
+    fn on_event(&mut self, paused: &Paused, event: DebugEvent) -> Step {
+        if event == DebugEvent::AsyncBreak {
+            if let Ok(frames) = paused.stack_trace() {
+                let frames = frames.into_iter()
+                    .rev()
+                    .map(|frame| (frame.script_id, frame.function_name, frame.line))
+                    .collect();
+                self.0.lock().unwrap().push(Sample { time: Instant::now(), frames: frames });
+            }
+        }
+        Step::Continue
+    }
+}
+
+/// Converts a duration to microseconds.
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e6 + duration.subsec_nanos() as f64 / 1e3
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use {test, script};
+    use super::Profiler;
+
+    #[test]
+    fn sample() {
+        test::run_with_context(|guard| {
+            let profiler = Profiler::start(guard, Duration::from_millis(1)).unwrap();
+            script::eval_with_name(guard, "profile.js", "
+                function inner(until) { while (Date.now() < until); }
+                function outer() { inner(Date.now() + 100); }
+                outer();").unwrap();
+            let profile = profiler.stop(guard).unwrap();
+
+            let outer = profile.root.find("outer").unwrap();
+            let inner = outer.find("inner").unwrap();
+            assert!(inner.hit_count > 0 && inner.url.ends_with("profile.js"));
+            assert!(outer.total_time >= inner.total_time);
+            assert_eq!(profile.root.hit_count, 0);
+            assert!(profile.sample_count() > 0);
+
+            let json = guard.json_parse(&profile.to_cpuprofile()).unwrap().into_object().unwrap();
+            let samples = json.get(guard, &::Property::new(guard, "samples")).into_array().unwrap();
+            assert_eq!(samples.len(guard), profile.sample_count());
+        });
+    }
+}
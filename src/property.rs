@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::fmt;
 use chakracore_sys::*;
 use context::{Context, ContextGuard};
+use value;
 
 /// A property identifier used with objects.
 #[derive(PartialEq)]
@@ -20,10 +22,32 @@ impl Property {
         }
     }
 
+    /// Creates a property identifier from a symbol.
+    pub fn from_symbol(_guard: &ContextGuard, symbol: &value::Symbol) -> Self {
+        let mut reference = JsPropertyIdRef::new();
+        unsafe {
+            jsassert!(JsGetPropertyIdFromSymbol(symbol.as_raw(), &mut reference));
+            Self::from_raw(reference)
+        }
+    }
+
+    /// Returns true if the property identifier is a symbol.
+    pub fn is_symbol(&self, _guard: &ContextGuard) -> bool {
+        let mut kind = JsPropertyIdType::String;
+        jsassert!(unsafe { JsGetPropertyIdType(self.as_raw(), &mut kind) });
+        kind == JsPropertyIdType::Symbol
+    }
+
     /// Converts a JavaScript property to a native string.
-    pub fn to_string(&self, _guard: &ContextGuard) -> String {
-        ::util::to_string_impl(self.as_raw(), JsCopyPropertyId)
-            .expect("converting property to string")
+    ///
+    /// Symbol properties are represented by their symbol's description (e.g
+    /// `Symbol(foo)`).
+    pub fn to_string(&self, guard: &ContextGuard) -> String {
+        match value::Symbol::from_property(guard, self) {
+            Some(symbol) => symbol.to_string(guard),
+            None => ::util::to_string_impl(self.as_raw(), JsCopyPropertyId)
+                .expect("converting property to string"),
+        }
     }
 }
 
@@ -40,9 +64,33 @@ impl fmt::Debug for Property {
 
 reference!(Property);
 
+/// A per-context cache of property identifiers, keyed by their name.
+///
+/// Used by `ContextGuard::property`. Cached identifiers live as long as the
+/// context.
+#[derive(Default)]
+pub(crate) struct PropertyCache(HashMap<String, Property>);
+
+// The cache is stored in the context's user data, which is only accessed by
+// the thread of the context's runtime.
+unsafe impl Send for PropertyCache {}
+
+impl PropertyCache {
+    /// Returns the identifier of a name, creating it if it is not cached.
+    pub fn get(&mut self, guard: &ContextGuard, name: &str) -> Property {
+        if let Some(property) = self.0.get(name) {
+            return property.clone();
+        }
+
+        let property = Property::new(guard, name);
+        self.0.insert(name.to_string(), property.clone());
+        property
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use {test, Property};
+    use {test, value, Property};
 
     #[test]
     fn string_conversion() {
@@ -51,4 +99,18 @@ mod tests {
             assert_eq!(property.to_string(guard), "foo");
         });
     }
+
+    #[test]
+    fn cache() {
+        test::run_with_context(|guard| {
+            let property = guard.property("foo");
+            assert_eq!(property, guard.property("foo"));
+            assert_eq!(property, Property::new(guard, "foo"));
+            assert!(property != guard.property("bar"));
+
+            let object = value::Object::new(guard);
+            object.set(guard, &guard.property("foo"), &value::Number::new(guard, 10));
+            assert_eq!(object.get(guard, &property).to_integer(guard), 10);
+        });
+    }
 }
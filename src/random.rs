@@ -0,0 +1,182 @@
+//! Host-controlled randomness for scripts.
+//!
+//! By default, `Math.random` uses the engine's own generator, and scripts
+//! have no access to `crypto.getRandomValues`. Installing a `RandomSource`
+//! in a context replaces `Math.random`, and provides `crypto.getRandomValues`,
+//! both drawing from the source. This allows a host to either supply a
+//! secure generator, or a seeded one for deterministic (e.g replayable)
+//! executions.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::random::{self, SeededRandom};
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! random::install(&guard, Box::new(SeededRandom::new(42))).unwrap();
+//! let value = js::script::eval(&guard, "Math.random()").unwrap();
+//! assert!(value.to_double(&guard) < 1.0);
+//! ```
+use std::{ptr, slice};
+use std::sync::{Arc, Mutex};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use value::{self, Value};
+use Property;
+
+/// The maximum number of bytes `crypto.getRandomValues` can fill at once.
+const MAX_BYTES: usize = 65536;
+
+/// A source of random data.
+pub trait RandomSource: Send {
+    /// Fills a buffer with random bytes.
+    fn fill_bytes(&mut self, buffer: &mut [u8]);
+
+    /// Returns a random number in the range `[0, 1)`.
+    ///
+    /// The default implementation uses 53 random bits from `fill_bytes`.
+    fn next_f64(&mut self) -> f64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        let bits = bytes.iter().fold(0u64, |bits, &byte| bits << 8 | byte as u64);
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A deterministic random source, using the SplitMix64 algorithm.
+///
+/// This is not suitable for cryptographic use.
+#[derive(Clone, Debug)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    /// Creates a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        SeededRandom { state: seed }
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            let value = self.next_u64();
+            for (index, byte) in chunk.iter_mut().enumerate() {
+                *byte = (value >> (index * 8)) as u8;
+            }
+        }
+    }
+}
+
+/// Installs a random source in the guard's context.
+///
+/// This replaces `Math.random`, and defines `crypto.getRandomValues` (the
+/// `crypto` object is created if it does not exist).
+pub fn install(guard: &ContextGuard, source: Box<RandomSource>) -> Result<()> {
+    let source = Arc::new(Mutex::new(source));
+    let global = guard.global();
+
+    let math = global.get(guard, &Property::new(guard, "Math"))
+        .into_object()
+        .ok_or_else(|| Error::from("Math object is missing"))?;
+    let random_source = source.clone();
+    let random = value::Function::with_name(guard, "random", Box::new(move |guard, _| {
+        let number = random_source.lock().unwrap().next_f64();
+        Ok(value::Number::from_double(guard, number).into())
+    }));
+    math.set(guard, &Property::new(guard, "random"), &random);
+
+    let crypto_property = Property::new(guard, "crypto");
+    let crypto = match global.get(guard, &crypto_property).into_object() {
+        Some(crypto) => crypto,
+        None => {
+            let crypto = value::Object::new(guard);
+            global.set(guard, &crypto_property, &crypto);
+            crypto
+        },
+    };
+
+    let get_random_values = value::Function::with_name(guard, "getRandomValues", Box::new(move |guard, info| {
+        let array = info.arguments.get(0).cloned().unwrap_or_else(|| value::undefined(guard));
+        let (data, length) = integer_array_storage(&array).ok_or_else(|| {
+            Value::from(value::Error::type_error(guard, "Argument must be an integer typed array"))
+        })?;
+
+        if length > MAX_BYTES {
+            let message = format!("Byte length exceeds the maximum of {}", MAX_BYTES);
+            let error = value::Error::new(guard, &message);
+            error.set(guard, &Property::new(guard, "name"), &value::String::new(guard, "QuotaExceededError"));
+            return Err(error.into());
+        }
+
+        // The array is kept alive by its argument reference
+        let bytes = unsafe { slice::from_raw_parts_mut(data, length) };
+        source.lock().unwrap().fill_bytes(bytes);
+        Ok(array)
+    }));
+    crypto.set(guard, &Property::new(guard, "getRandomValues"), &get_random_values);
+    Ok(())
+}
+
+/// Returns the storage (pointer and byte length) of an integer typed array.
+fn integer_array_storage(value: &Value) -> Option<(*mut u8, usize)> {
+    if value.get_type() != JsValueType::TypedArray {
+        return None;
+    }
+
+    let mut data = ptr::null_mut();
+    let mut size = 0;
+    let mut kind = JsTypedArrayType::Int8;
+    let mut element_size = 0;
+    unsafe {
+        jsassert!(JsGetTypedArrayStorage(value.as_raw(), &mut data, &mut size, &mut kind, &mut element_size));
+        match kind {
+            JsTypedArrayType::Float32 | JsTypedArrayType::Float64 => None,
+            _ => Some((data, size as usize)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, Context};
+    use super::SeededRandom;
+
+    #[test]
+    fn seeded() {
+        let (runtime, context) = test::setup_env();
+        let sample = |context: &Context| context.exec_with(|guard| {
+            super::install(guard, Box::new(SeededRandom::new(7))).unwrap();
+            let result = script::eval(guard, "
+                var numbers = [Math.random(), Math.random()];
+                [numbers, Array.from(crypto.getRandomValues(new Uint8Array(4)))]").unwrap();
+            result.to_json(guard).unwrap()
+        }).unwrap();
+
+        let other = Context::new(&runtime).unwrap();
+        assert_eq!(sample(&context), sample(&other));
+
+        context.exec_with(|guard| {
+            let numbers = script::eval(guard, "[Math.random(), Math.random()]").unwrap();
+            assert_ne!(numbers.to_json(guard).unwrap(), "[0,0]");
+
+            let result = script::eval(guard, "
+                var errors = [];
+                try { crypto.getRandomValues(new Float64Array(1)); } catch (ex) { errors.push(ex.name); }
+                try { crypto.getRandomValues(new Uint8Array(65537)); } catch (ex) { errors.push(ex.name); }
+                errors").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"["TypeError","QuotaExceededError"]"#);
+        }).unwrap();
+    }
+}
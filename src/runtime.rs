@@ -1,25 +1,122 @@
 //! Runtime and builder.
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use libc::c_void;
 use error::*;
 use chakracore_sys::*;
 use util::jstry;
 
+pub use policy::Policy;
+pub use pool::Pool;
+
 /// A callback triggered before objects are collected.
 pub type CollectCallback = Fn() + Send;
 
+/// A callback triggered for memory events, returning whether an allocation
+/// is allowed.
+pub type MemoryCallback = Fn(MemoryEvent, usize) -> bool + Send;
+
+/// A memory event of a runtime.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryEvent {
+    /// Memory is about to be allocated; the allocation can be rejected.
+    Allocate,
+    /// Memory has been freed.
+    Free,
+    /// An allocation has failed.
+    Failure,
+}
+
+/// A lifecycle event of a runtime, or of one of its contexts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The runtime has been created.
+    RuntimeCreated,
+    /// A context has been created.
+    ContextCreated,
+    /// A context has been garbage collected.
+    ContextCollected,
+    /// Script execution has been disabled, by an interrupt handle or a
+    /// timeout.
+    ExecutionDisabled,
+    /// An allocation has failed, e.g since the memory limit was exceeded.
+    MemoryLimitHit,
+}
+
+/// A subscribable channel of lifecycle events.
+///
+/// Clones share the same subscribers, so a supervisor can pass one channel
+/// to the builders of many runtimes. Events are emitted on the thread they
+/// occur on (e.g a watchdog's thread, for timeouts).
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::runtime::{Event, Events};
+/// let events = Events::new();
+/// let receiver = events.subscribe();
+///
+/// let runtime = js::Runtime::builder().events(events).build().unwrap();
+/// let _context = js::Context::new(&runtime).unwrap();
+/// assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [Event::RuntimeCreated, Event::ContextCreated]);
+/// ```
+#[derive(Clone, Default)]
+pub struct Events(Arc<Mutex<Vec<mpsc::Sender<Event>>>>);
+
+impl Events {
+    /// Creates a channel without any subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the events emitted from now on.
+    ///
+    /// A subscriber is removed once its receiver is dropped.
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Emits an event to all subscribers.
+    pub(crate) fn emit(&self, event: Event) {
+        self.0.lock().unwrap().retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
+
+/// The state of a runtime's collect callback.
+struct CollectHandler {
+    callback: Option<Box<CollectCallback>>,
+    collections: Arc<AtomicUsize>,
+}
+
+/// The state of a runtime's memory allocation callback.
+struct MemoryHandler {
+    callback: Option<Box<MemoryCallback>>,
+    events: Events,
+}
+
 /// A builder for the runtime type.
 pub struct Builder {
     memory_limit: Option<usize>,
     collect_callback: Option<Box<CollectCallback>>,
+    memory_callback: Option<Box<MemoryCallback>>,
+    events: Option<Events>,
+    policy: Option<Arc<Policy>>,
     attributes: JsRuntimeAttributes,
 }
 
 /// An isolated instance of a runtime.
 pub struct Runtime {
     #[allow(dead_code)]
-    callback: Option<Box<Box<CollectCallback>>>,
+    collect: Box<CollectHandler>,
+    #[allow(dead_code)]
+    memory: Box<MemoryHandler>,
+    events: Events,
+    policy: Option<Arc<Policy>>,
+    collections: Arc<AtomicUsize>,
     handle: JsRuntimeHandle,
+    interrupt: Arc<Mutex<Option<JsRuntimeHandle>>>,
     last_idle_tick: Option<Duration>,
     last_idle: Option<Instant>,
 }
@@ -35,15 +132,24 @@ impl Runtime {
         Builder {
             memory_limit: None,
             collect_callback: None,
+            memory_callback: None,
+            events: None,
+            policy: None,
             attributes: JsRuntimeAttributeNone,
         }
     }
 
     /// Performs a full garbage collection.
-    pub fn collect(&self) -> Result<()> {
+    pub fn collect_garbage(&self) -> Result<()> {
         jstry(unsafe { JsCollectGarbage(self.as_raw()) })
     }
 
+    /// Performs a full garbage collection.
+    #[deprecated(since = "0.2.1", note = "use `collect_garbage` instead")]
+    pub fn collect(&self) -> Result<()> {
+        self.collect_garbage()
+    }
+
     /// Runs any idle tasks that are in the queue. The returned duration is the
     /// least amount of time that should pass until this function is called
     /// again. This call will fail if the runtime was created without idle
@@ -57,23 +163,94 @@ impl Runtime {
         });
 
         if should_idle {
-            let mut ticks = 0;
-            jstry!(unsafe { JsIdle(&mut ticks) });
-
-            self.last_idle_tick = Some(Duration::from_millis(ticks as u64));
+            self.last_idle_tick = Some(self.run_idle()?);
             self.last_idle = Some(Instant::now());
         }
 
         Ok(should_idle)
     }
 
-    /// Returns the runtime's memory usage
-    pub fn get_memory_usage(&self) -> usize {
+    /// Performs idle processing (e.g garbage collection) immediately.
+    ///
+    /// Returns the suggested amount of time until the next call. In contrast
+    /// to `run_idle_tasks`, this does not keep track of when it should be
+    /// called again, so a host can schedule it as part of its own event loop.
+    /// A context of the runtime must be active on the current thread, and the
+    /// runtime must have been created with `supports_idle_tasks`.
+    pub fn run_idle(&self) -> Result<Duration> {
+        let mut ticks = 0;
+        jstry!(unsafe { JsIdle(&mut ticks) });
+        Ok(Duration::from_millis(ticks as u64))
+    }
+
+    /// Returns the runtime's memory usage, in bytes.
+    pub fn memory_usage(&self) -> usize {
         let mut usage = 0;
         jsassert!(unsafe { JsGetRuntimeMemoryUsage(self.as_raw(), &mut usage) });
         usage
     }
 
+    /// Returns the runtime's memory usage
+    #[deprecated(since = "0.2.1", note = "use `memory_usage` instead")]
+    pub fn get_memory_usage(&self) -> usize {
+        self.memory_usage()
+    }
+
+    /// Returns the runtime's memory limit (`usize::max_value()` if unlimited).
+    pub fn get_memory_limit(&self) -> usize {
+        let mut limit = 0;
+        jsassert!(unsafe { JsGetRuntimeMemoryLimit(self.as_raw(), &mut limit) });
+        limit
+    }
+
+    /// Sets the runtime's memory limit.
+    ///
+    /// Once the limit is exceeded, any allocation within the runtime will fail
+    /// with an out of memory error. Use `usize::max_value()` to remove the
+    /// limit.
+    pub fn set_memory_limit(&self, limit: usize) -> Result<()> {
+        jstry(unsafe { JsSetRuntimeMemoryLimit(self.as_raw(), limit) })
+    }
+
+    /// Returns a thread-safe handle for interrupting script execution.
+    ///
+    /// The runtime must have been created with `enable_script_interrupt`.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle(self.interrupt.clone(), self.events.clone())
+    }
+
+    /// Returns the channel of the runtime's lifecycle events.
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Returns the runtime's policy, if any.
+    pub(crate) fn policy(&self) -> Option<Arc<Policy>> {
+        self.policy.clone()
+    }
+
+    /// Returns the number of garbage collections the runtime has started.
+    pub fn collection_count(&self) -> usize {
+        self.collections.load(Ordering::SeqCst)
+    }
+
+    /// Returns the runtime's shared collection counter.
+    pub(crate) fn collections(&self) -> Arc<AtomicUsize> {
+        self.collections.clone()
+    }
+
+    /// Enables script execution after it has been interrupted.
+    pub fn enable_execution(&self) -> Result<()> {
+        jstry(unsafe { JsEnableRuntimeExecution(self.as_raw()) })
+    }
+
+    /// Returns whether script execution is disabled.
+    pub fn is_execution_disabled(&self) -> bool {
+        let mut disabled = false;
+        jsassert!(unsafe { JsIsRuntimeExecutionDisabled(self.as_raw(), &mut disabled) });
+        disabled
+    }
+
     /// Returns the underlying raw pointer behind this runtime.
     pub fn as_raw(&self) -> JsRuntimeHandle {
         self.handle
@@ -81,19 +258,64 @@ impl Runtime {
 
     /// A collector callback, triggered before objects are released.
     unsafe extern "system" fn before_collect(data: *mut c_void) {
-        let callback = data as *mut Box<CollectCallback>;
-        (*callback)();
+        let handler = &*(data as *mut CollectHandler);
+        handler.collections.fetch_add(1, Ordering::SeqCst);
+        if let Some(ref callback) = handler.callback {
+            callback();
+        }
+    }
+
+    /// A memory callback, triggered for allocations and deallocations.
+    unsafe extern "system" fn memory_event(data: *mut c_void, event: JsMemoryEventType, size: usize) -> bool {
+        let handler = &*(data as *mut MemoryHandler);
+        let event = match event {
+            JsMemoryEventType::Allocate => MemoryEvent::Allocate,
+            JsMemoryEventType::Free => MemoryEvent::Free,
+            JsMemoryEventType::Failure => MemoryEvent::Failure,
+        };
+
+        if event == MemoryEvent::Failure {
+            handler.events.emit(Event::MemoryLimitHit);
+        }
+        handler.callback.as_ref().map_or(true, |callback| callback(event, size))
     }
 }
 
 impl Drop for Runtime {
     fn drop(&mut self) {
+        // Ensure no interrupt is requested for a disposed runtime
+        self.interrupt.lock().unwrap().take();
         unsafe {
             jsassert!(JsDisposeRuntime(self.as_raw()));
         }
     }
 }
 
+/// A handle for interrupting a runtime's script execution.
+///
+/// The handle can be sent to, and used from, any thread. Once the runtime has
+/// been disposed, the handle has no effect.
+#[derive(Clone)]
+pub struct InterruptHandle(Arc<Mutex<Option<JsRuntimeHandle>>>, Events);
+
+impl InterruptHandle {
+    /// Terminates any running script, and disables further script execution.
+    ///
+    /// The script will fail with a `ScriptTerminated` error. Execution must be
+    /// enabled again using `Runtime::enable_execution` before the runtime can
+    /// be used. Returns false if the runtime has been disposed.
+    pub fn interrupt(&self) -> Result<bool> {
+        match *self.0.lock().unwrap() {
+            Some(handle) => {
+                jstry(unsafe { JsDisableRuntimeExecution(handle) })?;
+                self.1.emit(Event::ExecutionDisabled);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
 macro_rules! attr {
     ($name:ident, $attribute:ident, $doc:expr) => {
         #[doc=$doc]
@@ -113,7 +335,7 @@ impl Builder {
           "Disable `eval` and `function` by throwing an exception upon use.");
     attr!(disable_jit,
           JsRuntimeAttributeDisableNativeCodeGeneration,
-          "Disable just-in-time compilation.");
+          "Disable just-in-time compilation, so all of the runtime's contexts are interpreted.");
     attr!(enable_experimental,
           JsRuntimeAttributeEnableExperimentalFeatures,
           "Allow experimental JavaScript features.");
@@ -139,6 +361,35 @@ impl Builder {
         self
     }
 
+    /// Set a callback for the runtime's memory events.
+    ///
+    /// The callback is invoked for every allocation, and returning false
+    /// rejects it (causing an out of memory error). The return value is
+    /// ignored for other events.
+    pub fn memory_callback(mut self, callback: Box<MemoryCallback>) -> Self {
+        self.memory_callback = Some(callback);
+        self
+    }
+
+    /// Set the channel which the runtime's lifecycle events are emitted to.
+    ///
+    /// By default, each runtime has its own channel.
+    pub fn events(mut self, events: Events) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Set a policy restricting the scripts of the runtime's contexts.
+    ///
+    /// A policy limiting the number of statements enables script interrupts.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        if policy.counts_statements() {
+            self.attributes = self.attributes | JsRuntimeAttributeAllowScriptInterrupt;
+        }
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
     /// Creates the runtime object with associated settings.
     pub fn build(self) -> Result<Runtime> {
         let mut handle = JsRuntimeHandle::new();
@@ -148,20 +399,41 @@ impl Builder {
             jstry!(unsafe { JsSetRuntimeMemoryLimit(handle, limit) });
         }
 
-        let collect = self.collect_callback.map(|callback| unsafe {
-            let wrapper = Box::into_raw(Box::new(callback));
+        // The collect callback is always set, so collections can be counted
+        let collections = Arc::new(AtomicUsize::new(0));
+        let collect = unsafe {
+            let handler = CollectHandler { callback: self.collect_callback, collections: collections.clone() };
+            let wrapper = Box::into_raw(Box::new(handler));
             jsassert!(JsSetRuntimeBeforeCollectCallback(
                 handle,
                 wrapper as *mut _,
                 Some(Runtime::before_collect)));
             Box::from_raw(wrapper)
-        });
+        };
 
+        // The allocation callback is always set, so failures can be emitted
+        let events = self.events.unwrap_or_default();
+        let memory = unsafe {
+            let handler = MemoryHandler { callback: self.memory_callback, events: events.clone() };
+            let wrapper = Box::into_raw(Box::new(handler));
+            jsassert!(JsSetRuntimeMemoryAllocationCallback(
+                handle,
+                wrapper as *mut _,
+                Some(Runtime::memory_event)));
+            Box::from_raw(wrapper)
+        };
+
+        events.emit(Event::RuntimeCreated);
         Ok(Runtime {
             last_idle: None,
             last_idle_tick: None,
+            interrupt: Arc::new(Mutex::new(Some(handle))),
             handle: handle,
-            callback: collect,
+            collect: collect,
+            memory: memory,
+            events: events,
+            policy: self.policy,
+            collections: collections,
         })
     }
 }
@@ -169,8 +441,9 @@ impl Builder {
 #[cfg(test)]
 mod tests {
     use std::thread;
+    use std::time::Duration;
     use std::sync::{Arc, Mutex};
-    use {test, script, Runtime, Context};
+    use {test, error, script, Runtime, Context};
 
     #[test]
     fn minimal() {
@@ -180,6 +453,39 @@ mod tests {
         });
     }
 
+    #[test]
+    fn run_idle() {
+        {
+            let runtime = Runtime::builder().supports_idle_tasks().build().unwrap();
+            let context = Context::new(&runtime).unwrap();
+            let guard = context.make_current().unwrap();
+            script::eval(&guard, "new Array(1000).fill({})").unwrap();
+            assert!(runtime.run_idle().is_ok());
+        }
+
+        let (runtime, context) = test::setup_env();
+        let _guard = context.make_current().unwrap();
+        assert!(runtime.run_idle().is_err());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let limit = 10 * 1024 * 1024;
+        let runtime = Runtime::builder()
+            .memory_limit(limit)
+            .disable_jit()
+            .build()
+            .unwrap();
+        assert_eq!(runtime.get_memory_limit(), limit);
+
+        let context = Context::new(&runtime).unwrap();
+        let guard = context.make_current().unwrap();
+        assert!(script::eval(&guard, "new Array(1e8).fill(0)").is_err());
+
+        runtime.set_memory_limit(usize::max_value()).unwrap();
+        assert_eq!(script::eval(&guard, "1 + 1").unwrap().to_integer(&guard), 2);
+    }
+
     #[test]
     fn collect_callback() {
         let called = Arc::new(Mutex::new(false));
@@ -189,11 +495,101 @@ mod tests {
                 .collect_callback(Box::new(move || *called.lock().unwrap() = true))
                 .build()
                 .unwrap();
-            runtime.collect().unwrap();
+            runtime.collect_garbage().unwrap();
+            assert!(runtime.collection_count() > 0);
         }
         assert!(*called.lock().unwrap());
     }
 
+    #[test]
+    fn collect_garbage() {
+        let (runtime, context) = test::setup_env();
+        let guard = context.make_current().unwrap();
+        script::eval(&guard, "var list = new Array(1e6).fill(0).map((_, i) => ({ i: i }))").unwrap();
+        runtime.collect_garbage().unwrap();
+        let before = runtime.memory_usage();
+
+        script::eval(&guard, "list = null").unwrap();
+        runtime.collect_garbage().unwrap();
+        assert!(runtime.memory_usage() < before);
+    }
+
+    #[test]
+    fn memory_callback() {
+        let allocated = Arc::new(Mutex::new(0));
+        let reject = Arc::new(Mutex::new(false));
+        let runtime = {
+            let (allocated, reject) = (allocated.clone(), reject.clone());
+            Runtime::builder()
+                .memory_callback(Box::new(move |event, size| {
+                    if event == super::MemoryEvent::Allocate {
+                        *allocated.lock().unwrap() += size;
+                        return !*reject.lock().unwrap();
+                    }
+                    true
+                }))
+                .build()
+                .unwrap()
+        };
+
+        let context = Context::new(&runtime).unwrap();
+        let guard = context.make_current().unwrap();
+        script::eval(&guard, "new Array(1e5).fill(0)").unwrap();
+        assert!(*allocated.lock().unwrap() > 0);
+
+        *reject.lock().unwrap() = true;
+        assert!(script::eval(&guard, "new Array(1e7).fill(0)").is_err());
+        *reject.lock().unwrap() = false;
+    }
+
+    #[test]
+    fn interrupt() {
+        let runtime = Runtime::builder().enable_script_interrupt().build().unwrap();
+        let context = Context::new(&runtime).unwrap();
+        let guard = context.make_current().unwrap();
+
+        let handle = runtime.interrupt_handle();
+        let watchdog = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            handle.interrupt().unwrap()
+        });
+
+        let error = script::eval(&guard, "while (true) {}").unwrap_err();
+        assert_matches!(error.kind(), &error::ErrorKind::ScriptTerminated);
+        assert!(watchdog.join().unwrap());
+        assert!(runtime.is_execution_disabled());
+
+        runtime.enable_execution().unwrap();
+        assert_eq!(script::eval(&guard, "5 + 5").unwrap().to_integer(&guard), 10);
+    }
+
+    #[test]
+    fn events() {
+        let events = super::Events::new();
+        let receiver = events.subscribe();
+        {
+            let runtime = Runtime::builder()
+                .events(events.clone())
+                .enable_script_interrupt()
+                .memory_limit(10 * 1024 * 1024)
+                .disable_jit()
+                .build()
+                .unwrap();
+            let context = Context::new(&runtime).unwrap();
+            let guard = context.make_current().unwrap();
+
+            assert!(runtime.interrupt_handle().interrupt().unwrap());
+            assert!(script::eval(&guard, "1 + 1").is_err());
+            runtime.enable_execution().unwrap();
+            assert!(script::eval(&guard, "new Array(1e8).fill(0)").is_err());
+        }
+
+        use super::Event::*;
+        let mut received = receiver.try_iter().collect::<Vec<_>>();
+        received.dedup();
+        assert_eq!(received, [RuntimeCreated, ContextCreated, ExecutionDisabled, MemoryLimitHit, ContextCollected]);
+    }
+
     #[test]
     fn thread_send() {
         let runtime = Runtime::new().unwrap();
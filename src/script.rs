@@ -24,12 +24,40 @@
 //! let result = add.call(&guard, &[]).unwrap();
 //! assert_eq!(result.to_integer(&guard), 20);
 //! ```
+//!
+//! Scripts that are executed frequently can be compiled to bytecode once, and
+//! then executed many times. The bytecode can also be stored (e.g on disk),
+//! and reused across processes:
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let script = js::script::compile(&guard, "10 + 10").unwrap();
+//! let bytes = script.as_bytes().to_vec();
+//!
+//! let script = js::script::CompiledScript::from_bytes("", "10 + 10", bytes);
+//! assert_eq!(script.run(&guard).unwrap().to_integer(&guard), 20);
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::slice;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
 use chakracore_sys::*;
 use error::*;
-use context::ContextGuard;
+use context::{Context, ContextGuard};
+use deadline::{Deadline, Watchdog};
 use util::jstry;
-use value;
+use {audit, policy, stream, value};
+
+/// Used for generating unique compiled script identifiers.
+static SCRIPT_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// Evaluates code directly.
 pub fn eval(guard: &ContextGuard, code: &str) -> Result<value::Value> {
@@ -38,22 +66,514 @@ pub fn eval(guard: &ContextGuard, code: &str) -> Result<value::Value> {
 
 /// Evaluates code and associates it with a name.
 pub fn eval_with_name(guard: &ContextGuard, name: &str, code: &str) -> Result<value::Value> {
-    process_code(guard, name, code, CodeAction::Execute)
+    eval_with_options(guard, code, &ScriptOptions::new(name))
+}
+
+/// Evaluates code with options describing its source.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::script::ScriptOptions;
+/// # use std::time::Duration;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let options = ScriptOptions { start_line: 10, ..ScriptOptions::new("https://example.com/app.js") };
+/// let error = js::script::eval_with_options(&guard, "throw new Error()", &options).unwrap_err();
+/// assert!(error.to_string().contains("app.js:10:1"));
+/// ```
+pub fn eval_with_options(guard: &ContextGuard, code: &str, options: &ScriptOptions) -> Result<value::Value> {
+    policy::enforced(guard, &options.url, code, || {
+        audit::audited(guard, &options.url, code, || process_code(guard, options, code, CodeAction::Execute))
+    })
+}
+
+/// Options describing the source of a script.
+///
+/// These are used by stack traces, error diagnostics and debuggers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScriptOptions {
+    /// The URL (or file name) of the source.
+    pub url: String,
+    /// The line number of the source's first line, for code embedded in a
+    /// larger document (e.g a script element). Lines are 1-based, so both
+    /// zero and one leave the line numbers unchanged.
+    pub start_line: u32,
+    /// The source context cookie, identifying the script to debuggers.
+    pub cookie: Option<JsSourceContext>,
+}
+
+impl ScriptOptions {
+    /// Creates options with a source URL.
+    pub fn new(url: &str) -> Self {
+        ScriptOptions { url: url.to_string(), ..ScriptOptions::default() }
+    }
+}
+
+/// Statistics of a single evaluation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScriptStats {
+    /// The time spent parsing the script.
+    pub parse_time: Duration,
+    /// The time spent executing the script (zero if it could not be parsed).
+    pub execution_time: Duration,
+    /// The number of garbage collections started during the evaluation.
+    ///
+    /// JSRT only reports the start of a collection, so the duration of each
+    /// pause is included in the parse and execution times.
+    pub gc_pauses: usize,
+    /// The change of the runtime's memory usage, in bytes.
+    pub memory_delta: i64,
+}
+
+/// Evaluates code, and records statistics of its evaluation.
+///
+/// The script is parsed and executed separately, so both can be timed. The
+/// statistics are returned even if the evaluation fails (e.g for monitoring
+/// scripts which time out).
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::script::ScriptOptions;
+/// # use std::time::Duration;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let options = ScriptOptions::new("job.js");
+/// let (result, stats) = js::script::eval_with_stats(&guard, "[1, 2, 3].map(x => x * 2).length", &options);
+/// assert_eq!(result.unwrap().to_integer(&guard), 3);
+/// assert!(stats.parse_time + stats.execution_time < Duration::from_secs(1));
+/// ```
+pub fn eval_with_stats(guard: &ContextGuard, code: &str, options: &ScriptOptions) -> (Result<value::Value>, ScriptStats) {
+    let runtime = guard.context().runtime_handle();
+    let collections = guard.collections();
+    let (initial_collections, initial_memory) = (collections.load(Ordering::SeqCst), audit::memory_usage(runtime));
+
+    let mut stats = ScriptStats::default();
+    let result = policy::enforced(guard, &options.url, code, || {
+        audit::audited(guard, &options.url, code, || {
+            let start = Instant::now();
+            let function = process_code(guard, options, code, CodeAction::Parse);
+            stats.parse_time = start.elapsed();
+
+            let function = function?.into_function().ok_or("parsing result is not a function")?;
+            let start = Instant::now();
+            let result = function.call_with_this(guard, &guard.global().into(), &[]);
+            stats.execution_time = start.elapsed();
+            result
+        })
+    });
+
+    stats.gc_pauses = collections.load(Ordering::SeqCst) - initial_collections;
+    stats.memory_delta = audit::memory_usage(runtime) as i64 - initial_memory as i64;
+    (result, stats)
+}
+
+/// Evaluates code, and streams its result to a sink.
+///
+/// The result is walked incrementally (see the `stream` module), so a very
+/// large result is never copied in its entirety.
+pub fn eval_streaming(guard: &ContextGuard, code: &str, sink: &mut stream::StreamSink) -> Result<()> {
+    let result = eval(guard, code)?;
+    stream::write(guard, &result, sink)
+}
+
+/// Evaluates code, terminating it if it exceeds a timeout.
+///
+/// The runtime must have been created with `enable_script_interrupt`. If the
+/// script is terminated, a `ScriptTerminated` error is returned, and the
+/// runtime's execution is enabled again.
+///
+/// The timeout is measured by the context's scheduler. Since the evaluation
+/// blocks the current thread, a manual scheduler only terminates the script
+/// if it is advanced from another thread.
+pub fn eval_with_timeout(guard: &ContextGuard, code: &str, timeout: Duration) -> Result<value::Value> {
+    let watchdog = Watchdog::start(&guard.scheduler(), guard.context().runtime_handle(), guard.events(), timeout);
+    let result = eval(guard, code);
+    watchdog.stop()?;
+    result
+}
+
+/// Evaluates code asynchronously, resolving once its result has settled.
+///
+/// The code is evaluated when the future is first polled. Every poll drains
+/// the context's task queue, and if the result is a promise, the future
+/// resolves with its settled value (a rejection results in a
+/// `ScriptException` error). While scheduled host callbacks (e.g promise
+/// timeouts) are pending, the future is woken once the next one is due.
+///
+/// Since values are bound to their thread, the future must be polled on the
+/// guard's thread (e.g using a single-threaded executor).
+pub fn eval_async<'a>(guard: &'a ContextGuard<'a>, code: &str) -> EvalFuture<'a> {
+    EvalFuture {
+        guard: guard,
+        code: Some(code.to_string()),
+        promise: None,
+        wakeup: None,
+    }
+}
+
+/// A future for asynchronously evaluated code.
+pub struct EvalFuture<'a> {
+    guard: &'a ContextGuard<'a>,
+    code: Option<String>,
+    promise: Option<value::promise::PromiseFuture>,
+    wakeup: Option<Deadline>,
+}
+
+impl<'a> Future for EvalFuture<'a> {
+    type Output = Result<value::Value>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut task::Context) -> Poll<Self::Output> {
+        let guard = self.guard;
+        if let Some(code) = self.code.take() {
+            let result = match eval(guard, &code) {
+                Ok(result) => result,
+                Err(error) => return Poll::Ready(Err(error)),
+            };
+
+            match result.clone().into_promise() {
+                Some(promise) => match promise.into_future(guard) {
+                    Ok(promise) => self.promise = Some(promise),
+                    Err(error) => return Poll::Ready(Err(error)),
+                },
+                None => {
+                    guard.execute_tasks();
+                    return Poll::Ready(Ok(result));
+                },
+            }
+        }
+
+        guard.execute_tasks();
+        let promise = self.promise.as_mut().expect("polling a completed evaluation");
+        match Pin::new(promise).poll(context) {
+            Poll::Ready(Ok(value)) => Poll::Ready(Ok(value)),
+            Poll::Ready(Err(reason)) => {
+                let error = ScriptError::new(guard, &reason);
+                Poll::Ready(Err(ErrorKind::ScriptException(error).into()))
+            },
+            Poll::Pending => {
+                // Replacing the previous wake-up cancels it
+                self.wakeup = guard.next_deadline().map(|deadline| {
+                    let waker = context.waker().clone();
+                    guard.scheduler().schedule_at(deadline, Box::new(move || waker.wake()))
+                });
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// Calls a function a number of times, discarding the results.
+///
+/// The engine interprets functions at first, and compiles frequently called
+/// ones using its JIT tiers, based on profiling data gathered while they run.
+/// Warming up a handler before serving requests moves this work ahead of
+/// time, so the first requests do not suffer from it. JIT compilation is done
+/// on background threads, unless the runtime was created with
+/// `disable_background_work`, in which case it is finished once this
+/// returns. For predictable latency at the cost of peak throughput, create
+/// the runtime with `disable_jit` instead (this applies to all of the
+/// runtime's contexts, since the engine has no per-context tiering control).
+///
+/// An error is returned as soon as a call fails.
+pub fn warm_up(guard: &ContextGuard,
+               function: &value::Function,
+               arguments: &[&value::Value],
+               runs: usize) -> Result<()> {
+    for _ in 0..runs {
+        function.call(guard, arguments)?;
+    }
+    Ok(())
 }
 
 /// Parses code and returns it as a function.
+///
+/// The code is compiled, but not executed until the function is called. A
+/// syntax error results in a `ScriptCompile` error.
 pub fn parse(guard: &ContextGuard, code: &str) -> Result<value::Function> {
     parse_with_name(guard, "", code)
 }
 
+/// Validates the syntax of code without executing it.
+///
+/// This is useful for checking user-submitted code before it is stored or
+/// scheduled for execution. A syntax error results in a `ScriptCompile` error.
+pub fn validate(guard: &ContextGuard, code: &str) -> Result<()> {
+    parse(guard, code).map(|_| ())
+}
+
 /// Parses code and associates it with a name, returns it as a function.
 pub fn parse_with_name(guard: &ContextGuard, name: &str, code: &str) -> Result<value::Function> {
-    process_code(guard, name, code, CodeAction::Parse)
-        .map(|value| value
+    parse_with_options(guard, code, &ScriptOptions::new(name))
+}
+
+/// Parses code with options describing its source, returns it as a function.
+pub fn parse_with_options(guard: &ContextGuard, code: &str, options: &ScriptOptions) -> Result<value::Function> {
+    policy::consulted(guard, &options.url, code)?;
+    process_code(guard, options, code, CodeAction::Parse)?
+        .into_function()
+        .ok_or_else(|| "parsing result is not a function".into())
+}
+
+/// Compiles code to bytecode.
+pub fn compile(guard: &ContextGuard, code: &str) -> Result<CompiledScript> {
+    compile_with_name(guard, "", code)
+}
+
+/// Compiles code, associated with a name, to bytecode.
+pub fn compile_with_name(guard: &ContextGuard, name: &str, code: &str) -> Result<CompiledScript> {
+    let buffer = create_code_buffer(guard, code);
+    let mut result = JsValueRef::new();
+    unsafe {
+        jstry!(JsSerialize(buffer.as_raw(), &mut result, JsParseScriptAttributeNone));
+        let bytecode = value::ArrayBuffer::from_raw(result);
+        Ok(CompiledScript::from_bytes(name, code, bytecode.as_slice().to_vec()))
+    }
+}
+
+/// Renders a template with data.
+///
+/// This is a shorthand for `Template::compile` followed by `render`. If a
+/// template is rendered several times, prefer compiling it once.
+pub fn render_template<T: value::ToJs + ?Sized>(guard: &ContextGuard,
+                                                  source: &str,
+                                                  data: &T) -> Result<String> {
+    Template::compile(guard, source)?.render(guard, data)
+}
+
+/// A template based on JavaScript template literals.
+///
+/// The source is the body of a template literal, and the properties of the
+/// data are in scope for its placeholders:
+///
+/// ```rust
+/// # use chakracore as js;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let data = js::script::eval(&guard, "({ name: 'World' })").unwrap();
+/// let template = js::script::Template::compile(&guard, "Hello ${name}!").unwrap();
+/// assert_eq!(template.render(&guard, &data).unwrap(), "Hello World!");
+/// ```
+///
+/// A template is tied to the context it was compiled in.
+pub struct Template(value::Function);
+
+impl Template {
+    /// Compiles a template.
+    pub fn compile(guard: &ContextGuard, source: &str) -> Result<Template> {
+        let code = format!("(function(data) {{ with (data) {{ return `{}`; }} }})", escape_template(source));
+        eval_with_name(guard, "template", &code).map(|value| {
+            Template(value.into_function().expect("converting template to function"))
+        })
+    }
+
+    /// Renders the template with data.
+    pub fn render<T: value::ToJs + ?Sized>(&self, guard: &ContextGuard, data: &T) -> Result<String> {
+        let data = data.to_js(guard);
+        if !data.is_object() {
+            return Err("template data must be an object".into());
+        }
+        self.0.call(guard, &[&data]).map(|result| result.to_string(guard))
+    }
+}
+
+/// A construct of a template's source, while it is being escaped.
+enum TemplatePart {
+    /// The code of a placeholder, and the depth of its braces.
+    Placeholder(usize),
+    /// A template literal nested in a placeholder.
+    Nested,
+    /// A string literal in a placeholder, and its quote.
+    Quoted(char),
+}
+
+/// Escapes backticks that would otherwise terminate the template literal.
+///
+/// Only the backticks of the template's own text are escaped (unless they
+/// are escaped already), since within placeholders they delimit nested
+/// template literals.
+fn escape_template(source: &str) -> String {
+    let mut escaped = String::with_capacity(source.len());
+    let mut parts = Vec::new();
+    let mut characters = source.chars().peekable();
+    while let Some(character) = characters.next() {
+        let placeholder = characters.peek() == Some(&'{');
+        match (parts.last_mut(), character) {
+            // Escape sequences are copied as is, including escaped backticks
+            (None, '\\') | (Some(&mut TemplatePart::Nested), '\\') | (Some(&mut TemplatePart::Quoted(_)), '\\') => {
+                escaped.push(character);
+                if let Some(escape) = characters.next() {
+                    escaped.push(escape);
+                }
+                continue;
+            },
+            (None, '`') => escaped.push('\\'),
+            (Some(&mut TemplatePart::Nested), '`') => { parts.pop(); },
+            (Some(&mut TemplatePart::Quoted(quote)), _) if character == quote => { parts.pop(); },
+            (Some(&mut TemplatePart::Placeholder(ref mut depth)), '{') => *depth += 1,
+            (Some(&mut TemplatePart::Placeholder(0)), '}') => { parts.pop(); },
+            (Some(&mut TemplatePart::Placeholder(ref mut depth)), '}') => *depth -= 1,
+            (Some(&mut TemplatePart::Placeholder(_)), '`') => parts.push(TemplatePart::Nested),
+            (Some(&mut TemplatePart::Placeholder(_)), '\'') | (Some(&mut TemplatePart::Placeholder(_)), '"') => {
+                parts.push(TemplatePart::Quoted(character));
+            },
+            (None, '$') | (Some(&mut TemplatePart::Nested), '$') if placeholder => {
+                escaped.push(character);
+                escaped.push(characters.next().expect("reading placeholder brace"));
+                parts.push(TemplatePart::Placeholder(0));
+                continue;
+            },
+            _ => (),
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+/// A script compiled to bytecode.
+///
+/// The script is not tied to any context, and can be executed any number of
+/// times. The source code is retained, since the engine may require it to
+/// compile functions lazily.
+///
+/// This suits "compile once, run per-request" architectures: the script is
+/// compiled once, and then executed against each request's context (of any
+/// runtime), with that context's globals. In contrast, a function returned by
+/// `parse` can be called under any guard of the runtime it was created in,
+/// but its code always runs with the globals of the context that parsed it.
+#[derive(Clone, Debug)]
+pub struct CompiledScript {
+    id: usize,
+    name: String,
+    code: String,
+    bytecode: Vec<u8>,
+}
+
+/// The sources of compiled scripts executed within a context.
+///
+/// The engine may request a script's source for as long as its bytecode is
+/// alive, so each source is kept (with the number of live bytecode buffers of
+/// its script) until all the buffers have been collected.
+#[derive(Clone, Default)]
+struct CompiledSources(Arc<Mutex<HashMap<usize, (String, usize)>>>);
+
+impl CompiledScript {
+    /// Creates a script from previously compiled bytecode.
+    ///
+    /// The bytecode must have been produced by the same engine version, using
+    /// the same source code. Otherwise execution will fail.
+    pub fn from_bytes(name: &str, code: &str, bytecode: Vec<u8>) -> Self {
+        CompiledScript {
+            id: SCRIPT_ID.fetch_add(1, Ordering::SeqCst),
+            name: name.to_string(),
+            code: code.to_string(),
+            bytecode: bytecode,
+        }
+    }
+
+    /// Returns the script's bytecode.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// Returns the script's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Executes the script and returns its value.
+    pub fn run(&self, guard: &ContextGuard) -> Result<value::Value> {
+        policy::enforced(guard, &self.name, &self.code, || {
+            audit::audited(guard, &self.name, &self.code, || self.process(guard, JsRunSerialized))
+        })
+    }
+
+    /// Executes the script a number of times, discarding the results.
+    ///
+    /// This is not audited, and the script's side effects (e.g defined
+    /// globals) are repeated. See `warm_up` for details.
+    pub fn warm_up(&self, guard: &ContextGuard, runs: usize) -> Result<()> {
+        for _ in 0..runs {
+            self.process(guard, JsRunSerialized)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the script as a function, without executing it.
+    pub fn parse(&self, guard: &ContextGuard) -> Result<value::Function> {
+        policy::consulted(guard, &self.name, &self.code)?;
+        self.process(guard, JsParseSerialized)?
             .into_function()
-            .expect("converting parsing result to function"))
+            .ok_or_else(|| "parsing result is not a function".into())
+    }
+
+    /// Either parses or executes the bytecode.
+    fn process(&self, guard: &ContextGuard, api: SerializedCall) -> Result<value::Value> {
+        let context = guard.context();
+        if context.get_user_data::<CompiledSources>().is_none() {
+            context.insert_user_data(CompiledSources::default());
+        }
+
+        let sources = context.get_user_data::<CompiledSources>().expect("retrieving compiled sources").clone();
+        sources.0.lock().unwrap().entry(self.id).or_insert_with(|| (self.code.clone(), 0)).1 += 1;
+
+        // The engine refers to the bytecode for as long as the script is alive
+        let bytecode = value::ArrayBuffer::with_data(guard, self.bytecode.clone());
+        let id = self.id;
+        bytecode.on_collect(guard, Box::new(move || {
+            let mut sources = sources.0.lock().unwrap();
+            let unused = sources.get_mut(&id).map_or(false, |entry| {
+                entry.1 -= 1;
+                entry.1 == 0
+            });
+            if unused {
+                sources.remove(&id);
+            }
+        }));
+        let name = value::String::new(guard, &self.name);
+
+        unsafe {
+            let mut result = JsValueRef::new();
+            let code = api(bytecode.as_raw(),
+                           Some(Self::load_source),
+                           self.id as JsSourceContext,
+                           name.as_raw(),
+                           &mut result);
+            if code == JsErrorCode::BadSerializedScript {
+                return Err(format!("bytecode of script '{}' is incompatible with the engine", self.name).into());
+            }
+            jstry(code).map(|_| value::Value::from_raw(result))
+        }
+    }
+
+    /// A source callback, triggered when the engine requires the source code.
+    unsafe extern "system" fn load_source(id: JsSourceContext,
+                                          result: *mut JsValueRef,
+                                          attributes: *mut JsParseScriptAttributes) -> bool {
+        Context::exec_with_current(|guard| {
+            let context = guard.context();
+            let code = context.get_user_data::<CompiledSources>()
+                .and_then(|sources| sources.0.lock().unwrap().get(&(id as usize)).map(|entry| entry.0.clone()));
+
+            code.map_or(false, |code| {
+                let buffer = value::ArrayBuffer::with_data(guard, code.into_bytes());
+                *result = buffer.as_raw();
+                *attributes = JsParseScriptAttributeNone;
+                true
+            })
+        }).unwrap_or(false)
+    }
 }
 
+/// Function definition for serialized script calls.
+type SerializedCall = unsafe extern "system" fn(JsValueRef,
+                                                JsSerializedLoadScriptCallback,
+                                                JsSourceContext,
+                                                JsValueRef,
+                                                *mut JsValueRef) -> JsErrorCode;
+
 /// Used for processing code.
 #[derive(Copy, Clone, Debug)]
 enum CodeAction {
@@ -62,9 +582,18 @@ enum CodeAction {
 }
 
 /// Either parses or executes a script.
-fn process_code(guard: &ContextGuard, name: &str, code: &str, action: CodeAction) -> Result<value::Value> {
-    let name = value::String::new(guard, name);
-    let buffer = create_code_buffer(guard, code);
+fn process_code(guard: &ContextGuard, options: &ScriptOptions, code: &str, action: CodeAction) -> Result<value::Value> {
+    let name = value::String::new(guard, &options.url);
+
+    // The engine has no line offset, so the code is preceded by empty lines
+    // (the buffer references the code, so it must outlive the call)
+    let padding = options.start_line.saturating_sub(1) as usize;
+    let code = if padding > 0 {
+        Cow::Owned(format!("{}{}", "\n".repeat(padding), code))
+    } else {
+        Cow::Borrowed(code)
+    };
+    let buffer = create_code_buffer(guard, &code);
 
     let api = match action {
         CodeAction::Execute => JsRun,
@@ -74,7 +603,7 @@ fn process_code(guard: &ContextGuard, name: &str, code: &str, action: CodeAction
     unsafe {
         let mut result = JsValueRef::new();
         jstry(api(buffer.as_raw(),
-                  generate_source_context(),
+                  options.cookie.unwrap_or_else(generate_source_context),
                   name.as_raw(),
                   JsParseScriptAttributeNone,
                   &mut result))
@@ -100,7 +629,42 @@ fn generate_source_context() -> JsSourceContext {
 
 #[cfg(test)]
 mod tests {
-    use {test, error, script};
+    use std::time::Duration;
+    use {test, error, script, value, Context, Runtime};
+
+    #[test]
+    fn options() {
+        test::run_with_context(|guard| {
+            let options = script::ScriptOptions { start_line: 5, cookie: Some(7), ..script::ScriptOptions::new("file:///app.js") };
+            let error = script::eval_with_options(guard, "1 + 1;\n  null.foo", &options).unwrap_err();
+            let error = match *error.kind() {
+                error::ErrorKind::ScriptException(ref error) => error.clone(),
+                _ => panic!("expected script exception"),
+            };
+            assert_eq!((error.url.as_ref().map(|url| url.as_str()), error.line), (Some("file:///app.js"), Some(6)));
+
+            let error = script::parse_with_options(guard, "(", &options).err().unwrap();
+            assert_matches!(error.kind(), &error::ErrorKind::ScriptCompile(error::ScriptError { line: Some(5), .. }));
+        });
+    }
+
+    #[test]
+    fn stats() {
+        test::run_with_context(|guard| {
+            let options = script::ScriptOptions::new("stats.js");
+            let (result, stats) = script::eval_with_stats(guard, "
+                var total = 0;
+                for (var i = 0; i < 1000; i++) total += String(i).length;
+                total", &options);
+            assert_eq!(result.unwrap().to_integer(guard), 2890);
+            assert_eq!(script::eval(guard, "total").unwrap().to_integer(guard), 2890);
+            assert!(stats.execution_time > Duration::from_secs(0));
+
+            let (result, stats) = script::eval_with_stats(guard, "1 +)", &options);
+            assert_matches!(result.unwrap_err().kind(), &error::ErrorKind::ScriptCompile(_));
+            assert_eq!(stats.execution_time, Duration::from_secs(0));
+        });
+    }
 
     #[test]
     fn execute_exception() {
@@ -124,12 +688,181 @@ mod tests {
         });
     }
 
+    #[test]
+    fn run_in_contexts() {
+        let runtime = Runtime::new().unwrap();
+        let (first, second) = (Context::new(&runtime).unwrap(), Context::new(&runtime).unwrap());
+
+        let (script, function) = first.exec_with(|guard| {
+            script::eval(guard, "var origin = 'first'").unwrap();
+            (script::compile(guard, "origin").unwrap(), script::parse(guard, "origin").unwrap())
+        }).unwrap();
+
+        second.exec_with(|guard| {
+            script::eval(guard, "var origin = 'second'").unwrap();
+            for _ in 0..2 {
+                assert_eq!(script.run(guard).unwrap().to_string(guard), "second");
+                assert_eq!(function.call(guard, &[]).unwrap().to_string(guard), "first");
+            }
+
+            let invalid = script::CompiledScript::from_bytes("invalid", "origin", vec![0; 16]);
+            assert!(invalid.run(guard).unwrap_err().to_string().contains("incompatible"));
+        }).unwrap();
+    }
+
+    #[test]
+    fn warm_up() {
+        test::run_with_context(|guard| {
+            let handler = script::eval(guard, "var calls = 0; (function (x) { calls++; return x * 2; })")
+                .unwrap()
+                .into_function()
+                .unwrap();
+            script::warm_up(guard, &handler, &[&value::Number::new(guard, 2).into()], 50).unwrap();
+            assert_eq!(script::eval(guard, "calls").unwrap().to_integer(guard), 50);
+
+            let script = script::compile(guard, "calls++").unwrap();
+            script.warm_up(guard, 10).unwrap();
+            assert_eq!(script::eval(guard, "calls").unwrap().to_integer(guard), 60);
+        });
+    }
+
     #[test]
     fn parse_script() {
         test::run_with_context(|guard| {
             let func = script::parse(guard, "new Number(10)").unwrap();
             let result = func.call(guard, &[]).unwrap();
             assert_eq!(result.to_integer(guard), 10);
+
+            script::validate(guard, "var executed = true;").unwrap();
+            assert!(script::eval(guard, "typeof executed").unwrap().to_string(guard) == "undefined");
+            let error = script::validate(guard, "function (").unwrap_err();
+            assert_matches!(error.kind(), &error::ErrorKind::ScriptCompile(_));
+        });
+    }
+
+    #[test]
+    fn timeout() {
+        let runtime = Runtime::builder().enable_script_interrupt().build().unwrap();
+        let context = Context::new(&runtime).unwrap();
+        let guard = context.make_current().unwrap();
+
+        let timeout = Duration::from_millis(100);
+        let error = script::eval_with_timeout(&guard, "while (true) {}", timeout).unwrap_err();
+        assert_matches!(error.kind(), &error::ErrorKind::ScriptTerminated);
+
+        let result = script::eval_with_timeout(&guard, "5 + 5", timeout).unwrap();
+        assert_eq!(result.to_integer(&guard), 10);
+    }
+
+    #[test]
+    fn timeout_manual_scheduler() {
+        use std::thread;
+        use deadline::Scheduler;
+
+        let runtime = Runtime::builder().enable_script_interrupt().build().unwrap();
+        let context = Context::new(&runtime).unwrap();
+        let scheduler = Scheduler::manual();
+        context.set_scheduler(scheduler.clone());
+        let guard = context.make_current().unwrap();
+
+        let advance = thread::spawn(move || {
+            while scheduler.pending() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            scheduler.advance(Duration::from_secs(60)).unwrap();
+        });
+        let error = script::eval_with_timeout(&guard, "while (true) {}", Duration::from_secs(60)).unwrap_err();
+        assert_matches!(error.kind(), &error::ErrorKind::ScriptTerminated);
+        advance.join().unwrap();
+    }
+
+    #[test]
+    fn template() {
+        test::run_with_context(|guard| {
+            let data = script::eval(guard, "({ name: 'foo', items: [1, 2] })").unwrap();
+            let template = script::Template::compile(guard, "`${name}`: ${items.map(x => x * 2).join(', ')}").unwrap();
+            assert_eq!(template.render(guard, &data).unwrap(), "`foo`: 2, 4");
+            assert!(script::render_template(guard, "${name}", &5).is_err());
+            assert!(script::render_template(guard, "${", &data).is_err());
+
+            // Backticks are escaped unless the template escapes them itself
+            assert_eq!(script::render_template(guard, "C:\\\\`; throw 1; `", &data).unwrap(), "C:\\`; throw 1; `");
+            assert_eq!(script::render_template(guard, "a\\`b", &data).unwrap(), "a`b");
+
+            // Backticks in placeholders delimit nested templates
+            let nested = "${items.map(x => `<${x + '`'}>`).join(\"}\")}";
+            assert_eq!(script::render_template(guard, nested, &data).unwrap(), "<1`>}<2`>");
+        });
+    }
+
+    #[test]
+    fn compiled_script() {
+        let code = "(function() { var foo = function(x) { return x * 2; }; return foo(5); })()";
+        let bytecode = {
+            let (_runtime, context) = test::setup_env();
+            let guard = context.make_current().unwrap();
+
+            let script = script::compile(&guard, code).unwrap();
+            assert_eq!(script.run(&guard).unwrap().to_integer(&guard), 10);
+            assert_eq!(script.run(&guard).unwrap().to_integer(&guard), 10);
+            script.as_bytes().to_vec()
+        };
+
+        test::run_with_context(|guard| {
+            let script = script::CompiledScript::from_bytes("", code, bytecode);
+            let function = script.parse(guard).unwrap();
+            assert_eq!(function.call(guard, &[]).unwrap().to_integer(guard), 10);
+        });
+    }
+
+    #[test]
+    fn compiled_sources_released() {
+        let (runtime, context) = test::setup_env();
+        let guard = context.make_current().unwrap();
+        for index in 0..100 {
+            let script = script::compile(&guard, &format!("{} * 2", index)).unwrap();
+            assert_eq!(script.run(&guard).unwrap().to_integer(&guard), index * 2);
+        }
+
+        runtime.collect_garbage().unwrap();
+        let sources = guard.context().get_user_data::<script::CompiledSources>().unwrap().clone();
+        assert!(sources.0.lock().unwrap().len() < 100);
+    }
+
+    #[test]
+    fn eval_async() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+        use std::thread;
+
+        struct NoopWaker;
+
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) { }
+        }
+
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let waker = Waker::from(Arc::new(NoopWaker));
+            let mut context = Context::from_waker(&waker);
+            let mut future = Box::pin(future);
+            loop {
+                if let Poll::Ready(result) = future.as_mut().poll(&mut context) {
+                    return result;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        test::run_with_context(|guard| {
+            let result = block_on(script::eval_async(guard, "Promise.resolve(5).then(x => x * 2)")).unwrap();
+            assert_eq!(result.to_integer(guard), 10);
+
+            let result = block_on(script::eval_async(guard, "var a = []; Promise.resolve().then(() => a.push(1)); a"));
+            assert_eq!(result.unwrap().to_json(guard).unwrap(), "[1]");
+
+            let error = block_on(script::eval_async(guard, "Promise.reject(new Error('foo'))")).unwrap_err();
+            assert_matches!(error.kind(), &error::ErrorKind::ScriptException(ref error) if error.message == Some("foo".into()));
         });
     }
 }
@@ -0,0 +1,580 @@
+//! Serde integration, converting between Rust types and JavaScript values.
+//!
+//! Values are converted structurally; sequences become arrays, maps and
+//! structs become objects, and enums use an externally tagged representation
+//! (e.g `{ "Variant": value }`), matching the behavior of `serde_json`.
+//!
+//! Types of other crates can be converted by registering them, and wrapping
+//! their values in `value::Foreign` (see the `value::registry` module).
+//!
+//! This module requires the `serde` feature.
+use std::fmt::Display;
+use serde_lib::{de, ser};
+use serde_lib::de::IntoDeserializer;
+use context::ContextGuard;
+use error::*;
+use value::{self, registry, Value};
+use Property;
+
+/// Serializes a Rust value to a JavaScript value.
+pub fn to_value<T: ser::Serialize + ?Sized>(guard: &ContextGuard, value: &T) -> Result<Value> {
+    value.serialize(Serializer { guard: guard })
+}
+
+/// Deserializes a Rust value from a JavaScript value.
+pub fn from_value<T: de::DeserializeOwned>(guard: &ContextGuard, value: &Value) -> Result<T> {
+    T::deserialize(Deserializer { guard: guard, value: value.clone() })
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        message.to_string().into()
+    }
+}
+
+/// A serializer producing JavaScript values.
+#[derive(Copy, Clone)]
+struct Serializer<'a> {
+    guard: &'a ContextGuard<'a>,
+}
+
+/// A serializer for sequences and tuples.
+struct ArraySerializer<'a> {
+    guard: &'a ContextGuard<'a>,
+    array: value::Array,
+    index: u32,
+}
+
+/// A serializer for maps and structs.
+struct ObjectSerializer<'a> {
+    guard: &'a ContextGuard<'a>,
+    object: value::Object,
+    key: Option<String>,
+}
+
+/// A serializer for enum variants with fields, wrapping them in an object.
+struct VariantSerializer<'a, S> {
+    guard: &'a ContextGuard<'a>,
+    variant: &'static str,
+    inner: S,
+}
+
+impl<'a> Serializer<'a> {
+    fn array(self, length: usize) -> ArraySerializer<'a> {
+        ArraySerializer {
+            guard: self.guard,
+            array: value::Array::new(self.guard, length as u32),
+            index: 0,
+        }
+    }
+
+    fn object(self) -> ObjectSerializer<'a> {
+        ObjectSerializer {
+            guard: self.guard,
+            object: value::Object::new(self.guard),
+            key: None,
+        }
+    }
+
+    fn variant<S>(self, variant: &'static str, inner: S) -> VariantSerializer<'a, S> {
+        VariantSerializer { guard: self.guard, variant: variant, inner: inner }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = ArraySerializer<'a>;
+    type SerializeTuple = ArraySerializer<'a>;
+    type SerializeTupleStruct = ArraySerializer<'a>;
+    type SerializeTupleVariant = VariantSerializer<'a, ArraySerializer<'a>>;
+    type SerializeMap = ObjectSerializer<'a>;
+    type SerializeStruct = ObjectSerializer<'a>;
+    type SerializeStructVariant = VariantSerializer<'a, ObjectSerializer<'a>>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(value::Boolean::new(self.guard, v).into())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> { self.serialize_i32(v as i32) }
+    fn serialize_i16(self, v: i16) -> Result<Value> { self.serialize_i32(v as i32) }
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(value::Number::new(self.guard, v).into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value> { self.serialize_f64(v as f64) }
+    fn serialize_u8(self, v: u8) -> Result<Value> { self.serialize_i32(v as i32) }
+    fn serialize_u16(self, v: u16) -> Result<Value> { self.serialize_i32(v as i32) }
+    fn serialize_u32(self, v: u32) -> Result<Value> { self.serialize_f64(v as f64) }
+    fn serialize_u64(self, v: u64) -> Result<Value> { self.serialize_f64(v as f64) }
+    fn serialize_f32(self, v: f32) -> Result<Value> { self.serialize_f64(v as f64) }
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(value::Number::from_double(self.guard, v).into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(value::String::new(self.guard, v).into())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        let guard = self.guard;
+        Ok(value::Array::from_iter(guard, v.iter().map(|byte| value::Number::new(guard, *byte as i32))).into())
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(value::null(self.guard))
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(value::null(self.guard))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self,
+                              _name: &'static str,
+                              _index: u32,
+                              variant: &'static str) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(self,
+                                                            name: &'static str,
+                                                            value: &T) -> Result<Value> {
+        // Foreign values have already been converted using the registry
+        if name == registry::TOKEN {
+            return registry::SLOT.with(|slot| slot.borrow_mut().take()).ok_or_else(|| "missing foreign value".into());
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(self,
+                                                             _name: &'static str,
+                                                             _index: u32,
+                                                             variant: &'static str,
+                                                             value: &T) -> Result<Value> {
+        let object = value::Object::new(self.guard);
+        object.set(self.guard, &Property::new(self.guard, variant), &value.serialize(self)?);
+        Ok(object.into())
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(self.array(length.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, length: usize) -> Result<Self::SerializeTuple> {
+        Ok(self.array(length))
+    }
+
+    fn serialize_tuple_struct(self,
+                              _name: &'static str,
+                              length: usize) -> Result<Self::SerializeTupleStruct> {
+        Ok(self.array(length))
+    }
+
+    fn serialize_tuple_variant(self,
+                               _name: &'static str,
+                               _index: u32,
+                               variant: &'static str,
+                               length: usize) -> Result<Self::SerializeTupleVariant> {
+        Ok(self.variant(variant, self.array(length)))
+    }
+
+    fn serialize_map(self, _length: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(self.object())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _length: usize) -> Result<Self::SerializeStruct> {
+        Ok(self.object())
+    }
+
+    fn serialize_struct_variant(self,
+                                _name: &'static str,
+                                _index: u32,
+                                variant: &'static str,
+                                _length: usize) -> Result<Self::SerializeStructVariant> {
+        Ok(self.variant(variant, self.object()))
+    }
+}
+
+impl<'a> ArraySerializer<'a> {
+    fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let value = to_value(self.guard, value)?;
+        self.array.set_index(self.guard, self.index, &value);
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for ArraySerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.array.into())
+    }
+}
+
+impl<'a> ser::SerializeTuple for ArraySerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.array.into())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for ArraySerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.array.into())
+    }
+}
+
+impl<'a> ser::SerializeMap for ObjectSerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        let key = to_value(self.guard, key)?;
+        if !key.is_string() && !key.is_number() && !key.is_boolean() {
+            return Err(format!("{:?} value is not a valid key", key.get_type()).into());
+        }
+        self.key = Some(key.to_string(self.guard));
+        Ok(())
+    }
+
+    fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().expect("serializing map value without key");
+        let value = to_value(self.guard, value)?;
+        self.object.set(self.guard, &Property::new(self.guard, &key), &value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.object.into())
+    }
+}
+
+impl<'a> ser::SerializeStruct for ObjectSerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self,
+                                                   key: &'static str,
+                                                   value: &T) -> Result<()> {
+        let value = to_value(self.guard, value)?;
+        self.object.set(self.guard, &Property::new(self.guard, key), &value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.object.into())
+    }
+}
+
+impl<'a, S> VariantSerializer<'a, S> {
+    fn wrap(self, value: Value) -> Result<Value> {
+        let object = value::Object::new(self.guard);
+        object.set(self.guard, &Property::new(self.guard, self.variant), &value);
+        Ok(object.into())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for VariantSerializer<'a, ArraySerializer<'a>> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = self.inner.array.clone().into();
+        self.wrap(value)
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for VariantSerializer<'a, ObjectSerializer<'a>> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self,
+                                                   key: &'static str,
+                                                   value: &T) -> Result<()> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = self.inner.object.clone().into();
+        self.wrap(value)
+    }
+}
+
+/// A deserializer reading from JavaScript values.
+struct Deserializer<'a> {
+    guard: &'a ContextGuard<'a>,
+    value: Value,
+}
+
+/// Access to the elements of an array.
+struct ArrayAccess<'a> {
+    guard: &'a ContextGuard<'a>,
+    array: value::Array,
+    index: u32,
+    length: u32,
+}
+
+/// Access to the own enumerable properties of an object.
+struct ObjectAccess<'a> {
+    guard: &'a ContextGuard<'a>,
+    object: value::Object,
+    keys: ::std::vec::IntoIter<String>,
+    value: Option<Value>,
+}
+
+/// Access to an externally tagged enum variant.
+struct TaggedVariant<'a> {
+    guard: &'a ContextGuard<'a>,
+    variant: String,
+    value: Value,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let guard = self.guard;
+
+        if self.value.is_undefined() || self.value.is_null() {
+            return visitor.visit_unit();
+        }
+
+        if let Some(boolean) = self.value.clone().into_boolean() {
+            return visitor.visit_bool(boolean.value());
+        }
+
+        if let Some(number) = self.value.clone().into_number() {
+            let number = number.value_double();
+            return if number.fract() == 0.0 && number.abs() < 9007199254740992.0 {
+                visitor.visit_i64(number as i64)
+            } else {
+                visitor.visit_f64(number)
+            };
+        }
+
+        if let Some(string) = self.value.clone().into_string() {
+            return visitor.visit_string(string.value());
+        }
+
+        if self.value.is_function() || self.value.is_external() {
+            return Err(format!("{:?} value could not be deserialized", self.value.get_type()).into());
+        }
+
+        if let Some(array) = self.value.clone().into_array() {
+            let length = array.len(guard) as u32;
+            return visitor.visit_seq(ArrayAccess {
+                guard: guard,
+                array: array,
+                index: 0,
+                length: length,
+            });
+        }
+
+        let object = self.value.clone().into_object().expect("converting value to object");
+        let keys = object.get_own_property_names(guard)
+            .iter(guard)
+            .map(|key| key.to_string(guard))
+            .collect::<Vec<_>>();
+        visitor.visit_map(ObjectAccess {
+            guard: guard,
+            object: object,
+            keys: keys.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.value.is_undefined() || self.value.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(self,
+                                                       name: &'static str,
+                                                       visitor: V) -> Result<V::Value> {
+        // Foreign values are converted using the registry, by `Foreign`
+        if name == registry::TOKEN {
+            registry::SLOT.with(|slot| *slot.borrow_mut() = Some(self.value));
+            return visitor.visit_unit();
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(self,
+                                             _name: &'static str,
+                                             _variants: &'static [&'static str],
+                                             visitor: V) -> Result<V::Value> {
+        let guard = self.guard;
+        if let Some(string) = self.value.clone().into_string() {
+            return visitor.visit_enum(string.value().into_deserializer());
+        }
+
+        let variant = self.value.clone().into_object().and_then(|object| {
+            let keys = object.get_own_property_names(guard);
+            if keys.len(guard) != 1 {
+                return None;
+            }
+
+            let variant = keys.get_index(guard, 0).to_string(guard);
+            let value = object.get(guard, &Property::new(guard, &variant));
+            Some(TaggedVariant { guard: guard, variant: variant, value: value })
+        });
+
+        match variant {
+            Some(variant) => visitor.visit_enum(variant),
+            None => Err("expected String or Object with a single key for enum".into()),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for ArrayAccess<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.index >= self.length {
+            return Ok(None);
+        }
+
+        let value = self.array.get_index(self.guard, self.index);
+        self.index += 1;
+        seed.deserialize(Deserializer { guard: self.guard, value: value }).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.length - self.index) as usize)
+    }
+}
+
+impl<'de, 'a> de::MapAccess<'de> for ObjectAccess<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.keys.next() {
+            Some(key) => {
+                self.value = Some(self.object.get(self.guard, &Property::new(self.guard, &key)));
+                seed.deserialize(key.into_deserializer()).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().expect("deserializing map value without key");
+        seed.deserialize(Deserializer { guard: self.guard, value: value })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.keys.len())
+    }
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for TaggedVariant<'a> {
+    type Error = Error;
+    type Variant = Deserializer<'a>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, Deserializer { guard: self.guard, value: self.value }))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _length: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(self,
+                                           _fields: &'static [&'static str],
+                                           visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use {test, script};
+
+    #[test]
+    fn round_trip() {
+        test::run_with_context(|guard| {
+            let mut map = BTreeMap::new();
+            map.insert("foo".to_string(), (1u32, Some("bar".to_string()), vec![true, false]));
+            map.insert("baz".to_string(), (2u32, None, Vec::new()));
+
+            let value = super::to_value(guard, &map).unwrap();
+            assert_eq!(value.to_json(guard).unwrap(),
+                       r#"{"baz":[2,null,[]],"foo":[1,"bar",[true,false]]}"#);
+
+            let result: BTreeMap<String, (u32, Option<String>, Vec<bool>)> =
+                super::from_value(guard, &value).unwrap();
+            assert_eq!(result, map);
+        });
+    }
+
+    #[test]
+    fn invalid() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "({ foo: 'bar' })").unwrap();
+            assert!(super::from_value::<Vec<u32>>(guard, &value).is_err());
+            assert!(super::from_value::<BTreeMap<String, u32>>(guard, &value).is_err());
+        });
+    }
+}
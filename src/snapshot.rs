@@ -0,0 +1,193 @@
+//! Context independent snapshots of a context's global bindings.
+use std::sync::Arc;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use value::{self, ExportedValue, Value};
+use value::function::FunctionCallback;
+use {script, Property};
+
+/// A value in a snapshot's graph.
+enum Node {
+    /// A value copied using structured clone semantics.
+    Exported(ExportedValue),
+    /// A native function, re-created with the same callback.
+    Native(String, Arc<Box<FunctionCallback>>),
+    /// A script function, re-created from its source.
+    Script(String),
+    /// An object or array, and the nodes of its own properties.
+    Object(bool, Vec<(String, usize)>),
+}
+
+/// A snapshot of the global bindings defined in a context.
+///
+/// Values are stored as a graph, so shared and cyclic references are
+/// preserved. Values which cannot be copied (e.g external objects, or
+/// functions of the engine itself) are omitted, whereas writing a script
+/// function which cannot be re-created from its source is an error.
+pub struct Snapshot {
+    nodes: Vec<Node>,
+    globals: Vec<(String, usize)>,
+}
+
+impl Snapshot {
+    /// Reads the global bindings of the guard's context, except the ones in
+    /// a list of names (e.g the built-ins).
+    pub fn read(guard: &ContextGuard, excluded: &[String]) -> Result<Snapshot> {
+        let global = guard.global();
+        let mut reader = Reader { guard: guard, nodes: Vec::new(), visited: Vec::new() };
+        let mut globals = Vec::new();
+
+        for name in own_property_names(guard, &global) {
+            if excluded.contains(&name) {
+                continue;
+            }
+
+            let value = global.get(guard, &Property::new(guard, &name));
+            if let Some(node) = reader.read(&value) {
+                globals.push((name, node));
+            }
+        }
+
+        Ok(Snapshot { nodes: reader.nodes, globals: globals })
+    }
+
+    /// Defines the snapshot's bindings in the guard's context.
+    pub fn write(&self, guard: &ContextGuard) -> Result<()> {
+        let global = guard.global();
+        let mut values = vec![None; self.nodes.len()];
+        for &(ref name, node) in &self.globals {
+            let value = self.write_node(guard, node, name, &mut values)?;
+            global.set(guard, &Property::new(guard, name), &value);
+        }
+        Ok(())
+    }
+
+    /// Creates the value of a node (objects are created before their
+    /// properties, so cycles can be resolved).
+    fn write_node(&self,
+                  guard: &ContextGuard,
+                  index: usize,
+                  path: &str,
+                  values: &mut Vec<Option<Value>>) -> Result<Value> {
+        if let Some(ref value) = values[index] {
+            return Ok(value.clone());
+        }
+
+        let value = match self.nodes[index] {
+            Node::Exported(ref exported) => exported.write(guard),
+            Node::Native(ref name, ref callback) => {
+                value::Function::with_shared_callback(guard, name, callback.clone()).into()
+            },
+            Node::Script(ref source) => script_function(guard, source).ok_or_else(|| {
+                format!("script function `{}` cannot be re-created from its source", path)
+            })?,
+            Node::Object(is_array, ref properties) => {
+                let object: value::Object = if is_array {
+                    value::Array::new(guard, 0).into()
+                } else {
+                    value::Object::new(guard)
+                };
+
+                values[index] = Some(object.clone().into());
+                for &(ref key, node) in properties {
+                    let property = self.write_node(guard, node, &format!("{}.{}", path, key), values)?;
+                    object.set(guard, &Property::new(guard, key), &property);
+                }
+                object.into()
+            },
+        };
+
+        values[index] = Some(value.clone());
+        Ok(value)
+    }
+}
+
+/// Re-creates a script function from its source.
+fn script_function(guard: &ContextGuard, source: &str) -> Option<Value> {
+    script::eval(guard, &format!("({})", source)).ok().or_else(|| {
+        // Sources such as method shorthands are only valid in object literals
+        let shorthand = format!("(function() {{
+            var object = {{ {} }};
+            var descriptor = Object.getOwnPropertyDescriptor(object, Reflect.ownKeys(object)[0]);
+            return descriptor.value || descriptor.get || descriptor.set;
+        }})()", source);
+        script::eval(guard, &shorthand).ok()
+    }).and_then(|function| if function.is_function() { Some(function) } else { None })
+}
+
+/// The state of a snapshot being read.
+struct Reader<'a> {
+    guard: &'a ContextGuard<'a>,
+    nodes: Vec<Node>,
+    visited: Vec<(Value, usize)>,
+}
+
+impl<'a> Reader<'a> {
+    /// Reads a value into a node, unless it cannot be copied.
+    fn read(&mut self, value: &Value) -> Option<usize> {
+        let guard = self.guard;
+        if let Some(&(_, index)) = self.visited.iter().find(|&&(ref visited, _)| visited.strict_equals(guard, value)) {
+            return Some(index);
+        }
+
+        let node = if let Some(function) = value.clone().into_function() {
+            match function.shared_callback() {
+                Some(callback) => {
+                    let name = function.get(guard, &Property::new(guard, "name")).to_string(guard);
+                    Node::Native(name, callback)
+                },
+                None => {
+                    let source = value.to_string(guard);
+                    if source.contains("[native code]") {
+                        return None;
+                    }
+                    Node::Script(source)
+                },
+            }
+        } else if value.is_external() {
+            return None;
+        } else if value.is_object() && !is_buffer(value) {
+            let object = value.clone().into_object().expect("converting value to object");
+            let index = self.push(value, Node::Object(value.is_array(), Vec::new()));
+
+            let mut properties = Vec::new();
+            for key in own_property_names(guard, &object) {
+                let property = object.get(guard, &Property::new(guard, &key));
+                if let Some(node) = self.read(&property) {
+                    properties.push((key, node));
+                }
+            }
+
+            self.nodes[index] = Node::Object(value.is_array(), properties);
+            return Some(index);
+        } else {
+            Node::Exported(value.export(guard).ok()?)
+        };
+
+        Some(self.push(value, node))
+    }
+
+    /// Adds a node, returning its index.
+    fn push(&mut self, value: &Value, node: Node) -> usize {
+        self.nodes.push(node);
+        self.visited.push((value.clone(), self.nodes.len() - 1));
+        self.nodes.len() - 1
+    }
+}
+
+/// Returns whether a value is an array buffer or typed array.
+fn is_buffer(value: &Value) -> bool {
+    match value.get_type() {
+        JsValueType::ArrayBuffer | JsValueType::TypedArray => true,
+        _ => false,
+    }
+}
+
+/// Returns the own property names of an object.
+pub fn own_property_names(guard: &ContextGuard, object: &value::Object) -> Vec<String> {
+    object.get_own_property_names(guard)
+        .iter(guard)
+        .map(|name| name.to_string(guard))
+        .collect()
+}
@@ -0,0 +1,153 @@
+//! Registry of source maps for transformed sources.
+//!
+//! Whenever a source is transformed before being parsed (e.g by a module
+//! loader's transformers), the emitted source maps are registered with the
+//! context. The positions of `ScriptError`s in the executed code are mapped
+//! back to the original source using these (only version 3 maps are
+//! supported, and the `stack` property is left as is).
+use std::collections::HashMap;
+use context::ContextGuard;
+use Context;
+use Property;
+
+/// The base64 alphabet of VLQ encoded mappings.
+const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The source maps of a context, keyed by source name.
+struct Registry(HashMap<String, Vec<String>>);
+
+/// Registers a source map for a source name.
+///
+/// A source may be transformed several times, therefore multiple maps can be
+/// associated with the same name. They are kept in the order in which they
+/// were registered (i.e from the first transformation to the last).
+pub fn register(context: &Context, name: &str, map: String) {
+    if context.get_user_data::<Registry>().is_none() {
+        context.insert_user_data(Registry(HashMap::new()));
+    }
+
+    let registry = context.get_user_data_mut::<Registry>().expect("retrieving source map registry");
+    registry.0.entry(name.to_string()).or_insert_with(Vec::new).push(map);
+}
+
+/// Returns the source maps registered for a source name.
+pub fn get<'a>(context: &'a Context, name: &str) -> Option<&'a [String]> {
+    context.get_user_data::<Registry>()
+        .and_then(|registry| registry.0.get(name))
+        .map(|maps| maps.as_slice())
+}
+
+/// Removes all source maps registered for a source name.
+pub fn remove(context: &Context, name: &str) -> Option<Vec<String>> {
+    context.get_user_data_mut::<Registry>().and_then(|registry| registry.0.remove(name))
+}
+
+/// Maps a zero-based position in a transformed source to its original
+/// source, returning the original's name and zero-based position.
+///
+/// The maps are applied from the last transformation to the first. `None` is
+/// returned if no maps are registered, or if one of them has no mapping for
+/// the position (or cannot be parsed).
+pub(crate) fn original_position(guard: &ContextGuard, name: &str, line: u32, column: u32) -> Option<(String, u32, u32)> {
+    let maps = get(&guard.context(), name)?.to_vec();
+    maps.iter().rev().try_fold((name.to_string(), line, column), |(_, line, column), map| {
+        lookup(guard, map, line, column)
+    })
+}
+
+/// Finds the original position of a generated position in a source map.
+fn lookup(guard: &ContextGuard, map: &str, line: u32, column: u32) -> Option<(String, u32, u32)> {
+    let map = guard.json_parse(map).ok()?.into_object()?;
+    let property = |name: &str| map.try_get(guard, &Property::new(guard, name)).ok();
+    let mappings = property("mappings")?.into_string()?.value();
+    let sources = property("sources")?.into_array()?;
+
+    // The source index and original position are relative to the previous
+    // segment, across lines, while the generated column resets on each line
+    let (mut source, mut original_line, mut original_column) = (0i64, 0i64, 0i64);
+    let mut found = None;
+    for (index, segments) in mappings.split(';').enumerate() {
+        let mut generated = 0i64;
+        for segment in segments.split(',').filter(|segment| !segment.is_empty()) {
+            let fields = decode(segment)?;
+            generated += fields[0];
+            if fields.len() >= 4 {
+                source += fields[1];
+                original_line += fields[2];
+                original_column += fields[3];
+            }
+
+            if index as u32 == line && generated <= column as i64 && fields.len() >= 4 {
+                found = Some((source, original_line, original_column));
+            }
+        }
+
+        if index as u32 == line {
+            break;
+        }
+    }
+
+    let (source, line, column) = found?;
+    let name = sources.get_index(guard, source as u32).into_string()?.value();
+    Some((name, line as u32, column as u32))
+}
+
+/// Decodes the base64 VLQ fields of a mapping segment.
+fn decode(segment: &str) -> Option<Vec<i64>> {
+    let mut fields = Vec::new();
+    let (mut value, mut shift) = (0i64, 0);
+    for byte in segment.bytes() {
+        let digit = ALPHABET.iter().position(|&character| character == byte)? as i64;
+        if shift > 60 {
+            return None;
+        }
+
+        value += (digit & 0x1F) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+            continue;
+        }
+
+        fields.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+        value = 0;
+        shift = 0;
+    }
+    if shift == 0 && !fields.is_empty() { Some(fields) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use test;
+
+    #[test]
+    fn registry() {
+        test::run_with_context(|guard| {
+            let context = guard.context();
+            assert!(super::get(&context, "foo.js").is_none());
+
+            super::register(&context, "foo.js", "{}".to_string());
+            super::register(&context, "foo.js", "[]".to_string());
+            assert_eq!(super::get(&context, "foo.js").unwrap(), ["{}", "[]"]);
+
+            assert!(super::remove(&context, "foo.js").is_some());
+            assert!(super::get(&context, "foo.js").is_none());
+        });
+    }
+
+    #[test]
+    fn original_position() {
+        test::run_with_context(|guard| {
+            let context = guard.context();
+            assert!(super::original_position(guard, "out.js", 0, 0).is_none());
+
+            // The first line is inserted, and the second shifted by two columns
+            let map = r#"{ "version": 3, "sources": ["in.ts"], "mappings": ";AAAA,EAAE;AACA" }"#;
+            super::register(&context, "out.js", map.to_string());
+            assert_eq!(super::original_position(guard, "out.js", 1, 4), Some(("in.ts".to_string(), 0, 2)));
+            assert_eq!(super::original_position(guard, "out.js", 2, 0), Some(("in.ts".to_string(), 1, 2)));
+            assert!(super::original_position(guard, "out.js", 0, 0).is_none());
+            assert_eq!(super::decode("2H"), Some(vec![123]));
+            assert_eq!(super::decode("D"), Some(vec![-1]));
+        });
+    }
+}
@@ -0,0 +1,249 @@
+//! Incremental streaming of values to a sink.
+//!
+//! Instead of converting a value in one shot (e.g using `ExportedValue`),
+//! a value is walked element by element, and each element is emitted to a
+//! `StreamSink` as soon as it has been read. This avoids materializing a
+//! copy of very large values on the host side.
+//!
+//! Values are streamed using JSON semantics: `undefined` and functions are
+//! omitted from objects and emitted as nulls in arrays, and non-finite
+//! numbers are emitted as they are (the `JsonWriter` writes them as nulls).
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::stream::JsonWriter;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let mut writer = JsonWriter::new(Vec::new());
+//! js::script::eval_streaming(&guard, "({ rows: [[1, 2], [3, 4]] })", &mut writer).unwrap();
+//! assert_eq!(writer.into_inner(), br#"{"rows":[[1,2],[3,4]]}"#);
+//! ```
+use std::io::Write;
+use chakracore_sys::JsValueType;
+use context::ContextGuard;
+use error::*;
+use util::quote;
+use value::Value;
+use Property;
+
+/// A receiver of streamed values.
+///
+/// Arrays and objects are emitted as a sequence of events, delimited by
+/// their `begin` and `end` events. Each object property is preceded by a
+/// `key` event.
+pub trait StreamSink {
+    /// Emits a null (or undefined) value.
+    fn null(&mut self) -> Result<()>;
+    /// Emits a boolean.
+    fn boolean(&mut self, value: bool) -> Result<()>;
+    /// Emits a number.
+    fn number(&mut self, value: f64) -> Result<()>;
+    /// Emits a string.
+    fn string(&mut self, value: &str) -> Result<()>;
+    /// Emits the contents of an array buffer.
+    fn bytes(&mut self, value: &[u8]) -> Result<()>;
+    /// Begins an array (or typed array) with a number of elements.
+    fn begin_array(&mut self, length: usize) -> Result<()>;
+    /// Ends the current array.
+    fn end_array(&mut self) -> Result<()>;
+    /// Begins an object.
+    fn begin_object(&mut self) -> Result<()>;
+    /// Emits the key of the next object property.
+    fn key(&mut self, key: &str) -> Result<()>;
+    /// Ends the current object.
+    fn end_object(&mut self) -> Result<()>;
+}
+
+/// Streams a value to a sink.
+///
+/// Functions and external objects cannot be streamed at the top level, and
+/// cyclic structures result in an error. The sink may already have received
+/// events when an error occurs.
+pub fn write(guard: &ContextGuard, value: &Value, sink: &mut StreamSink) -> Result<()> {
+    if is_omitted(value) && !value.is_undefined() {
+        return Err(format!("{:?} value could not be streamed", value.get_type()).into());
+    }
+    write_value(guard, value, sink, &mut Vec::new())
+}
+
+/// Returns whether a value is omitted from objects.
+fn is_omitted(value: &Value) -> bool {
+    value.is_undefined() || value.is_function() || value.is_external()
+}
+
+/// Streams a value, keeping track of its ancestors to detect cycles.
+fn write_value(guard: &ContextGuard, value: &Value, sink: &mut StreamSink, ancestors: &mut Vec<Value>) -> Result<()> {
+    if is_omitted(value) || value.is_null() {
+        return sink.null();
+    }
+
+    if let Some(boolean) = value.clone().into_boolean() {
+        return sink.boolean(boolean.value());
+    }
+
+    if let Some(number) = value.clone().into_number() {
+        return sink.number(number.value_double());
+    }
+
+    if let Some(string) = value.clone().into_string() {
+        return sink.string(&string.value());
+    }
+
+    if let Some(buffer) = value.clone().into_array_buffer() {
+        return sink.bytes(buffer.as_slice());
+    }
+
+    if ancestors.iter().any(|ancestor| ancestor.strict_equals(guard, value)) {
+        return Err("cyclic value could not be streamed".into());
+    }
+
+    let object = value.clone().into_object().expect("converting value to object");
+    ancestors.push(value.clone());
+
+    match value.get_type() {
+        JsValueType::Array | JsValueType::TypedArray => {
+            let length = object.get(guard, &Property::new(guard, "length")).to_integer(guard).max(0) as u32;
+            sink.begin_array(length as usize)?;
+            for index in 0..length {
+                write_value(guard, &object.get_index(guard, index), sink, ancestors)?;
+            }
+            sink.end_array()?;
+        },
+        _ => {
+            sink.begin_object()?;
+            for key in object.get_own_property_names(guard).iter(guard) {
+                let key = key.to_string(guard);
+                let property = object.get(guard, &Property::new(guard, &key));
+                if !is_omitted(&property) {
+                    sink.key(&key)?;
+                    write_value(guard, &property, sink, ancestors)?;
+                }
+            }
+            sink.end_object()?;
+        },
+    }
+
+    ancestors.pop();
+    Ok(())
+}
+
+/// A sink writing streamed values as JSON.
+///
+/// Array buffers are written as arrays of bytes.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    /// Whether the current array or object has any elements yet.
+    nested: Vec<bool>,
+    /// Whether a key has been written, but not its value.
+    keyed: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Creates a sink writing JSON to a writer.
+    pub fn new(writer: W) -> Self {
+        JsonWriter { writer: writer, nested: Vec::new(), keyed: false }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes the separator preceding a value, followed by the value.
+    fn value(&mut self, json: &str) -> Result<()> {
+        if self.keyed {
+            self.keyed = false;
+        } else if let Some(populated) = self.nested.last_mut().map(|populated| ::std::mem::replace(populated, true)) {
+            if populated {
+                self.raw(",")?;
+            }
+        }
+        self.raw(json)
+    }
+
+    /// Writes text as it is.
+    fn raw(&mut self, text: &str) -> Result<()> {
+        self.writer.write_all(text.as_bytes()).chain_err(|| "writing JSON")
+    }
+}
+
+impl<W: Write> StreamSink for JsonWriter<W> {
+    fn null(&mut self) -> Result<()> {
+        self.value("null")
+    }
+
+    fn boolean(&mut self, value: bool) -> Result<()> {
+        self.value(if value { "true" } else { "false" })
+    }
+
+    fn number(&mut self, value: f64) -> Result<()> {
+        if value.is_finite() { self.value(&value.to_string()) } else { self.null() }
+    }
+
+    fn string(&mut self, value: &str) -> Result<()> {
+        self.value(&quote(value))
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> Result<()> {
+        let bytes = value.iter().map(|byte| byte.to_string()).collect::<Vec<_>>();
+        self.value(&format!("[{}]", bytes.join(",")))
+    }
+
+    fn begin_array(&mut self, _length: usize) -> Result<()> {
+        self.value("[")?;
+        self.nested.push(false);
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        self.nested.pop();
+        self.raw("]")
+    }
+
+    fn begin_object(&mut self) -> Result<()> {
+        self.value("{")?;
+        self.nested.push(false);
+        Ok(())
+    }
+
+    fn key(&mut self, key: &str) -> Result<()> {
+        self.value(&quote(key))?;
+        self.raw(":")?;
+        self.keyed = true;
+        Ok(())
+    }
+
+    fn end_object(&mut self) -> Result<()> {
+        self.nested.pop();
+        self.raw("}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use super::JsonWriter;
+
+    #[test]
+    fn json() {
+        test::run_with_context(|guard| {
+            let mut writer = JsonWriter::new(Vec::new());
+            script::eval_streaming(guard, r#"({
+                text: 'a "quoted"\n\u0001 line',
+                list: [1.5, -2, null, undefined, () => {}, NaN],
+                skipped: undefined,
+                typed: new Uint8Array([7, 8]),
+                nested: { empty: {}, none: [] },
+            })"#, &mut writer).unwrap();
+
+            let json = String::from_utf8(writer.into_inner()).unwrap();
+            assert_eq!(json, r#"{"text":"a \"quoted\"\n\u0001 line","list":[1.5,-2,null,null,null,null],"typed":[7,8],"nested":{"empty":{},"none":[]}}"#);
+            assert_eq!(guard.json_parse(&json).unwrap().to_json(guard).unwrap(), json);
+
+            let mut writer = JsonWriter::new(Vec::new());
+            assert!(script::eval_streaming(guard, "var cycle = [1]; cycle.push(cycle); cycle", &mut writer).is_err());
+            assert!(script::eval_streaming(guard, "(function () {})", &mut writer).is_err());
+        });
+    }
+}
@@ -0,0 +1,268 @@
+//! Templates describing host APIs, which can be instantiated in any context.
+//!
+//! Values cannot be shared between contexts, so hosts exposing the same API
+//! to several contexts would otherwise have to create every function and
+//! object once per context. A template describes the properties, accessors
+//! and methods of an object (or function) once, on the host side, and creates
+//! them whenever it is instantiated. Callbacks are shared by all instances.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::template::{FunctionTemplate, ObjectTemplate};
+//! let mut point = FunctionTemplate::new("Point", Box::new(|guard, info| {
+//!     let this = info.this.into_object().unwrap();
+//!     this.set(guard, &js::Property::new(guard, "x"), &info.arguments[0]);
+//!     Ok(this.into())
+//! }));
+//! point.prototype().method("double", Box::new(|guard, info| {
+//!     let x = info.this.into_object().unwrap().get(guard, &js::Property::new(guard, "x"));
+//!     Ok(js::value::Number::new(guard, x.to_integer(guard) * 2).into())
+//! }));
+//!
+//! let mut api = ObjectTemplate::new();
+//! api.set("version", "1.0").function("Point", point);
+//!
+//! let runtime = js::Runtime::new().unwrap();
+//! for _ in 0..2 {
+//!     let context = js::Context::new(&runtime).unwrap();
+//!     let guard = context.make_current().unwrap();
+//!     api.apply(&guard, &guard.global()).unwrap();
+//!
+//!     let result = js::script::eval(&guard, "new Point(21).double()").unwrap();
+//!     assert_eq!(result.to_integer(&guard), 42);
+//! }
+//! ```
+use std::fmt;
+use std::sync::Arc;
+use context::ContextGuard;
+use error::*;
+use value::{self, HostValue, PropertyDescriptor};
+use value::function::FunctionCallback;
+use Property;
+
+/// A template for objects.
+///
+/// Properties are created in the order they were added, and later properties
+/// overwrite earlier ones with the same key.
+#[derive(Clone, Debug, Default)]
+pub struct ObjectTemplate {
+    properties: Vec<(String, Entry)>,
+}
+
+/// A template for native functions.
+///
+/// Besides its callback, the template describes the function's prototype
+/// (i.e the methods of objects created with `new`) and its own properties.
+#[derive(Clone, Debug)]
+pub struct FunctionTemplate {
+    name: String,
+    callback: Callback,
+    prototype: ObjectTemplate,
+    properties: ObjectTemplate,
+}
+
+/// A callback shared by all instances of a template.
+#[derive(Clone)]
+struct Callback(Arc<Box<FunctionCallback>>);
+
+#[derive(Clone, Debug)]
+enum Entry {
+    Value(HostValue),
+    Method(Callback),
+    Accessor(Callback, Option<Callback>),
+    Object(ObjectTemplate),
+    Function(FunctionTemplate),
+}
+
+impl ObjectTemplate {
+    /// Creates an empty object template.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a data property.
+    ///
+    /// Existing values (i.e `HostValue::Value`) belong to a specific context,
+    /// so they should not be part of templates instantiated in other ones.
+    pub fn set<K: Into<String>, V: Into<HostValue>>(&mut self, key: K, value: V) -> &mut Self {
+        self.add(key, Entry::Value(value.into()))
+    }
+
+    /// Adds a method, named after its key.
+    pub fn method<K: Into<String>>(&mut self, key: K, callback: Box<FunctionCallback>) -> &mut Self {
+        self.add(key, Entry::Method(Callback(Arc::new(callback))))
+    }
+
+    /// Adds an accessor property.
+    ///
+    /// The getter is called with the object as `this`, and the setter with
+    /// the assigned value as its argument. If no setter is specified, the
+    /// property is read-only.
+    pub fn accessor<K: Into<String>>(&mut self,
+                                     key: K,
+                                     getter: Box<FunctionCallback>,
+                                     setter: Option<Box<FunctionCallback>>) -> &mut Self {
+        let setter = setter.map(|setter| Callback(Arc::new(setter)));
+        self.add(key, Entry::Accessor(Callback(Arc::new(getter)), setter))
+    }
+
+    /// Adds a nested object, created from another template.
+    pub fn object<K: Into<String>>(&mut self, key: K, template: ObjectTemplate) -> &mut Self {
+        self.add(key, Entry::Object(template))
+    }
+
+    /// Adds a function, created from a function template.
+    pub fn function<K: Into<String>>(&mut self, key: K, template: FunctionTemplate) -> &mut Self {
+        self.add(key, Entry::Function(template))
+    }
+
+    /// Returns the number of properties added.
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Returns whether no properties have been added.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Creates a new object from the template in the guard's context.
+    pub fn instantiate(&self, guard: &ContextGuard) -> Result<value::Object> {
+        let object = value::Object::new(guard);
+        self.apply(guard, &object)?;
+        Ok(object)
+    }
+
+    /// Adds the template's properties to an existing object (e.g a context's
+    /// global object).
+    pub fn apply(&self, guard: &ContextGuard, object: &value::Object) -> Result<()> {
+        for &(ref key, ref entry) in &self.properties {
+            let property = Property::new(guard, key);
+            match *entry {
+                Entry::Value(ref value) => object.set(guard, &property, &value.build(guard)),
+                Entry::Method(ref callback) => object.set(guard, &property, &callback.function(guard, key)),
+                Entry::Object(ref template) => {
+                    let value = template.instantiate(guard)?;
+                    object.set(guard, &property, &value);
+                },
+                Entry::Function(ref template) => {
+                    let value = template.instantiate(guard)?;
+                    object.set(guard, &property, &value);
+                },
+                Entry::Accessor(ref getter, ref setter) => {
+                    let mut descriptor = PropertyDescriptor::new()
+                        .getter(getter.function(guard, &format!("get {}", key)))
+                        .enumerable(true)
+                        .configurable(true);
+                    if let Some(ref setter) = *setter {
+                        descriptor = descriptor.setter(setter.function(guard, &format!("set {}", key)));
+                    }
+                    object.define(guard, &property, &descriptor)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn add<K: Into<String>>(&mut self, key: K, entry: Entry) -> &mut Self {
+        self.properties.push((key.into(), entry));
+        self
+    }
+}
+
+impl FunctionTemplate {
+    /// Creates a function template with a name and a callback.
+    pub fn new<N: Into<String>>(name: N, callback: Box<FunctionCallback>) -> Self {
+        FunctionTemplate {
+            name: name.into(),
+            callback: Callback(Arc::new(callback)),
+            prototype: ObjectTemplate::new(),
+            properties: ObjectTemplate::new(),
+        }
+    }
+
+    /// Returns the template of the function's prototype.
+    pub fn prototype(&mut self) -> &mut ObjectTemplate {
+        &mut self.prototype
+    }
+
+    /// Returns the template of the function's own properties (i.e its static
+    /// members).
+    pub fn properties(&mut self) -> &mut ObjectTemplate {
+        &mut self.properties
+    }
+
+    /// Creates a new function from the template in the guard's context.
+    pub fn instantiate(&self, guard: &ContextGuard) -> Result<value::Function> {
+        let function = self.callback.function(guard, &self.name);
+        let prototype = self.prototype.instantiate(guard)?;
+        prototype.define(guard, &Property::new(guard, "constructor"), &PropertyDescriptor::new()
+            .value(function.clone())
+            .writable(true)
+            .configurable(true))?;
+
+        function.set(guard, &Property::new(guard, "prototype"), &prototype);
+        self.properties.apply(guard, &function)?;
+        Ok(function)
+    }
+}
+
+impl Callback {
+    fn function(&self, guard: &ContextGuard, name: &str) -> value::Function {
+        value::Function::with_shared_callback(guard, name, self.0.clone())
+    }
+}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Callback")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {script, test, value, Context, Property};
+    use super::{FunctionTemplate, ObjectTemplate};
+
+    #[test]
+    fn instantiate() {
+        let mut counter = FunctionTemplate::new("Counter", Box::new(|guard, info| {
+            let this = info.this.into_object().unwrap();
+            this.set(guard, &Property::new(guard, "count"), &value::Number::new(guard, 0));
+            Ok(this.into())
+        }));
+        counter.properties().set("kind", "counter");
+        counter.prototype()
+            .method("increment", Box::new(|guard, info| {
+                let this = info.this.into_object().unwrap();
+                let count = this.get(guard, &Property::new(guard, "count")).to_integer(guard) + 1;
+                this.set(guard, &Property::new(guard, "count"), &value::Number::new(guard, count));
+                Ok(value::Number::new(guard, count).into())
+            }))
+            .accessor("even", Box::new(|guard, info| {
+                let count = info.this.into_object().unwrap().get(guard, &Property::new(guard, "count"));
+                Ok(value::Boolean::new(guard, count.to_integer(guard) % 2 == 0).into())
+            }), None);
+
+        let mut settings = ObjectTemplate::new();
+        settings.set("debug", false);
+        let mut api = ObjectTemplate::new();
+        api.object("settings", settings).function("Counter", counter);
+        assert_eq!(api.len(), 2);
+
+        let (runtime, first) = test::setup_env();
+        let second = Context::new(&runtime).unwrap();
+        for context in &[first, second] {
+            context.exec_with(|guard| {
+                api.apply(guard, &guard.global()).unwrap();
+                let result = script::eval(guard, "
+                    var counter = new Counter();
+                    counter.increment();
+                    settings.debug = true;
+                    [counter.increment(), counter.even, Counter.kind, counter instanceof Counter,
+                     Counter.prototype.constructor === Counter, Object.keys(Counter.prototype)]").unwrap();
+                assert_eq!(result.to_json(guard).unwrap(), r#"[2,true,"counter",true,true,["increment","even"]]"#);
+            }).unwrap();
+        }
+    }
+}
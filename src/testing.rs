@@ -0,0 +1,668 @@
+//! Utilities for testing script output.
+//!
+//! Values are rendered deterministically (object keys sorted, numbers
+//! normalized), which makes them suitable for snapshot assertions:
+//!
+//! ```rust
+//! # #[macro_use] extern crate chakracore as js;
+//! # fn main() {
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! let value = js::script::eval(&guard, "({ b: [1, 2.5], a: 'foo' })").unwrap();
+//! assert_value_snapshot!(&guard, value, @r#"{
+//!   "a": "foo",
+//!   "b": [
+//!     1,
+//!     2.5
+//!   ]
+//! }"#);
+//! # }
+//! ```
+//!
+//! Snapshots can also be stored in files, by supplying a name instead of an
+//! inline snapshot. These are stored in a `snapshots` directory, relative to
+//! the crate's manifest. If a snapshot does not exist, or if it differs, the
+//! new output is written to a `.snap.new` file for review. Setting the
+//! environment variable `CHAKRACORE_UPDATE_SNAPSHOTS=1` accepts all changes.
+//!
+//! Two values can also be structurally compared using `diff`, which reports
+//! every difference along with its path (e.g `$.foo[2]`).
+//!
+//! Interactions between scripts and host functions can be recorded with a
+//! `Recorder`. The recorded calls can be stored as a golden file (using
+//! `assert_snapshot`), and replayed with a `Replayer`, which mocks the host
+//! functions by returning the recorded results.
+use std::collections::VecDeque;
+use std::{env, fmt, fs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use context::ContextGuard;
+use error::*;
+use value::{self, Value};
+use value::function::FunctionCallback;
+use util::quote;
+use Property;
+
+/// Asserts that a value matches a snapshot.
+///
+/// The snapshot is either inline (`@"..."`), or a name identifying a file in
+/// the `snapshots` directory.
+#[macro_export]
+macro_rules! assert_value_snapshot {
+    ($guard:expr, $value:expr, @ $expected:expr) => {{
+        let actual = $crate::testing::render($guard, &$value);
+        assert!(actual == $expected,
+                "value snapshot does not match\n--- expected\n{}\n+++ actual\n{}\n",
+                $expected, actual);
+    }};
+
+    ($guard:expr, $name:expr, $value:expr) => {{
+        let actual = $crate::testing::render($guard, &$value);
+        $crate::testing::assert_snapshot($name, &actual);
+    }};
+}
+
+/// Asserts that two values are structurally equal.
+///
+/// Upon failure, all differences between the values are reported.
+#[macro_export]
+macro_rules! assert_value_eq {
+    ($guard:expr, $actual:expr, $expected:expr) => {{
+        let diff = $crate::testing::diff($guard, &$actual, &$expected);
+        assert!(diff.is_empty(), "values are not equal\n{}", diff);
+    }};
+}
+
+/// A difference between two values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// A property (or element) of the expected value is missing.
+    Missing { path: String, expected: String },
+    /// A property (or element) does not exist in the expected value.
+    Unexpected { path: String, actual: String },
+    /// The values are of different types.
+    TypeMismatch { path: String, actual: &'static str, expected: &'static str },
+    /// The values are of the same type, but they are not equal.
+    Value { path: String, actual: String, expected: String },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difference::Missing { ref path, ref expected } =>
+                write!(f, "{}: missing, expected {}", path, expected),
+            Difference::Unexpected { ref path, ref actual } =>
+                write!(f, "{}: unexpected {}", path, actual),
+            Difference::TypeMismatch { ref path, actual, expected } =>
+                write!(f, "{}: expected type {}, found {}", path, expected, actual),
+            Difference::Value { ref path, ref actual, ref expected } =>
+                write!(f, "{}: expected {}, found {}", path, expected, actual),
+        }
+    }
+}
+
+/// The structural differences between two values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueDiff {
+    /// All differences, in depth-first order.
+    pub differences: Vec<Difference>,
+}
+
+impl ValueDiff {
+    /// Returns whether the values are structurally equal.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl fmt::Display for ValueDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Structurally compares two values.
+///
+/// Arrays are compared element-wise, and objects are compared by their own
+/// enumerable properties (regardless of order). All other values are compared
+/// by their rendered representation (see `render`).
+pub fn diff(guard: &ContextGuard, actual: &Value, expected: &Value) -> ValueDiff {
+    let mut differences = Vec::new();
+    diff_value(guard, "$".to_string(), actual, expected, 0, &mut differences);
+    ValueDiff { differences: differences }
+}
+
+/// The maximum depth compared, used to prevent infinite recursion.
+const MAX_DIFF_DEPTH: usize = 64;
+
+/// Compares two values, appending any differences.
+fn diff_value(guard: &ContextGuard,
+              path: String,
+              actual: &Value,
+              expected: &Value,
+              depth: usize,
+              differences: &mut Vec<Difference>) {
+    let (actual_type, expected_type) = (type_name(actual), type_name(expected));
+    if actual_type != expected_type {
+        differences.push(Difference::TypeMismatch {
+            path: path,
+            actual: actual_type,
+            expected: expected_type,
+        });
+        return;
+    }
+
+    let is_container = actual_type == "array" || actual_type == "object";
+    if !is_container || depth >= MAX_DIFF_DEPTH || actual.strict_equals(guard, expected) {
+        let (actual, expected) = (render(guard, actual), render(guard, expected));
+        if actual != expected {
+            differences.push(Difference::Value { path: path, actual: actual, expected: expected });
+        }
+        return;
+    }
+
+    let (actual, expected) = (entries(guard, actual), entries(guard, expected));
+    for &(ref key, ref expected) in &expected {
+        let path = child_path(&path, key, actual_type == "array");
+        match actual.iter().find(|&&(ref other, _)| other == key) {
+            Some(&(_, ref actual)) => diff_value(guard, path, actual, expected, depth + 1, differences),
+            None => differences.push(Difference::Missing { path: path, expected: render(guard, expected) }),
+        }
+    }
+
+    for &(ref key, ref actual) in &actual {
+        if !expected.iter().any(|&(ref other, _)| other == key) {
+            differences.push(Difference::Unexpected {
+                path: child_path(&path, key, actual_type == "array"),
+                actual: render(guard, actual),
+            });
+        }
+    }
+}
+
+/// Returns the properties (or elements) of an object.
+fn entries(guard: &ContextGuard, value: &Value) -> Vec<(String, Value)> {
+    let object = value.clone().into_object().expect("converting value to object");
+    match value.clone().into_array() {
+        Some(array) => array.iter(guard)
+            .enumerate()
+            .map(|(index, element)| (index.to_string(), element))
+            .collect(),
+        None => object.get_own_property_names(guard)
+            .iter(guard)
+            .map(|key| {
+                let key = key.to_string(guard);
+                let value = object.get(guard, &Property::new(guard, &key));
+                (key, value)
+            })
+            .collect(),
+    }
+}
+
+/// Returns the path of a property (or element).
+fn child_path(path: &str, key: &str, index: bool) -> String {
+    if index {
+        format!("{}[{}]", path, key)
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Returns a descriptive name of a value's type.
+fn type_name(value: &Value) -> &'static str {
+    if value.is_undefined() {
+        "undefined"
+    } else if value.is_null() {
+        "null"
+    } else if value.is_boolean() {
+        "boolean"
+    } else if value.is_number() {
+        "number"
+    } else if value.is_string() {
+        "string"
+    } else if value.is_function() {
+        "function"
+    } else if value.is_array() {
+        "array"
+    } else {
+        "object"
+    }
+}
+
+/// Renders a value deterministically.
+///
+/// - Object keys are sorted.
+/// - Integral numbers are rendered without a fraction, and negative zero is
+///   rendered as zero.
+/// - Functions are rendered as `[Function: name]`.
+/// - Cyclic references are rendered as `[Circular]`.
+pub fn render(guard: &ContextGuard, value: &Value) -> String {
+    let mut output = String::new();
+    render_value(guard, value, &mut Vec::new(), 0, &mut output);
+    output
+}
+
+/// Compares rendered output with a snapshot file.
+///
+/// See the [module documentation](index.html) for details.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let directory = env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join("snapshots");
+    let path = directory.join(format!("{}.snap", name));
+    let pending = directory.join(format!("{}.snap.new", name));
+
+    let expected = fs::read_to_string(&path).ok();
+    if expected.as_ref().map(|expected| expected.as_str()) == Some(actual) {
+        let _ = fs::remove_file(&pending);
+        return;
+    }
+
+    fs::create_dir_all(&directory).expect("creating snapshot directory");
+    if env::var("CHAKRACORE_UPDATE_SNAPSHOTS").map(|value| value == "1").unwrap_or(false) {
+        fs::write(&path, actual).expect("writing snapshot");
+        let _ = fs::remove_file(&pending);
+        return;
+    }
+
+    fs::write(&pending, actual).expect("writing pending snapshot");
+    match expected {
+        None => panic!("missing value snapshot '{}', review {:?}", name, pending),
+        Some(expected) => panic!("value snapshot '{}' does not match, review {:?}\n\
+                                  --- expected\n{}\n+++ actual\n{}\n",
+                                 name, pending, expected, actual),
+    }
+}
+
+/// A recorded call of a host function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedCall {
+    /// The name of the host function.
+    pub function: String,
+    /// The call's arguments, as a JSON array.
+    pub arguments: String,
+    /// The call's outcome.
+    pub outcome: Outcome,
+}
+
+/// The outcome of a recorded call.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// The function returned a value, serialized as JSON.
+    Returned(String),
+    /// The function threw an exception, recorded as its message.
+    Threw(String),
+}
+
+impl RecordedCall {
+    /// Parses a call from its textual representation.
+    pub fn parse(line: &str) -> Result<RecordedCall> {
+        let fields = line.splitn(4, '\t').collect::<Vec<_>>();
+        if fields.len() != 4 {
+            return Err(format!("invalid recorded call: {}", line).into());
+        }
+
+        let outcome = match fields[2] {
+            "returned" => Outcome::Returned(fields[3].to_string()),
+            "threw" => Outcome::Threw(unquote(fields[3])),
+            kind => return Err(format!("invalid recorded outcome: {}", kind).into()),
+        };
+
+        Ok(RecordedCall {
+            function: fields[0].to_string(),
+            arguments: fields[1].to_string(),
+            outcome: outcome,
+        })
+    }
+}
+
+/// The textual representation is a line of tab separated fields; the name,
+/// arguments, outcome kind and outcome value.
+impl fmt::Display for RecordedCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.outcome {
+            Outcome::Returned(ref json) => {
+                write!(f, "{}\t{}\treturned\t{}", self.function, self.arguments, json)
+            },
+            Outcome::Threw(ref message) => {
+                write!(f, "{}\t{}\tthrew\t{}", self.function, self.arguments, quote(message))
+            },
+        }
+    }
+}
+
+/// Records calls of host functions.
+///
+/// The recorder can be cloned, and all clones share the same recording.
+#[derive(Clone, Default)]
+pub struct Recorder {
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+}
+
+impl Recorder {
+    /// Creates a new recorder.
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Creates a host function whose calls are recorded.
+    pub fn function(&self, guard: &ContextGuard, name: &str, callback: Box<FunctionCallback>) -> value::Function {
+        let calls = self.calls.clone();
+        let function = name.to_string();
+        value::Function::with_name(guard, name, Box::new(move |guard, info| {
+            let arguments = serialize_arguments(guard, &info.arguments);
+            let result = callback(guard, info);
+            let outcome = match result {
+                Ok(ref value) => Outcome::Returned(serialize(guard, value)),
+                Err(ref exception) => Outcome::Threw(exception_message(guard, exception)),
+            };
+
+            calls.lock().unwrap().push(RecordedCall {
+                function: function.clone(),
+                arguments: arguments,
+                outcome: outcome,
+            });
+            result
+        }))
+    }
+
+    /// Returns all recorded calls.
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns the recording as text, with one call per line.
+    pub fn log(&self) -> String {
+        self.calls().iter().map(|call| format!("{}\n", call)).collect()
+    }
+}
+
+/// Replays recorded calls of host functions.
+///
+/// Each call of a replayed function must match the next recorded call, i.e
+/// the same function must be called with the same arguments. Otherwise the
+/// function throws an `Error`.
+#[derive(Clone)]
+pub struct Replayer {
+    calls: Arc<Mutex<VecDeque<RecordedCall>>>,
+}
+
+impl Replayer {
+    /// Creates a replayer for a sequence of calls.
+    pub fn new(calls: Vec<RecordedCall>) -> Self {
+        Replayer { calls: Arc::new(Mutex::new(calls.into_iter().collect())) }
+    }
+
+    /// Creates a replayer from a recording's text representation.
+    pub fn parse(log: &str) -> Result<Self> {
+        log.lines()
+            .filter(|line| !line.is_empty())
+            .map(RecordedCall::parse)
+            .collect::<Result<Vec<_>>>()
+            .map(Self::new)
+    }
+
+    /// Creates a mock host function, returning the recorded results.
+    pub fn function(&self, guard: &ContextGuard, name: &str) -> value::Function {
+        let calls = self.calls.clone();
+        let function = name.to_string();
+        value::Function::with_name(guard, name, Box::new(move |guard, info| {
+            let arguments = serialize_arguments(guard, &info.arguments);
+            let call = calls.lock().unwrap().pop_front();
+
+            let call = match call {
+                Some(ref call) if call.function == function && call.arguments == arguments => call.clone(),
+                Some(call) => {
+                    let message = format!("unexpected call {}({}), expected {}({})",
+                                          function, arguments, call.function, call.arguments);
+                    return Err(value::Error::new(guard, &message).into());
+                },
+                None => {
+                    let message = format!("unexpected call {}({}), no calls remaining", function, arguments);
+                    return Err(value::Error::new(guard, &message).into());
+                },
+            };
+
+            match call.outcome {
+                Outcome::Returned(ref json) => deserialize(guard, json).map_err(|error| {
+                    value::Error::new(guard, &error.to_string()).into()
+                }),
+                Outcome::Threw(ref message) => Err(value::Error::new(guard, message).into()),
+            }
+        }))
+    }
+
+    /// Returns the number of calls that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+/// Serializes call arguments as a JSON array.
+fn serialize_arguments(guard: &ContextGuard, arguments: &[Value]) -> String {
+    let array = value::Array::from_iter(guard, arguments.iter().cloned());
+    serialize(guard, &array)
+}
+
+/// Serializes a value as JSON, with `undefined` being represented as-is.
+fn serialize(guard: &ContextGuard, value: &Value) -> String {
+    if value.is_undefined() {
+        return "undefined".to_string();
+    }
+    value.to_json(guard).unwrap_or_else(|_| render(guard, value))
+}
+
+/// Deserializes a value serialized with `serialize`.
+fn deserialize(guard: &ContextGuard, json: &str) -> Result<Value> {
+    if json == "undefined" {
+        Ok(value::undefined(guard))
+    } else {
+        Value::from_json(guard, json)
+    }
+}
+
+/// Returns the message of a thrown value.
+fn exception_message(guard: &ContextGuard, exception: &Value) -> String {
+    exception.clone()
+        .into_object()
+        .map(|object| object.get(guard, &Property::new(guard, "message")))
+        .filter(|message| message.is_string())
+        .unwrap_or_else(|| exception.clone())
+        .to_string(guard)
+}
+
+/// Reverses `quote`.
+fn unquote(string: &str) -> String {
+    let string = if string.len() >= 2 && string.starts_with('"') && string.ends_with('"') {
+        &string[1..string.len() - 1]
+    } else {
+        string
+    };
+    let mut output = String::with_capacity(string.len());
+    let mut characters = string.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            output.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some('u') => {
+                let code = characters.by_ref().take(4).collect::<String>();
+                if let Some(character) = u32::from_str_radix(&code, 16).ok().and_then(::std::char::from_u32) {
+                    output.push(character);
+                }
+            },
+            Some(character) => output.push(character),
+            None => (),
+        }
+    }
+    output
+}
+
+/// Renders a value, tracking its ancestors to detect cycles.
+fn render_value(guard: &ContextGuard,
+                value: &Value,
+                ancestors: &mut Vec<Value>,
+                depth: usize,
+                output: &mut String) {
+    if value.is_undefined() || value.is_null() || value.is_boolean() {
+        output.push_str(&value.to_string(guard));
+    } else if let Some(number) = value.clone().into_number() {
+        output.push_str(&render_number(number.value_double()));
+    } else if let Some(string) = value.clone().into_string() {
+        output.push_str(&quote(&string.value()));
+    } else if let Some(function) = value.clone().into_function() {
+        let name = function.get(guard, &Property::new(guard, "name")).to_string(guard);
+        output.push_str(&format!("[Function: {}]", name));
+    } else if ancestors.iter().any(|ancestor| ancestor.strict_equals(guard, value)) {
+        output.push_str("[Circular]");
+    } else if let Some(object) = value.clone().into_object() {
+        ancestors.push(value.clone());
+        let entries: Vec<(Option<String>, Value)> = match value.clone().into_array() {
+            Some(array) => array.iter(guard).map(|element| (None, element)).collect(),
+            None => {
+                let mut keys = object.get_own_property_names(guard)
+                    .iter(guard)
+                    .map(|key| key.to_string(guard))
+                    .collect::<Vec<_>>();
+                keys.sort();
+                keys.into_iter().map(|key| {
+                    let value = object.get(guard, &Property::new(guard, &key));
+                    (Some(key), value)
+                }).collect()
+            },
+        };
+
+        let (open, close) = if value.is_array() { ("[", "]") } else { ("{", "}") };
+        output.push_str(open);
+        for (index, &(ref key, ref value)) in entries.iter().enumerate() {
+            output.push_str(if index == 0 { "\n" } else { ",\n" });
+            indent(depth + 1, output);
+            if let Some(ref key) = *key {
+                output.push_str(&quote(key));
+                output.push_str(": ");
+            }
+            render_value(guard, value, ancestors, depth + 1, output);
+        }
+        if !entries.is_empty() {
+            output.push('\n');
+            indent(depth, output);
+        }
+        output.push_str(close);
+        ancestors.pop();
+    } else {
+        output.push_str(&value.to_string(guard));
+    }
+}
+
+/// Renders a number in a normalized format.
+fn render_number(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if number.fract() == 0.0 && number.abs() < 9007199254740992.0 {
+        // This also normalizes negative zero
+        format!("{}", number as i64)
+    } else {
+        format!("{}", number)
+    }
+}
+
+/// Appends indentation for a depth.
+fn indent(depth: usize, output: &mut String) {
+    for _ in 0..depth {
+        output.push_str("  ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value, Property};
+
+    #[test]
+    fn render() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "
+                var value = { zoo: -0, bar: [1.5, 'baz', null], foo: function foo() {} };
+                value.self = value;
+                value").unwrap();
+            assert_value_snapshot!(guard, value, @r#"{
+  "bar": [
+    1.5,
+    "baz",
+    null
+  ],
+  "foo": [Function: foo],
+  "self": [Circular],
+  "zoo": 0
+}"#);
+        });
+    }
+
+    #[test]
+    fn record_replay() {
+        let log = {
+            let (_runtime, context) = test::setup_env();
+            let guard = context.make_current().unwrap();
+
+            let recorder = super::Recorder::new();
+            let double = recorder.function(&guard, "double", Box::new(|guard, info| {
+                match info.arguments.get(0).map(|value| value.to_integer(guard)) {
+                    Some(number) => Ok(value::Number::new(guard, number * 2).into()),
+                    None => Err(value::Error::type_error(guard, "missing\targument").into()),
+                }
+            }));
+            guard.global().set(&guard, &Property::new(&guard, "double"), &double);
+
+            let result = script::eval(&guard, "try { double(); } catch (ex) {} double(2) + double(3)").unwrap();
+            assert_eq!(result.to_integer(&guard), 10);
+            recorder.log()
+        };
+        assert_eq!(log, "double\t[]\tthrew\t\"missing\\targument\"\n\
+                         double\t[2]\treturned\t4\n\
+                         double\t[3]\treturned\t6\n");
+
+        test::run_with_context(|guard| {
+            let replayer = super::Replayer::parse(&log).unwrap();
+            let double = replayer.function(guard, "double");
+            guard.global().set(guard, &Property::new(guard, "double"), &double);
+
+            let result = script::eval(guard, "
+                var message;
+                try { double(); } catch (ex) { message = ex.message; }
+                [message, double(2) + double(3)]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"["missing\targument",10]"#);
+            assert_eq!(replayer.remaining(), 0);
+            assert!(script::eval(guard, "double(4)").is_err());
+        });
+    }
+
+    #[test]
+    fn diff() {
+        test::run_with_context(|guard| {
+            let actual = script::eval(guard, "({ foo: [1, 2, 3], bar: 'baz', qux: 1 })").unwrap();
+            let expected = script::eval(guard, "({ foo: [1, 5], bar: 1, quux: null })").unwrap();
+
+            let diff = super::diff(guard, &actual, &expected);
+            assert_eq!(diff.to_string(), "  $.foo[1]: expected 5, found 2
+  $.foo[2]: unexpected 3
+  $.bar: expected type number, found string
+  $.quux: missing, expected null
+  $.qux: unexpected 1
+");
+
+            let expected = script::eval(guard, "({ bar: 'baz', qux: 1, foo: [1, 2, 3] })").unwrap();
+            assert_value_eq!(guard, actual, expected);
+        });
+    }
+}
@@ -0,0 +1,127 @@
+//! The `setTimeout` and `setInterval` family of globals, and `queueMicrotask`.
+//!
+//! These are not installed by default, so contexts (e.g sandboxed ones) only
+//! have them if the host installs them. The timers are kept in the context's
+//! task queue, and expired timers are executed by
+//! `ContextGuard::execute_tasks`. To run a script's timers to completion, use
+//! `ContextGuard::run_timers_until_idle`.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! js::timers::install(&guard).unwrap();
+//! let result = js::script::eval(&guard, "
+//!     var result = [];
+//!     setTimeout(value => result.push(value), 10, 'foo');
+//!     result").unwrap();
+//!
+//! guard.run_timers_until_idle().unwrap();
+//! assert_eq!(result.to_json(&guard).unwrap(), r#"["foo"]"#);
+//! ```
+use std::time::Duration;
+use context::ContextGuard;
+use error::*;
+use value::{self, Value};
+use value::function::{CallbackInfo, CallbackResult};
+use Property;
+
+/// Installs `setTimeout`, `setInterval`, `clearTimeout`, `clearInterval` and
+/// `queueMicrotask` in the guard's context.
+///
+/// Unlike browsers, string callbacks are not evaluated, and throw a
+/// `TypeError` instead.
+pub fn install(guard: &ContextGuard) -> Result<()> {
+    let global = guard.global();
+    let function = |name: &str, callback: Box<value::function::FunctionCallback>| {
+        let function = value::Function::with_name(guard, name, callback);
+        global.set(guard, &Property::new(guard, name), &function);
+    };
+
+    function("setTimeout", Box::new(|guard, info| schedule(guard, info, false)));
+    function("setInterval", Box::new(|guard, info| schedule(guard, info, true)));
+    function("clearTimeout", Box::new(clear));
+    function("clearInterval", Box::new(clear));
+    function("queueMicrotask", Box::new(queue_microtask));
+    Ok(())
+}
+
+/// Schedules a timer, returning its identifier.
+fn schedule(guard: &ContextGuard, info: CallbackInfo, repeat: bool) -> CallbackResult {
+    let callback = info.arguments.get(0)
+        .and_then(|callback| callback.clone().into_function())
+        .ok_or_else(|| Value::from(value::Error::type_error(guard, "Callback must be a function")))?;
+
+    // Invalid and negative delays are treated as zero
+    let milliseconds = info.arguments.get(1).map_or(0.0, |delay| delay.to_double(guard));
+    let milliseconds = if milliseconds > 0.0 { milliseconds.min(u32::max_value() as f64) } else { 0.0 };
+    let delay = Duration::from_millis(milliseconds as u64);
+
+    let arguments = info.arguments.iter().skip(2).cloned().collect();
+    let id = guard.schedule_timer(delay, callback, arguments, if repeat { Some(delay) } else { None });
+    Ok(value::Number::from_double(guard, id as f64).into())
+}
+
+/// Cancels a timer.
+fn clear(guard: &ContextGuard, info: CallbackInfo) -> CallbackResult {
+    if let Some(id) = info.arguments.get(0).filter(|id| id.is_number()) {
+        guard.cancel_timer(id.to_double(guard) as u32);
+    }
+    Ok(value::undefined(guard))
+}
+
+/// Queues a microtask, executed once the current task has finished.
+fn queue_microtask(guard: &ContextGuard, info: CallbackInfo) -> CallbackResult {
+    let callback = info.arguments.get(0)
+        .and_then(|callback| callback.clone().into_function())
+        .ok_or_else(|| Value::from(value::Error::type_error(guard, "Callback must be a function")))?;
+    if !guard.queue_microtask(callback) {
+        return Err(value::Error::new(guard, "Context is shutting down").into());
+    }
+    Ok(value::undefined(guard))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use {test, script, value, Property};
+
+    #[test]
+    fn timers() {
+        test::run_with_context(|guard| {
+            super::install(guard).unwrap();
+            let result = script::eval(guard, "
+                var order = [];
+                var count = 0;
+                var interval = setInterval(() => {
+                    order.push('interval');
+                    if (++count === 3) clearInterval(interval);
+                }, 5);
+                var cancelled = setTimeout(() => order.push('cancelled'), 1);
+                clearTimeout(cancelled);
+                setTimeout((a, b) => order.push(a + b), 30, 'ti', 'meout');
+                setTimeout(() => {
+                    order.push('zero');
+                    Promise.resolve().then(() => order.push('microtask'));
+                });
+                order").unwrap();
+
+            guard.run_timers_until_idle().unwrap();
+            assert_eq!(result.to_json(guard).unwrap(),
+                       r#"["zero","microtask","interval","interval","interval","timeout"]"#);
+            assert!(script::eval(guard, "setTimeout('1 + 1')").is_err());
+
+            // Host callbacks cannot be cancelled by guessing their identifier
+            let called = value::Object::new(guard);
+            let flag = called.clone();
+            guard.schedule(Duration::from_millis(0), value::Function::new(guard, Box::new(move |guard, _| {
+                flag.set(guard, &Property::new(guard, "value"), &value::Boolean::new(guard, true));
+                Ok(value::undefined(guard))
+            })));
+            script::eval(guard, "for (var id = 0; id < 100; id++) clearTimeout(id)").unwrap();
+            guard.run_timers_until_idle().unwrap();
+            assert!(called.get(guard, &Property::new(guard, "value")).to_bool(guard));
+        });
+    }
+}
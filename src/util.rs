@@ -2,7 +2,8 @@ use std::ptr;
 use chakracore_sys::*;
 use error::*;
 use context::{Context, ContextGuard};
-use value;
+use value::{self, Value};
+use Property;
 
 /// Type for `JsCreateString` & `JsCreatePropertyId`
 pub type StringCall = unsafe extern "system" fn(JsRef, *mut i8, usize, *mut usize) -> JsErrorCode;
@@ -33,12 +34,16 @@ pub fn release_reference(reference: JsRef) {
     debug_assert!(count < ::libc::c_uint::max_value());
 }
 
-/// Returns a script result as a `Function`.
+/// Returns a built-in function, identified by its path from the global object.
 ///
 /// This is useful for functionality that the underlying JSRT API does not
 /// provide, such as JSON methods, or `RegExp` constructor.
 pub fn jsfunc(guard: &ContextGuard, function: &str) -> Option<value::Function> {
-    ::script::eval(guard, function).ok().and_then(|val| val.into_function())
+    function.split('.')
+        .try_fold(value::Value::from(guard.global()), |object, name| {
+            object.into_object().map(|object| object.get(guard, &::Property::new(guard, name)))
+        })
+        .and_then(|function| function.into_function())
 }
 
 /// Converts a JSRT error code to a result.
@@ -47,28 +52,60 @@ pub fn jstry(code: JsErrorCode) -> Result<()> {
         JsErrorCode::NoError => Ok(()),
         JsErrorCode::ScriptException | JsErrorCode::ScriptCompile => {
             Context::exec_with_current(|guard| {
-                // TODO: Use an exception with stack trace.
-                let exception = get_and_clear_exception(guard);
-                let message = exception.to_string(guard);
+                let metadata = get_and_clear_exception(guard);
+                let error = ScriptError::from_metadata(guard, &metadata);
 
                 Err(if code == JsErrorCode::ScriptException {
-                    ErrorKind::ScriptException(message).into()
+                    ErrorKind::ScriptException(error).into()
                 } else {
-                    ErrorKind::ScriptCompile(message).into()
+                    ErrorKind::ScriptCompile(error).into()
                 })
             }).expect("active context in result handler")
         },
+        JsErrorCode::ScriptTerminated => Err(ErrorKind::ScriptTerminated.into()),
         error @ _ => Err(format!("JSRT call failed with: {:?}", error).into()),
     }
 }
 
-/// Retrieves and clears any exception thrown during compilation or execution.
+/// Returns an argument, or `undefined` if it is missing.
+pub fn argument(guard: &ContextGuard, arguments: &[Value], index: usize) -> Value {
+    arguments.get(index).cloned().unwrap_or_else(|| value::undefined(guard))
+}
+
+/// Creates an error with a custom name (i.e a `DOMException` equivalent).
+pub fn named_error(guard: &ContextGuard, name: &str, message: &str) -> Value {
+    let error = value::Error::new(guard, message);
+    error.set(guard, &Property::new(guard, "name"), &value::String::new(guard, name));
+    error.into()
+}
+
+/// Returns a string as a quoted JSON string.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if (character as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Retrieves and clears any exception thrown during compilation or execution,
+/// returning the exception's metadata (including its position).
 ///
 /// The runtime is set to a disabled state whenever an exception is thrown.
 fn get_and_clear_exception(_guard: &ContextGuard) -> value::Value {
-    let mut exception = JsValueRef::new();
+    let mut metadata = JsValueRef::new();
     unsafe {
-        jsassert!(JsGetAndClearException(&mut exception));
-        value::Value::from_raw(exception)
+        jsassert!(JsGetAndClearExceptionWithMetadata(&mut metadata));
+        value::Value::from_raw(metadata)
     }
 }
@@ -3,10 +3,13 @@ use std::{mem, ptr, slice};
 use chakracore_sys::*;
 use boolinator::Boolinator;
 use context::ContextGuard;
+use error::*;
+use util::jstry;
 use super::{Value, Object};
 use Property;
 
 /// A JavaScript array.
+#[repr(transparent)]
 pub struct Array(JsValueRef);
 
 /// An iterator for a JavaScript array.
@@ -18,8 +21,18 @@ pub struct ArrayIter<'a> {
 }
 
 /// A JavaScript array buffer.
+#[repr(transparent)]
 pub struct ArrayBuffer(JsValueRef);
 
+/// A JavaScript data view, providing typed access to an array buffer.
+///
+/// Accessors take an offset relative to the view, and whether the value is
+/// stored in little-endian byte order (the default is big-endian, as with
+/// JavaScript's `DataView`). Accessing data outside of the view results in an
+/// error.
+#[repr(transparent)]
+pub struct DataView(JsValueRef);
+
 impl Array {
     /// Creates a new array with a specified length.
     pub fn new(_guard: &ContextGuard, length: u32) -> Self {
@@ -30,6 +43,24 @@ impl Array {
         }
     }
 
+    /// Creates an array from the elements of an iterator.
+    pub fn from_iter<I, T>(guard: &ContextGuard, elements: I) -> Self
+            where I: IntoIterator<Item = T>, T: Into<Value> {
+        let elements = elements.into_iter();
+        let array = Self::new(guard, elements.size_hint().0 as u32);
+        for (index, element) in elements.enumerate() {
+            array.set_index(guard, index as u32, &element.into());
+        }
+        array
+    }
+
+    /// Returns the elements of the array.
+    ///
+    /// Use `Vec::<T>::from_js` to convert the elements to a native type.
+    pub fn to_vec(&self, guard: &ContextGuard) -> Vec<Value> {
+        self.iter(guard).collect()
+    }
+
     /// Returns the length of the array.
     pub fn len(&self, guard: &ContextGuard) -> usize {
         let length = Property::new(&guard, "length");
@@ -111,6 +142,99 @@ impl ArrayBuffer {
     }
 }
 
+macro_rules! accessor {
+    ($get:ident, $get_doc:expr, $set:ident, $set_doc:expr, $typ:ident, $size:expr) => {
+        #[doc=$get_doc]
+        pub fn $get(&self, offset: usize, little_endian: bool) -> Result<$typ> {
+            let mut bytes = [0; $size];
+            self.read(offset, &mut bytes)?;
+            Ok(if little_endian { $typ::from_le_bytes(bytes) } else { $typ::from_be_bytes(bytes) })
+        }
+
+        #[doc=$set_doc]
+        pub fn $set(&self, offset: usize, value: $typ, little_endian: bool) -> Result<()> {
+            let bytes = if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+            self.write(offset, &bytes)
+        }
+    };
+}
+
+impl DataView {
+    /// Creates a new data view over a region of an array buffer.
+    pub fn new(_guard: &ContextGuard, buffer: &ArrayBuffer, offset: u32, length: u32) -> Result<Self> {
+        let mut reference = JsValueRef::new();
+        unsafe {
+            jstry(JsCreateDataView(buffer.as_raw(), offset, length, &mut reference))
+                .map(|_| Self::from_raw(reference))
+        }
+    }
+
+    /// Returns the length of the view in bytes.
+    pub fn len(&self) -> usize {
+        self.storage().1
+    }
+
+    /// Returns the memory viewed by the data view.
+    pub fn as_slice(&self) -> &[u8] {
+        let (data, size) = self.storage();
+        unsafe { slice::from_raw_parts(data, size) }
+    }
+
+    accessor!(get_u8, "Reads an unsigned byte at an offset.",
+              set_u8, "Writes an unsigned byte at an offset.", u8, 1);
+    accessor!(get_i8, "Reads a signed byte at an offset.",
+              set_i8, "Writes a signed byte at an offset.", i8, 1);
+    accessor!(get_u16, "Reads an unsigned 16-bit integer at an offset.",
+              set_u16, "Writes an unsigned 16-bit integer at an offset.", u16, 2);
+    accessor!(get_i16, "Reads a signed 16-bit integer at an offset.",
+              set_i16, "Writes a signed 16-bit integer at an offset.", i16, 2);
+    accessor!(get_u32, "Reads an unsigned 32-bit integer at an offset.",
+              set_u32, "Writes an unsigned 32-bit integer at an offset.", u32, 4);
+    accessor!(get_i32, "Reads a signed 32-bit integer at an offset.",
+              set_i32, "Writes a signed 32-bit integer at an offset.", i32, 4);
+    accessor!(get_f32, "Reads a 32-bit float at an offset.",
+              set_f32, "Writes a 32-bit float at an offset.", f32, 4);
+    accessor!(get_f64, "Reads a 64-bit float at an offset.",
+              set_f64, "Writes a 64-bit float at an offset.", f64, 8);
+
+    is_same!(DataView, "Returns true if the value is a `DataView`.");
+
+    /// Copies bytes from the view, starting at an offset.
+    fn read(&self, offset: usize, bytes: &mut [u8]) -> Result<()> {
+        let (data, size) = self.storage();
+        Self::check_bounds(offset, bytes.len(), size)?;
+        unsafe { ptr::copy_nonoverlapping(data.offset(offset as isize), bytes.as_mut_ptr(), bytes.len()) };
+        Ok(())
+    }
+
+    /// Copies bytes to the view, starting at an offset.
+    fn write(&self, offset: usize, bytes: &[u8]) -> Result<()> {
+        let (data, size) = self.storage();
+        Self::check_bounds(offset, bytes.len(), size)?;
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), data.offset(offset as isize), bytes.len()) };
+        Ok(())
+    }
+
+    /// Ensures that a range is within the view's bounds.
+    fn check_bounds(offset: usize, length: usize, size: usize) -> Result<()> {
+        if offset.checked_add(length).map_or(true, |end| end > size) {
+            Err(format!("offset {} is outside the bounds of the data view", offset).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the view's underlying storage.
+    fn storage(&self) -> (*mut u8, usize) {
+        let mut data = ptr::null_mut();
+        let mut size = 0;
+        unsafe {
+            jsassert!(JsGetDataViewStorage(self.as_raw(), &mut data, &mut size));
+        }
+        (data, size as usize)
+    }
+}
+
 impl<'a> Iterator for ArrayIter<'a> {
     type Item = Value;
 
@@ -129,6 +253,9 @@ subtype!(Array, Value);
 reference!(ArrayBuffer);
 inherit!(ArrayBuffer, Object);
 subtype!(ArrayBuffer, Value);
+reference!(DataView);
+inherit!(DataView, Object);
+subtype!(DataView, Value);
 
 #[cfg(test)]
 mod tests {
@@ -149,6 +276,18 @@ mod tests {
         });
     }
 
+    #[test]
+    fn from_iter() {
+        test::run_with_context(|guard| {
+            let array = value::Array::from_iter(guard, (0..3).map(|i| value::Number::new(guard, i)));
+            assert_eq!(array.to_json(guard).unwrap(), "[0,1,2]");
+
+            let elements = array.to_vec(guard);
+            assert_eq!(elements.iter().map(|element| element.to_integer(guard)).collect::<Vec<_>>(), [0, 1, 2]);
+            assert_eq!(value::Array::from_iter(guard, elements.into_iter().filter(|_| false)).len(guard), 0);
+        });
+    }
+
     #[test]
     fn buffer_storage() {
         test::run_with_context(|guard| {
@@ -161,4 +300,22 @@ mod tests {
             assert_eq!(array.as_slice(), data.as_slice());
         });
     }
+
+    #[test]
+    fn data_view() {
+        test::run_with_context(|guard| {
+            let buffer = value::ArrayBuffer::new(guard, 16);
+            let view = value::DataView::new(guard, &buffer, 4, 8).unwrap();
+            assert_eq!(view.len(), 8);
+
+            view.set_u32(0, 0x01020304, false).unwrap();
+            view.set_f32(4, 1.5, true).unwrap();
+            assert_eq!(&buffer.as_slice()[4..8], &[1, 2, 3, 4]);
+            assert_eq!(view.get_u16(0, true).unwrap(), 0x0201);
+            assert_eq!(view.get_f32(4, true).unwrap(), 1.5);
+
+            assert!(view.get_u32(6, false).is_err());
+            assert!(value::DataView::new(guard, &buffer, 12, 8).is_err());
+        });
+    }
 }
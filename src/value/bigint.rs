@@ -0,0 +1,129 @@
+//! A JavaScript arbitrary precision integer.
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use super::Value;
+
+/// A JavaScript `BigInt`.
+///
+/// BigInts are only available if the engine has been built (or configured)
+/// with support for them, see `is_supported`. Since JSRT does not provide an
+/// API for them, values are converted through their decimal representation.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::value::BigInt;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// if BigInt::is_supported(&guard) {
+///     let big = BigInt::from_i128(&guard, i64::max_value() as i128 * 4).unwrap();
+///     assert_eq!(big.to_i128(&guard).unwrap(), 36893488147419103228);
+///     assert!(big.to_i64(&guard).is_err());
+/// }
+/// ```
+#[repr(transparent)]
+pub struct BigInt(JsValueRef);
+
+impl BigInt {
+    /// Returns whether the guard's context supports BigInts.
+    pub fn is_supported(guard: &ContextGuard) -> bool {
+        guard.builtins().bigint().is_some()
+    }
+
+    /// Creates a BigInt from a signed integer.
+    pub fn from_i64(guard: &ContextGuard, number: i64) -> Result<Self> {
+        Self::parse(guard, &number.to_string())
+    }
+
+    /// Creates a BigInt from an unsigned integer.
+    pub fn from_u64(guard: &ContextGuard, number: u64) -> Result<Self> {
+        Self::parse(guard, &number.to_string())
+    }
+
+    /// Creates a BigInt from a 128-bit integer.
+    pub fn from_i128(guard: &ContextGuard, number: i128) -> Result<Self> {
+        Self::parse(guard, &number.to_string())
+    }
+
+    /// Creates a BigInt from its string representation (e.g `-42` or `0xff`),
+    /// as parsed by `BigInt(string)`.
+    pub fn parse(guard: &ContextGuard, number: &str) -> Result<Self> {
+        let builtins = guard.builtins();
+        let constructor = builtins.bigint().ok_or("BigInt is not supported")?;
+        let number = super::String::new(guard, number).into();
+        let result = constructor.call(guard, &[&number])?;
+        Ok(Self::from_value(guard, result).expect("creating BigInt"))
+    }
+
+    /// Represents a value as a `BigInt`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a BigInt primitive (i.e `typeof value ===
+    /// 'bigint'`).
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        let value_of = match builtins.bigint_value_of() {
+            Some(value_of) => value_of,
+            None => return false,
+        };
+
+        // `valueOf` also accepts wrapper objects, which are equal to their
+        // object representation (unlike primitives)
+        value_of.call_with_this(guard, value, &[]).is_ok()
+            && !value.object_representation(guard).strict_equals(guard, value)
+    }
+
+    /// Converts the BigInt to a 128-bit integer, failing if it is out of range.
+    pub fn to_i128(&self, guard: &ContextGuard) -> Result<i128> {
+        let string = self.to_string(guard);
+        string.parse().chain_err(|| format!("BigInt {} is out of range", string))
+    }
+
+    /// Converts the BigInt to a signed integer, failing if it is out of range.
+    pub fn to_i64(&self, guard: &ContextGuard) -> Result<i64> {
+        let string = self.to_string(guard);
+        string.parse().chain_err(|| format!("BigInt {} is out of range", string))
+    }
+
+    /// Converts the BigInt to an unsigned integer, failing if it is out of
+    /// range.
+    pub fn to_u64(&self, guard: &ContextGuard) -> Result<u64> {
+        let string = self.to_string(guard);
+        string.parse().chain_err(|| format!("BigInt {} is out of range", string))
+    }
+}
+
+reference!(BigInt);
+inherit!(BigInt, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+    use super::BigInt;
+
+    #[test]
+    fn bigint() {
+        test::run_with_context(|guard| {
+            if !BigInt::is_supported(guard) {
+                assert!(BigInt::from_i64(guard, 1).is_err());
+                assert!(!BigInt::is_same(guard, &value::Number::new(guard, 1)));
+                return;
+            }
+
+            let big = script::eval(guard, "2n ** 64n - 1n").unwrap();
+            assert_eq!(big.value_type(guard), value::ValueType::BigInt);
+            let big = BigInt::from_value(guard, big).unwrap();
+            assert_eq!(big.to_u64(guard).unwrap(), u64::max_value());
+            assert!(big.to_i64(guard).is_err());
+
+            let small = BigInt::from_i64(guard, -5).unwrap();
+            guard.global().set(guard, &::Property::new(guard, "small"), &small);
+            assert_eq!(script::eval(guard, "typeof small + (small * 2n)").unwrap().to_string(guard), "bigint-10");
+            assert!(BigInt::from_value(guard, script::eval(guard, "Object(1n)").unwrap()).is_none());
+            assert!(BigInt::parse(guard, "1.5").is_err());
+        });
+    }
+}
@@ -3,6 +3,7 @@ use context::ContextGuard;
 use super::Value;
 
 /// A JavaScript boolean.
+#[repr(transparent)]
 pub struct Boolean(JsValueRef);
 
 impl Boolean {
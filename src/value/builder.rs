@@ -0,0 +1,369 @@
+//! Host-side builders for large values.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use chakracore_sys::*;
+use context::ContextGuard;
+use Property;
+use super::function::FunctionCallback;
+use super::{Function, ToJs, Value};
+
+/// A host-side description of a value, used by the builders.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(ArrayBuilder),
+    Object(ObjectBuilder),
+    /// A named native function.
+    Function(HostFunction),
+    /// An existing value, which is used as it is.
+    Value(Value),
+}
+
+/// A host-side description of a named native function.
+///
+/// Clones share the same callback.
+#[derive(Clone)]
+pub struct HostFunction {
+    name: String,
+    callback: Arc<Box<FunctionCallback>>,
+}
+
+impl HostFunction {
+    /// Describes a function with a name and a callback.
+    pub fn new<N: Into<String>>(name: N, callback: Box<FunctionCallback>) -> Self {
+        HostFunction { name: name.into(), callback: Arc::new(callback) }
+    }
+}
+
+impl fmt::Debug for HostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostFunction({:?})", self.name)
+    }
+}
+
+impl PartialEq for HostFunction {
+    fn eq(&self, other: &HostFunction) -> bool {
+        self.name == other.name && Arc::ptr_eq(&self.callback, &other.callback)
+    }
+}
+
+macro_rules! host_value {
+    ($typ:ty, $value:ident => $conversion:expr) => {
+        impl From<$typ> for HostValue {
+            fn from($value: $typ) -> Self {
+                $conversion
+            }
+        }
+    };
+}
+
+host_value!(bool, value => HostValue::Boolean(value));
+host_value!(i32, value => HostValue::Number(value as f64));
+host_value!(u32, value => HostValue::Number(value as f64));
+host_value!(f64, value => HostValue::Number(value));
+host_value!(String, value => HostValue::String(value));
+host_value!(ArrayBuilder, value => HostValue::Array(value));
+host_value!(ObjectBuilder, value => HostValue::Object(value));
+host_value!(HostFunction, value => HostValue::Function(value));
+host_value!(Value, value => HostValue::Value(value));
+
+impl<'a> From<&'a str> for HostValue {
+    fn from(value: &'a str) -> Self {
+        HostValue::String(value.to_string())
+    }
+}
+
+impl<T: Into<HostValue>> From<Option<T>> for HostValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(HostValue::Null, Into::into)
+    }
+}
+
+/// A builder describing an object on the host side.
+///
+/// Building large values by interleaving `Object::new` and `set` calls
+/// creates a property identifier for every key, and reference counts every
+/// intermediate value. Instead, a builder describes the entire value graph
+/// first, and then creates it in a single depth-first pass, where each
+/// distinct key is only interned once.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::value::{ArrayBuilder, ObjectBuilder};
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let mut rows = ArrayBuilder::new();
+/// for id in 0..2 {
+///     let mut row = ObjectBuilder::new();
+///     row.set("id", id).set("name", format!("row {}", id));
+///     rows.push(row);
+/// }
+///
+/// let value = ObjectBuilder::new().set("rows", rows).build(&guard);
+/// assert_eq!(value.to_json(&guard).unwrap(), r#"{"rows":[{"id":0,"name":"row 0"},{"id":1,"name":"row 1"}]}"#);
+/// ```
+///
+/// Host functions and existing values can be part of the graph as well,
+/// e.g when constructing a module namespace:
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::value::ObjectBuilder;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let namespace = ObjectBuilder::new()
+///     .set("version", "1.0")
+///     .set_fn("double", Box::new(|guard, info| {
+///         let number = info.arguments.get(0).map_or(0.0, |value| value.to_double(guard));
+///         Ok(js::value::Number::from_double(guard, number * 2.0).into())
+///     }))
+///     .build(&guard);
+///
+/// guard.global().set(&guard, &js::Property::new(&guard, "math"), &namespace);
+/// assert_eq!(js::script::eval(&guard, "math.double(21)").unwrap().to_integer(&guard), 42);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjectBuilder {
+    properties: Vec<(String, HostValue)>,
+}
+
+impl ObjectBuilder {
+    /// Creates an empty object builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an object builder with capacity for a number of properties.
+    pub fn with_capacity(capacity: usize) -> Self {
+        ObjectBuilder { properties: Vec::with_capacity(capacity) }
+    }
+
+    /// Adds a property. Later properties overwrite earlier ones with the
+    /// same key.
+    pub fn set<K: Into<String>, V: Into<HostValue>>(&mut self, key: K, value: V) -> &mut Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    /// Adds a host function, named after its key.
+    pub fn set_fn<K: Into<String>>(&mut self, key: K, callback: Box<FunctionCallback>) -> &mut Self {
+        let key = key.into();
+        let function = HostFunction::new(key.clone(), callback);
+        self.set(key, function)
+    }
+
+    /// Returns the number of properties added.
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Returns whether no properties have been added.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Creates the object in the guard's context.
+    pub fn build(&self, guard: &ContextGuard) -> Value {
+        let mut materializer = Materializer::new(guard);
+        materializer.intern_object(self);
+        unsafe { Value::from_raw(materializer.create_object(self)) }
+    }
+}
+
+/// A builder describing an array on the host side.
+///
+/// See `ObjectBuilder` for details.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArrayBuilder {
+    elements: Vec<HostValue>,
+}
+
+impl ArrayBuilder {
+    /// Creates an empty array builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an array builder with capacity for a number of elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        ArrayBuilder { elements: Vec::with_capacity(capacity) }
+    }
+
+    /// Appends an element.
+    pub fn push<V: Into<HostValue>>(&mut self, value: V) -> &mut Self {
+        self.elements.push(value.into());
+        self
+    }
+
+    /// Returns the number of elements added.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns whether no elements have been added.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Creates the array in the guard's context.
+    pub fn build(&self, guard: &ContextGuard) -> Value {
+        let mut materializer = Materializer::new(guard);
+        materializer.intern_array(self);
+        unsafe { Value::from_raw(materializer.create_array(self)) }
+    }
+}
+
+impl HostValue {
+    /// Creates the value in the guard's context.
+    pub fn build(&self, guard: &ContextGuard) -> Value {
+        let mut materializer = Materializer::new(guard);
+        materializer.intern(self);
+        unsafe { Value::from_raw(materializer.create(self)) }
+    }
+}
+
+macro_rules! to_js_builder {
+    ($($typ:ident),+) => {
+        $(
+            impl ToJs for $typ {
+                fn to_js(&self, guard: &ContextGuard) -> Value {
+                    self.build(guard)
+                }
+            }
+        )+
+    };
+}
+
+to_js_builder!(HostValue, ObjectBuilder, ArrayBuilder);
+
+/// The state of a value graph being created.
+struct Materializer<'a> {
+    guard: &'a ContextGuard<'a>,
+    keys: HashMap<&'a str, Property>,
+}
+
+impl<'a> Materializer<'a> {
+    fn new(guard: &'a ContextGuard<'a>) -> Self {
+        Materializer { guard: guard, keys: HashMap::new() }
+    }
+
+    /// Creates the property identifiers of all distinct keys in a value graph.
+    fn intern(&mut self, value: &'a HostValue) {
+        match *value {
+            HostValue::Array(ref array) => self.intern_array(array),
+            HostValue::Object(ref object) => self.intern_object(object),
+            _ => (),
+        }
+    }
+
+    fn intern_array(&mut self, array: &'a ArrayBuilder) {
+        for element in &array.elements {
+            self.intern(element);
+        }
+    }
+
+    fn intern_object(&mut self, object: &'a ObjectBuilder) {
+        for &(ref key, ref property) in &object.properties {
+            let guard = self.guard;
+            self.keys.entry(key).or_insert_with(|| Property::new(guard, key));
+            self.intern(property);
+        }
+    }
+
+    /// Creates a value, and its descendants, depth-first.
+    ///
+    /// Intermediate references are only kept on the stack (which is scanned by
+    /// the garbage collector), or by their parent, so they are not reference
+    /// counted.
+    fn create(&self, value: &HostValue) -> JsValueRef {
+        let mut result = JsValueRef::new();
+        unsafe {
+            match *value {
+                HostValue::Undefined => { jsassert!(JsGetUndefinedValue(&mut result)); },
+                HostValue::Null => { jsassert!(JsGetNullValue(&mut result)); },
+                HostValue::Boolean(boolean) => { jsassert!(JsBoolToBoolean(boolean, &mut result)); },
+                HostValue::Number(number) => { jsassert!(JsDoubleToNumber(number, &mut result)); },
+                HostValue::String(ref string) => {
+                    jsassert!(JsCreateString(string.as_ptr() as _, string.len(), &mut result));
+                },
+                HostValue::Array(ref array) => return self.create_array(array),
+                HostValue::Object(ref object) => return self.create_object(object),
+                HostValue::Function(ref function) => {
+                    let function = Function::with_shared_callback(self.guard, &function.name, function.callback.clone());
+                    result = function.as_raw();
+                },
+                HostValue::Value(ref value) => result = value.as_raw(),
+            }
+        }
+        result
+    }
+
+    fn create_array(&self, array: &ArrayBuilder) -> JsValueRef {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jsassert!(JsCreateArray(array.elements.len() as u32, &mut result));
+            for (index, element) in array.elements.iter().enumerate() {
+                let mut index_value = JsValueRef::new();
+                jsassert!(JsIntToNumber(index as i32, &mut index_value));
+                jsassert!(JsSetIndexedProperty(result, index_value, self.create(element)));
+            }
+        }
+        result
+    }
+
+    fn create_object(&self, object: &ObjectBuilder) -> JsValueRef {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jsassert!(JsCreateObject(&mut result));
+            for &(ref key, ref property) in &object.properties {
+                let key = self.keys[key.as_str()].as_raw();
+                jsassert!(JsSetProperty(result, key, self.create(property), true));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test;
+    use {script, value, Property};
+    use super::{ArrayBuilder, HostValue, ObjectBuilder};
+
+    #[test]
+    fn build() {
+        test::run_with_context(|guard| {
+            let mut items = ArrayBuilder::with_capacity(3);
+            items.push(1.5).push("two").push(None::<bool>);
+
+            let mut nested = ObjectBuilder::new();
+            nested.set("flag", true).set("flag", false).set("items", items);
+
+            let value = ObjectBuilder::new().set("nested", nested).set("empty", ArrayBuilder::new()).build(guard);
+            assert_eq!(value.to_json(guard).unwrap(), r#"{"nested":{"flag":false,"items":[1.5,"two",null]},"empty":[]}"#);
+            assert!(HostValue::Undefined.build(guard).is_undefined());
+        });
+    }
+
+    #[test]
+    fn functions() {
+        test::run_with_context(|guard| {
+            let existing: value::Value = value::Number::new(guard, 5).into();
+            let value = ObjectBuilder::new()
+                .set("existing", existing)
+                .set_fn("answer", Box::new(|guard, _| Ok(value::Number::new(guard, 42).into())))
+                .build(guard);
+
+            guard.global().set(guard, &Property::new(guard, "namespace"), &value);
+            let result = script::eval(guard, "[namespace.existing, namespace.answer(), namespace.answer.name]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"[5,42,"answer"]"#);
+        });
+    }
+}
@@ -0,0 +1,146 @@
+//! Built-in functions cached by each context.
+use context::ContextGuard;
+use error::*;
+use {util, Property};
+use super::{Function, Object};
+
+/// The source of a built-in function.
+enum Source {
+    /// A function, identified by its path from the global object.
+    Function(&'static str),
+    /// The getter of a prototype property, identified by its constructor and name.
+    Getter(&'static str, &'static str),
+}
+
+macro_rules! builtins {
+    ($($(#[$attribute:meta])* $name:ident: $source:expr,)*) => {
+        /// The sources of the built-in functions, in the order of their methods.
+        const SOURCES: &[Source] = &[$($source),*];
+
+        #[allow(non_camel_case_types)]
+        enum Index { $($name),* }
+
+        impl<'a> Builtins<'a> {
+            $(
+                $(#[$attribute])*
+                pub fn $name(&self) -> Option<Function> {
+                    self.get(Index::$name as u32)
+                }
+            )*
+        }
+    };
+}
+
+/// The built-in functions used by the value wrappers of built-in types.
+///
+/// The functions are retrieved when a context is created, before any script
+/// is evaluated, so scripts replacing them (on the global object, on an
+/// instance or on a prototype) do not affect the wrappers. Each function is
+/// optional, since a context's globals may be restricted by its runtime's
+/// policy, and some are only provided by certain engine configurations.
+///
+/// Instead of being rooted by the host (which would keep the context alive),
+/// the functions are stored in the context's intrinsics, an object held by
+/// the global object itself.
+pub(crate) struct Builtins<'a> {
+    guard: &'a ContextGuard<'a>,
+    functions: Option<Object>,
+}
+
+builtins! {
+    array_from: Source::Function("Array.from"),
+    /// Only available if the engine supports BigInts.
+    bigint: Source::Function("BigInt"),
+    bigint_value_of: Source::Function("BigInt.prototype.valueOf"),
+    date: Source::Function("Date"),
+    date_get_time: Source::Function("Date.prototype.getTime"),
+    date_to_iso_string: Source::Function("Date.prototype.toISOString"),
+    map: Source::Function("Map"),
+    map_get: Source::Function("Map.prototype.get"),
+    map_set: Source::Function("Map.prototype.set"),
+    map_has: Source::Function("Map.prototype.has"),
+    map_delete: Source::Function("Map.prototype.delete"),
+    map_size: Source::Getter("Map", "size"),
+    regexp: Source::Function("RegExp"),
+    regexp_exec: Source::Function("RegExp.prototype.exec"),
+    regexp_test: Source::Function("RegExp.prototype.test"),
+    regexp_source: Source::Getter("RegExp", "source"),
+    regexp_flags: Source::Getter("RegExp", "flags"),
+    set: Source::Function("Set"),
+    set_add: Source::Function("Set.prototype.add"),
+    set_has: Source::Function("Set.prototype.has"),
+    set_delete: Source::Function("Set.prototype.delete"),
+    set_size: Source::Getter("Set", "size"),
+    /// Only available if the engine has shared array buffers enabled.
+    shared_array_buffer: Source::Function("SharedArrayBuffer"),
+    shared_array_buffer_byte_length: Source::Getter("SharedArrayBuffer", "byteLength"),
+}
+
+impl<'a> Builtins<'a> {
+    /// Returns the built-in functions of the guard's context.
+    pub fn new(guard: &'a ContextGuard<'a>, functions: Option<Object>) -> Self {
+        Builtins { guard: guard, functions: functions }
+    }
+
+    /// Retrieves the built-in functions of the guard's context, as an object
+    /// to be stored in its intrinsics.
+    pub fn capture(guard: &ContextGuard) -> Object {
+        let function = |name: &str| util::jsfunc(guard, name);
+        let getter = |constructor: &str, name: &str| {
+            function(constructor)
+                .and_then(|constructor| constructor.get_own_property_descriptor(guard, &Property::new(guard, "prototype")))
+                .and_then(|descriptor| descriptor.get_value().and_then(|prototype| prototype.clone().into_object()))
+                .and_then(|prototype| prototype.get_own_property_descriptor(guard, &Property::new(guard, name)))
+                .and_then(|descriptor| descriptor.get_getter().cloned())
+        };
+
+        let functions = Object::new(guard);
+        for (index, source) in SOURCES.iter().enumerate() {
+            let function = match *source {
+                Source::Function(path) => function(path),
+                Source::Getter(constructor, name) => getter(constructor, name),
+            };
+
+            if let Some(function) = function {
+                functions.set_index(guard, index as u32, &function);
+            }
+        }
+        functions
+    }
+
+    /// Returns a built-in function, unless it is not available.
+    fn get(&self, index: u32) -> Option<Function> {
+        self.functions.as_ref().and_then(|functions| functions.get_index(self.guard, index).into_function())
+    }
+}
+
+/// Returns a built-in function, or an error if it is not available.
+pub(crate) fn required(function: Option<Function>, name: &str) -> Result<Function> {
+    function.ok_or_else(|| format!("built-in {} is not available", name).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+    use {test, script, value};
+
+    #[test]
+    fn replaced_globals() {
+        test::run_with_context(|guard| {
+            script::eval(guard, "delete Date; delete Map; RegExp.prototype.exec = () => null;").unwrap();
+            script::eval(guard, "
+                for (var symbol of Object.getOwnPropertySymbols(this)) {
+                    this[symbol].builtins[3] = null;
+                    delete this[symbol];
+                }").unwrap();
+            let object = script::eval(guard, "({})").unwrap();
+            assert_eq!(object.value_type(guard), value::ValueType::Object);
+
+            let date = value::Date::new(guard, UNIX_EPOCH);
+            assert_eq!(date.millis(guard), 0.0);
+            assert!(value::Map::is_same(guard, &value::Map::new(guard)));
+            let regexp = value::RegExp::new(guard, "a+", "").unwrap();
+            assert!(regexp.exec(guard, "baa").unwrap().is_some());
+        });
+    }
+}
@@ -0,0 +1,207 @@
+//! JavaScript keyed collections.
+use std::vec;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use super::builtins::{self, Builtins};
+use super::{Value, Object, Function};
+
+/// A JavaScript `Map`.
+#[repr(transparent)]
+pub struct Map(JsValueRef);
+
+/// A JavaScript `Set`.
+#[repr(transparent)]
+pub struct Set(JsValueRef);
+
+impl Map {
+    /// Creates an empty map.
+    pub fn new(guard: &ContextGuard) -> Self {
+        let builtins = guard.builtins();
+        builtins.map()
+            .and_then(|map| map.construct(guard, &[]).ok())
+            .and_then(|map| Self::from_value(guard, map))
+            .expect("constructing Map")
+    }
+
+    /// Represents a value as a `Map`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a `Map` (of the guard's context).
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        match (value.clone().into_object(), builtins.map()) {
+            (Some(object), Some(map)) => object.instance_of(guard, &map),
+            _ => false,
+        }
+    }
+
+    /// Returns the value associated with a key (`undefined` if there is none).
+    pub fn get(&self, guard: &ContextGuard, key: &Value) -> Result<Value> {
+        self.call(guard, |functions| (functions.map_get(), "Map.prototype.get"), &[key])
+    }
+
+    /// Associates a value with a key.
+    pub fn set(&self, guard: &ContextGuard, key: &Value, value: &Value) -> Result<()> {
+        self.call(guard, |functions| (functions.map_set(), "Map.prototype.set"), &[key, value]).map(|_| ())
+    }
+
+    /// Returns whether a key has an associated value.
+    pub fn has(&self, guard: &ContextGuard, key: &Value) -> Result<bool> {
+        self.call(guard, |functions| (functions.map_has(), "Map.prototype.has"), &[key]).map(|result| result.to_bool(guard))
+    }
+
+    /// Removes a key, returning whether it was present.
+    pub fn delete(&self, guard: &ContextGuard, key: &Value) -> Result<bool> {
+        self.call(guard, |functions| (functions.map_delete(), "Map.prototype.delete"), &[key]).map(|result| result.to_bool(guard))
+    }
+
+    /// Returns the number of entries.
+    pub fn size(&self, guard: &ContextGuard) -> Result<usize> {
+        self.call(guard, |functions| (functions.map_size(), "Map.prototype.size"), &[]).map(|size| size.to_integer(guard) as usize)
+    }
+
+    /// Returns an iterator of the map's entries, in insertion order.
+    ///
+    /// The entries are read once this is called, so any later modifications
+    /// of the map are not reflected by the iterator.
+    pub fn iter(&self, guard: &ContextGuard) -> Result<vec::IntoIter<(Value, Value)>> {
+        let entries = elements(guard, self)?.into_iter().map(|entry| {
+            let entry = entry.into_object().expect("converting map entry to object");
+            (entry.get_index(guard, 0), entry.get_index(guard, 1))
+        });
+        Ok(entries.collect::<Vec<_>>().into_iter())
+    }
+
+    /// Calls one of the cached prototype methods, with the map as `this`.
+    fn call<F>(&self, guard: &ContextGuard, method: F, arguments: &[&Value]) -> Result<Value>
+            where F: FnOnce(&Builtins) -> (Option<Function>, &'static str) {
+        let builtins = guard.builtins();
+        let (function, name) = method(&builtins);
+        builtins::required(function, name)?.call_with_this(guard, self, arguments)
+    }
+}
+
+impl Set {
+    /// Creates an empty set.
+    pub fn new(guard: &ContextGuard) -> Self {
+        let builtins = guard.builtins();
+        builtins.set()
+            .and_then(|set| set.construct(guard, &[]).ok())
+            .and_then(|set| Self::from_value(guard, set))
+            .expect("constructing Set")
+    }
+
+    /// Represents a value as a `Set`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a `Set` (of the guard's context).
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        match (value.clone().into_object(), builtins.set()) {
+            (Some(object), Some(set)) => object.instance_of(guard, &set),
+            _ => false,
+        }
+    }
+
+    /// Adds a value, unless it is already present.
+    pub fn add(&self, guard: &ContextGuard, value: &Value) -> Result<()> {
+        self.call(guard, |functions| (functions.set_add(), "Set.prototype.add"), &[value]).map(|_| ())
+    }
+
+    /// Returns whether a value is present.
+    pub fn has(&self, guard: &ContextGuard, value: &Value) -> Result<bool> {
+        self.call(guard, |functions| (functions.set_has(), "Set.prototype.has"), &[value]).map(|result| result.to_bool(guard))
+    }
+
+    /// Removes a value, returning whether it was present.
+    pub fn delete(&self, guard: &ContextGuard, value: &Value) -> Result<bool> {
+        self.call(guard, |functions| (functions.set_delete(), "Set.prototype.delete"), &[value]).map(|result| result.to_bool(guard))
+    }
+
+    /// Returns the number of values.
+    pub fn size(&self, guard: &ContextGuard) -> Result<usize> {
+        self.call(guard, |functions| (functions.set_size(), "Set.prototype.size"), &[]).map(|size| size.to_integer(guard) as usize)
+    }
+
+    /// Returns an iterator of the set's values, in insertion order.
+    ///
+    /// The values are read once this is called, so any later modifications
+    /// of the set are not reflected by the iterator.
+    pub fn iter(&self, guard: &ContextGuard) -> Result<vec::IntoIter<Value>> {
+        elements(guard, self).map(|values| values.into_iter())
+    }
+
+    /// Calls one of the cached prototype methods, with the set as `this`.
+    fn call<F>(&self, guard: &ContextGuard, method: F, arguments: &[&Value]) -> Result<Value>
+            where F: FnOnce(&Builtins) -> (Option<Function>, &'static str) {
+        let builtins = guard.builtins();
+        let (function, name) = method(&builtins);
+        builtins::required(function, name)?.call_with_this(guard, self, arguments)
+    }
+}
+
+/// Returns the elements of an iterable collection, using `Array.from`.
+fn elements(guard: &ContextGuard, collection: &Object) -> Result<Vec<Value>> {
+    let builtins = guard.builtins();
+    let array = builtins::required(builtins.array_from(), "Array.from")?.call(guard, &[collection])?;
+    Ok(array.into_array().expect("converting Array.from result to array").to_vec(guard))
+}
+
+reference!(Map);
+inherit!(Map, Object);
+subtype!(Map, Value);
+reference!(Set);
+inherit!(Set, Object);
+subtype!(Set, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+    use value::ToJs;
+
+    #[test]
+    fn map() {
+        test::run_with_context(|guard| {
+            let result = script::eval(guard, "var m = new Map([['a', 1], [2, 'b']]); m.get = null; m").unwrap();
+            assert!(value::Set::from_value(guard, result.clone()).is_none());
+
+            let map = value::Map::from_value(guard, result).unwrap();
+            assert_eq!(map.get(guard, &"a".to_js(guard)).unwrap().to_integer(guard), 1);
+            assert!(map.get(guard, &"c".to_js(guard)).unwrap().is_undefined());
+
+            map.set(guard, &"c".to_js(guard), &true.to_js(guard)).unwrap();
+            assert!(map.has(guard, &"c".to_js(guard)).unwrap());
+            assert!(map.delete(guard, &2.to_js(guard)).unwrap());
+            assert_eq!(map.size(guard).unwrap(), 2);
+
+            let entries = map.iter(guard).unwrap()
+                .map(|(key, value)| format!("{}={}", key.to_string(guard), value.to_string(guard)))
+                .collect::<Vec<_>>();
+            assert_eq!(entries, ["a=1", "c=true"]);
+            assert_eq!(value::Map::new(guard).size(guard).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn set() {
+        test::run_with_context(|guard| {
+            let set = value::Set::new(guard);
+            set.add(guard, &1.to_js(guard)).unwrap();
+            set.add(guard, &"two".to_js(guard)).unwrap();
+            set.add(guard, &1.to_js(guard)).unwrap();
+
+            assert_eq!(set.size(guard).unwrap(), 2);
+            assert!(set.has(guard, &"two".to_js(guard)).unwrap());
+            assert!(!set.delete(guard, &3.to_js(guard)).unwrap());
+            assert_eq!(set.iter(guard).unwrap().map(|value| value.to_string(guard)).collect::<Vec<_>>(), ["1", "two"]);
+
+            guard.global().set(guard, &::Property::new(guard, "s"), &set);
+            assert!(script::eval(guard, "s instanceof Set && s.has(1)").unwrap().to_bool(guard));
+        });
+    }
+}
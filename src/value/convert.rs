@@ -0,0 +1,326 @@
+//! Conversions between JavaScript values and native types.
+use std::convert::TryFrom;
+use context::ContextGuard;
+use error::*;
+use super::Value;
+
+/// A type that can be created from a JavaScript value.
+///
+/// Unlike the `to_*` methods of `Value`, these conversions do not use
+/// JavaScript semantics; a value of the wrong type results in an error.
+pub trait FromJs: Sized {
+    /// Converts a JavaScript value to the native type.
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self>;
+
+    /// Returns whether the value may be omitted, as a trailing argument of a
+    /// typed host function.
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+/// A type that can be converted to a JavaScript value.
+pub trait ToJs {
+    /// Converts the native type to a JavaScript value.
+    fn to_js(&self, guard: &ContextGuard) -> Value;
+}
+
+/// A value paired with the guard of its context.
+///
+/// The standard conversion traits have no room for a guard, so this wrapper
+/// carries one; `TryFrom` converts JavaScript values to native types (like
+/// `FromJs`), and `From` creates JavaScript values (like `ToJs`).
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use std::convert::TryFrom;
+/// # use js::value::Guarded;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let value: js::value::Value = Guarded(&guard, "foo").into();
+/// assert_eq!(String::try_from(Guarded(&guard, &value)).unwrap(), "foo");
+/// assert!(i32::try_from(Guarded(&guard, &value)).is_err());
+/// ```
+pub struct Guarded<'a, T>(pub &'a ContextGuard<'a>, pub T);
+
+macro_rules! guarded {
+    ($($typ:ty),+) => {
+        $(
+            impl<'a, 'b> TryFrom<Guarded<'a, &'b Value>> for $typ {
+                type Error = Error;
+
+                fn try_from(guarded: Guarded<'a, &'b Value>) -> Result<Self> {
+                    <$typ>::from_js(guarded.0, guarded.1)
+                }
+            }
+        )+
+    };
+}
+
+guarded!(bool, f64, i32, String);
+
+macro_rules! guarded_value {
+    ($($typ:ty),+) => {
+        $(
+            impl<'a> From<Guarded<'a, $typ>> for Value {
+                fn from(guarded: Guarded<'a, $typ>) -> Self {
+                    guarded.1.to_js(guarded.0)
+                }
+            }
+        )+
+    };
+}
+
+guarded_value!(bool, f64, i32, &'a str, String);
+
+/// Returns an error for an unexpected value type.
+fn mismatch<T>(value: &Value, expected: &str) -> Result<T> {
+    Err(format!("expected {}, found {:?}", expected, value.get_type()).into())
+}
+
+impl FromJs for Value {
+    fn from_js(_guard: &ContextGuard, value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromJs for bool {
+    fn from_js(_guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match value.clone().into_boolean() {
+            Some(boolean) => Ok(boolean.value()),
+            None => mismatch(value, "Boolean"),
+        }
+    }
+}
+
+impl FromJs for f64 {
+    fn from_js(_guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match value.clone().into_number() {
+            Some(number) => Ok(number.value_double()),
+            None => mismatch(value, "Number"),
+        }
+    }
+}
+
+impl FromJs for i32 {
+    fn from_js(_guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match value.clone().into_number() {
+            Some(number) => Ok(number.value()),
+            None => mismatch(value, "Number"),
+        }
+    }
+}
+
+impl FromJs for String {
+    fn from_js(_guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match value.clone().into_string() {
+            Some(string) => Ok(string.value()),
+            None => mismatch(value, "String"),
+        }
+    }
+}
+
+/// `undefined` and `null` are converted to `None`.
+impl<T: FromJs> FromJs for Option<T> {
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        if value.is_undefined() || value.is_null() {
+            Ok(None)
+        } else {
+            T::from_js(guard, value).map(Some)
+        }
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+impl<T: FromJs> FromJs for Vec<T> {
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match value.clone().into_array() {
+            Some(array) => array.iter(guard).map(|element| T::from_js(guard, &element)).collect(),
+            None => mismatch(value, "Array"),
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($length:expr => $($name:ident : $index:tt),+) => {
+        /// Tuples are converted from arrays of the same length.
+        impl<$($name: FromJs),+> FromJs for ($($name,)+) {
+            fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+                let array = match value.clone().into_array() {
+                    Some(array) => array,
+                    None => return mismatch(value, "Array"),
+                };
+
+                if array.len(guard) != $length {
+                    return Err(format!("expected Array of length {}", $length).into());
+                }
+
+                Ok(($($name::from_js(guard, &array.get_index(guard, $index))?,)+))
+            }
+        }
+
+        /// Tuples are converted to arrays of the same length.
+        impl<$($name: ToJs),+> ToJs for ($($name,)+) {
+            fn to_js(&self, guard: &ContextGuard) -> Value {
+                let array = super::Array::new(guard, $length);
+                $(array.set_index(guard, $index, &self.$index.to_js(guard));)+
+                array.into()
+            }
+        }
+    };
+}
+
+tuple!(1 => A: 0);
+tuple!(2 => A: 0, B: 1);
+tuple!(3 => A: 0, B: 1, C: 2);
+tuple!(4 => A: 0, B: 1, C: 2, D: 3);
+tuple!(5 => A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple!(6 => A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+impl<'a, T: ToJs + ?Sized> ToJs for &'a T {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        (**self).to_js(guard)
+    }
+}
+
+macro_rules! to_js_value {
+    ($($typ:ident),+) => {
+        $(
+            impl ToJs for super::$typ {
+                fn to_js(&self, _guard: &ContextGuard) -> Value {
+                    let value: &Value = self;
+                    value.clone()
+                }
+            }
+        )+
+    };
+}
+
+to_js_value!(Object, Array, Function, External, Promise, Boolean, Number, Map, Set, Date, RegExp, BigInt, SharedArrayBuffer);
+
+impl ToJs for Value {
+    fn to_js(&self, _guard: &ContextGuard) -> Value {
+        self.clone()
+    }
+}
+
+/// The unit type is converted to `undefined`.
+impl ToJs for () {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::undefined(guard)
+    }
+}
+
+impl ToJs for bool {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::Boolean::new(guard, *self).into()
+    }
+}
+
+impl ToJs for i32 {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::Number::new(guard, *self).into()
+    }
+}
+
+impl ToJs for u32 {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::Number::from_u32(guard, *self).into()
+    }
+}
+
+impl ToJs for f64 {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::Number::from_double(guard, *self).into()
+    }
+}
+
+impl ToJs for str {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::String::new(guard, self).into()
+    }
+}
+
+impl ToJs for String {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        self.as_str().to_js(guard)
+    }
+}
+
+/// `None` is converted to `null`.
+impl<T: ToJs> ToJs for Option<T> {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        match *self {
+            Some(ref value) => value.to_js(guard),
+            None => super::null(guard),
+        }
+    }
+}
+
+impl<T: ToJs> ToJs for [T] {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        super::Array::from_iter(guard, self.iter().map(|element| element.to_js(guard))).into()
+    }
+}
+
+impl<T: ToJs> ToJs for Vec<T> {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        self.as_slice().to_js(guard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use std::convert::TryFrom;
+    use super::{FromJs, Guarded, ToJs};
+
+    #[test]
+    fn conversions() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "[1, 'foo', [true, null]]").unwrap();
+            let (number, string, rest) = <(i32, String, (bool, Option<f64>))>::from_js(guard, &value).unwrap();
+            assert_eq!(number, 1);
+            assert_eq!(string, "foo");
+            assert_eq!(rest, (true, None));
+
+            assert!(String::from_js(guard, &value).is_err());
+            assert!(<(i32, String)>::from_js(guard, &value).is_err());
+        });
+    }
+
+    #[test]
+    fn to_js() {
+        test::run_with_context(|guard| {
+            let value = (vec![Some(1), None], "foo", 2.5);
+            let array = [value.0.to_js(guard), value.1.to_js(guard), value.2.to_js(guard)];
+            assert_eq!(array.to_js(guard).to_json(guard).unwrap(), r#"[[1,null],"foo",2.5]"#);
+            assert_eq!(value.to_js(guard).to_json(guard).unwrap(), r#"[[1,null],"foo",2.5]"#);
+        });
+    }
+
+    #[test]
+    fn guarded() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "[1.5, 'foo', true, 7]").unwrap();
+            let array = value.into_array().unwrap();
+            assert_eq!(f64::try_from(Guarded(guard, &array.get_index(guard, 0))).unwrap(), 1.5);
+            assert_eq!(String::try_from(Guarded(guard, &array.get_index(guard, 1))).unwrap(), "foo");
+            assert!(bool::try_from(Guarded(guard, &array.get_index(guard, 2))).unwrap());
+            assert_eq!(array.get_index(guard, 3).convert::<i32>(guard).unwrap(), 7);
+            assert!(i32::try_from(Guarded(guard, &array.get_index(guard, 1))).is_err());
+
+            let values: Vec<::value::Value> = vec![
+                Guarded(guard, 1).into(),
+                Guarded(guard, 2.5).into(),
+                Guarded(guard, "foo").into(),
+                Guarded(guard, false).into(),
+            ];
+            assert_eq!(values.to_js(guard).to_json(guard).unwrap(), r#"[1,2.5,"foo",false]"#);
+        });
+    }
+}
@@ -0,0 +1,172 @@
+//! A JavaScript date.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use super::{FromJs, ToJs, Value, Object};
+
+/// A JavaScript `Date`.
+///
+/// Dates are represented by their time value, i.e the number of milliseconds
+/// since the Unix epoch (which is `NaN` for an invalid date).
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use std::time::{Duration, UNIX_EPOCH};
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let time = UNIX_EPOCH + Duration::from_millis(86_400_000);
+/// let date = js::value::Date::new(&guard, time);
+/// assert_eq!(date.to_string(&guard).contains("1970"), true);
+///
+/// let result = js::script::eval(&guard, "new Date(Date.UTC(2000, 0, 1))").unwrap();
+/// assert!(result.is_date(&guard));
+/// let date = js::value::Date::from_value(&guard, result).unwrap();
+/// assert_eq!(date.millis(&guard), 946_684_800_000.0);
+/// ```
+#[repr(transparent)]
+pub struct Date(JsValueRef);
+
+impl Date {
+    /// Creates a date from a system time.
+    pub fn new(guard: &ContextGuard, time: SystemTime) -> Self {
+        let millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => millis(duration),
+            Err(error) => -millis(error.duration()),
+        };
+        Self::from_millis(guard, millis)
+    }
+
+    /// Creates a date from a number of milliseconds since the Unix epoch.
+    ///
+    /// The time value is truncated to whole milliseconds, and it results in an
+    /// invalid date if it is out of range of JavaScript dates.
+    pub fn from_millis(guard: &ContextGuard, millis: f64) -> Self {
+        let millis = super::Number::from_double(guard, millis).into();
+        let builtins = guard.builtins();
+        builtins.date()
+            .and_then(|date| date.construct(guard, &[&millis]).ok())
+            .and_then(|date| Self::from_value(guard, date))
+            .expect("constructing Date")
+    }
+
+    /// Represents a value as a `Date`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a `Date` (of the guard's context).
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        match (value.clone().into_object(), builtins.date()) {
+            (Some(object), Some(date)) => object.instance_of(guard, &date),
+            _ => false,
+        }
+    }
+
+    /// Returns the number of milliseconds since the Unix epoch (`NaN` for an
+    /// invalid date).
+    pub fn millis(&self, guard: &ContextGuard) -> f64 {
+        let builtins = guard.builtins();
+        builtins.date_get_time()
+            .and_then(|get_time| get_time.call_with_this(guard, self, &[]).ok())
+            .map(|time| time.to_double(guard))
+            .unwrap_or(::std::f64::NAN)
+    }
+
+    /// Returns whether the date has a valid time value.
+    pub fn is_valid(&self, guard: &ContextGuard) -> bool {
+        !self.millis(guard).is_nan()
+    }
+
+    /// Returns the date in ISO 8601 format (e.g `2000-01-01T00:00:00.000Z`),
+    /// unless it is invalid.
+    pub fn to_iso_string(&self, guard: &ContextGuard) -> Option<String> {
+        let builtins = guard.builtins();
+        builtins.date_to_iso_string()
+            .and_then(|to_iso_string| to_iso_string.call_with_this(guard, self, &[]).ok())
+            .map(|string| string.to_string(guard))
+    }
+
+    /// Converts the date to a system time, unless it is invalid.
+    pub fn to_system_time(&self, guard: &ContextGuard) -> Option<SystemTime> {
+        let millis = self.millis(guard);
+        if millis.is_nan() {
+            None
+        } else if millis >= 0.0 {
+            UNIX_EPOCH.checked_add(Duration::from_millis(millis as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_millis(-millis as u64))
+        }
+    }
+
+    /// Creates a date from a `chrono` date time.
+    #[cfg(feature = "chrono")]
+    pub fn from_chrono<Tz: ::chrono::TimeZone>(guard: &ContextGuard, time: &::chrono::DateTime<Tz>) -> Self {
+        Self::from_millis(guard, time.timestamp_millis() as f64)
+    }
+
+    /// Converts the date to a `chrono` UTC date time, unless it is invalid.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(&self, guard: &ContextGuard) -> Option<::chrono::DateTime<::chrono::Utc>> {
+        use chrono::TimeZone;
+        let millis = self.millis(guard);
+        if millis.is_nan() { None } else { ::chrono::Utc.timestamp_millis_opt(millis as i64).single() }
+    }
+}
+
+/// Converts a duration to (whole) milliseconds.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + (duration.subsec_nanos() / 1_000_000) as f64
+}
+
+impl ToJs for SystemTime {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        Date::new(guard, *self).into()
+    }
+}
+
+/// Reads a valid `Date`.
+impl FromJs for SystemTime {
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        match Date::from_value(guard, value.clone()) {
+            Some(date) => date.to_system_time(guard).ok_or_else(|| "invalid Date".into()),
+            None => Err(format!("expected Date, found {:?}", value.get_type()).into()),
+        }
+    }
+}
+
+reference!(Date);
+inherit!(Date, Object);
+subtype!(Date, Value);
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use {test, script, value};
+    use value::{FromJs, ToJs};
+
+    #[test]
+    fn date() {
+        test::run_with_context(|guard| {
+            let time = UNIX_EPOCH + Duration::from_millis(1_500_000_000_123);
+            let date = value::Date::new(guard, time);
+            assert_eq!(date.millis(guard), 1_500_000_000_123.0);
+            assert_eq!(date.to_system_time(guard), Some(time));
+
+            guard.global().set(guard, &::Property::new(guard, "d"), &time.to_js(guard));
+            assert_eq!(date.to_iso_string(guard).unwrap(), "2017-07-14T02:40:00.123Z");
+            assert_eq!(script::eval(guard, "d.getTime()").unwrap().to_double(guard), 1_500_000_000_123.0);
+
+            let before = script::eval(guard, "new Date(-1000)").unwrap();
+            assert_eq!(SystemTime::from_js(guard, &before).unwrap(), UNIX_EPOCH - Duration::from_secs(1));
+
+            let invalid = script::eval(guard, "new Date(NaN)").unwrap();
+            assert!(invalid.is_date(guard));
+            assert_eq!(value::Date::from_value(guard, invalid.clone()).unwrap().to_iso_string(guard), None);
+            assert!(SystemTime::from_js(guard, &invalid).is_err());
+            assert!(!script::eval(guard, "Date.now()").unwrap().is_date(guard));
+        });
+    }
+}
@@ -0,0 +1,153 @@
+use boolinator::Boolinator;
+use context::ContextGuard;
+use super::{Value, Boolean, Function, Object};
+use Property;
+
+/// A property descriptor, used to define properties with full control over
+/// their attributes.
+///
+/// A descriptor is either a data descriptor (with a value and optionally
+/// `writable`), or an accessor descriptor (with a getter and/or setter).
+/// Unspecified attributes use the defaults of `Object.defineProperty`, i.e
+/// they are `false`.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use js::value::PropertyDescriptor;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let getter = js::value::Function::new(&guard, Box::new(|guard, _| {
+///     Ok(js::value::Number::new(guard, 42).into())
+/// }));
+///
+/// let object = js::value::Object::new(&guard);
+/// let descriptor = PropertyDescriptor::new().getter(getter).enumerable(true);
+/// object.define(&guard, &js::Property::new(&guard, "answer"), &descriptor).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct PropertyDescriptor {
+    value: Option<Value>,
+    getter: Option<Function>,
+    setter: Option<Function>,
+    enumerable: Option<bool>,
+    configurable: Option<bool>,
+    writable: Option<bool>,
+}
+
+impl PropertyDescriptor {
+    /// Creates an empty property descriptor.
+    pub fn new() -> Self {
+        PropertyDescriptor::default()
+    }
+
+    /// Sets the property's value.
+    pub fn value<T: Into<Value>>(mut self, value: T) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Sets the property's getter.
+    pub fn getter(mut self, getter: Function) -> Self {
+        self.getter = Some(getter);
+        self
+    }
+
+    /// Sets the property's setter.
+    pub fn setter(mut self, setter: Function) -> Self {
+        self.setter = Some(setter);
+        self
+    }
+
+    /// Sets whether the property shows up during enumeration.
+    pub fn enumerable(mut self, enumerable: bool) -> Self {
+        self.enumerable = Some(enumerable);
+        self
+    }
+
+    /// Sets whether the property can be changed or deleted.
+    pub fn configurable(mut self, configurable: bool) -> Self {
+        self.configurable = Some(configurable);
+        self
+    }
+
+    /// Sets whether the property's value can be changed by assignment.
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = Some(writable);
+        self
+    }
+
+    /// Returns the property's value, if any.
+    pub fn get_value(&self) -> Option<&Value> {
+        self.value.as_ref()
+    }
+
+    /// Returns the property's getter, if any.
+    pub fn get_getter(&self) -> Option<&Function> {
+        self.getter.as_ref()
+    }
+
+    /// Returns the property's setter, if any.
+    pub fn get_setter(&self) -> Option<&Function> {
+        self.setter.as_ref()
+    }
+
+    /// Returns whether the property is enumerable, if specified.
+    pub fn is_enumerable(&self) -> Option<bool> {
+        self.enumerable
+    }
+
+    /// Returns whether the property is configurable, if specified.
+    pub fn is_configurable(&self) -> Option<bool> {
+        self.configurable
+    }
+
+    /// Returns whether the property is writable, if specified.
+    pub fn is_writable(&self) -> Option<bool> {
+        self.writable
+    }
+
+    /// Creates a descriptor object, as used by `Object.defineProperty`.
+    pub fn to_object(&self, guard: &ContextGuard) -> Object {
+        let object = Object::new(guard);
+        let set = |name: &str, value: &Value| object.set(guard, &Property::new(guard, name), value);
+        let flag = |value: bool| -> Value { Boolean::new(guard, value).into() };
+
+        if let Some(ref value) = self.value {
+            set("value", value);
+        }
+        if let Some(ref getter) = self.getter {
+            set("get", getter);
+        }
+        if let Some(ref setter) = self.setter {
+            set("set", setter);
+        }
+        if let Some(enumerable) = self.enumerable {
+            set("enumerable", &flag(enumerable));
+        }
+        if let Some(configurable) = self.configurable {
+            set("configurable", &flag(configurable));
+        }
+        if let Some(writable) = self.writable {
+            set("writable", &flag(writable));
+        }
+        object
+    }
+
+    /// Reads a descriptor object, as returned by `Object.getOwnPropertyDescriptor`.
+    pub fn from_object(guard: &ContextGuard, object: &Object) -> Self {
+        let get = |name: &str| {
+            let property = Property::new(guard, name);
+            object.has(guard, &property).as_some_from(|| object.get(guard, &property))
+        };
+
+        PropertyDescriptor {
+            value: get("value"),
+            getter: get("get").and_then(|value| value.into_function()),
+            setter: get("set").and_then(|value| value.into_function()),
+            enumerable: get("enumerable").map(|value| value.to_bool(guard)),
+            configurable: get("configurable").map(|value| value.to_bool(guard)),
+            writable: get("writable").map(|value| value.to_bool(guard)),
+        }
+    }
+}
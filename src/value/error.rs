@@ -13,6 +13,7 @@ macro_rules! ctor {
 }
 
 /// A JavaScript error.
+#[repr(transparent)]
 pub struct Error(JsValueRef);
 
 impl Error {
@@ -23,12 +24,44 @@ impl Error {
     ctor!(type_error, JsCreateTypeError, "Creates a new type error.");
     ctor!(uri_error, JsCreateURIError, "Creates a new URI error.");
 
+    /// Creates a new error with a custom name (e.g `ValidationError`).
+    ///
+    /// The name is an own property of the error, so it is used by `toString`
+    /// and stack traces, while the error remains an instance of `Error`.
+    pub fn custom(guard: &ContextGuard, name: &str, message: &str) -> Self {
+        let error = Self::new(guard, message);
+        error.set(guard, &Property::new(guard, "name"), &super::String::new(guard, name));
+        error
+    }
+
     /// Returns the error's message.
     pub fn message(&self, guard: &ContextGuard) -> String {
         let property = Property::new(guard, "message");
         self.get(guard, &property).to_string(guard)
     }
 
+    /// Returns the error's name (e.g `TypeError`).
+    pub fn name(&self, guard: &ContextGuard) -> String {
+        let property = Property::new(guard, "name");
+        self.get(guard, &property).to_string(guard)
+    }
+
+    /// Returns the error's stack trace, if it has one.
+    ///
+    /// Errors created by the host only have a stack once they are thrown.
+    pub fn stack(&self, guard: &ContextGuard) -> Option<String> {
+        let stack = self.get(guard, &Property::new(guard, "stack"));
+        if stack.is_undefined() { None } else { Some(stack.to_string(guard)) }
+    }
+
+    /// Throws the error from a native callback.
+    ///
+    /// This is a shorthand for `Err(error.into())`, and can be used with any
+    /// callback result (e.g `return Error::range_error(guard, "...").throw()`).
+    pub fn throw<T>(self) -> ::std::result::Result<T, Value> {
+        Err(self.into())
+    }
+
     is_same!(Error, "Returns true if the value is an `Error`.");
 }
 
@@ -51,7 +84,7 @@ subtype!(Error, Value);
 
 #[cfg(test)]
 mod tests {
-    use {test, value};
+    use {test, script, value, Property};
 
     #[test]
     fn string_conversion() {
@@ -60,4 +93,25 @@ mod tests {
             assert_eq!(error.to_string(guard), "TypeError: FooBar");
         });
     }
+
+    #[test]
+    fn accessors() {
+        test::run_with_context(|guard| {
+            let error = value::Error::custom(guard, "ValidationError", "invalid input");
+            assert_eq!(error.to_string(guard), "ValidationError: invalid input");
+            assert_eq!(error.name(guard), "ValidationError");
+            assert_eq!(error.message(guard), "invalid input");
+            assert_eq!(value::Error::uri_error(guard, "bad").name(guard), "URIError");
+
+            let thrower = value::Function::new(guard, Box::new(|guard, _| {
+                value::Error::range_error(guard, "out of range").throw()
+            }));
+            guard.global().set(guard, &Property::new(guard, "thrower"), &thrower);
+            let result = script::eval(guard, "try { thrower() } catch (e) { e }").unwrap().into_error().unwrap();
+            assert_eq!(result.name(guard), "RangeError");
+
+            let result = script::eval(guard, "(function fail() { try { null.x } catch (e) { return e } })()").unwrap();
+            assert!(result.into_error().unwrap().stack(guard).unwrap().contains("fail"));
+        });
+    }
 }
\ No newline at end of file
@@ -0,0 +1,265 @@
+//! Context independent copies of values.
+use std::{ptr, slice};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use Property;
+use super::{Value, Array, ArrayBuffer, Object};
+
+/// A context independent copy of a JavaScript value.
+///
+/// A value is first read into this representation while its own context is
+/// active. Afterwards it can be shared between threads and inspected without
+/// any engine involvement, or written into any other context.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportedValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<ExportedValue>),
+    Object(Vec<(String, ExportedValue)>),
+    ArrayBuffer(Vec<u8>),
+    /// A typed array, and the bytes of its elements.
+    TypedArray(JsTypedArrayType, Vec<u8>),
+}
+
+impl ExportedValue {
+    /// Reads a value using structured clone semantics.
+    ///
+    /// Functions, external objects and cyclic structures cannot be exported
+    /// and will result in an error.
+    pub fn read(guard: &ContextGuard, value: &Value) -> Result<ExportedValue> {
+        read_value(guard, value, &mut Vec::new())
+    }
+
+    /// Returns the value of a property, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&ExportedValue> {
+        match *self {
+            ExportedValue::Object(ref properties) => {
+                properties.iter().find(|&&(ref name, _)| name == key).map(|&(_, ref value)| value)
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns an element, if this is an array.
+    pub fn get_index(&self, index: usize) -> Option<&ExportedValue> {
+        match *self {
+            ExportedValue::Array(ref elements) => elements.get(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean, if this is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            ExportedValue::Boolean(boolean) => Some(boolean),
+            _ => None,
+        }
+    }
+
+    /// Returns the number, if this is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            ExportedValue::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            ExportedValue::String(ref string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is `null` or `undefined`.
+    pub fn is_null(&self) -> bool {
+        match *self {
+            ExportedValue::Undefined | ExportedValue::Null => true,
+            _ => false,
+        }
+    }
+
+    /// Writes the exported value into the guard's context.
+    pub fn write(&self, guard: &ContextGuard) -> Value {
+        match *self {
+            ExportedValue::Undefined => super::undefined(guard),
+            ExportedValue::Null => super::null(guard),
+            ExportedValue::Boolean(boolean) => super::Boolean::new(guard, boolean).into(),
+            ExportedValue::Number(number) => super::Number::from_double(guard, number).into(),
+            ExportedValue::String(ref string) => super::String::new(guard, string).into(),
+            ExportedValue::Array(ref elements) => {
+                Array::from_iter(guard, elements.iter().map(|element| element.write(guard))).into()
+            },
+            ExportedValue::Object(ref properties) => {
+                let object = Object::new(guard);
+                for &(ref key, ref value) in properties {
+                    object.set(guard, &Property::new(guard, key), &value.write(guard));
+                }
+                object.into()
+            },
+            ExportedValue::ArrayBuffer(ref bytes) => ArrayBuffer::with_data(guard, bytes.clone()).into(),
+            ExportedValue::TypedArray(kind, ref bytes) => {
+                let buffer = ArrayBuffer::with_data(guard, bytes.clone());
+                let length = bytes.len() / element_size(kind);
+                let mut array = JsValueRef::new();
+                unsafe {
+                    jsassert!(JsCreateTypedArray(kind, buffer.as_raw(), 0, length as _, &mut array));
+                    Value::from_raw(array)
+                }
+            },
+        }
+    }
+}
+
+/// Copies a value from one context to another.
+///
+/// The value is deep-copied using the same semantics as `Value::export`, so
+/// the contexts may belong to different runtimes.
+pub fn transfer(from: &ContextGuard, to: &ContextGuard, value: &Value) -> Result<Value> {
+    let exported = from.context().exec_with(|guard| value.export(guard))??;
+    to.context().exec_with(|guard| exported.write(guard))
+}
+
+/// Returns the size in bytes of a typed array's elements.
+fn element_size(kind: JsTypedArrayType) -> usize {
+    match kind {
+        JsTypedArrayType::Int8 | JsTypedArrayType::Uint8 | JsTypedArrayType::Uint8Clamped => 1,
+        JsTypedArrayType::Int16 | JsTypedArrayType::Uint16 => 2,
+        JsTypedArrayType::Int32 | JsTypedArrayType::Uint32 | JsTypedArrayType::Float32 => 4,
+        JsTypedArrayType::Float64 => 8,
+    }
+}
+
+/// Reads a value, keeping track of its ancestors to detect cycles.
+fn read_value(guard: &ContextGuard, value: &Value, ancestors: &mut Vec<Value>) -> Result<ExportedValue> {
+    if value.is_undefined() {
+        return Ok(ExportedValue::Undefined);
+    }
+
+    if value.is_null() {
+        return Ok(ExportedValue::Null);
+    }
+
+    if let Some(boolean) = value.clone().into_boolean() {
+        return Ok(ExportedValue::Boolean(boolean.value()));
+    }
+
+    if let Some(number) = value.clone().into_number() {
+        return Ok(ExportedValue::Number(number.value_double()));
+    }
+
+    if let Some(string) = value.clone().into_string() {
+        return Ok(ExportedValue::String(string.value()));
+    }
+
+    if value.is_function() || value.is_external() {
+        return Err(format!("{:?} value could not be exported", value.get_type()).into());
+    }
+
+    match value.get_type() {
+        JsValueType::ArrayBuffer => {
+            let buffer = value.clone().into_array_buffer().expect("converting value to array buffer");
+            return Ok(ExportedValue::ArrayBuffer(buffer.as_slice().to_vec()));
+        },
+        JsValueType::TypedArray => {
+            let mut data = ptr::null_mut();
+            let mut size = 0;
+            let mut kind = JsTypedArrayType::Int8;
+            let mut element_size = 0;
+            unsafe {
+                jsassert!(JsGetTypedArrayStorage(value.as_raw(), &mut data, &mut size, &mut kind, &mut element_size));
+                let bytes = slice::from_raw_parts(data, size as usize).to_vec();
+                return Ok(ExportedValue::TypedArray(kind, bytes));
+            }
+        },
+        _ => (),
+    }
+
+    if ancestors.iter().any(|ancestor| ancestor.strict_equals(guard, value)) {
+        return Err("cyclic value could not be exported".into());
+    }
+
+    ancestors.push(value.clone());
+    let result = if let Some(array) = value.clone().into_array() {
+        array.iter(guard)
+            .map(|element| read_value(guard, &element, ancestors))
+            .collect::<Result<Vec<_>>>()
+            .map(ExportedValue::Array)
+    } else {
+        let object = value.clone().into_object().expect("converting value to object");
+        object.get_own_property_names(guard)
+            .iter(guard)
+            .map(|key| {
+                let key = key.to_string(guard);
+                let property = object.get(guard, &Property::new(guard, &key));
+                read_value(guard, &property, ancestors).map(|value| (key, value))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(ExportedValue::Object)
+    };
+    ancestors.pop();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use {test, script, value, Context, Property, Runtime};
+    use super::ExportedValue;
+
+    #[test]
+    fn round_trip() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "({ foo: [1, 'bar', null], baz: true })").unwrap();
+            let cloned = ExportedValue::read(guard, &value).unwrap();
+            assert_eq!(cloned.write(guard).to_json(guard).unwrap(),
+                       r#"{"foo":[1,"bar",null],"baz":true}"#);
+        });
+    }
+
+    #[test]
+    fn export() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "({ foo: [1, 'bar'], baz: null })").unwrap();
+            let exported = value.export(guard).unwrap();
+
+            let result = thread::spawn(move || {
+                let foo = exported.get("foo").unwrap();
+                (foo.get_index(0).and_then(ExportedValue::as_f64),
+                 foo.get_index(1).and_then(ExportedValue::as_str).map(String::from),
+                 exported.get("baz").map(ExportedValue::is_null))
+            }).join().unwrap();
+            assert_eq!(result, (Some(1.0), Some("bar".to_string()), Some(true)));
+        });
+    }
+
+    #[test]
+    fn transfer() {
+        let (_runtime, context) = test::setup_env();
+        let guard = context.make_current().unwrap();
+        let value = script::eval(&guard, "({ list: [1, 'foo'], bytes: new Int16Array([1, -2]) })").unwrap();
+
+        let other_runtime = Runtime::new().unwrap();
+        let other = Context::new(&other_runtime).unwrap();
+        let other_guard = other.make_current().unwrap();
+        let result = value::transfer(&guard, &other_guard, &value).unwrap();
+        other_guard.global().set(&other_guard, &Property::new(&other_guard, "result"), &result);
+
+        let check = script::eval(&other_guard, "
+            result.bytes instanceof Int16Array && result.bytes[1] === -2 && result.list[1]").unwrap();
+        assert_eq!(check.to_string(&other_guard), "foo");
+    }
+
+    #[test]
+    fn cyclic() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "var foo = {}; foo.bar = foo; foo").unwrap();
+            assert!(ExportedValue::read(guard, &value).is_err());
+        });
+    }
+}
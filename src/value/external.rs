@@ -1,12 +1,48 @@
+use std::any::{Any, TypeId};
+use std::collections::HashSet;
 use std::ptr;
+use std::sync::{Arc, Mutex};
 use libc::c_void;
 use chakracore_sys::*;
 use context::ContextGuard;
 use super::{Value, Object};
 
 /// A JavaScript external object.
+///
+/// The data of an external created with `typed` is tagged with its type, so
+/// it can be borrowed using `value_ref` and `value_mut`, which return `None`
+/// for any other type (or for externals created by other means).
+#[repr(transparent)]
 pub struct External(JsValueRef);
 
+/// The data of an external created by `External::typed`.
+///
+/// The layout is fixed, so the header can be read without knowing `T`.
+#[repr(C)]
+struct Typed<T> {
+    type_id: TypeId,
+    registry: Registry,
+    value: T,
+}
+
+/// The addresses of the live `Typed` data created in a context.
+///
+/// Each data keeps a handle to its registry, so it can be unregistered when
+/// finalized (even after its context has been disposed).
+#[derive(Clone, Default)]
+struct Registry(Arc<Mutex<HashSet<usize>>>);
+
+impl Registry {
+    /// Returns the registry of the guard's context.
+    fn of(guard: &ContextGuard) -> Self {
+        let context = guard.context();
+        if context.get_user_data::<Registry>().is_none() {
+            context.insert_user_data(Registry::default());
+        }
+        context.get_user_data::<Registry>().expect("retrieving external registry").clone()
+    }
+}
+
 // TODO: Make the entire implementation generic
 impl External {
     /// Creates a new object with external data.
@@ -17,7 +53,8 @@ impl External {
     ///
     /// As long as the object is referenced on the stack or in any script
     /// context, the `external` data will be kept alive (i.e it is not tied to
-    /// the handle).
+    /// the handle). The data can only be accessed using `value_unchecked`;
+    /// prefer `typed` for data that can be borrowed safely.
     pub fn new<T>(_guard: &ContextGuard, external: Box<T>) -> Self {
         let mut value = JsValueRef::new();
         unsafe {
@@ -28,6 +65,28 @@ impl External {
         }
     }
 
+    /// Creates a new object with external data tagged with its type.
+    ///
+    /// The data is owned by the object, like with `new`, and can be borrowed
+    /// using `value_ref` and `value_mut` from the context it was created in.
+    pub fn typed<T: Any>(guard: &ContextGuard, external: T) -> Self {
+        let registry = Registry::of(guard);
+        let data = Box::into_raw(Box::new(Typed {
+            type_id: TypeId::of::<T>(),
+            registry: registry.clone(),
+            value: external,
+        }));
+        registry.0.lock().unwrap().insert(data as usize);
+
+        let mut value = JsValueRef::new();
+        unsafe {
+            jsassert!(JsCreateExternalObject(data as *mut _,
+                                            Some(Self::finalize_typed::<T>),
+                                            &mut value));
+            Self::from_raw(value)
+        }
+    }
+
     /// Creates a new object with external data.
     ///
     /// This is unsafe because the object does not take ownership of the
@@ -41,11 +100,56 @@ impl External {
         Self::from_raw(value)
     }
 
+    /// Borrows the external object's data, if it is of type `T`.
+    ///
+    /// This is only supported for objects created using `typed`, within the
+    /// guard's context.
+    pub fn value_ref<T: Any>(&self, guard: &ContextGuard) -> Option<&T> {
+        self.typed_data::<T>(guard).map(|data| unsafe { &(*data).value })
+    }
+
+    /// Mutably borrows the external object's data, if it is of type `T`.
+    ///
+    /// The data is shared by all handles to the object, so it must not be
+    /// borrowed through another handle at the same time.
+    pub fn value_mut<T: Any>(&mut self, guard: &ContextGuard) -> Option<&mut T> {
+        self.typed_data::<T>(guard).map(|data| unsafe { &mut (*data).value })
+    }
+
     /// Returns the external object's data.
+    #[deprecated(since = "0.2.1", note = "use `value_ref` or `value_unchecked` instead")]
     pub unsafe fn value<T>(&self) -> &mut T {
+        self.value_unchecked()
+    }
+
+    /// Returns the external object's data, without any type checks.
+    ///
+    /// This is unsafe since the data may be of any type (or dangling, for
+    /// objects created using `from_ptr`). The data of objects created using
+    /// `typed` is not a `T`, and must be borrowed using `value_ref` instead.
+    pub unsafe fn value_unchecked<T>(&self) -> &mut T {
+        (self.data() as *mut T).as_mut().expect("retrieving external data")
+    }
+
+    /// Returns the data of an object created using `typed`, if it is of type
+    /// `T`.
+    fn typed_data<T: Any>(&self, guard: &ContextGuard) -> Option<*mut Typed<T>> {
+        let data = self.data();
+        let is_typed = Registry::of(guard).0.lock().unwrap().contains(&(data as usize));
+
+        // Since the layout is fixed, the type can be read through any `Typed`
+        if is_typed && unsafe { (*(data as *const Typed<()>)).type_id } == TypeId::of::<T>() {
+            Some(data as *mut Typed<T>)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the external object's raw data pointer.
+    fn data(&self) -> *mut c_void {
         let mut data = ptr::null_mut();
-        jsassert!(JsGetExternalData(self.as_raw(), &mut data));
-        (data as *mut T).as_mut().expect("retrieving external data")
+        jsassert!(unsafe { JsGetExternalData(self.as_raw(), &mut data) });
+        data
     }
 
     /// Returns true if the value is an `External`.
@@ -62,7 +166,13 @@ impl External {
 
     /// A finalizer callback, triggered before an external is removed.
     unsafe extern "system" fn finalize<T>(data: *mut c_void) {
-        Box::from_raw(data as *mut T);
+        drop(Box::from_raw(data as *mut T));
+    }
+
+    /// A finalizer callback of typed data, which is unregistered as well.
+    unsafe extern "system" fn finalize_typed<T>(data: *mut c_void) {
+        let typed = Box::from_raw(data as *mut Typed<T>);
+        typed.registry.0.lock().unwrap().remove(&(data as usize));
     }
 }
 
@@ -88,8 +198,32 @@ mod tests {
 
             test::run_with_context(|guard| {
                 let _ = value::External::new(guard, Box::new(Foo(10)));
+                let _ = value::External::typed(guard, Foo(10));
             });
         }
         assert!(unsafe { CALLED });
     }
+
+    #[test]
+    fn value() {
+        test::run_with_context(|guard| {
+            let mut external = value::External::typed(guard, String::from("foo"));
+            assert_eq!(external.value_ref::<String>(guard).map(|value| value.as_str()), Some("foo"));
+            assert!(external.value_ref::<i32>(guard).is_none());
+
+            external.value_mut::<String>(guard).unwrap().push_str("bar");
+            let value: value::Value = external.into();
+            let external = value.into_external().unwrap();
+            assert_eq!(external.value_ref::<String>(guard).unwrap(), "foobar");
+
+            let mut number = 5;
+            let borrowed = unsafe { value::External::from_ptr(guard, &mut number) };
+            assert!(borrowed.value_ref::<i32>(guard).is_none());
+            assert_eq!(unsafe { *borrowed.value_unchecked::<i32>() }, 5);
+
+            let untyped = value::External::new(guard, Box::new(7));
+            assert!(untyped.value_ref::<i32>(guard).is_none());
+            assert_eq!(unsafe { *untyped.value_unchecked::<i32>() }, 7);
+        });
+    }
 }
@@ -1,11 +1,17 @@
 //! A JavaScript function and associated types.
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use std::slice;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use libc::{c_void, c_ushort};
 use chakracore_sys::*;
 use context::{Context, ContextGuard};
 use error::*;
+use dry_run;
+use policy;
 use util::jstry;
-use super::{Value, Object};
+use super::{FromJs, ToJs, Value, Object};
 
 /// The information passed to `FunctionCallback` closures.
 #[derive(Clone, Debug)]
@@ -27,25 +33,210 @@ pub type CallbackResult = ::std::result::Result<Value, Value>;
 pub type FunctionCallback =
     Fn(&ContextGuard, CallbackInfo) -> CallbackResult + Send;
 
+/// Callback type for middleware wrapping host functions.
+///
+/// The middleware receives the name of the called function, and either
+/// returns a result itself (e.g to deny the call), or continues the chain.
+pub type Middleware =
+    Fn(&ContextGuard, &str, CallbackInfo, Next) -> CallbackResult + Send;
+
+/// The arguments of a typed host function.
+///
+/// This is implemented for tuples of `FromJs` types, where each element is
+/// converted from the argument at its position. Omitting a required argument
+/// is an error, while missing optional arguments (i.e trailing `Option`s) are
+/// converted from `undefined`. Additional arguments are ignored.
+pub trait FromArguments: Sized {
+    /// Converts the arguments of a call.
+    fn from_arguments(guard: &ContextGuard, arguments: &[Value]) -> Result<Self>;
+
+    /// Returns the number of required arguments.
+    fn arity() -> usize;
+}
+
+impl FromArguments for () {
+    fn from_arguments(_guard: &ContextGuard, _arguments: &[Value]) -> Result<Self> {
+        Ok(())
+    }
+
+    fn arity() -> usize {
+        0
+    }
+}
+
+macro_rules! from_arguments {
+    ($($name:ident : $index:expr),+) => {
+        impl<$($name: FromJs),+> FromArguments for ($($name,)+) {
+            fn from_arguments(guard: &ContextGuard, arguments: &[Value]) -> Result<Self> {
+                let arity = Self::arity();
+                if arguments.len() < arity {
+                    let plural = if arity == 1 { "" } else { "s" };
+                    return Err(format!("expected at least {} argument{}, found {}", arity, plural, arguments.len()).into());
+                }
+
+                let argument = |index: usize| arguments.get(index).cloned().unwrap_or_else(|| super::undefined(guard));
+                Ok(($($name::from_js(guard, &argument($index)).chain_err(|| format!("argument {}", $index + 1))?,)+))
+            }
+
+            fn arity() -> usize {
+                // Any argument after the last required one is optional
+                [$($name::is_optional()),+].iter().rposition(|optional| !optional).map_or(0, |index| index + 1)
+            }
+        }
+    };
+}
+
+from_arguments!(A: 0);
+from_arguments!(A: 0, B: 1);
+from_arguments!(A: 0, B: 1, C: 2);
+from_arguments!(A: 0, B: 1, C: 2, D: 3);
+from_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4);
+from_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+/// Converts the arguments of a typed call, or returns a `TypeError`.
+///
+/// This is used by typed host functions, and the code generated by
+/// `chakracore-derive`.
+#[doc(hidden)]
+pub fn convert_arguments<A: FromArguments>(guard: &ContextGuard, arguments: &[Value]) -> ::std::result::Result<A, Value> {
+    A::from_arguments(guard, arguments).map_err(|error| {
+        // Include the cause, e.g "argument 1: expected String, found Number"
+        let message = error.iter().map(|error| error.to_string()).collect::<Vec<_>>().join(": ");
+        super::Error::type_error(guard, &message).into()
+    })
+}
+
+/// Converts the result of a typed call, or returns the thrown value (for a
+/// `Throw` error), or an `Error`.
+#[doc(hidden)]
+pub fn convert_result<R: ToJs>(guard: &ContextGuard, result: Result<R>) -> CallbackResult {
+    result.map(|result| result.to_js(guard)).map_err(|error| {
+        let thrown = match *error.kind() {
+            ErrorKind::Throw(ref thrown) => thrown.take(guard),
+            _ => None,
+        };
+        thrown.unwrap_or_else(|| super::Error::new(guard, &error.to_string()).into())
+    })
+}
+
+/// The remainder of a middleware chain.
+pub struct Next<'a> {
+    function: &'a str,
+    middleware: &'a [Rc<Box<Middleware>>],
+    callback: &'a Fn(&ContextGuard, CallbackInfo) -> CallbackResult,
+}
+
+impl<'a> Next<'a> {
+    /// Calls the next middleware, or the host function itself.
+    pub fn call(self, guard: &ContextGuard, info: CallbackInfo) -> CallbackResult {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware(guard, self.function, info, Next { middleware: rest, ..self }),
+            None => (self.callback)(guard, info),
+        }
+    }
+}
+
+/// The data associated with a native function.
+struct FunctionData {
+    callback: Arc<Box<FunctionCallback>>,
+    /// The name of a host function (i.e a named native function).
+    name: Option<String>,
+    revoked: AtomicBool,
+}
+
+/// The data of all live native functions, keyed by their object address.
+///
+/// This is used for looking up a function's data from its handle, which the
+/// JSRT API does not provide.
+static FUNCTIONS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
 /// A JavaScript function object.
+#[repr(transparent)]
 pub struct Function(JsValueRef);
 
 impl Function {
     /// Creates an anonymous function
     pub fn new(_guard: &ContextGuard, callback: Box<FunctionCallback>) -> Self {
-        Self::create(callback, |context, reference| unsafe {
+        Self::create(Arc::new(callback), None, |context, reference| unsafe {
             JsCreateFunction(Some(Self::callback), context, reference)
         })
     }
 
     /// Creates a named function
     pub fn with_name(guard: &ContextGuard, name: &str, callback: Box<FunctionCallback>) -> Self {
-        Self::create(callback, |context, reference| unsafe {
+        Self::with_shared_callback(guard, name, Arc::new(callback))
+    }
+
+    /// Creates a named function with a typed signature.
+    ///
+    /// The arguments are converted using `FromArguments`, and the result
+    /// using `ToJs`. Too few arguments, or a failed conversion, throws a
+    /// `TypeError` (e.g "argument 2: expected String, found Number"), while
+    /// an error returned by the callback throws an `Error`. Multiple values
+    /// can be returned as a tuple, which is converted to an array (or as an
+    /// `ObjectBuilder`, for an object), so scripts can destructure the
+    /// result.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let divide = js::value::Function::typed(&guard, "divide", |_, (a, b): (i32, i32)| {
+    ///     if b == 0 { Err("division by zero".into()) } else { Ok((a / b, a % b)) }
+    /// });
+    ///
+    /// guard.global().set(&guard, &js::Property::new(&guard, "divide"), &divide);
+    /// let code = "var [quotient, rest] = divide(7, 2); quotient * 10 + rest";
+    /// let result = js::script::eval(&guard, code).unwrap();
+    /// assert_eq!(result.to_integer(&guard), 31);
+    /// assert!(js::script::eval(&guard, "divide(1, 0)").is_err());
+    /// ```
+    pub fn typed<A, R, F>(guard: &ContextGuard, name: &str, callback: F) -> Self
+            where A: FromArguments,
+                  R: ToJs,
+                  F: Fn(&ContextGuard, A) -> Result<R> + Send + 'static {
+        Self::with_name(guard, name, Box::new(move |guard, info| {
+            let arguments = convert_arguments(guard, &info.arguments)?;
+            convert_result(guard, callback(guard, arguments))
+        }))
+    }
+
+    /// Creates a named function, sharing a callback with other functions.
+    pub(crate) fn with_shared_callback(guard: &ContextGuard,
+                                       name: &str,
+                                       callback: Arc<Box<FunctionCallback>>) -> Self {
+        Self::create(callback, Some(name.to_string()), |context, reference| unsafe {
             let name = super::String::new(guard, name);
             JsCreateNamedFunction(name.as_raw(), Some(Self::callback), context, reference)
         })
     }
 
+    /// Returns the callback of a native function, unless it has been revoked.
+    pub(crate) fn shared_callback(&self) -> Option<Arc<Box<FunctionCallback>>> {
+        self.with_data(|data| {
+            if data.revoked.load(Ordering::SeqCst) { None } else { Some(data.callback.clone()) }
+        }).and_then(|callback| callback)
+    }
+
+    /// Revokes a native function's callback.
+    ///
+    /// Any subsequent calls to the function will throw a `TypeError`, instead
+    /// of invoking the callback. This should be used whenever the state that
+    /// a callback depends on is invalidated, while scripts may still hold a
+    /// reference to the function. The callback itself is kept until the
+    /// function is collected.
+    ///
+    /// Returns false if the function is not a native function.
+    pub fn revoke(&self, _guard: &ContextGuard) -> bool {
+        self.with_data(|data| data.revoked.store(true, Ordering::SeqCst)).is_some()
+    }
+
+    /// Returns whether a native function's callback has been revoked.
+    pub fn is_revoked(&self, _guard: &ContextGuard) -> bool {
+        self.with_data(|data| data.revoked.load(Ordering::SeqCst)).unwrap_or(false)
+    }
+
     /// Returns whether the object is an instance of this `Function` or not.
     pub fn instance_of(&self, _guard: &ContextGuard, object: &Object) -> bool {
         let mut result = false;
@@ -57,25 +248,30 @@ impl Function {
 
     /// Calls a function and returns the result. The context (i.e `this`) will
     /// be the global object associated with the `ContextGuard`.
+    ///
+    /// The guard may belong to any context of the function's runtime, but the
+    /// function's code runs with the globals of the context it was created in.
     pub fn call(&self, guard: &ContextGuard, arguments: &[&Value]) -> Result<Value> {
         self.call_with_this(guard, &guard.global().into(), arguments)
     }
 
-    /// Calls a function, with a context, and returns the result.
-    pub fn call_with_this(&self, _guard: &ContextGuard, this: &Value, arguments: &[&Value]) -> Result<Value> {
-        self.invoke(_guard, this, arguments, false)
+    /// Calls a function, with a context (i.e `this`), and returns the result.
+    pub fn call_with_this(&self, guard: &ContextGuard, this: &Value, arguments: &[&Value]) -> Result<Value> {
+        self.invoke(guard, this, arguments, false)
     }
 
-    /// Calls a function as a constructor and returns the result.
-    pub fn construct(&self, _guard: &ContextGuard, this: &Value, args: &[&Value]) -> Result<Value> {
-        self.invoke(_guard, this, args, true)
+    /// Calls a function as a constructor (i.e using `new`) and returns the
+    /// created object.
+    pub fn construct(&self, guard: &ContextGuard, arguments: &[&Value]) -> Result<Value> {
+        // The `this` argument is ignored for construct calls
+        self.invoke(guard, &super::undefined(guard), arguments, true)
     }
 
     is_same!(Function, "Returns true if the value is a `Function`.");
 
     /// Invokes a function and returns the result.
     fn invoke(&self,
-              _guard: &ContextGuard,
+              guard: &ContextGuard,
               this: &Value,
               arguments: &[&Value],
               constructor: bool)
@@ -91,32 +287,46 @@ impl Function {
             JsCallFunction
         };
 
-        unsafe {
+        // Calls from the host are counted like top-level evaluations
+        policy::counted(guard, || unsafe {
             let mut result = JsValueRef::new();
-            jstry(api(self.0,
-                      forward.as_mut_ptr(),
-                      forward.len() as c_ushort,
-                      &mut result))
-                .map(|_| Value::from_raw(result))
-        }
+            let code = api(self.0, forward.as_mut_ptr(), forward.len() as c_ushort, &mut result);
+            if code == JsErrorCode::WrongRuntime {
+                return Err("function belongs to a different runtime than the active context".into());
+            }
+            jstry(code).map(|_| Value::from_raw(result))
+        })
+    }
+
+    /// Executes a closure with a native function's data.
+    fn with_data<Ret, T: FnOnce(&FunctionData) -> Ret>(&self, callback: T) -> Option<Ret> {
+        let functions = FUNCTIONS.lock().unwrap();
+        functions.get(&(self.as_raw().0 as usize))
+            .map(|&data| callback(unsafe { &*(data as *const FunctionData) }))
     }
 
     /// Prevents boilerplate code in constructors.
-    fn create<T>(callback: Box<FunctionCallback>, initialize: T) -> Self
+    fn create<T>(callback: Arc<Box<FunctionCallback>>, name: Option<String>, initialize: T) -> Self
         where T: FnOnce(*mut c_void, &mut JsValueRef) -> JsErrorCode
     {
         // Because a boxed callback can be a fat pointer, it needs to be wrapped
-        // in an additional Box to ensure it fits in a single pointer.
-        let wrapper = Box::into_raw(Box::new(callback));
+        // in an additional structure to ensure it fits in a single pointer.
+        let data = Box::into_raw(Box::new(FunctionData {
+            callback: callback,
+            name: name,
+            revoked: AtomicBool::new(false),
+        }));
 
         unsafe {
             let mut reference = JsValueRef::new();
-            jsassert!(initialize(wrapper as *mut _, &mut reference));
+            jsassert!(initialize(data as *mut _, &mut reference));
             let function = Self::from_raw(reference);
+            FUNCTIONS.lock().unwrap().insert(reference.0 as usize, data as usize);
 
             // Ensure the heap objects are freed
-            function.set_collect_callback(Box::new(move |_| {
-                Box::from_raw(wrapper);
+            function.set_collect_callback(Box::new(move |value| {
+                FUNCTIONS.lock().unwrap().remove(&(value.as_raw().0 as usize));
+                Box::from_raw(data);
             }));
             function
         }
@@ -130,7 +340,7 @@ impl Function {
                                        data: *mut c_void)
                                        -> JsRef {
         // This memory is cleaned up during object collection
-        let callback = data as *mut Box<FunctionCallback>;
+        let data = &*(data as *const FunctionData);
 
         // There is always an active context in callbacks
         Context::exec_with_current(|guard| {
@@ -143,8 +353,26 @@ impl Function {
                 this: Value::from_raw(arguments[0]),
             };
 
-            // Call the user supplied callback
-            match (*callback)(&guard, info) {
+            if data.revoked.load(Ordering::SeqCst) {
+                let error = super::Error::type_error(guard, "Native function has been revoked");
+                jsassert!(JsSetException(error.as_raw()));
+                return error.as_raw();
+            }
+
+            // Call the user supplied callback (host functions are not called
+            // in dry-run mode)
+            let result = match data.name {
+                Some(ref name) => {
+                    let callback = |guard: &ContextGuard, info: CallbackInfo| {
+                        dry_run::intercept(guard, name, &info).unwrap_or_else(|| (data.callback)(guard, info))
+                    };
+                    let middleware = guard.middleware();
+                    Next { function: name, middleware: &middleware[..], callback: &callback }.call(guard, info)
+                },
+                None => (data.callback)(&guard, info),
+            };
+
+            match result {
                 Ok(value) => value.as_raw(),
                 Err(error) => {
                     jsassert!(JsSetException(error.as_raw()));
@@ -189,6 +417,19 @@ mod tests {
         });
     }
 
+    #[test]
+    fn call_with_this() {
+        test::run_with_context(|guard| {
+            let function = script::eval(guard, "(function(x) { return this.value * x; })")
+                .unwrap()
+                .into_function()
+                .unwrap();
+            let this = script::eval(guard, "({ value: 3 })").unwrap();
+            let result = function.call_with_this(guard, &this, &[&value::Number::new(guard, 4).into()]).unwrap();
+            assert_eq!(result.to_integer(guard), 12);
+        });
+    }
+
     #[test]
     fn exception() {
         test::run_with_context(|guard| {
@@ -207,6 +448,55 @@ mod tests {
         });
     }
 
+    #[test]
+    fn revoke() {
+        test::run_with_context(|guard| {
+            let function = value::Function::new(guard, Box::new(move |guard, _| {
+                Ok(value::Number::new(guard, 10).into())
+            }));
+            guard.global().set(guard, &Property::new(guard, "test"), &function);
+            assert_eq!(script::eval(guard, "test()").unwrap().to_integer(guard), 10);
+
+            assert!(!function.is_revoked(guard));
+            assert!(function.revoke(guard));
+            assert!(function.is_revoked(guard));
+
+            let result = script::eval(guard,
+                "try { test(); } catch (ex) { ex instanceof TypeError; }").unwrap();
+            assert!(result.to_bool(guard));
+
+            let script_function = script::eval(guard, "(function() {})")
+                .unwrap()
+                .into_function()
+                .unwrap();
+            assert!(!script_function.revoke(guard));
+        });
+    }
+
+    #[test]
+    fn construct() {
+        test::run_with_context(|guard| {
+            let map = script::eval(guard, "Map").unwrap().into_function().unwrap();
+            let entries = script::eval(guard, "[['foo', 1], ['bar', 2]]").unwrap();
+            let result = map.construct(guard, &[&entries])
+                .unwrap()
+                .into_object()
+                .unwrap();
+            assert_eq!(result.get(guard, &Property::new(guard, "size")).to_integer(guard), 2);
+            assert!(map.instance_of(guard, &result));
+
+            let class = script::eval(guard, "(class Foo { constructor(x) { this.x = x * 2; } })")
+                .unwrap()
+                .into_function()
+                .unwrap();
+            let result = class.construct(guard, &[&value::Number::new(guard, 4).into()])
+                .unwrap()
+                .into_object()
+                .unwrap();
+            assert_eq!(result.get(guard, &Property::new(guard, "x")).to_integer(guard), 8);
+        });
+    }
+
     #[test]
     fn instance_of() {
         test::run_with_context(|guard| {
@@ -226,4 +516,58 @@ mod tests {
             assert!(function.instance_of(guard, &result));
         });
     }
+
+    #[test]
+    fn typed() {
+        test::run_with_context(|guard| {
+            let split = value::Function::typed(guard, "split", |_, (text, count): (String, Option<i32>)| {
+                let count = count.unwrap_or(1) as usize;
+                Ok((text[..count].to_string(), text[count..].to_string()))
+            });
+            let point = value::Function::typed(guard, "point", |_, ()| {
+                Ok(value::ObjectBuilder::new().set("x", 1).set("y", 2).clone())
+            });
+            guard.global().set(guard, &Property::new(guard, "split"), &split);
+            guard.global().set(guard, &Property::new(guard, "point"), &point);
+
+            let result = script::eval(guard, "var [head, tail] = split('abc', 2); var { x, y } = point(); [head, tail, split('xy'), x + y]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), r#"["ab","c",["x","y"],3]"#);
+            assert_eq!(script::eval(guard, "try { split(5) } catch (e) { e instanceof TypeError }").unwrap().to_string(guard), "true");
+
+            let message = script::eval(guard, "try { split() } catch (e) { e instanceof TypeError && e.message }").unwrap();
+            assert_eq!(message.to_string(guard), "expected at least 1 argument, found 0");
+        });
+    }
+
+    #[test]
+    fn middleware() {
+        test::run_with_context(|guard| {
+            let calls = ::std::sync::Arc::new(::std::sync::Mutex::new(Vec::new()));
+            let log = calls.clone();
+            guard.context().add_middleware(Box::new(move |guard, function, info, next| {
+                log.lock().unwrap().push(function.to_string());
+                next.call(guard, info)
+            }));
+            guard.context().add_middleware(Box::new(|guard, function, info, next| {
+                if function == "denied" {
+                    return Err(value::Error::new(guard, "Permission denied").into());
+                }
+                next.call(guard, info)
+            }));
+
+            for name in &["allowed", "denied"] {
+                let function = value::Function::with_name(guard, name, Box::new(|guard, _| {
+                    Ok(value::Number::new(guard, 10).into())
+                }));
+                guard.global().set(guard, &Property::new(guard, name), &function);
+            }
+            let anonymous = value::Function::new(guard, Box::new(|guard, _| Ok(value::undefined(guard))));
+            guard.global().set(guard, &Property::new(guard, "anonymous"), &anonymous);
+
+            assert_eq!(script::eval(guard, "allowed()").unwrap().to_integer(guard), 10);
+            assert!(script::eval(guard, "denied()").is_err());
+            script::eval(guard, "anonymous()").unwrap();
+            assert_eq!(*calls.lock().unwrap(), ["allowed", "denied"]);
+        });
+    }
 }
@@ -0,0 +1,186 @@
+//! Indexed properties backed by native memory.
+//!
+//! An object's indexed properties (i.e `object[0]` to `object[length - 1]`)
+//! can be stored in a native buffer of numbers, instead of as ordinary
+//! properties. Scripts access the elements like those of a typed array, while
+//! the host reads and writes the buffer directly, which avoids converting
+//! large datasets element by element.
+use std::{mem, ptr, slice};
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use util::jstry;
+use super::Object;
+
+/// A numeric type that can be used as an element of indexed external data.
+pub trait Element: Copy + Send + 'static {
+    /// The typed array type with the same representation.
+    fn array_type() -> JsTypedArrayType;
+}
+
+macro_rules! element {
+    ($($typ:ty => $kind:ident),+) => {
+        $(
+            impl Element for $typ {
+                fn array_type() -> JsTypedArrayType {
+                    JsTypedArrayType::$kind
+                }
+            }
+        )+
+    };
+}
+
+element!(i8 => Int8, u8 => Uint8, i16 => Int16, u16 => Uint16, i32 => Int32, u32 => Uint32, f32 => Float32, f64 => Float64);
+
+impl Object {
+    /// Backs the object's indexed properties with a buffer of numbers.
+    ///
+    /// The object takes ownership of the buffer, which is released once the
+    /// object is collected. Setting the data of an object that already has
+    /// some replaces it (the previous buffer is retained until collection).
+    /// Objects with indexed properties of their own (e.g arrays) cannot have
+    /// external data.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let samples = js::value::Object::new(&guard);
+    /// samples.set_indexed_data(&guard, vec![0.5f64, 1.5, 2.5]).unwrap();
+    /// guard.global().set(&guard, &js::Property::new(&guard, "samples"), &samples);
+    ///
+    /// js::script::eval(&guard, "samples[1] *= 2").unwrap();
+    /// assert_eq!(samples.read_indexed::<f64>(&guard), Some(vec![0.5, 3.0, 2.5]));
+    /// ```
+    pub fn set_indexed_data<T: Element>(&self, guard: &ContextGuard, data: Vec<T>) -> Result<()> {
+        let mut data = data.into_boxed_slice();
+        let (address, length) = (data.as_mut_ptr() as usize, data.len());
+        unsafe { self.set_indexed_data_ptr(guard, data.as_mut_ptr(), length)? };
+        mem::forget(data);
+
+        self.on_collect(guard, Box::new(move || unsafe {
+            drop(Box::from_raw(slice::from_raw_parts_mut(address as *mut T, length)));
+        }));
+        Ok(())
+    }
+
+    /// Backs the object's indexed properties with a buffer owned by the host.
+    ///
+    /// This is unsafe because the object does not take ownership of the
+    /// buffer, which must outlive the object (or until its indexed data is
+    /// replaced), and must not be accessed while scripts may modify it.
+    pub unsafe fn set_indexed_data_ptr<T: Element>(&self,
+                                                   _guard: &ContextGuard,
+                                                   data: *mut T,
+                                                   length: usize) -> Result<()> {
+        if length > u32::max_value() as usize {
+            return Err("indexed data cannot exceed 2^32 - 1 elements".into());
+        }
+
+        jstry(JsSetIndexedPropertiesToExternalData(self.as_raw(), data as *mut _, T::array_type(), length as u32))
+    }
+
+    /// Returns whether the object's indexed properties are backed by external
+    /// data.
+    pub fn has_indexed_data(&self, _guard: &ContextGuard) -> bool {
+        let mut result = false;
+        jsassert!(unsafe { JsHasIndexedPropertiesExternalData(self.as_raw(), &mut result) });
+        result
+    }
+
+    /// Copies the object's indexed external data, if its elements are of type
+    /// `T`.
+    pub fn read_indexed<T: Element>(&self, guard: &ContextGuard) -> Option<Vec<T>> {
+        self.indexed_data_raw::<T>(guard).map(|(data, length)| unsafe { slice::from_raw_parts(data, length).to_vec() })
+    }
+
+    /// Copies elements into the object's indexed external data, starting at
+    /// an offset.
+    ///
+    /// An error is returned if the object has no external data of type `T`,
+    /// or if the elements do not fit within it.
+    pub fn write_indexed<T: Element>(&self, guard: &ContextGuard, offset: usize, elements: &[T]) -> Result<()> {
+        let (data, length) = self.indexed_data_raw::<T>(guard)
+            .ok_or_else(|| Error::from("object has no indexed data of this element type"))?;
+        if offset.checked_add(elements.len()).map_or(true, |end| end > length) {
+            return Err("elements exceed the object's indexed data".into());
+        }
+
+        unsafe { ptr::copy_nonoverlapping(elements.as_ptr(), data.add(offset), elements.len()) };
+        Ok(())
+    }
+
+    /// Borrows the object's indexed external data, if its elements are of
+    /// type `T`.
+    ///
+    /// This is unsafe because scripts (and other handles to the object) may
+    /// modify the elements, or replace the data, while the slice is borrowed.
+    /// Prefer `read_indexed` unless copying is too expensive.
+    pub unsafe fn indexed_data<T: Element>(&self, guard: &ContextGuard) -> Option<&[T]> {
+        self.indexed_data_raw::<T>(guard).map(|(data, length)| slice::from_raw_parts(data, length))
+    }
+
+    /// Mutably borrows the object's indexed external data, if its elements are
+    /// of type `T`.
+    ///
+    /// This is unsafe because scripts (and other handles to the object) must
+    /// not access the elements while the slice is borrowed. Prefer
+    /// `write_indexed` unless copying is too expensive.
+    pub unsafe fn indexed_data_mut<T: Element>(&mut self, guard: &ContextGuard) -> Option<&mut [T]> {
+        self.indexed_data_raw::<T>(guard).map(|(data, length)| slice::from_raw_parts_mut(data, length))
+    }
+
+    /// Returns the pointer and length of the object's indexed external data.
+    fn indexed_data_raw<T: Element>(&self, guard: &ContextGuard) -> Option<(*mut T, usize)> {
+        if !self.has_indexed_data(guard) {
+            return None;
+        }
+
+        let mut data = ptr::null_mut();
+        let mut kind = JsTypedArrayType::Int8;
+        let mut length = 0;
+        jsassert!(unsafe { JsGetIndexedPropertiesExternalData(self.as_raw(), &mut data, &mut kind, &mut length) });
+
+        if kind != T::array_type() {
+            None
+        } else if data.is_null() {
+            // An empty buffer may not have an address
+            Some((ptr::NonNull::dangling().as_ptr(), 0))
+        } else {
+            Some((data as *mut T, length as usize))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value, Property};
+
+    #[test]
+    fn indexed_data() {
+        test::run_with_context(|guard| {
+            let object = value::Object::new(guard);
+            assert!(!object.has_indexed_data(guard) && object.read_indexed::<u8>(guard).is_none());
+
+            object.set_indexed_data(guard, vec![1u8, 2, 255]).unwrap();
+            guard.global().set(guard, &Property::new(guard, "bytes"), &object);
+            assert!(object.has_indexed_data(guard));
+            assert!(object.read_indexed::<i8>(guard).is_none());
+
+            object.write_indexed(guard, 0, &[10u8]).unwrap();
+            assert!(object.write_indexed(guard, 2, &[1u8, 2]).is_err());
+            assert!(object.write_indexed(guard, 0, &[1i8]).is_err());
+            let result = script::eval(guard, "bytes[2] += 1; [bytes[0], bytes[1], bytes[2], bytes[3]]").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), "[10,2,0,null]");
+            assert_eq!(object.read_indexed::<u8>(guard), Some(vec![10, 2, 0]));
+
+            let mut object = object;
+            unsafe { object.indexed_data_mut::<u8>(guard).unwrap()[1] = 20 };
+            assert_eq!(unsafe { object.indexed_data::<u8>(guard) }, Some(&[10, 20, 0][..]));
+
+            let array = value::Array::new(guard, 1);
+            assert!(array.set_indexed_data(guard, vec![1i32]).is_err());
+        });
+    }
+}
@@ -0,0 +1,818 @@
+//! Rendering of values like Node's `util.inspect`, and for logs with
+//! sensitive data masked.
+use chakracore_sys::JsValueType;
+use context::ContextGuard;
+use util;
+use Property;
+use super::{Function, Object, Value};
+
+/// The number of innermost levels of nested objects which may be combined
+/// onto a single line (like Node's default `compact` option).
+const COMPACT: usize = 3;
+
+/// The space taken by the separator in between array elements (`, `).
+const SEPARATOR: usize = 2;
+
+/// Options of `Value::inspect`, mirroring those of Node's `util.inspect`.
+#[derive(Clone, Debug)]
+pub struct InspectOptions {
+    depth: Option<usize>,
+    max_array_length: Option<usize>,
+    colors: bool,
+    break_length: usize,
+}
+
+impl InspectOptions {
+    /// Creates the default options (the same as Node's).
+    pub fn new() -> Self {
+        InspectOptions {
+            depth: Some(2),
+            max_array_length: Some(100),
+            colors: false,
+            break_length: 80,
+        }
+    }
+
+    /// Sets how many times nested objects are recursed into (defaults to 2),
+    /// or `None` to recurse without limit.
+    pub fn depth(mut self, depth: Option<usize>) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the maximum number of array, map and set elements which are
+    /// rendered (defaults to 100), or `None` to render all of them.
+    pub fn max_array_length(mut self, length: Option<usize>) -> Self {
+        self.max_array_length = length;
+        self
+    }
+
+    /// Sets whether the output is styled using ANSI colors (defaults to
+    /// false).
+    pub fn colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Sets the length at which an object's entries are split onto multiple
+    /// lines (defaults to 80).
+    pub fn break_length(mut self, length: usize) -> Self {
+        self.break_length = length;
+        self
+    }
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        InspectOptions::new()
+    }
+}
+
+/// A policy deciding which properties are masked by `redacted_inspect`.
+///
+/// Keys are matched case-insensitively, and a key is redacted if it contains
+/// any of the policy's patterns (e.g `apiToken` matches `token`). The default
+/// policy redacts keys containing `password`, `token` or `secret`.
+#[derive(Clone, Debug)]
+pub struct RedactionPolicy {
+    patterns: Vec<String>,
+    mask: String,
+    max_depth: usize,
+}
+
+impl RedactionPolicy {
+    /// Creates a policy without any patterns.
+    pub fn new() -> Self {
+        RedactionPolicy {
+            patterns: Vec::new(),
+            mask: "[REDACTED]".to_string(),
+            max_depth: 8,
+        }
+    }
+
+    /// Adds a pattern of keys to redact.
+    pub fn key(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.to_lowercase());
+        self
+    }
+
+    /// Sets the text replacing redacted values (defaults to `[REDACTED]`).
+    pub fn mask(mut self, mask: &str) -> Self {
+        self.mask = mask.to_string();
+        self
+    }
+
+    /// Sets how deep nested objects are rendered (defaults to 8).
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Returns whether a key should be redacted.
+    pub fn is_redacted(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns.iter().any(|pattern| key.contains(pattern.as_str()))
+    }
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        RedactionPolicy::new().key("password").key("token").key("secret")
+    }
+}
+
+impl Value {
+    /// Renders the value like Node's `util.inspect`.
+    ///
+    /// Strings are quoted, and objects are rendered with their own enumerable
+    /// properties, e.g `{ a: 1, b: [ 'c' ] }`. Entries are split onto
+    /// multiple lines once they exceed the options' break length, and long
+    /// arrays are grouped into columns. Property getters are not invoked.
+    pub fn inspect(&self, guard: &ContextGuard, options: &InspectOptions) -> String {
+        Inspector::new(guard, options).format(self, 0)
+    }
+
+    /// Renders the value for logging, masking properties matching a policy.
+    ///
+    /// The output resembles an object literal, e.g `{ user: "foo", password:
+    /// [REDACTED] }`. Circular references are rendered as `[Circular]`, and
+    /// objects nested beyond the policy's maximum depth as `[Object]` (or
+    /// `[Array]`). Property getters are invoked during rendering, and the ones
+    /// which throw are rendered as `[Getter threw]`.
+    pub fn redacted_inspect(&self, guard: &ContextGuard, policy: &RedactionPolicy) -> String {
+        let mut output = String::new();
+        inspect(guard, policy, self, &mut Vec::new(), &mut output);
+        output
+    }
+}
+
+/// Renders a value, with `parents` being the objects currently rendered.
+///
+/// Only own enumerable properties (i.e `Object.keys`) are rendered.
+fn inspect(guard: &ContextGuard,
+           policy: &RedactionPolicy,
+           value: &Value,
+           parents: &mut Vec<Value>,
+           output: &mut String) {
+    if value.is_string() {
+        output.push_str(&guard.json_stringify(value).unwrap_or_else(|_| value.to_string(guard)));
+    } else if value.is_symbol() {
+        let string = util::jsfunc(guard, "String").expect("retrieving String function");
+        output.push_str(&string.call(guard, &[value]).map(|name| name.to_string(guard))
+            .unwrap_or_else(|_| "Symbol()".to_string()));
+    } else if value.is_function() {
+        let name = value.clone().into_object()
+            .map(|function| function_name(guard, &function))
+            .unwrap_or_default();
+        if name.is_empty() {
+            output.push_str("[Function]");
+        } else {
+            output.push_str(&format!("[Function: {}]", name));
+        }
+    } else if let Some(object) = value.clone().into_object() {
+        let is_array = value.is_array();
+        if parents.iter().any(|parent| parent.strict_equals(guard, value)) {
+            output.push_str("[Circular]");
+            return;
+        }
+
+        if parents.len() >= policy.max_depth {
+            output.push_str(if is_array { "[Array]" } else { "[Object]" });
+            return;
+        }
+
+        parents.push(value.clone());
+        let entries = util::jsfunc(guard, "Object.keys")
+            .and_then(|keys| keys.call(guard, &[value]).ok())
+            .and_then(|keys| keys.into_array())
+            .map_or_else(Vec::new, |keys| keys.iter(guard).map(|key| key.to_string(guard)).collect());
+
+        output.push_str(if is_array { "[" } else { "{" });
+        for (index, key) in entries.iter().enumerate() {
+            output.push_str(if index == 0 { " " } else { ", " });
+            if !is_array {
+                output.push_str(key);
+                output.push_str(": ");
+            }
+
+            if policy.is_redacted(key) {
+                output.push_str(&policy.mask);
+            } else {
+                match object.try_get(guard, &Property::new(guard, key)) {
+                    Ok(property) => inspect(guard, policy, &property, parents, output),
+                    Err(_) => output.push_str("[Getter threw]"),
+                }
+            }
+        }
+        output.push_str(if entries.is_empty() { "" } else { " " });
+        output.push_str(if is_array { "]" } else { "}" });
+        parents.pop();
+    } else {
+        output.push_str(&string(guard, value));
+    }
+}
+
+/// The styles of rendered values, colored like Node's defaults.
+#[derive(Copy, Clone)]
+enum Style {
+    Special,
+    Number,
+    Boolean,
+    Undefined,
+    Null,
+    String,
+    Symbol,
+    Date,
+    RegExp,
+}
+
+impl Style {
+    /// Returns the ANSI codes enabling and disabling the style.
+    fn codes(self) -> (u8, u8) {
+        match self {
+            Style::Special => (36, 39),
+            Style::Number | Style::Boolean => (33, 39),
+            Style::Undefined => (90, 39),
+            Style::Null => (1, 22),
+            Style::String | Style::Symbol => (32, 39),
+            Style::Date => (35, 39),
+            Style::RegExp => (31, 39),
+        }
+    }
+}
+
+/// The kinds of objects, differing in how their entries are rendered.
+enum Kind {
+    /// An array (or typed array) of a length.
+    Array(usize),
+    Map(super::Map),
+    Set(super::Set),
+    Object,
+}
+
+/// The rendered entries of an object.
+struct Entries {
+    output: Vec<String>,
+    /// Whether the entries are array elements, which may be grouped.
+    array: bool,
+    /// Whether all elements are numbers, which are aligned to the right.
+    numeric: bool,
+    /// Whether the last entry notes the number of elements left out.
+    truncated: bool,
+}
+
+/// The state of an inspection, following Node's implementation.
+struct Inspector<'a> {
+    guard: &'a ContextGuard<'a>,
+    options: &'a InspectOptions,
+    keys: Option<Function>,
+    /// The objects currently rendered.
+    seen: Vec<Value>,
+    /// The objects referenced circularly, numbered by their position.
+    circular: Vec<Value>,
+    indentation: usize,
+    /// The depth of the object rendered most recently.
+    current_depth: usize,
+}
+
+impl<'a> Inspector<'a> {
+    fn new(guard: &'a ContextGuard<'a>, options: &'a InspectOptions) -> Self {
+        Inspector {
+            guard: guard,
+            options: options,
+            keys: util::jsfunc(guard, "Object.keys"),
+            seen: Vec::new(),
+            circular: Vec::new(),
+            indentation: 0,
+            current_depth: 0,
+        }
+    }
+
+    /// Renders a value, nested at a depth.
+    fn format(&mut self, value: &Value, depth: usize) -> String {
+        let guard = self.guard;
+        match value.get_type() {
+            JsValueType::Undefined => self.stylize("undefined", Style::Undefined),
+            JsValueType::Null => self.stylize("null", Style::Null),
+            JsValueType::Boolean => self.stylize(&value.to_string(guard), Style::Boolean),
+            JsValueType::Number => {
+                let number = value.to_double(guard);
+                let negative_zero = number == 0.0 && number.is_sign_negative();
+                self.stylize(&if negative_zero { "-0".to_string() } else { value.to_string(guard) }, Style::Number)
+            },
+            JsValueType::String => self.stylize(&quote(&value.to_string(guard)), Style::String),
+            JsValueType::Symbol => self.stylize(&symbol_description(guard, value), Style::Symbol),
+            _ => match value.clone().into_object() {
+                Some(object) => self.format_object(value, &object, depth),
+                None => string(guard, value),
+            },
+        }
+    }
+
+    fn format_object(&mut self, value: &Value, object: &Object, depth: usize) -> String {
+        let guard = self.guard;
+        if self.seen.iter().any(|seen| seen.strict_equals(guard, value)) {
+            let index = match self.circular.iter().position(|circular| circular.strict_equals(guard, value)) {
+                Some(index) => index,
+                None => {
+                    self.circular.push(value.clone());
+                    self.circular.len() - 1
+                },
+            };
+            return self.stylize(&format!("[Circular *{}]", index + 1), Style::Special);
+        }
+
+        let constructor = constructor_name(guard, object);
+        let prefix = |fallback: &str, size: &str| match constructor {
+            Some(ref name) => format!("{}{} ", name, size),
+            None => format!("[{}{}: null prototype] ", fallback, size),
+        };
+
+        let mut keys = self.keys.as_ref()
+            .and_then(|keys| keys.call(guard, &[value]).ok())
+            .and_then(|keys| keys.into_array())
+            .map_or_else(Vec::new, |keys| keys.iter(guard).map(|key| key.to_string(guard)).collect());
+        let mut base = String::new();
+        let mut fallback = "Object";
+
+        let (kind, open, close) = if value.is_array() || value.get_type() == JsValueType::TypedArray {
+            let length = object.try_get(guard, &Property::new(guard, "length"))
+                .and_then(|length| length.try_to_double(guard))
+                .map_or(0, |length| if length > 0.0 { length as usize } else { 0 });
+            let prefix = match constructor {
+                Some(ref name) if name == "Array" => String::new(),
+                _ => prefix("Array", &format!("({})", length)),
+            };
+
+            keys.retain(|key| !is_index(key));
+            if length == 0 && keys.is_empty() {
+                return format!("{}[]", prefix);
+            }
+            fallback = "Array";
+            (Kind::Array(length), format!("{}[", prefix), "]")
+        } else if let Some(map) = super::Map::from_value(guard, value.clone()) {
+            let prefix = prefix("Map", &format!("({})", map.size(guard).unwrap_or(0)));
+            if map.size(guard).unwrap_or(0) == 0 && keys.is_empty() {
+                return format!("{}{{}}", prefix);
+            }
+            (Kind::Map(map), format!("{}{{", prefix), "}")
+        } else if let Some(set) = super::Set::from_value(guard, value.clone()) {
+            let prefix = prefix("Set", &format!("({})", set.size(guard).unwrap_or(0)));
+            if set.size(guard).unwrap_or(0) == 0 && keys.is_empty() {
+                return format!("{}{{}}", prefix);
+            }
+            (Kind::Set(set), format!("{}{{", prefix), "}")
+        } else {
+            let date = super::Date::from_value(guard, value.clone());
+            if value.is_function() {
+                base = self.stylize(&function_base(guard, object, &constructor), Style::Special);
+            } else if let Some(regexp) = super::RegExp::from_value(guard, value.clone()) {
+                base = self.stylize(&format!("/{}/{}", regexp.source(guard), regexp.flags(guard)), Style::RegExp);
+                if self.options.depth.map_or(false, |max| depth > max) {
+                    return base;
+                }
+            } else if let Some(date) = date {
+                base = self.stylize(&date.to_iso_string(guard).unwrap_or_else(|| "Invalid Date".to_string()), Style::Date);
+            } else if value.get_type() == JsValueType::Error {
+                let stack = object.try_get(guard, &Property::new(guard, "stack")).ok();
+                base = match stack {
+                    Some(ref stack) if stack.is_string() => guard.format_stack(&stack.to_string(guard)),
+                    _ => format!("[{}]", string(guard, value)),
+                };
+
+                // The stack's lines are indented as well
+                if self.indentation > 0 {
+                    base = base.replace('\n', &format!("\n{}", " ".repeat(self.indentation)));
+                }
+            }
+
+            let open = match constructor {
+                Some(ref name) if !base.is_empty() || name == "Object" => "{".to_string(),
+                _ => format!("{}{{", prefix("Object", "")),
+            };
+            if keys.is_empty() {
+                return if base.is_empty() { format!("{}}}", open) } else { base };
+            }
+            (Kind::Object, open, "}")
+        };
+
+        if self.options.depth.map_or(false, |max| depth > max) {
+            let name = match constructor {
+                Some(name) => format!("[{}]", name),
+                None => format!("[{}: null prototype]", fallback),
+            };
+            return self.stylize(&name, Style::Special);
+        }
+
+        self.seen.push(value.clone());
+        self.current_depth = depth;
+        let mut entries = self.format_entries(object, kind, depth);
+        for key in &keys {
+            let property = self.format_property(object, key, depth);
+            entries.output.push(property);
+        }
+        self.seen.pop();
+
+        if let Some(index) = self.circular.iter().position(|circular| circular.strict_equals(guard, value)) {
+            let reference = self.stylize(&format!("<ref *{}>", index + 1), Style::Special);
+            base = if base.is_empty() { reference } else { format!("{} {}", reference, base) };
+        }
+        self.reduce(entries, &base, &open, close, depth)
+    }
+
+    /// Renders the elements of an array, or the entries of a map or set.
+    fn format_entries(&mut self, object: &Object, kind: Kind, depth: usize) -> Entries {
+        let guard = self.guard;
+        let limit = self.options.max_array_length.unwrap_or(usize::max_value());
+        let mut entries = Entries { output: Vec::new(), array: false, numeric: true, truncated: false };
+
+        self.indentation += 2;
+        let remaining = match kind {
+            Kind::Array(length) => {
+                entries.array = true;
+                let mut index = 0;
+                while index < length && entries.output.len() < limit {
+                    if object.has_index(guard, index as u32) {
+                        let element = object.get_index(guard, index as u32);
+                        entries.numeric &= element.is_number();
+                        entries.output.push(self.format(&element, depth + 1));
+                        index += 1;
+                    } else {
+                        let start = index;
+                        while index < length && !object.has_index(guard, index as u32) {
+                            index += 1;
+                        }
+                        let holes = format!("<{} empty item{}>", index - start, plural(index - start));
+                        entries.numeric = false;
+                        entries.output.push(self.stylize(&holes, Style::Undefined));
+                    }
+                }
+                length - index
+            },
+            Kind::Map(map) => {
+                let elements = map.iter(guard).map(|elements| elements.collect()).unwrap_or_else(|_| Vec::new());
+                for &(ref key, ref value) in elements.iter().take(limit) {
+                    let entry = format!("{} => {}", self.format(key, depth + 1), self.format(value, depth + 1));
+                    entries.output.push(entry);
+                }
+                elements.len().saturating_sub(limit)
+            },
+            Kind::Set(set) => {
+                let elements = set.iter(guard).map(|elements| elements.collect()).unwrap_or_else(|_| Vec::new());
+                for element in elements.iter().take(limit) {
+                    let entry = self.format(element, depth + 1);
+                    entries.output.push(entry);
+                }
+                elements.len().saturating_sub(limit)
+            },
+            Kind::Object => 0,
+        };
+        self.indentation -= 2;
+
+        if remaining > 0 {
+            entries.output.push(format!("... {} more item{}", remaining, plural(remaining)));
+            entries.truncated = true;
+        }
+        entries
+    }
+
+    /// Renders an own property of an object.
+    fn format_property(&mut self, object: &Object, key: &str, depth: usize) -> String {
+        let guard = self.guard;
+        let property = Property::new(guard, key);
+        let descriptor = object.try_get_own_property_descriptor(guard, &property);
+
+        // The value is read from the descriptor, so getters are not invoked
+        self.indentation += 2;
+        let value = match descriptor {
+            Ok(Some(descriptor)) => match (descriptor.get_getter().is_some(), descriptor.get_setter().is_some()) {
+                (true, true) => self.stylize("[Getter/Setter]", Style::Special),
+                (true, false) => self.stylize("[Getter]", Style::Special),
+                (false, true) => self.stylize("[Setter]", Style::Special),
+                _ => {
+                    let value = descriptor.get_value().cloned().unwrap_or_else(|| super::undefined(guard));
+                    self.format(&value, depth + 1)
+                },
+            },
+            Ok(None) => self.format(&super::undefined(guard), depth + 1),
+            Err(_) => self.stylize("[Getter threw]", Style::Special),
+        };
+        self.indentation -= 2;
+
+        let name = if is_identifier(key) { key.to_string() } else { self.stylize(&quote(key), Style::String) };
+        format!("{}: {}", name, value)
+    }
+
+    /// Combines an object's entries onto a single line, if they fit, or
+    /// otherwise onto one line each.
+    fn reduce(&self, mut entries: Entries, base: &str, open: &str, close: &str, depth: usize) -> String {
+        let count = entries.output.len();
+        if entries.array && count > 6 {
+            entries.output = self.group(&entries);
+        }
+
+        if self.current_depth - depth < COMPACT && count == entries.output.len() {
+            // This mirrors Node's (somewhat arbitrary) estimate of the length
+            let start = entries.output.len() + self.indentation + width(open) + width(base) + 10;
+            if self.is_below_break_length(&entries.output, start, base) {
+                let joined = entries.output.join(", ");
+                if !joined.contains('\n') {
+                    return format!("{}{} {} {}", spaced(base), open, joined, close);
+                }
+            }
+        }
+
+        let indentation = format!("\n{}", " ".repeat(self.indentation));
+        let separator = format!(",{}  ", indentation);
+        format!("{}{}{}  {}{}{}", spaced(base), open, indentation, entries.output.join(&separator), indentation, close)
+    }
+
+    fn is_below_break_length(&self, output: &[String], start: usize, base: &str) -> bool {
+        let mut length = output.len() + start;
+        if length + output.len() > self.options.break_length {
+            return false;
+        }
+
+        for entry in output {
+            length += width(entry);
+            if length > self.options.break_length {
+                return false;
+            }
+        }
+        !base.contains('\n')
+    }
+
+    /// Groups array elements into columns, unless their lengths differ too
+    /// much.
+    fn group(&self, entries: &Entries) -> Vec<String> {
+        let output = &entries.output;
+        let length = if entries.truncated { output.len() - 1 } else { output.len() };
+        let widths = output[..length].iter().map(|entry| width(entry)).collect::<Vec<_>>();
+        let total = widths.iter().map(|width| width + SEPARATOR).sum::<usize>();
+        let max_width = widths.iter().cloned().max().unwrap_or(0);
+        let actual_max = max_width + SEPARATOR;
+
+        // At least three elements must fit next to each other
+        if actual_max * 3 + self.indentation >= self.options.break_length ||
+           (total as f64 / actual_max as f64 <= 5.0 && max_width > 6) {
+            return output.clone();
+        }
+
+        // Node expects a character to be 2.5 times as high as wide, and aims
+        // for a square, biased towards more columns for short elements
+        let average_bias = (actual_max as f64 - total as f64 / output.len() as f64).sqrt();
+        let biased_max = (actual_max as f64 - 3.0 - average_bias).max(1.0);
+        let columns = ((2.5 * biased_max * length as f64).sqrt() / biased_max).round() as usize;
+        let columns = columns
+            .min((self.options.break_length - self.indentation) / actual_max)
+            .min(COMPACT * 4)
+            .min(15);
+        if columns <= 1 {
+            return output.clone();
+        }
+
+        let column_widths = (0..columns)
+            .map(|column| widths.iter().skip(column).step_by(columns).cloned().max().unwrap_or(0) + SEPARATOR)
+            .collect::<Vec<_>>();
+
+        let mut grouped = Vec::new();
+        for start in (0..length).step_by(columns) {
+            let end = (start + columns).min(length);
+            let mut line = String::new();
+            for index in start..end - 1 {
+                let cell = format!("{}, ", output[index]);
+                line.push_str(&pad(&cell, widths[index] + SEPARATOR, column_widths[index - start], entries.numeric));
+            }
+
+            let last = end - 1;
+            if entries.numeric {
+                line.push_str(&pad(&output[last], widths[last], column_widths[last - start] - SEPARATOR, true));
+            } else {
+                line.push_str(&output[last]);
+            }
+            grouped.push(line);
+        }
+
+        if entries.truncated {
+            grouped.push(output[length].clone());
+        }
+        grouped
+    }
+
+    fn stylize(&self, text: &str, style: Style) -> String {
+        if self.options.colors {
+            let (enable, disable) = style.codes();
+            format!("\x1b[{}m{}\x1b[{}m", enable, text, disable)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Returns the name of an object's constructor, found along its prototype
+/// chain, or `None` if it has none (e.g a null prototype).
+fn constructor_name(guard: &ContextGuard, object: &Object) -> Option<String> {
+    let key = Property::new(guard, "constructor");
+    let mut current = object.clone();
+    loop {
+        let constructor = current.try_get_own_property_descriptor(guard, &key)
+            .ok()
+            .and_then(|descriptor| descriptor)
+            .and_then(|descriptor| descriptor.get_value().cloned())
+            .and_then(|value| value.into_function());
+        if let Some(constructor) = constructor {
+            let name = function_name(guard, &constructor);
+            if !name.is_empty() {
+                return Some(name);
+            }
+        }
+        current = current.get_prototype(guard).into_object()?;
+    }
+}
+
+/// Returns the base of a function's representation, e.g `[Function: foo]`.
+fn function_base(guard: &ContextGuard, function: &Object, constructor: &Option<String>) -> String {
+    let name = function_name(guard, function);
+    let source = util::jsfunc(guard, "Function.prototype.toString")
+        .and_then(|to_string| to_string.call_with_this(guard, function, &[]).ok())
+        .map(|source| source.to_string(guard))
+        .unwrap_or_default();
+
+    if source.starts_with("class") {
+        let mut base = format!("class {}", if name.is_empty() { "(anonymous)" } else { &name });
+        let parent = function.get_prototype(guard).into_object()
+            .map(|parent| function_name(guard, &parent))
+            .unwrap_or_default();
+        if !parent.is_empty() {
+            base.push_str(&format!(" extends {}", parent));
+        }
+        return format!("[{}]", base);
+    }
+
+    let kind = match *constructor {
+        Some(ref name) if name.ends_with("Function") => name.as_str(),
+        _ => "Function",
+    };
+    if name.is_empty() { format!("[{} (anonymous)]", kind) } else { format!("[{}: {}]", kind, name) }
+}
+
+/// Returns a function's name, or an empty string if it cannot be read (e.g a
+/// throwing static getter).
+fn function_name(guard: &ContextGuard, function: &Object) -> String {
+    function.try_get(guard, &Property::new(guard, "name"))
+        .and_then(|name| name.try_to_string(guard))
+        .unwrap_or_default()
+}
+
+/// Converts a value to a string, or a placeholder if its conversion throws.
+fn string(guard: &ContextGuard, value: &Value) -> String {
+    value.try_to_string(guard).unwrap_or_else(|_| "[object]".to_string())
+}
+
+/// Returns the description of a symbol, e.g `Symbol(foo)`.
+fn symbol_description(guard: &ContextGuard, symbol: &Value) -> String {
+    util::jsfunc(guard, "String")
+        .and_then(|string| string.call(guard, &[symbol]).ok())
+        .map_or_else(|| "Symbol()".to_string(), |description| description.to_string(guard))
+}
+
+/// Quotes a string like Node, preferring single quotes, and escaping control
+/// characters.
+fn quote(string: &str) -> String {
+    let quote = if !string.contains('\'') {
+        '\''
+    } else if !string.contains('"') {
+        '"'
+    } else if !string.contains('`') && !string.contains("${") {
+        '`'
+    } else {
+        '\''
+    };
+
+    let mut output = String::with_capacity(string.len() + 2);
+    output.push(quote);
+    for character in string.chars() {
+        match character {
+            '\u{8}' => output.push_str("\\b"),
+            '\t' => output.push_str("\\t"),
+            '\n' => output.push_str("\\n"),
+            '\u{c}' => output.push_str("\\f"),
+            '\r' => output.push_str("\\r"),
+            '\\' => output.push_str("\\\\"),
+            '\'' if quote == '\'' => output.push_str("\\'"),
+            character if character < ' ' || (character >= '\u{7f}' && character <= '\u{9f}') => {
+                output.push_str(&format!("\\x{:02X}", character as u32));
+            },
+            character => output.push(character),
+        }
+    }
+    output.push(quote);
+    output
+}
+
+/// Returns whether a key can be written without quotes.
+fn is_identifier(key: &str) -> bool {
+    let mut characters = key.chars();
+    characters.next().map_or(false, |first| first == '_' || first.is_ascii_alphabetic()) &&
+        characters.all(|character| character == '_' || character.is_ascii_alphanumeric())
+}
+
+/// Returns whether a key is an array index.
+fn is_index(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|character| character.is_ascii_digit()) &&
+        (key == "0" || !key.starts_with('0'))
+}
+
+/// Returns the displayed width of a text, excluding ANSI escape codes.
+fn width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escaped = false;
+    for character in text.chars() {
+        match character {
+            '\x1b' => escaped = true,
+            'm' if escaped => escaped = false,
+            _ if escaped => (),
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// Pads a text of a width with spaces, at its start or its end.
+fn pad(text: &str, width: usize, target: usize, start: bool) -> String {
+    let padding = " ".repeat(target.saturating_sub(width));
+    if start { format!("{}{}", padding, text) } else { format!("{}{}", text, padding) }
+}
+
+/// Returns the base of an object's representation, followed by a space.
+fn spaced(base: &str) -> String {
+    if base.is_empty() { String::new() } else { format!("{} ", base) }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+    use super::{InspectOptions, RedactionPolicy};
+
+    #[test]
+    fn inspect() {
+        test::run_with_context(|guard| {
+            let inspect = |code: &str| script::eval(guard, code).unwrap().inspect(guard, &InspectOptions::default());
+
+            assert_eq!(inspect("({ a: 1, 'b-c': 'd', e: [1, 2, [3, [4]]], f: function f() {}, g: null, h: undefined })"),
+                       "{\n  a: 1,\n  'b-c': 'd',\n  e: [ 1, 2, [ 3, [Array] ] ],\n  f: [Function: f],\n  g: null,\n  \
+                        h: undefined\n}");
+            assert_eq!(inspect("[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]"), "[\n  0, 1, 2, 3, 4,\n  5, 6, 7, 8, 9\n]");
+            assert_eq!(inspect("var o = { s: \"it's\", m: new Map([['a', 1]]), t: new Set([1]) }; o.self = o; o"),
+                       "<ref *1> {\n  s: \"it's\",\n  m: Map(1) { 'a' => 1 },\n  t: Set(1) { 1 },\n  self: [Circular *1]\n}");
+            assert_eq!(inspect("
+                class Point { constructor() { this.x = 1; } }
+                [new Point(), Object.create(null), { get a() { return 1; } }, [1, , 3], -0, new Date(0), Symbol('s')]"),
+                       "[\n  Point { x: 1 },\n  [Object: null prototype] {},\n  { a: [Getter] },\n  [ 1, <1 empty item>, 3 ],\n  \
+                        -0,\n  1970-01-01T00:00:00.000Z,\n  Symbol(s)\n]");
+            assert_eq!(inspect("[class A extends Object {}, function () {}, 'a\\nb', /a+/gi]"),
+                       "[ [class A extends Object], [Function (anonymous)], 'a\\nb', /a+/gi ]");
+            assert_eq!(inspect("[class { static get name() { throw 1; } }]"), "[ [class (anonymous)] ]");
+
+            let value = script::eval(guard, "({ a: { b: 1 }, c: [], d: [1, 'a', null] })").unwrap();
+            assert_eq!(value.inspect(guard, &InspectOptions::new().depth(Some(0))), "{ a: [Object], c: [], d: [Array] }");
+            assert_eq!(value.inspect(guard, &InspectOptions::new().max_array_length(Some(1))),
+                       "{ a: { b: 1 }, c: [], d: [ 1, ... 2 more items ] }");
+
+            let colored = value.inspect(guard, &InspectOptions::new().depth(None).colors(true).break_length(30));
+            assert_eq!(colored, "{\n  a: { b: \u{1b}[33m1\u{1b}[39m },\n  c: [],\n  \
+                                 d: [ \u{1b}[33m1\u{1b}[39m, \u{1b}[32m'a'\u{1b}[39m, \u{1b}[1mnull\u{1b}[22m ]\n}");
+        });
+    }
+
+    #[test]
+    fn redacted_inspect() {
+        test::run_with_context(|guard| {
+            let value = script::eval(guard, "
+                var value = {
+                    user: 'foo',
+                    apiToken: 'abc',
+                    nested: { Password: 1, list: [1, null, undefined] },
+                    send: function send() {},
+                };
+                value.self = value;
+                value").unwrap();
+
+            assert_eq!(value.redacted_inspect(guard, &RedactionPolicy::default()),
+                       "{ user: \"foo\", apiToken: [REDACTED], nested: { Password: [REDACTED], \
+                        list: [ 1, null, undefined ] }, send: [Function: send], self: [Circular] }");
+
+            let policy = RedactionPolicy::new().key("user").mask("***").max_depth(1);
+            assert_eq!(value.redacted_inspect(guard, &policy),
+                       "{ user: ***, apiToken: \"abc\", nested: [Object], send: [Function: send], \
+                        self: [Circular] }");
+
+            let value = script::eval(guard, "({ get a() { throw 1; }, b: 2 })").unwrap();
+            assert_eq!(value.redacted_inspect(guard, &RedactionPolicy::default()), "{ a: [Getter threw], b: 2 }");
+        });
+    }
+}
@@ -0,0 +1,126 @@
+//! Frozen literals for constant host data.
+use std::collections::HashMap;
+use context::ContextGuard;
+use Property;
+use super::{Value, Array, Object, ToJs};
+
+/// Creates a frozen JavaScript value from a JSON-like literal.
+///
+/// Objects and arrays are frozen (see `Object::freeze`) after they have been
+/// constructed, so scripts cannot modify the data. Keys must be string
+/// literals, and values can be `null`, `undefined`, nested literals or any
+/// expression implementing `ToJs`. Expressions consisting of more than one
+/// token (e.g negative numbers) must be wrapped in parentheses.
+///
+/// ```rust
+/// # #[macro_use] extern crate chakracore as js;
+/// # fn main() {
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let name = "x";
+/// let value = literal!(&guard, { "name": name, "sizes": [1, 2, (-3)] });
+/// assert_eq!(value.to_json(&guard).unwrap(), r#"{"name":"x","sizes":[1,2,-3]}"#);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! literal {
+    (@ $builder:ident, null) => { $builder.null() };
+    (@ $builder:ident, undefined) => { $builder.undefined() };
+    (@ $builder:ident, [ $($element:tt),* ]) => {{
+        let elements = vec![$(literal!(@ $builder, $element)),*];
+        $builder.array(elements)
+    }};
+    (@ $builder:ident, [ $($element:tt),+ , ]) => { literal!(@ $builder, [ $($element),+ ]) };
+    (@ $builder:ident, { $($key:tt : $value:tt),* }) => {{
+        let properties = vec![$(($key, literal!(@ $builder, $value))),*];
+        $builder.object(properties)
+    }};
+    (@ $builder:ident, { $($key:tt : $value:tt),+ , }) => {
+        literal!(@ $builder, { $($key : $value),+ })
+    };
+    (@ $builder:ident, $value:expr) => { $builder.value(&$value) };
+    ($guard:expr, $($literal:tt)+) => {{
+        #[allow(unused_mut)]
+        let mut builder = $crate::value::LiteralBuilder::new($guard);
+        literal!(@ builder, $($literal)+)
+    }};
+}
+
+/// A builder for frozen values, used by the `literal!` macro.
+///
+/// Property identifiers are cached, so each distinct key is only created once
+/// per literal.
+pub struct LiteralBuilder<'a> {
+    guard: &'a ContextGuard<'a>,
+    properties: HashMap<&'static str, Property>,
+}
+
+impl<'a> LiteralBuilder<'a> {
+    /// Creates a new literal builder.
+    pub fn new(guard: &'a ContextGuard<'a>) -> Self {
+        LiteralBuilder {
+            guard: guard,
+            properties: HashMap::new(),
+        }
+    }
+
+    /// Returns `null`.
+    pub fn null(&self) -> Value {
+        super::null(self.guard)
+    }
+
+    /// Returns `undefined`.
+    pub fn undefined(&self) -> Value {
+        super::undefined(self.guard)
+    }
+
+    /// Converts a native value.
+    pub fn value<T: ToJs + ?Sized>(&self, value: &T) -> Value {
+        value.to_js(self.guard)
+    }
+
+    /// Creates a frozen array.
+    pub fn array(&mut self, elements: Vec<Value>) -> Value {
+        let array = Array::from_iter(self.guard, elements);
+        array.freeze(self.guard).expect("freezing literal");
+        array.into()
+    }
+
+    /// Creates a frozen object.
+    pub fn object(&mut self, properties: Vec<(&'static str, Value)>) -> Value {
+        let object = Object::new(self.guard);
+        for (key, value) in properties {
+            let guard = self.guard;
+            let property = self.properties.entry(key).or_insert_with(|| Property::new(guard, key));
+            object.set(guard, property, &value);
+        }
+        object.freeze(self.guard).expect("freezing literal");
+        object.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, Property};
+
+    #[test]
+    fn frozen() {
+        test::run_with_context(|guard| {
+            let sizes = vec![1, 2];
+            let value = literal!(guard, {
+                "name": "x",
+                "sizes": sizes,
+                "nested": { "list": [true, null, (-1.5)], "name": undefined },
+            });
+            assert_eq!(value.to_json(guard).unwrap(),
+                       r#"{"name":"x","sizes":[1,2],"nested":{"list":[true,null,-1.5]}}"#);
+
+            guard.global().set(guard, &Property::new(guard, "value"), &value);
+            let error = script::eval(guard, "value.nested.list.push(1)").unwrap_err();
+            assert!(error.to_string().contains("TypeError"));
+            let frozen = script::eval(guard, "Object.isFrozen(value.nested.list)").unwrap();
+            assert!(frozen.to_bool(guard));
+        });
+    }
+}
@@ -18,35 +18,72 @@
 //!
 //! All created values are tied to a specific context. Because of this a
 //! `ContextGuard` is required whenever creating new values, and they should
-//! not be passed between different contexts.
+//! not be passed between different contexts. To keep a value across guard
+//! scopes (e.g in a host cache), use [Persistent](struct.Persistent.html),
+//! or [Shared](struct.Shared.html) if the handle must be sent between threads.
 use context::ContextGuard;
 use chakracore_sys::*;
 
 // TODO: Add typed arrays and buffer view.
 pub use self::array::*;
+pub use self::bigint::BigInt;
 pub use self::boolean::Boolean;
+pub use self::builder::{ArrayBuilder, HostFunction, HostValue, ObjectBuilder};
+pub use self::collection::{Map, Set};
+pub(crate) use self::builtins::Builtins;
+pub use self::convert::{FromJs, Guarded, ToJs};
+pub use self::date::Date;
+pub use self::descriptor::PropertyDescriptor;
 pub use self::error::Error;
+pub use self::export::{ExportedValue, transfer};
 pub use self::external::External;
-pub use self::function::Function;
+pub use self::function::{FromArguments, Function};
+pub use self::indexed::Element;
+pub use self::inspect::{InspectOptions, RedactionPolicy};
+pub use self::literal::LiteralBuilder;
 pub use self::number::Number;
 pub use self::object::Object;
-pub use self::promise::Promise;
+pub use self::persistent::Persistent;
+pub use self::promise::{Promise, RetryPolicy};
+pub use self::registry::Foreign;
+pub use self::regexp::{RegExp, RegExpMatch};
+pub use self::shared::Shared;
+pub use self::shared_buffer::{SharedArrayBuffer, SharedContents};
 pub use self::string::String;
-pub use self::value::Value;
+pub use self::symbol::Symbol;
+pub use self::value::{Value, ValueType};
 
 #[macro_use]
 mod macros;
+#[macro_use]
+mod literal;
 
 // Modules
 pub mod function;
 pub mod promise;
+pub mod registry;
 mod object;
 mod array;
+mod bigint;
 mod boolean;
+mod builder;
+mod builtins;
+mod collection;
+mod convert;
+mod date;
+mod descriptor;
 mod error;
+mod export;
 mod external;
+mod indexed;
+mod inspect;
 mod number;
+mod persistent;
+mod regexp;
+mod shared;
+mod shared_buffer;
 mod string;
+mod symbol;
 mod value;
 
 /// Creates a `false` value.
@@ -3,9 +3,14 @@ use context::ContextGuard;
 use super::Value;
 
 /// A JavaScript number.
+#[repr(transparent)]
 pub struct Number(JsValueRef);
 
 impl Number {
+    /// The largest integer `n`, such that `n` and `n + 1` are both exactly
+    /// representable (i.e `Number.MAX_SAFE_INTEGER`).
+    pub const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
     /// Creates a new number.
     pub fn new(_guard: &ContextGuard, number: i32) -> Self {
         let mut value = JsValueRef::new();
@@ -24,6 +29,62 @@ impl Number {
         }
     }
 
+    /// Creates a new number from an unsigned integer, which is always exact.
+    pub fn from_u32(guard: &ContextGuard, number: u32) -> Self {
+        Self::from_double(guard, number as f64)
+    }
+
+    /// Creates a new number from a 64-bit integer.
+    ///
+    /// Numbers are doubles, so integers beyond `MAX_SAFE_INTEGER` (i.e 2^53 -
+    /// 1) in magnitude are rounded to the nearest representable value; use
+    /// `from_i64_exact` to detect this.
+    pub fn from_i64(guard: &ContextGuard, number: i64) -> Self {
+        Self::from_double(guard, number as f64)
+    }
+
+    /// Creates a new number from a 64-bit integer, unless it would lose
+    /// precision.
+    pub fn from_i64_exact(guard: &ContextGuard, number: i64) -> Option<Self> {
+        if -Self::MAX_SAFE_INTEGER <= number && number <= Self::MAX_SAFE_INTEGER {
+            Some(Self::from_i64(guard, number))
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the number is an integer (i.e `Number.isInteger`).
+    ///
+    /// This is a property of the value, not of its representation; `1.0` is
+    /// an integer, while `NaN` and infinities are not.
+    pub fn is_integer(&self, _guard: &ContextGuard) -> bool {
+        let number = self.value_double();
+        number.is_finite() && number.trunc() == number
+    }
+
+    /// Converts the number to a 64-bit integer, truncating any fraction.
+    ///
+    /// Values out of range saturate at `i64::MIN` or `i64::MAX`, and `NaN` is
+    /// converted to zero. Integers beyond `MAX_SAFE_INTEGER` may already have
+    /// been rounded when the number was created.
+    pub fn to_i64_lossy(&self, _guard: &ContextGuard) -> i64 {
+        self.value_double() as i64
+    }
+
+    /// Converts the number to an unsigned integer, using JavaScript's ToUint32
+    /// semantics (e.g as `number >>> 0`).
+    ///
+    /// The number is truncated and wrapped modulo 2^32, while `NaN` and
+    /// infinities are converted to zero.
+    pub fn to_u32(&self, _guard: &ContextGuard) -> u32 {
+        let number = self.value_double();
+        if number.is_finite() {
+            number.trunc().rem_euclid(4294967296.0) as u32
+        } else {
+            0
+        }
+    }
+
     /// Converts a JavaScript number to a double.
     pub fn value_double(&self) -> f64 {
         let mut double = 0f64;
@@ -47,3 +108,30 @@ impl Number {
 
 reference!(Number);
 inherit!(Number, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+
+    #[test]
+    fn conversions() {
+        test::run_with_context(|guard| {
+            let number = |code: &str| script::eval(guard, code).unwrap().into_number().unwrap();
+            assert!(number("1.0").is_integer(guard));
+            assert!(!number("1.5").is_integer(guard) && !number("NaN").is_integer(guard));
+
+            for code in &["-1", "4294967297.9", "-4294967295.5", "NaN", "Infinity", "2147483648"] {
+                let expected = number(&format!("({}) >>> 0", code)).value_double() as u32;
+                assert_eq!(number(code).to_u32(guard), expected, "{}", code);
+            }
+
+            assert_eq!(number("-1e300").to_i64_lossy(guard), i64::min_value());
+            assert_eq!(number("-12.9").to_i64_lossy(guard), -12);
+            assert_eq!(value::Number::from_u32(guard, u32::max_value()).value_double(), 4294967295.0);
+            assert_eq!(value::Number::from_i64(guard, -(1 << 40)).to_i64_lossy(guard), -(1 << 40));
+            assert!(value::Number::from_i64_exact(guard, value::Number::MAX_SAFE_INTEGER).is_some());
+            assert!(value::Number::from_i64_exact(guard, 1 << 53).is_none());
+            assert!(value::Number::from_i64_exact(guard, i64::min_value()).is_none());
+        });
+    }
+}
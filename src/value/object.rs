@@ -1,15 +1,31 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 use libc::c_void;
 use chakracore_sys::*;
 use context::ContextGuard;
 use error::*;
 use util::jstry;
 use Property;
-use super::{Value, Array};
+use super::{Value, Array, FromJs, PropertyDescriptor};
 
 /// Callback type for collector.
 type BeforeCollectCallback = Fn(&Value);
 
+/// Callback type for `on_collect`.
+pub type CollectCallback = FnOnce() + Send;
+
+/// The callbacks executed before an object is collected.
+type CollectCallbacks = Vec<Box<FnOnce(&Value)>>;
+
+/// The collect callbacks of objects, keyed by their address.
+///
+/// The engine only supports a single callback per object, so every callback
+/// is dispatched from a list associated with the object.
+static COLLECT_CALLBACKS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
 /// A JavaScript object.
+#[repr(transparent)]
 pub struct Object(JsValueRef);
 
 // TODO: Add `for .. in` iterator
@@ -23,6 +39,45 @@ impl Object {
         }
     }
 
+    /// Creates an object with a set of properties.
+    ///
+    /// Each property is assigned directly, without creating any intermediate
+    /// values. See `ObjectBuilder` for describing nested values.
+    pub fn from_pairs(guard: &ContextGuard, pairs: &[(&str, &Value)]) -> Self {
+        let object = Self::new(guard);
+        for &(key, value) in pairs {
+            let key = Property::new(guard, key);
+            jsassert!(unsafe { JsSetProperty(object.as_raw(), key.as_raw(), value.as_raw(), true) });
+        }
+        object
+    }
+
+    /// Creates a new empty object with a specified prototype.
+    pub fn with_prototype(guard: &ContextGuard, prototype: &Value) -> Result<Self> {
+        let object = Self::new(guard);
+        object.set_prototype(guard, prototype).map(|_| object)
+    }
+
+    /// Creates a new empty object inheriting from a prototype, or from nothing
+    /// (i.e `Object.create(null)`) if it is `None`.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # use js::value::Object;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let animal = Object::new(&guard);
+    /// let dog = Object::create_with_prototype(&guard, Some(&animal));
+    /// let rex = Object::create_with_prototype(&guard, Some(&dog));
+    /// assert!(rex.prototype(&guard).unwrap().prototype(&guard).unwrap().strict_equals(&guard, &animal));
+    /// assert!(Object::create_with_prototype(&guard, None).prototype(&guard).is_none());
+    /// ```
+    pub fn create_with_prototype(guard: &ContextGuard, prototype: Option<&Object>) -> Self {
+        let prototype = prototype.map_or_else(|| super::null(guard), |prototype| prototype.clone().into());
+        Self::with_prototype(guard, &prototype).expect("setting the prototype of a new object")
+    }
+
     /// Sets an object's property's value.
     pub fn set(&self, _guard: &ContextGuard, key: &Property, value: &Value) {
         jsassert!(unsafe { JsSetProperty(self.as_raw(), key.as_raw(), value.as_raw(), false) });
@@ -43,6 +98,16 @@ impl Object {
         }
     }
 
+    /// Returns an object's property's value, or an error if it throws (e.g a
+    /// getter or a proxy trap).
+    pub fn try_get(&self, _guard: &ContextGuard, key: &Property) -> Result<Value> {
+        let mut result = JsValueRef::new();
+        unsafe {
+            jstry(JsGetProperty(self.as_raw(), key.as_raw(), &mut result))?;
+            Ok(Value::from_raw(result))
+        }
+    }
+
     /// Returns an object's index value.
     pub fn get_index(&self, guard: &ContextGuard, index: u32) -> Value {
         let index = super::Number::new(guard, index as i32);
@@ -53,6 +118,23 @@ impl Object {
         }
     }
 
+    /// Returns whether the object is an instance of a constructor (i.e
+    /// `object instanceof constructor`).
+    ///
+    /// This is equivalent to `Function::instance_of`, with the operands in
+    /// the order of the operator.
+    pub fn instance_of(&self, guard: &ContextGuard, constructor: &super::Function) -> bool {
+        constructor.instance_of(guard, self)
+    }
+
+    /// Calls one of the object's methods, with the object as `this`.
+    pub fn call_method(&self, guard: &ContextGuard, name: &str, arguments: &[&Value]) -> Result<Value> {
+        self.get(guard, &Property::new(guard, name))
+            .into_function()
+            .ok_or_else(|| format!("property '{}' is not a function", name).into())
+            .and_then(|method| method.call_with_this(guard, self, arguments))
+    }
+
     /// Deletes an object's property.
     pub fn delete(&self, _guard: &ContextGuard, key: &Property) -> bool {
         let mut result = JsValueRef::new();
@@ -94,18 +176,99 @@ impl Object {
         result
     }
 
-    /// Sets the object's prototype. This will result in an error if it's called
-    /// on the context's global object.
+    /// Defines or modifies a property using a descriptor.
+    ///
+    /// An invalid descriptor (e.g one with both a value and a getter), or
+    /// redefining a non-configurable property, results in an error.
+    pub fn define(&self, guard: &ContextGuard, key: &Property, descriptor: &PropertyDescriptor) -> Result<()> {
+        let descriptor = descriptor.to_object(guard);
+        let mut result = false;
+        unsafe {
+            jstry!(JsDefineProperty(self.as_raw(), key.as_raw(), descriptor.as_raw(), &mut result));
+        }
+        if result {
+            Ok(())
+        } else {
+            Err(format!("property {:?} could not be defined", key).into())
+        }
+    }
+
+    /// Returns the descriptor of one of the object's own properties.
+    pub fn get_own_property_descriptor(&self, guard: &ContextGuard, key: &Property) -> Option<PropertyDescriptor> {
+        self.try_get_own_property_descriptor(guard, key).expect("retrieving own property descriptor")
+    }
+
+    /// Returns the descriptor of one of the object's own properties, or an
+    /// error if it throws (e.g a proxy trap).
+    pub fn try_get_own_property_descriptor(&self,
+                                           guard: &ContextGuard,
+                                           key: &Property) -> Result<Option<PropertyDescriptor>> {
+        let mut descriptor = JsValueRef::new();
+        unsafe {
+            jstry(JsGetOwnPropertyDescriptor(self.as_raw(), key.as_raw(), &mut descriptor))?;
+            Ok(Value::from_raw(descriptor)
+                .into_object()
+                .map(|descriptor| PropertyDescriptor::from_object(guard, &descriptor)))
+        }
+    }
+
+    /// Extracts a set of property paths, and converts them to a native type.
+    ///
+    /// Each path is a dot separated list of property names (e.g `foo.bar`).
+    /// Missing properties result in `undefined`. The extracted values are
+    /// converted as an array, in the same order as the paths, so a tuple can
+    /// be used to retrieve multiple fields at once. Shared path prefixes are
+    /// only looked up once.
+    pub fn project<T: FromJs>(&self, guard: &ContextGuard, paths: &[&str]) -> Result<T> {
+        let mut cache: HashMap<&str, Value> = HashMap::new();
+        let values = Array::new(guard, paths.len() as u32);
+
+        for (index, path) in paths.iter().enumerate() {
+            let mut current: Value = self.clone().into();
+            for (offset, _) in path.match_indices('.').chain(Some((path.len(), ""))) {
+                let prefix = &path[..offset];
+                if let Some(value) = cache.get(prefix) {
+                    current = value.clone();
+                    continue;
+                }
+
+                let name = &prefix[prefix.rfind('.').map_or(0, |start| start + 1)..];
+                current = match current.into_object() {
+                    Some(object) => object.get(guard, &Property::new(guard, name)),
+                    None => super::undefined(guard),
+                };
+                cache.insert(prefix, current.clone());
+            }
+            values.set_index(guard, index as u32, &current);
+        }
+
+        T::from_js(guard, &values)
+    }
+
+    /// Sets the object's prototype (either an object, or `null`). This will
+    /// result in an error if it's called on the context's global object, or if
+    /// it would create a cyclic prototype chain.
     pub fn set_prototype(&self, _guard: &ContextGuard, prototype: &Value) -> Result<()> {
         unsafe { jstry(JsSetPrototype(self.as_raw(), prototype.as_raw())) }
     }
 
-    /// Returns the object's prototype.
-    pub fn get_prototype(&self, _guard: &ContextGuard) -> Value {
+    /// Returns the object's prototype, or `None` if it is `null`.
+    pub fn prototype(&self, guard: &ContextGuard) -> Option<Object> {
+        self.get_prototype(guard).into_object()
+    }
+
+    /// Returns the object's prototype (as a value, since it may be `null`).
+    pub fn get_prototype(&self, guard: &ContextGuard) -> Value {
+        self.try_get_prototype(guard).expect("retrieving prototype")
+    }
+
+    /// Returns the object's prototype, or an error if it throws (e.g a proxy
+    /// trap).
+    pub fn try_get_prototype(&self, _guard: &ContextGuard) -> Result<Value> {
         let mut prototype = JsValueRef::new();
         unsafe {
-            jsassert!(JsGetPrototype(self.as_raw(), &mut prototype));
-            Value::from_raw(prototype)
+            jstry(JsGetPrototype(self.as_raw(), &mut prototype))?;
+            Ok(Value::from_raw(prototype))
         }
     }
 
@@ -118,11 +281,141 @@ impl Object {
         }
     }
 
+    /// Returns the object's own symbol properties.
+    pub fn get_own_property_symbols(&self, _guard: &ContextGuard) -> Array {
+        let mut symbols = JsValueRef::new();
+        unsafe {
+            jsassert!(JsGetOwnPropertySymbols(self.as_raw(), &mut symbols));
+            Array::from_raw(symbols)
+        }
+    }
+
+    /// Returns the descriptors of all the object's own properties.
+    ///
+    /// Like `Reflect.ownKeys`, the string keyed properties are followed by the
+    /// symbol keyed properties. Properties removed while the descriptors are
+    /// read (e.g by a proxy) are omitted.
+    pub fn get_own_property_descriptors(&self, guard: &ContextGuard) -> Vec<(Property, PropertyDescriptor)> {
+        self.try_get_own_property_descriptors(guard).expect("retrieving own property descriptors")
+    }
+
+    /// Returns the descriptors of all the object's own properties, or an error
+    /// if the enumeration throws (e.g a proxy trap).
+    pub fn try_get_own_property_descriptors(&self, guard: &ContextGuard) -> Result<Vec<(Property, PropertyDescriptor)>> {
+        let (mut names, mut symbols) = (JsValueRef::new(), JsValueRef::new());
+        unsafe {
+            jstry(JsGetOwnPropertyNames(self.as_raw(), &mut names))?;
+            jstry(JsGetOwnPropertySymbols(self.as_raw(), &mut symbols))?;
+        }
+
+        let names = unsafe { Array::from_raw(names) }.iter(guard)
+            .map(|name| Property::new(guard, &name.to_string(guard)))
+            .collect::<Vec<_>>();
+        let symbols = unsafe { Array::from_raw(symbols) }.iter(guard)
+            .filter_map(|symbol| symbol.into_symbol())
+            .map(|symbol| Property::from_symbol(guard, &symbol))
+            .collect::<Vec<_>>();
+
+        let mut descriptors = Vec::new();
+        for key in names.into_iter().chain(symbols) {
+            if let Some(descriptor) = self.try_get_own_property_descriptor(guard, &key)? {
+                descriptors.push((key, descriptor));
+            }
+        }
+        Ok(descriptors)
+    }
+
     /// Makes an object non-extensible.
     pub fn prevent_extension(&self) {
         jsassert!(unsafe { JsPreventExtension(self.as_raw()) });
     }
 
+    /// Freezes the object (i.e `Object.freeze`).
+    ///
+    /// A frozen object is non-extensible, and its properties can neither be
+    /// reconfigured, nor have their values changed. This is shallow; objects
+    /// referenced by its properties remain mutable (see `freeze_deep`).
+    ///
+    /// This uses JSRT directly, so it is unaffected by scripts replacing the
+    /// global `Object.freeze`.
+    pub fn freeze(&self, guard: &ContextGuard) -> Result<()> {
+        self.restrict(guard, true)
+    }
+
+    /// Freezes the object, and every object reachable from its own data
+    /// properties.
+    ///
+    /// Accessor properties are not followed, since their getters may return
+    /// different objects on each access.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let config = js::script::eval(&guard, "var config = { limits: { memory: 64 } }; config").unwrap();
+    /// config.into_object().unwrap().freeze_deep(&guard).unwrap();
+    ///
+    /// assert!(js::script::eval(&guard, "'use strict'; config.limits.memory = 1024").is_err());
+    /// assert_eq!(js::script::eval(&guard, "config.limits.memory").unwrap().to_integer(&guard), 64);
+    /// ```
+    pub fn freeze_deep(&self, guard: &ContextGuard) -> Result<()> {
+        self.freeze(guard)?;
+        for (_, descriptor) in self.get_own_property_descriptors(guard) {
+            let object = match descriptor.get_value().and_then(|value| value.clone().into_object()) {
+                Some(object) => object,
+                None => continue,
+            };
+
+            // Frozen objects are skipped, which also stops at cycles
+            if !object.is_frozen(guard) {
+                object.freeze_deep(guard)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the object is frozen.
+    pub fn is_frozen(&self, guard: &ContextGuard) -> bool {
+        self.is_restricted(guard, true)
+    }
+
+    /// Seals the object (i.e `Object.seal`).
+    ///
+    /// A sealed object is non-extensible, and its properties cannot be
+    /// reconfigured, although writable properties can still be changed.
+    pub fn seal(&self, guard: &ContextGuard) -> Result<()> {
+        self.restrict(guard, false)
+    }
+
+    /// Returns whether the object is sealed.
+    pub fn is_sealed(&self, guard: &ContextGuard) -> bool {
+        self.is_restricted(guard, false)
+    }
+
+    /// Prevents extensions, and makes every own property non-configurable
+    /// (and data properties read-only, if frozen).
+    fn restrict(&self, guard: &ContextGuard, frozen: bool) -> Result<()> {
+        jstry(unsafe { JsPreventExtension(self.as_raw()) })?;
+        for (key, descriptor) in self.get_own_property_descriptors(guard) {
+            let mut restricted = PropertyDescriptor::new().configurable(false);
+            if frozen && descriptor.get_value().is_some() {
+                restricted = restricted.writable(false);
+            }
+            self.define(guard, &key, &restricted)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the object is non-extensible, and every own property
+    /// is non-configurable (and data properties read-only, if frozen).
+    fn is_restricted(&self, guard: &ContextGuard, frozen: bool) -> bool {
+        !self.is_extensible() && self.get_own_property_descriptors(guard).iter().all(|&(_, ref descriptor)| {
+            descriptor.is_configurable() == Some(false)
+                && (!frozen || descriptor.get_value().is_none() || descriptor.is_writable() == Some(false))
+        })
+    }
+
     /// Returns whether the object is extensible or not.
     pub fn is_extensible(&self) -> bool {
         let mut result = false;
@@ -130,17 +423,73 @@ impl Object {
         result
     }
 
-    /// Sets a callback that is executed before the object is collected.
+    /// Attaches native data to the object.
+    ///
+    /// The data is held by an `External`, stored in a hidden (symbol keyed,
+    /// non-enumerable) property, and is dropped once the object is collected.
+    /// Since the property cannot be modified, the data can only be set once;
+    /// any mutable state should use interior mutability (e.g `RefCell`).
+    ///
+    /// This fails if the object already has data, or is not extensible.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # use std::cell::Cell;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let socket = js::value::Object::new(&guard);
+    /// socket.set_external_data(&guard, Box::new(Cell::new(0u32))).unwrap();
     ///
-    /// This is highly unsafe to use. There is no bookkeeping whether any other
-    /// caller replaces the current callback or not. It is also used internally
-    /// by `Function` to cleanup user data (if it's replaced, memory will leak).
+    /// let sent = socket.get_external_data::<Cell<u32>>(&guard).unwrap();
+    /// sent.set(sent.get() + 1);
+    /// assert_eq!(socket.get_external_data::<Cell<u32>>(&guard).unwrap().get(), 1);
+    /// ```
+    pub fn set_external_data<T: Any>(&self, guard: &ContextGuard, data: Box<T>) -> Result<()> {
+        let key = guard.external_data_key();
+        if self.has_own_property(guard, &key) {
+            return Err("object already has external data".into());
+        }
+
+        let external = super::External::typed(guard, *data);
+        self.define(guard, &key, &PropertyDescriptor::new().value(external))
+    }
+
+    /// Borrows the object's native data, if it has data of type `T`.
+    ///
+    /// Only the object's own data is returned, not that of its prototypes,
+    /// and only within the context the data was attached in.
+    pub fn get_external_data<T: Any>(&self, guard: &ContextGuard) -> Option<&T> {
+        let external = self.get_own_property_descriptor(guard, &guard.external_data_key())
+            .and_then(|descriptor| descriptor.get_value().cloned())
+            .and_then(|value| value.into_external())?;
+
+        // The property is immutable, so the data lives as long as the object
+        external.value_ref::<T>(guard).map(|data| unsafe { &*(data as *const T) })
+    }
+
+    /// Returns whether the object has an own property.
+    fn has_own_property(&self, guard: &ContextGuard, key: &Property) -> bool {
+        self.get_own_property_descriptor(guard, key).is_some()
+    }
+
+    /// Adds a callback that is executed before the object is collected.
+    ///
+    /// The callback is executed during garbage collection, and must not use
+    /// the value, nor any other JavaScript values. The host can use it to
+    /// release associated native resources (e.g file handles or sockets).
+    /// Callbacks are executed in the order they were added.
+    pub fn on_collect(&self, _guard: &ContextGuard, callback: Box<CollectCallback>) {
+        unsafe { self.add_collect_callback(Box::new(move |_: &Value| callback())) };
+    }
+
+    /// Adds a callback that is executed before the object is collected.
+    ///
+    /// This is highly unsafe to use, since the callback is provided the value
+    /// while it is being collected. It is also used internally by `Function`
+    /// to cleanup user data.
     pub unsafe fn set_collect_callback(&self, callback: Box<BeforeCollectCallback>) {
-        let wrapper = Box::new(callback);
-        let api = JsSetObjectBeforeCollectCallback;
-        jsassert!(api(self.as_raw(),
-                      Box::into_raw(wrapper) as *mut _,
-                      Some(Self::collect)));
+        self.add_collect_callback(Box::new(move |value: &Value| callback(value)));
     }
 
     /// Returns true if the value is an `Object`.
@@ -157,10 +506,31 @@ impl Object {
         }
     }
 
+    /// Adds a callback to the object's list of collect callbacks.
+    unsafe fn add_collect_callback(&self, callback: Box<FnOnce(&Value)>) {
+        let address = self.as_raw().0 as usize;
+        let mut registry = COLLECT_CALLBACKS.lock().unwrap();
+
+        if let Some(&callbacks) = registry.get(&address) {
+            (*(callbacks as *mut CollectCallbacks)).push(callback);
+            return;
+        }
+
+        let callbacks = Box::into_raw(Box::new(vec![callback]));
+        registry.insert(address, callbacks as usize);
+        jsassert!(JsSetObjectBeforeCollectCallback(self.as_raw(),
+                                                   callbacks as *mut _,
+                                                   Some(Self::collect)));
+    }
+
     /// A collect callback, triggered before the object is destroyed.
     unsafe extern "system" fn collect(value: JsValueRef, data: *mut c_void) {
-        let wrapper: Box<Box<BeforeCollectCallback>> = Box::from_raw(data as *mut _);
-        wrapper(&Value::from_raw(value));
+        COLLECT_CALLBACKS.lock().unwrap().remove(&(value.0 as usize));
+        let callbacks: Box<CollectCallbacks> = Box::from_raw(data as *mut _);
+        let value = Value::from_raw(value);
+        for callback in *callbacks {
+            callback(&value);
+        }
     }
 }
 
@@ -169,7 +539,19 @@ inherit!(Object, Value);
 
 #[cfg(test)]
 mod tests {
-    use {test, value, Property};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use {test, script, value, Property};
+
+    #[test]
+    fn from_pairs() {
+        test::run_with_context(|guard| {
+            let number: value::Value = value::Number::new(guard, 1).into();
+            let string: value::Value = value::String::new(guard, "two").into();
+            let object = value::Object::from_pairs(guard, &[("a", &number), ("b", &string), ("a", &string)]);
+            assert_eq!(object.to_json(guard).unwrap(), r#"{"a":"two","b":"two"}"#);
+        });
+    }
 
     #[test]
     fn properties() {
@@ -200,4 +582,173 @@ mod tests {
             assert!(!object.has(guard, &prop_foo));
         });
     }
+
+    #[test]
+    fn define() {
+        test::run_with_context(|guard| {
+            let object = value::Object::new(guard);
+            let getter = value::Function::new(guard, Box::new(|guard, _| {
+                Ok(value::Number::new(guard, 42).into())
+            }));
+
+            let answer = Property::new(guard, "answer");
+            let descriptor = value::PropertyDescriptor::new().getter(getter).enumerable(true);
+            object.define(guard, &answer, &descriptor).unwrap();
+
+            let constant = Property::new(guard, "constant");
+            let descriptor = value::PropertyDescriptor::new().value(value::Number::new(guard, 1));
+            object.define(guard, &constant, &descriptor).unwrap();
+            object.set(guard, &constant, &value::Number::new(guard, 2));
+
+            assert_eq!(object.get(guard, &answer).to_integer(guard), 42);
+            assert_eq!(object.get(guard, &constant).to_integer(guard), 1);
+            assert_eq!(object.to_json(guard).unwrap(), r#"{"answer":42}"#);
+            assert!(object.define(guard, &constant, &value::PropertyDescriptor::new().enumerable(true)).is_err());
+
+            let descriptor = object.get_own_property_descriptor(guard, &constant).unwrap();
+            assert_eq!(descriptor.get_value().map(|value| value.to_integer(guard)), Some(1));
+            assert_eq!((descriptor.is_writable(), descriptor.is_configurable()), (Some(false), Some(false)));
+            assert!(object.get_own_property_descriptor(guard, &Property::new(guard, "foo")).is_none());
+        });
+    }
+
+    #[test]
+    fn property_descriptors() {
+        test::run_with_context(|guard| {
+            let object = script::eval(guard, "var o = Object.defineProperty({ a: 1 }, 'b', { get: () => 2 });
+                                              o[Symbol.iterator] = null; o").unwrap().into_object().unwrap();
+            let descriptors = object.get_own_property_descriptors(guard);
+            let keys = descriptors.iter().map(|&(ref key, _)| key.to_string(guard)).collect::<Vec<_>>();
+            assert_eq!(keys, ["a", "b", "Symbol(Symbol.iterator)"]);
+
+            let (a, b) = (&descriptors[0].1, &descriptors[1].1);
+            assert_eq!(a.get_value().map(|value| value.to_integer(guard)), Some(1));
+            assert_eq!((a.is_writable(), a.is_enumerable(), a.is_configurable()), (Some(true), Some(true), Some(true)));
+            assert!(b.get_getter().is_some() && b.get_setter().is_none());
+            assert_eq!((b.is_writable(), b.is_enumerable()), (None, Some(false)));
+        });
+    }
+
+    #[test]
+    fn prototype() {
+        test::run_with_context(|guard| {
+            let base = script::eval(guard, "({ greet() { return 'hi ' + this.name; } })").unwrap().into_object().unwrap();
+            let derived = value::Object::create_with_prototype(guard, Some(&base));
+            let instance = value::Object::with_prototype(guard, &derived).unwrap();
+            instance.set(guard, &Property::new(guard, "name"), &value::String::new(guard, "bob"));
+
+            assert_eq!(instance.call_method(guard, "greet", &[]).unwrap().to_string(guard), "hi bob");
+            assert!(instance.prototype(guard).unwrap().strict_equals(guard, &derived));
+            assert!(base.set_prototype(guard, &instance).is_err());
+
+            derived.set_prototype(guard, &value::null(guard)).unwrap();
+            assert!(derived.prototype(guard).is_none());
+            assert!(instance.call_method(guard, "greet", &[]).is_err());
+        });
+    }
+
+    #[test]
+    fn instance_of() {
+        test::run_with_context(|guard| {
+            let error = script::eval(guard, "new RangeError()").unwrap().into_object().unwrap();
+            let constructor = |name: &str| guard.global().get(guard, &Property::new(guard, name)).into_function().unwrap();
+            assert!(error.instance_of(guard, &constructor("Error")));
+            assert!(error.instance_of(guard, &constructor("RangeError")));
+            assert!(!error.instance_of(guard, &constructor("TypeError")));
+
+            let number = value::Number::new(guard, 1);
+            assert!(number.equals(guard, &value::String::new(guard, "1")));
+            assert!(!number.strict_equals(guard, &value::String::new(guard, "1")));
+        });
+    }
+
+    #[test]
+    fn freeze() {
+        test::run_with_context(|guard| {
+            let object = script::eval(guard, "({ a: 1, nested: { b: 2 } })").unwrap().into_object().unwrap();
+            let sealed = value::Object::new(guard);
+            sealed.seal(guard).unwrap();
+            assert!(sealed.is_sealed(guard) && sealed.is_frozen(guard) && !sealed.is_extensible());
+
+            object.seal(guard).unwrap();
+            assert!(object.is_sealed(guard) && !object.is_frozen(guard));
+            object.set(guard, &Property::new(guard, "a"), &value::Number::new(guard, 3));
+            assert_eq!(object.get(guard, &Property::new(guard, "a")).to_integer(guard), 3);
+
+            // Scripts replacing the built-ins do not affect freezing
+            script::eval(guard, "Object.freeze = o => o; Object.isFrozen = () => true;").unwrap();
+            object.freeze_deep(guard).unwrap();
+            let nested = object.get(guard, &Property::new(guard, "nested")).into_object().unwrap();
+            assert!(object.is_frozen(guard) && nested.is_frozen(guard));
+            object.set(guard, &Property::new(guard, "a"), &value::Number::new(guard, 4));
+            assert_eq!(object.to_json(guard).unwrap(), r#"{"a":3,"nested":{"b":2}}"#);
+        });
+    }
+
+    #[test]
+    fn external_data() {
+        test::run_with_context(|guard| {
+            let object = value::Object::new(guard);
+            assert!(object.get_external_data::<i32>(guard).is_none());
+            object.set_external_data(guard, Box::new(5)).unwrap();
+            assert!(object.set_external_data(guard, Box::new(6)).is_err());
+
+            assert_eq!(object.get_external_data::<i32>(guard), Some(&5));
+            assert!(object.get_external_data::<u32>(guard).is_none());
+            assert_eq!(object.to_json(guard).unwrap(), "{}");
+
+            let child = value::Object::with_prototype(guard, &object).unwrap();
+            assert!(child.get_external_data::<i32>(guard).is_none());
+
+            child.prevent_extension();
+            assert!(child.set_external_data(guard, Box::new(7)).is_err());
+        });
+    }
+
+    #[test]
+    fn call_method() {
+        test::run_with_context(|guard| {
+            let object = script::eval(guard, "({ value: 5, add: function(x) { return this.value + x; } })")
+                .unwrap()
+                .into_object()
+                .unwrap();
+            let argument = value::Number::new(guard, 10).into();
+            let result = object.call_method(guard, "add", &[&argument]).unwrap();
+            assert_eq!(result.to_integer(guard), 15);
+            assert!(object.call_method(guard, "value", &[]).is_err());
+        });
+    }
+
+    #[test]
+    fn project() {
+        test::run_with_context(|guard| {
+            let object = script::eval(guard, "({ a: 1, b: { c: 'foo', d: [true] }, e: null })")
+                .unwrap()
+                .into_object()
+                .unwrap();
+            let (a, c, d, missing) = object
+                .project::<(i32, String, Vec<bool>, Option<i32>)>(guard, &["a", "b.c", "b.d", "e.f"])
+                .unwrap();
+            assert_eq!((a, c.as_str(), d, missing), (1, "foo", vec![true], None));
+            assert!(object.project::<(String,)>(guard, &["a"]).is_err());
+        });
+    }
+
+    #[test]
+    fn on_collect() {
+        let (runtime, context) = test::setup_env();
+        let collected = Arc::new(AtomicUsize::new(0));
+
+        context.exec_with(|guard| {
+            for _ in 0..100 {
+                let collected = collected.clone();
+                let object = value::Object::new(guard);
+                object.on_collect(guard, Box::new(move || { collected.fetch_add(1, Ordering::SeqCst); }));
+                object.on_collect(guard, Box::new(|| ()));
+            }
+        }).unwrap();
+
+        runtime.collect_garbage().unwrap();
+        assert!(collected.load(Ordering::SeqCst) > 0);
+    }
 }
@@ -0,0 +1,113 @@
+//! Long-lived value handles.
+use std::{fmt, mem};
+use context::{Context, ContextGuard};
+use error::*;
+use super::Value;
+
+/// A rooted handle to a value, which can be stored across guard scopes.
+///
+/// The handle holds a reference to the value (using `JsAddRef`), and to its
+/// context, so neither is collected while the handle is alive. The reference
+/// is released (using `JsRelease`) within the value's context once the handle
+/// is dropped, regardless of which context is active at the time. If the
+/// context cannot be entered at that point, the reference is leaked instead.
+///
+/// Accessing the value requires a guard of the context it was created in.
+/// A handle is bound to its runtime's thread, and therefore not `Send`.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// let handle = context.exec_with(|guard| {
+///     let value = js::script::eval(guard, "[1, 2, 3]").unwrap();
+///     js::value::Persistent::new(guard, value)
+/// }).unwrap();
+///
+/// runtime.collect_garbage().unwrap();
+/// context.exec_with(|guard| {
+///     assert_eq!(handle.get(guard).unwrap().to_json(guard).unwrap(), "[1,2,3]");
+/// }).unwrap();
+/// ```
+pub struct Persistent<T: Clone + Into<Value>> {
+    context: Context,
+    value: Option<T>,
+}
+
+impl<T: Clone + Into<Value>> Persistent<T> {
+    /// Creates a handle to a value of the guard's context.
+    pub fn new(guard: &ContextGuard, value: T) -> Self {
+        Persistent {
+            context: guard.context(),
+            value: Some(value),
+        }
+    }
+
+    /// Returns the value, if the guard belongs to the value's context.
+    pub fn get(&self, guard: &ContextGuard) -> Result<T> {
+        if guard.context() != self.context {
+            return Err("persistent value accessed from another context".into());
+        }
+        Ok(self.value.clone().expect("retrieving persistent value"))
+    }
+
+    /// Returns the context the value belongs to.
+    pub fn context(&self) -> Context {
+        self.context.clone()
+    }
+}
+
+impl<T: Clone + Into<Value>> Clone for Persistent<T> {
+    fn clone(&self) -> Self {
+        Persistent {
+            context: self.context.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Into<Value>> fmt::Debug for Persistent<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Persistent({:?})", self.context)
+    }
+}
+
+impl<T: Clone + Into<Value>> Drop for Persistent<T> {
+    /// Releases the value within its own context.
+    fn drop(&mut self) {
+        let value = self.value.take();
+        match self.context.make_current() {
+            Ok(_guard) => drop(value),
+            // The value is leaked if its context cannot be entered (e.g from
+            // another thread, while the runtime is active)
+            Err(_) => mem::forget(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value, Context};
+    use super::Persistent;
+
+    #[test]
+    fn outlives_guard() {
+        let (runtime, context) = test::setup_env();
+        let handle = context.exec_with(|guard| {
+            let object = script::eval(guard, "({ foo: 'bar' })").unwrap().into_object().unwrap();
+            Persistent::<value::Object>::new(guard, object)
+        }).unwrap();
+
+        runtime.collect_garbage().unwrap();
+        context.exec_with(|guard| {
+            let object = handle.get(guard).unwrap();
+            assert_eq!(object.to_json(guard).unwrap(), r#"{"foo":"bar"}"#);
+        }).unwrap();
+
+        let other = Context::new(&runtime).unwrap();
+        other.exec_with(|guard| {
+            assert!(handle.get(guard).is_err());
+            drop(handle);
+        }).unwrap();
+    }
+}
@@ -1,8 +1,16 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll, Waker};
+use std::time::Duration;
 use chakracore_sys::*;
 use context::ContextGuard;
-use super::{Value, Object, Function};
+use super::{Value, Object, Function, ToJs};
 use error::*;
-use {util, Context};
+use {util, Context, Property};
+
+/// The outcome of a settled promise, either its value or rejection reason.
+pub type PromiseResult = ::std::result::Result<Value, Value>;
 
 /// A JavaScript promise executor.
 pub struct Executor {
@@ -25,10 +33,15 @@ impl Executor {
 /// A JavaScript promise.
 ///
 /// To support promises within a context, see [Context](../context/struct.Context.html).
+#[repr(transparent)]
 pub struct Promise(JsValueRef);
 
 impl Promise {
     /// Creates a new promise with an associated executor.
+    ///
+    /// The executor can be stored (or sent to another thread) and used to
+    /// settle the promise at a later time, allowing host functions to return
+    /// asynchronous results to scripts.
     pub fn new(_guard: &ContextGuard) -> (Self, Executor) {
         let mut reference = JsValueRef::new();
         let mut resolve = JsValueRef::new();
@@ -43,6 +56,76 @@ impl Promise {
         }
     }
 
+    /// Creates a promise that is already fulfilled with a value.
+    pub fn resolved(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        let (promise, executor) = Self::new(guard);
+        executor.resolve(guard, &[value]).map(|_| promise)
+    }
+
+    /// Creates a promise that is already rejected with a reason.
+    pub fn rejected(guard: &ContextGuard, reason: &Value) -> Result<Self> {
+        let (promise, executor) = Self::new(guard);
+        executor.reject(guard, &[reason]).map(|_| promise)
+    }
+
+    /// Converts the promise into a `Future` which resolves once the promise is
+    /// settled.
+    ///
+    /// The future does not drive the promise by itself. The promise's
+    /// reactions are executed as part of the context's task queue, therefore
+    /// `execute_tasks` must be called for the future to make any progress.
+    pub fn into_future(self, guard: &ContextGuard) -> Result<PromiseFuture> {
+        let state = Arc::new(Mutex::new(FutureState { result: None, waker: None }));
+        let fulfilled = Self::reaction(guard, state.clone(), Ok);
+        let rejected = Self::reaction(guard, state.clone(), Err);
+        self.then(guard, &fulfilled, &rejected).map(|_| PromiseFuture { state: state })
+    }
+
+    /// Returns a promise that is rejected with a `TimeoutError` unless this
+    /// promise settles within the specified duration.
+    ///
+    /// The timeout is triggered by `execute_tasks`, so it must be called
+    /// regularly for the deadline to be enforced.
+    pub fn with_timeout(&self, guard: &ContextGuard, duration: Duration) -> Result<Promise> {
+        let (timeout, executor) = Self::new(guard);
+        let executor = Mutex::new(Some(executor));
+        guard.schedule(duration, Function::new(guard, Box::new(move |guard, _| {
+            if let Some(executor) = executor.lock().unwrap().take() {
+                let error = super::Error::new(guard, "Promise timed out");
+                error.set(guard, &Property::new(guard, "name"), &super::String::new(guard, "TimeoutError"));
+                executor.reject(guard, &[&error]).map_err(|error| {
+                    Value::from(super::Error::new(guard, &error.to_string()))
+                })?;
+            }
+            Ok(super::undefined(guard))
+        })));
+
+        let promises = [Value::from(self.clone()), Value::from(timeout)].to_js(guard);
+        util::jsfunc(guard, "Promise")
+            .expect("retrieving Promise constructor")
+            .call_method(guard, "race", &[&promises])
+            .map(|result| result.into_promise().expect("converting race result to promise"))
+    }
+
+    /// Returns a promise for an operation that is retried upon rejection.
+    ///
+    /// The factory is invoked to start each attempt. If the attempt's promise
+    /// is rejected, a new attempt is scheduled according to the policy. Once
+    /// all attempts have failed, the returned promise is rejected with the
+    /// reason of the last attempt. Retries are executed by `execute_tasks`.
+    pub fn retrying(guard: &ContextGuard,
+                    factory: Box<PromiseFactory>,
+                    policy: RetryPolicy) -> Promise {
+        let (promise, executor) = Self::new(guard);
+        let retry = Arc::new(Retry {
+            factory: Mutex::new(factory),
+            policy: policy,
+            executor: Mutex::new(Some(executor)),
+        });
+        retry.attempt(guard, 0);
+        promise
+    }
+
     /// Returns true if the value is a `Promise`.
     pub fn is_same(value: &Value) -> bool {
         // See: https://github.com/Microsoft/ChakraCore/issues/135
@@ -57,6 +140,161 @@ impl Promise {
             .expect("missing associated context for Promise comparison")
         })
     }
+
+    /// Attaches fulfillment and rejection reactions to the promise.
+    fn then(&self, guard: &ContextGuard, fulfilled: &Function, rejected: &Function) -> Result<()> {
+        self.call_method(guard, "then", &[fulfilled, rejected]).map(|_| ())
+    }
+
+    /// Creates a reaction function that settles a future's state.
+    fn reaction(guard: &ContextGuard,
+                state: Arc<Mutex<FutureState>>,
+                settle: fn(Value) -> PromiseResult) -> Function {
+        Function::new(guard, Box::new(move |guard, info| {
+            let value = info.arguments.into_iter()
+                .next()
+                .unwrap_or_else(|| super::undefined(guard));
+
+            let mut state = state.lock().unwrap();
+            state.result = Some(settle(value));
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            Ok(super::undefined(guard))
+        }))
+    }
+}
+
+/// Callback type for creating the promise of an operation.
+pub type PromiseFactory = Fn(&ContextGuard) -> Result<Promise> + Send;
+
+/// A policy for retrying rejected operations.
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub attempts: u32,
+    /// The delay before the first retry.
+    pub delay: Duration,
+    /// The factor by which the delay is multiplied after each retry.
+    ///
+    /// The resulting delay saturates at one day.
+    pub backoff: u32,
+}
+
+/// The longest delay between retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(100),
+            backoff: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the retry following an attempt.
+    fn delay_after(&self, attempt: u32) -> Duration {
+        self.backoff.checked_pow(attempt)
+            .and_then(|factor| self.delay.checked_mul(factor))
+            .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+    }
+}
+
+/// The state of a retried operation.
+struct Retry {
+    factory: Mutex<Box<PromiseFactory>>,
+    policy: RetryPolicy,
+    executor: Mutex<Option<Executor>>,
+}
+
+impl Retry {
+    /// Starts an attempt of the operation.
+    fn attempt(self: &Arc<Self>, guard: &ContextGuard, attempt: u32) {
+        let result = (self.factory.lock().unwrap())(guard);
+        let promise = match result {
+            Ok(promise) => promise,
+            Err(error) => {
+                let reason = super::Error::new(guard, &error.to_string());
+                return self.retry(guard, attempt, reason.into());
+            },
+        };
+
+        let retry = self.clone();
+        let fulfilled = Function::new(guard, Box::new(move |guard, info| {
+            let value = info.arguments.get(0).cloned().unwrap_or_else(|| super::undefined(guard));
+            retry.settle(guard, Ok(value));
+            Ok(super::undefined(guard))
+        }));
+
+        let retry = self.clone();
+        let rejected = Function::new(guard, Box::new(move |guard, info| {
+            let reason = info.arguments.get(0).cloned().unwrap_or_else(|| super::undefined(guard));
+            retry.retry(guard, attempt, reason);
+            Ok(super::undefined(guard))
+        }));
+
+        if let Err(error) = promise.then(guard, &fulfilled, &rejected) {
+            let reason = super::Error::new(guard, &error.to_string());
+            self.settle(guard, Err(reason.into()));
+        }
+    }
+
+    /// Schedules another attempt, or rejects the operation if there are none left.
+    fn retry(self: &Arc<Self>, guard: &ContextGuard, attempt: u32, reason: Value) {
+        let next = attempt + 1;
+        if next >= self.policy.attempts {
+            return self.settle(guard, Err(reason));
+        }
+
+        let retry = self.clone();
+        let delay = self.policy.delay_after(attempt);
+        guard.schedule(delay, Function::new(guard, Box::new(move |guard, _| {
+            retry.attempt(guard, next);
+            Ok(super::undefined(guard))
+        })));
+    }
+
+    /// Settles the operation's promise.
+    fn settle(&self, guard: &ContextGuard, result: PromiseResult) {
+        if let Some(executor) = self.executor.lock().unwrap().take() {
+            match result {
+                Ok(value) => executor.resolve(guard, &[&value]),
+                Err(reason) => executor.reject(guard, &[&reason]),
+            }.expect("settling retried promise");
+        }
+    }
+}
+
+/// The state shared between a future and its promise reactions.
+struct FutureState {
+    result: Option<PromiseResult>,
+    waker: Option<Waker>,
+}
+
+/// A future for a JavaScript promise.
+///
+/// It resolves to `Ok` if the promise was fulfilled, and `Err` if the promise
+/// was rejected.
+pub struct PromiseFuture {
+    state: Arc<Mutex<FutureState>>,
+}
+
+impl Future for PromiseFuture {
+    type Output = PromiseResult;
+
+    fn poll(self: Pin<&mut Self>, context: &mut task::Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
 }
 
 reference!(Promise);
@@ -65,8 +303,20 @@ subtype!(Promise, Value);
 
 #[cfg(test)]
 mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::time::Duration;
     use {test, value, script, Property};
 
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) { }
+    }
+
     #[test]
     fn resolve() {
         test::run_with_context(|guard| {
@@ -89,6 +339,88 @@ mod tests {
         });
     }
 
+    #[test]
+    fn timeout() {
+        test::run_with_context(|guard| {
+            let promise = script::eval(guard, "new Promise(function() {})")
+                .unwrap()
+                .into_promise()
+                .unwrap();
+            let timed = promise.with_timeout(guard, Duration::from_millis(0)).unwrap();
+            guard.global().set(guard, &Property::new(guard, "timed"), &timed);
+
+            script::eval(guard, "var state; timed.catch(function(error) { state = error.name; })").unwrap();
+            guard.execute_tasks();
+            assert_eq!(script::eval(guard, "state").unwrap().to_string(guard), "TimeoutError");
+        });
+    }
+
+    #[test]
+    fn retrying() {
+        test::run_with_context(|guard| {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let counter = attempts.clone();
+
+            let policy = value::RetryPolicy { delay: Duration::from_millis(0), ..Default::default() };
+            let promise = value::Promise::retrying(guard, Box::new(move |guard| {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                let value = value::Number::new(guard, attempt as i32).into();
+                if attempt < 2 {
+                    value::Promise::rejected(guard, &value)
+                } else {
+                    value::Promise::resolved(guard, &value)
+                }
+            }), policy);
+            guard.global().set(guard, &Property::new(guard, "retried"), &promise);
+
+            script::eval(guard, "var result; retried.then(function(value) { result = value; })").unwrap();
+            guard.execute_tasks();
+            assert_eq!(script::eval(guard, "result").unwrap().to_integer(guard), 2);
+            assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn retry_delay_saturates() {
+        let policy = value::RetryPolicy { attempts: 100, delay: Duration::from_secs(1), backoff: 10 };
+        assert_eq!(policy.delay_after(0), Duration::from_secs(1));
+        assert_eq!(policy.delay_after(2), Duration::from_secs(100));
+        assert_eq!(policy.delay_after(20), Duration::from_secs(24 * 60 * 60));
+        assert_eq!(policy.delay_after(99), Duration::from_secs(24 * 60 * 60));
+    }
+
+    #[test]
+    fn host_function() {
+        test::run_with_context(|guard| {
+            let executors = Arc::new(Mutex::new(Vec::new()));
+            let pending = executors.clone();
+
+            let function = value::Function::new(guard, Box::new(move |guard, _| {
+                let (promise, executor) = value::Promise::new(guard);
+                pending.lock().unwrap().push(executor);
+                Ok(promise.into())
+            }));
+            guard.global().set(guard, &Property::new(guard, "delayed"), &function);
+
+            let result = script::eval(guard, "
+                var result = {};
+                delayed().then(value => result.val = value, reason => result.err = reason);
+                delayed().then(value => result.val = value, reason => result.err = reason);
+                result")
+                .unwrap()
+                .into_object()
+                .unwrap();
+
+            let mut executors = executors.lock().unwrap();
+            executors.remove(0).resolve(guard, &[&value::Number::new(guard, 10)]).unwrap();
+            executors.remove(0).reject(guard, &[&value::String::new(guard, "foo")]).unwrap();
+            guard.execute_tasks();
+
+            assert_eq!(result.get(guard, &Property::new(guard, "val")).to_integer(guard), 10);
+            assert_eq!(result.get(guard, &Property::new(guard, "err")).to_string(guard), "foo");
+        });
+    }
+
     #[test]
     fn conversion() {
         test::run_with_context(|guard| {
@@ -102,4 +434,39 @@ mod tests {
             assert!(promise.is_some());
         });
     }
+
+    #[test]
+    fn future() {
+        test::run_with_context(|guard| {
+            let waker = Waker::from(Arc::new(NoopWaker));
+            let mut context = Context::from_waker(&waker);
+
+            let mut future = script::eval(guard, "Promise.resolve(5).then(val => val * 2)")
+                .unwrap()
+                .into_promise()
+                .unwrap()
+                .into_future(guard)
+                .unwrap();
+            assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+
+            guard.execute_tasks();
+            match Pin::new(&mut future).poll(&mut context) {
+                Poll::Ready(Ok(value)) => assert_eq!(value.to_integer(guard), 10),
+                _ => panic!("promise was not fulfilled"),
+            }
+
+            let mut future = script::eval(guard, "Promise.reject('foo')")
+                .unwrap()
+                .into_promise()
+                .unwrap()
+                .into_future(guard)
+                .unwrap();
+
+            guard.execute_tasks();
+            match Pin::new(&mut future).poll(&mut context) {
+                Poll::Ready(Err(reason)) => assert_eq!(reason.to_string(guard), "foo"),
+                _ => panic!("promise was not rejected"),
+            }
+        });
+    }
 }
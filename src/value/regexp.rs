@@ -0,0 +1,140 @@
+//! A JavaScript regular expression.
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use Property;
+use super::{Value, Object, Function};
+
+/// A JavaScript `RegExp`.
+///
+/// This uses the engine's regular expression implementation, so patterns
+/// behave exactly as they do in scripts.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let regexp = js::value::RegExp::new(&guard, r"(\d+)-(\d+)?", "").unwrap();
+/// assert!(regexp.test(&guard, "page 10-").unwrap());
+///
+/// let found = regexp.exec(&guard, "page 10-").unwrap().unwrap();
+/// assert_eq!(found.index, 5);
+/// assert_eq!(found.captures, [Some("10-".to_string()), Some("10".to_string()), None]);
+/// ```
+#[repr(transparent)]
+pub struct RegExp(JsValueRef);
+
+/// A match of a regular expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegExpMatch {
+    /// The index of the match in the input (in UTF-16 code units).
+    pub index: usize,
+    /// The matched text followed by the capture groups, which are `None` if
+    /// they did not participate in the match.
+    pub captures: Vec<Option<String>>,
+}
+
+impl RegExp {
+    /// Creates a regular expression from a pattern and flags (e.g `gi`).
+    ///
+    /// An error is returned if either of them is invalid.
+    pub fn new(guard: &ContextGuard, pattern: &str, flags: &str) -> Result<Self> {
+        let pattern = super::String::new(guard, pattern).into();
+        let flags = super::String::new(guard, flags).into();
+        let builtins = guard.builtins();
+        let regexp = super::builtins::required(builtins.regexp(), "RegExp")?.construct(guard, &[&pattern, &flags])?;
+        Ok(Self::from_value(guard, regexp).expect("constructing RegExp"))
+    }
+
+    /// Represents a value as a `RegExp`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a `RegExp` (of the guard's context).
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        match (value.clone().into_object(), builtins.regexp()) {
+            (Some(object), Some(regexp)) => object.instance_of(guard, &regexp),
+            _ => false,
+        }
+    }
+
+    /// Searches for a match in a string.
+    ///
+    /// Like `RegExp.prototype.exec`, this starts at the `lastIndex` of global
+    /// or sticky expressions, and updates it.
+    pub fn exec(&self, guard: &ContextGuard, input: &str) -> Result<Option<RegExpMatch>> {
+        let result = self.call(guard, |functions| (functions.regexp_exec(), "RegExp.prototype.exec"), input)?;
+        Ok(result.into_array().map(|array| RegExpMatch {
+            index: array.get(guard, &Property::new(guard, "index")).to_integer(guard) as usize,
+            captures: array.iter(guard)
+                .map(|capture| if capture.is_undefined() { None } else { Some(capture.to_string(guard)) })
+                .collect(),
+        }))
+    }
+
+    /// Returns whether the expression matches a string.
+    pub fn test(&self, guard: &ContextGuard, input: &str) -> Result<bool> {
+        self.call(guard, |functions| (functions.regexp_test(), "RegExp.prototype.test"), input).map(|result| result.to_bool(guard))
+    }
+
+    /// Returns the expression's pattern.
+    pub fn source(&self, guard: &ContextGuard) -> String {
+        let builtins = guard.builtins();
+        builtins.regexp_source()
+            .and_then(|source| source.call_with_this(guard, self, &[]).ok())
+            .map(|source| source.to_string(guard))
+            .expect("retrieving RegExp source")
+    }
+
+    /// Returns the expression's flags, in canonical order.
+    pub fn flags(&self, guard: &ContextGuard) -> String {
+        let builtins = guard.builtins();
+        builtins.regexp_flags()
+            .and_then(|flags| flags.call_with_this(guard, self, &[]).ok())
+            .map(|flags| flags.to_string(guard))
+            .expect("retrieving RegExp flags")
+    }
+
+    /// Calls one of the cached prototype methods, with the expression as
+    /// `this` and a string as argument.
+    fn call<F>(&self, guard: &ContextGuard, method: F, input: &str) -> Result<Value>
+            where F: FnOnce(&super::Builtins) -> (Option<Function>, &'static str) {
+        let input = super::String::new(guard, input).into();
+        let builtins = guard.builtins();
+        let (function, name) = method(&builtins);
+        super::builtins::required(function, name)?.call_with_this(guard, self, &[&input])
+    }
+}
+
+reference!(RegExp);
+inherit!(RegExp, Object);
+subtype!(RegExp, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+
+    #[test]
+    fn regexp() {
+        test::run_with_context(|guard| {
+            let regexp = value::RegExp::new(guard, "a(b)?", "gi").unwrap();
+            assert_eq!((regexp.source(guard), regexp.flags(guard)), ("a(b)?".to_string(), "gi".to_string()));
+
+            let first = regexp.exec(guard, "xAb a").unwrap().unwrap();
+            assert_eq!((first.index, first.captures), (1, vec![Some("Ab".to_string()), Some("b".to_string())]));
+            let second = regexp.exec(guard, "xAb a").unwrap().unwrap();
+            assert_eq!((second.index, second.captures), (4, vec![Some("a".to_string()), None]));
+            assert_eq!(regexp.exec(guard, "xAb a").unwrap(), None);
+            assert!(value::RegExp::new(guard, "(", "").is_err());
+
+            let result = script::eval(guard, "/\\d+/").unwrap();
+            assert!(value::RegExp::is_same(guard, &result));
+            let regexp = value::RegExp::from_value(guard, result).unwrap();
+            assert!(regexp.test(guard, "42").unwrap() && !regexp.test(guard, "x").unwrap());
+            assert!(value::RegExp::from_value(guard, script::eval(guard, "'/a/'").unwrap()).is_none());
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! A registry of conversions for foreign types.
+//!
+//! The conversion traits cannot be implemented for types of other crates
+//! (e.g `uuid::Uuid`), unless one of the crates depends on the other. Instead,
+//! binding crates can register converters for such types at runtime, and
+//! values are converted by wrapping them in `Foreign`.
+//!
+//! The wrapper implements `ToJs` and `FromJs`, so it can be used by typed
+//! host functions. With the `serde` feature, it also implements `Serialize`
+//! and `Deserialize`, which are understood by the `serde` module (other
+//! serializers are not supported).
+//!
+//! Converters are shared by all threads (and contexts), and registering a
+//! type again replaces its converter.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # use js::value::{registry, Foreign, FromJs, ToJs};
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! // A type of another crate, represented as a string
+//! #[derive(Debug, PartialEq)]
+//! struct Uuid(u128);
+//!
+//! registry::register(
+//!     |guard, uuid: &Uuid| format!("{:032x}", uuid.0).to_js(guard),
+//!     |guard, value| {
+//!         let string = String::from_js(guard, value)?;
+//!         u128::from_str_radix(&string, 16).map(Uuid).map_err(|error| error.to_string().into())
+//!     });
+//!
+//! let next = js::value::Function::typed(&guard, "next", |_, (uuid,): (Foreign<Uuid>,)| {
+//!     Ok(Foreign(Uuid(uuid.0 .0 + 1)))
+//! });
+//!
+//! let result = next.call(&guard, &[&Foreign(Uuid(9)).to_js(&guard)]).unwrap();
+//! assert_eq!(result.to_string(&guard), "0000000000000000000000000000000a");
+//! assert_eq!(registry::from_js::<Uuid>(&guard, &result).unwrap(), Uuid(10));
+//! ```
+use std::any::{self, Any, TypeId};
+#[cfg(feature = "serde")]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use context::ContextGuard;
+use error::*;
+use super::{FromJs, ToJs, Value};
+
+/// A converter of a registered type to a JavaScript value.
+type IntoJs = Fn(&ContextGuard, &Any) -> Value + Send + Sync;
+
+/// A converter of a JavaScript value to a registered type.
+type FromValue = Fn(&ContextGuard, &Value) -> Result<Box<Any>> + Send + Sync;
+
+/// The conversions of a registered type.
+struct Converter {
+    to_js: Box<IntoJs>,
+    from_js: Box<FromValue>,
+}
+
+/// The registered converters, created once the first type is registered.
+static REGISTRY: Mutex<Option<HashMap<TypeId, Arc<Converter>>>> = Mutex::new(None);
+
+/// The newtype name marking foreign values in serde's data model.
+#[cfg(feature = "serde")]
+pub(crate) const TOKEN: &str = "$chakracore::Foreign";
+
+#[cfg(feature = "serde")]
+thread_local! {
+    /// A foreign value passed between the serde module and `Foreign`.
+    pub(crate) static SLOT: RefCell<Option<Value>> = RefCell::new(None);
+}
+
+/// Registers the conversions of a type, replacing any previous ones.
+pub fn register<T, F, G>(to_js: F, from_js: G)
+        where T: Any,
+              F: Fn(&ContextGuard, &T) -> Value + Send + Sync + 'static,
+              G: Fn(&ContextGuard, &Value) -> Result<T> + Send + Sync + 'static {
+    let converter = Converter {
+        to_js: Box::new(move |guard, value| {
+            to_js(guard, value.downcast_ref::<T>().expect("downcasting registered type"))
+        }),
+        from_js: Box::new(move |guard, value| from_js(guard, value).map(|value| Box::new(value) as Box<Any>)),
+    };
+
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.get_or_insert_with(HashMap::new).insert(TypeId::of::<T>(), Arc::new(converter));
+}
+
+/// Returns whether the conversions of a type have been registered.
+pub fn is_registered<T: Any>() -> bool {
+    converter::<T>().is_ok()
+}
+
+/// Converts a value of a registered type to a JavaScript value.
+pub fn to_js<T: Any>(guard: &ContextGuard, value: &T) -> Result<Value> {
+    let converter = converter::<T>()?;
+    Ok((converter.to_js)(guard, value))
+}
+
+/// Converts a JavaScript value to a registered type.
+pub fn from_js<T: Any>(guard: &ContextGuard, value: &Value) -> Result<T> {
+    let converter = converter::<T>()?;
+    (converter.from_js)(guard, value).map(|value| *value.downcast::<T>().expect("downcasting converted value"))
+}
+
+/// Returns the converter of a type, without holding the registry's lock
+/// during conversions (so converters may use the registry themselves).
+fn converter<T: Any>() -> Result<Arc<Converter>> {
+    REGISTRY.lock().unwrap()
+        .as_ref()
+        .and_then(|registry| registry.get(&TypeId::of::<T>()).cloned())
+        .ok_or_else(|| format!("no converter is registered for {}", any::type_name::<T>()).into())
+}
+
+/// A value of a foreign type, converted using the registry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Foreign<T>(pub T);
+
+/// Converts the value using its registered converter.
+///
+/// # Panics
+///
+/// Panics if the type has not been registered, since this is a programming
+/// error (use `registry::to_js` to handle it instead).
+impl<T: Any> ToJs for Foreign<T> {
+    fn to_js(&self, guard: &ContextGuard) -> Value {
+        to_js(guard, &self.0).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl<T: Any> FromJs for Foreign<T> {
+    fn from_js(guard: &ContextGuard, value: &Value) -> Result<Self> {
+        from_js(guard, value).map(Foreign)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde {
+    use std::any::{self, Any};
+    use std::fmt;
+    use std::marker::PhantomData;
+    use serde_lib::{de, ser};
+    use Context;
+    use super::{Foreign, SLOT, TOKEN};
+
+    /// Serializes the converted value as a marked newtype, which the `serde`
+    /// module's serializer takes from the slot.
+    impl<T: Any> ser::Serialize for Foreign<T> {
+        fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let value = Context::exec_with_current(|guard| super::to_js(guard, &self.0))
+                .unwrap_or_else(|| Err("no active context".into()))
+                .map_err(ser::Error::custom)?;
+
+            SLOT.with(|slot| *slot.borrow_mut() = Some(value));
+            let result = serializer.serialize_newtype_struct(TOKEN, &());
+            SLOT.with(|slot| slot.borrow_mut().take());
+            result
+        }
+    }
+
+    /// Deserializes a marked newtype, for which the `serde` module's
+    /// deserializer puts the JavaScript value in the slot.
+    impl<'de, T: Any> de::Deserialize<'de> for Foreign<T> {
+        fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_newtype_struct(TOKEN, Visitor(PhantomData))
+        }
+    }
+
+    struct Visitor<T>(PhantomData<T>);
+
+    impl<'de, T: Any> de::Visitor<'de> for Visitor<T> {
+        type Value = Foreign<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a JavaScript value convertible to {}", any::type_name::<T>())
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            let value = SLOT.with(|slot| slot.borrow_mut().take())
+                .ok_or_else(|| E::custom("foreign values can only be deserialized from JavaScript values"))?;
+            Context::exec_with_current(|guard| super::from_js(guard, &value))
+                .unwrap_or_else(|| Err("no active context".into()))
+                .map(Foreign)
+                .map_err(E::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+    use value::{FromJs, ToJs};
+    use super::Foreign;
+
+    #[derive(Debug, PartialEq)]
+    struct Celsius(f64);
+
+    #[test]
+    fn registry() {
+        test::run_with_context(|guard| {
+            assert!(!super::is_registered::<Celsius>());
+            assert!(Foreign::<Celsius>::from_js(guard, &1.to_js(guard)).is_err());
+
+            super::register(
+                |guard, celsius: &Celsius| celsius.0.to_js(guard),
+                |guard, value| f64::from_js(guard, value).map(Celsius));
+            assert!(super::is_registered::<Celsius>());
+
+            let warm = value::Function::typed(guard, "warm", |_, (value,): (Foreign<Celsius>,)| {
+                Ok(Foreign(Celsius((value.0).0 + 1.5)))
+            });
+            guard.global().set(guard, &::Property::new(guard, "warm"), &warm);
+
+            let result = script::eval(guard, "warm(20)").unwrap();
+            assert_eq!(super::from_js::<Celsius>(guard, &result).unwrap(), Celsius(21.5));
+            assert!(script::eval(guard, "warm('cold')").is_err());
+        });
+    }
+}
@@ -0,0 +1,127 @@
+//! Value handles that can be sent between threads.
+use std::{fmt, mem, thread};
+use context::{Context, ContextGuard};
+use error::*;
+use super::{Persistent, Value};
+
+/// A rooted handle to a value, which can be moved to other threads.
+///
+/// JavaScript values can only be used on the thread of their runtime. This
+/// handle can be passed through channels or stored in thread-safe host
+/// structures, but the value is only accessible on the thread the handle was
+/// created on, with a guard of the value's context. This is verified at
+/// runtime, and any other access results in an error.
+///
+/// The value is released when the handle is dropped on its owning thread. If
+/// it is dropped on another thread, the value cannot be released safely and
+/// is leaked for the lifetime of its context.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use std::thread;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// let value = js::script::eval(&guard, "'foo'").unwrap();
+/// let shared = js::value::Shared::new(&guard, value);
+///
+/// // The handle can be sent back and forth, but only used on this thread
+/// let shared = thread::spawn(move || shared).join().unwrap();
+/// assert_eq!(shared.get(&guard).unwrap().to_string(&guard), "foo");
+/// ```
+pub struct Shared<T: Clone + Into<Value>> {
+    value: Option<Persistent<T>>,
+    thread: thread::ThreadId,
+}
+
+// The value is only accessed (and released) on its owning thread.
+unsafe impl<T: Clone + Into<Value>> Send for Shared<T> {}
+
+impl<T: Clone + Into<Value>> Shared<T> {
+    /// Creates a handle to a value of the guard's context.
+    pub fn new(guard: &ContextGuard, value: T) -> Self {
+        Shared {
+            value: Some(Persistent::new(guard, value)),
+            thread: thread::current().id(),
+        }
+    }
+
+    /// Returns the value, if used on the owning thread and context.
+    pub fn get(&self, guard: &ContextGuard) -> Result<T> {
+        if !self.is_owning_thread() {
+            return Err("shared value accessed from another thread".into());
+        }
+        self.persistent().get(guard)
+    }
+
+    /// Converts the handle to a `Persistent`, if used on the owning thread.
+    pub fn into_persistent(mut self) -> Result<Persistent<T>> {
+        if !self.is_owning_thread() {
+            return Err("shared value accessed from another thread".into());
+        }
+        Ok(self.value.take().expect("retrieving shared value"))
+    }
+
+    /// Returns the context the value belongs to.
+    ///
+    /// The context may only be used on the owning thread.
+    pub fn context(&self) -> Result<Context> {
+        if !self.is_owning_thread() {
+            return Err("shared value accessed from another thread".into());
+        }
+        Ok(self.persistent().context())
+    }
+
+    /// Returns whether the current thread owns the value.
+    pub fn is_owning_thread(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+
+    /// Returns the underlying persistent handle.
+    fn persistent(&self) -> &Persistent<T> {
+        self.value.as_ref().expect("retrieving shared value")
+    }
+}
+
+impl<T: Clone + Into<Value>> fmt::Debug for Shared<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Shared({:?})", self.thread)
+    }
+}
+
+impl<T: Clone + Into<Value>> Drop for Shared<T> {
+    /// Releases the value, unless dropped on another thread.
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            if self.is_owning_thread() {
+                drop(value);
+            } else {
+                mem::forget(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use {test, script, value};
+    use super::Shared;
+
+    #[test]
+    fn owning_thread() {
+        test::run_with_context(|guard| {
+            let array = script::eval(guard, "[1, 2]").unwrap().into_array().unwrap();
+            let shared = Shared::<value::Array>::new(guard, array);
+
+            let shared = thread::spawn(move || {
+                assert!(!shared.is_owning_thread());
+                assert!(shared.context().is_err());
+                shared
+            }).join().unwrap();
+
+            assert_eq!(shared.get(guard).unwrap().len(guard), 2);
+            assert!(shared.into_persistent().is_ok());
+        });
+    }
+}
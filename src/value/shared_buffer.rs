@@ -0,0 +1,159 @@
+//! Memory shared between runtimes.
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use util::jstry;
+use super::{Number, Object, Value};
+
+/// A JavaScript shared array buffer.
+///
+/// Shared array buffers are only available if the engine has them enabled,
+/// see `is_supported`. Their memory can be shared with other runtimes (e.g
+/// the runtimes of worker threads) by passing the buffer's `SharedContents`,
+/// and accesses can be coordinated by scripts with `Atomics`.
+///
+/// ```rust
+/// # use chakracore as js;
+/// # use std::thread;
+/// # use js::value::SharedArrayBuffer;
+/// # let runtime = js::Runtime::new().unwrap();
+/// # let context = js::Context::new(&runtime).unwrap();
+/// # let guard = context.make_current().unwrap();
+/// if SharedArrayBuffer::is_supported(&guard) {
+///     let buffer = SharedArrayBuffer::new(&guard, 4).unwrap();
+///     let contents = buffer.contents(&guard);
+///
+///     thread::spawn(move || {
+///         let runtime = js::Runtime::new().unwrap();
+///         let context = js::Context::new(&runtime).unwrap();
+///         let guard = context.make_current().unwrap();
+///
+///         let buffer = SharedArrayBuffer::from_contents(&guard, contents).unwrap();
+///         guard.global().set(&guard, &js::Property::new(&guard, "buffer"), &buffer);
+///         js::script::eval(&guard, "Atomics.store(new Int32Array(buffer), 0, 42)").unwrap();
+///     }).join().unwrap();
+///
+///     guard.global().set(&guard, &js::Property::new(&guard, "buffer"), &buffer);
+///     let result = js::script::eval(&guard, "Atomics.load(new Int32Array(buffer), 0)").unwrap();
+///     assert_eq!(result.to_integer(&guard), 42);
+/// }
+/// ```
+#[repr(transparent)]
+pub struct SharedArrayBuffer(JsValueRef);
+
+/// A handle to the memory of a shared array buffer, which can be sent to
+/// other threads.
+///
+/// The memory is reference counted, and remains valid while any buffer or
+/// handle refers to it. Releasing the handle requires an active context (of
+/// any runtime), so a handle that is not passed to `from_contents` should be
+/// dropped within a guard's scope; otherwise its reference is leaked.
+pub struct SharedContents(JsSharedArrayBufferContentHandle);
+
+// The contents are reference counted by the engine, independently of runtimes.
+unsafe impl Send for SharedContents {}
+unsafe impl Sync for SharedContents {}
+
+impl SharedArrayBuffer {
+    /// Returns whether the guard's context supports shared array buffers.
+    pub fn is_supported(guard: &ContextGuard) -> bool {
+        guard.builtins().shared_array_buffer().is_some()
+    }
+
+    /// Creates a new shared array buffer with a specified size.
+    pub fn new(guard: &ContextGuard, size: u32) -> Result<Self> {
+        let builtins = guard.builtins();
+        let constructor = builtins.shared_array_buffer().ok_or("SharedArrayBuffer is not supported")?;
+        let size = Number::from_u32(guard, size).into();
+        let result = constructor.construct(guard, &[&size])?;
+        Ok(unsafe { Self::from_raw(result.as_raw()) })
+    }
+
+    /// Creates a shared array buffer using the memory of another buffer.
+    ///
+    /// The handle is released once the buffer has been created; use
+    /// `contents` to retrieve another one.
+    pub fn from_contents(_guard: &ContextGuard, contents: SharedContents) -> Result<Self> {
+        let mut buffer = JsValueRef::new();
+        unsafe {
+            jstry(JsCreateSharedArrayBufferWithSharedContent(contents.0, &mut buffer))?;
+            Ok(Self::from_raw(buffer))
+        }
+    }
+
+    /// Represents a value as a `SharedArrayBuffer`, if it is one.
+    pub fn from_value(guard: &ContextGuard, value: Value) -> Option<Self> {
+        if Self::is_same(guard, &value) { Some(unsafe { Self::from_raw(value.as_raw()) }) } else { None }
+    }
+
+    /// Returns true if the value is a `SharedArrayBuffer`.
+    pub fn is_same(guard: &ContextGuard, value: &Value) -> bool {
+        let builtins = guard.builtins();
+        builtins.shared_array_buffer_byte_length()
+            .map_or(false, |byte_length| byte_length.call_with_this(guard, value, &[]).is_ok())
+    }
+
+    /// Returns a handle to the buffer's memory.
+    pub fn contents(&self, _guard: &ContextGuard) -> SharedContents {
+        let mut contents = JsSharedArrayBufferContentHandle::new();
+        jsassert!(unsafe { JsGetSharedArrayBufferContent(self.as_raw(), &mut contents) });
+        SharedContents(contents)
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn len(&self, guard: &ContextGuard) -> usize {
+        let builtins = guard.builtins();
+        let byte_length = builtins.shared_array_buffer_byte_length().expect("retrieving byteLength");
+        byte_length.call_with_this(guard, self, &[]).expect("retrieving byteLength").to_integer(guard) as usize
+    }
+}
+
+impl Drop for SharedContents {
+    fn drop(&mut self) {
+        unsafe { JsReleaseSharedArrayBufferContentHandle(self.0) };
+    }
+}
+
+reference!(SharedArrayBuffer);
+inherit!(SharedArrayBuffer, Object);
+subtype!(SharedArrayBuffer, Value);
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use {test, script, value, Context, Property, Runtime};
+    use super::SharedArrayBuffer;
+
+    #[test]
+    fn contents() {
+        test::run_with_context(|guard| {
+            if !SharedArrayBuffer::is_supported(guard) {
+                assert!(SharedArrayBuffer::new(guard, 8).is_err());
+                return;
+            }
+
+            let result = script::eval(guard, "new SharedArrayBuffer(8)").unwrap();
+            assert_eq!(result.value_type(guard), value::ValueType::SharedArrayBuffer);
+            assert!(!SharedArrayBuffer::is_same(guard, &script::eval(guard, "new ArrayBuffer(8)").unwrap()));
+
+            let buffer = SharedArrayBuffer::from_value(guard, result).unwrap();
+            assert_eq!(buffer.len(guard), 8);
+            let contents = buffer.contents(guard);
+
+            let length = thread::spawn(move || {
+                let runtime = Runtime::new().unwrap();
+                let context = Context::new(&runtime).unwrap();
+                context.exec_with(|guard| {
+                    let buffer = SharedArrayBuffer::from_contents(guard, contents).unwrap();
+                    guard.global().set(guard, &Property::new(guard, "buffer"), &buffer);
+                    script::eval(guard, "new Uint8Array(buffer)[7] = 255").unwrap();
+                    buffer.len(guard)
+                }).unwrap()
+            }).join().unwrap();
+
+            assert_eq!(length, 8);
+            guard.global().set(guard, &Property::new(guard, "buffer"), &buffer);
+            assert_eq!(script::eval(guard, "new Uint8Array(buffer)[7]").unwrap().to_integer(guard), 255);
+        });
+    }
+}
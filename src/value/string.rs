@@ -1,8 +1,11 @@
+use std::cmp;
+use std::ops::Range;
 use chakracore_sys::*;
 use context::ContextGuard;
 use super::Value;
 
 /// A JavaScript string.
+#[repr(transparent)]
 pub struct String(JsValueRef);
 
 impl String {
@@ -15,20 +18,105 @@ impl String {
         }
     }
 
-    /// Returns the length of the string.
+    /// Creates a string value from UTF-16 code units.
+    ///
+    /// Unlike `new`, this can represent any JavaScript string, including ones
+    /// with unpaired surrogates.
+    pub fn from_utf16(_guard: &ContextGuard, string: &[u16]) -> Self {
+        let mut value = JsValueRef::new();
+        unsafe {
+            jsassert!(JsCreateStringUtf16(string.as_ptr(), string.len(), &mut value));
+            Self::from_raw(value)
+        }
+    }
+
+    /// Returns the length of the string, in UTF-16 code units (i.e the value
+    /// of its `length` property).
     pub fn len(&self) -> usize {
         let mut length = 0;
         jsassert!(unsafe { JsGetStringLength(self.as_raw(), &mut length) });
         length as usize
     }
 
+    /// Returns true if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Converts a JavaScript string to a native string.
+    ///
+    /// Unpaired surrogates cannot be represented in UTF-8, so they are lost in
+    /// the conversion; use `to_utf16` to preserve them.
     pub fn value(&self) -> ::std::string::String {
         ::util::to_string_impl(self.as_raw(), JsCopyString).expect("converting string to native")
     }
 
+    /// Copies the string's UTF-16 code units.
+    pub fn to_utf16(&self, guard: &ContextGuard) -> Vec<u16> {
+        self.slice_utf16(guard, 0..self.len())
+    }
+
+    /// Copies a range of the string's UTF-16 code units, without copying the
+    /// rest of the string. The range is clamped to the string's length.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let string = js::value::String::new(&guard, "h\u{e9}llo");
+    /// assert_eq!(string.slice_utf16(&guard, 1..3), [0xE9, 'l' as u16]);
+    /// assert!(string.slice_utf16(&guard, 4..10).len() == 1);
+    /// ```
+    pub fn slice_utf16(&self, _guard: &ContextGuard, range: Range<usize>) -> Vec<u16> {
+        let end = cmp::min(range.end, self.len());
+        let start = cmp::min(range.start, end);
+
+        let mut buffer = vec![0u16; end - start];
+        let mut written = 0;
+        jsassert!(unsafe {
+            JsCopyStringUtf16(self.as_raw(), start as _, buffer.len() as _, buffer.as_mut_ptr(), &mut written)
+        });
+        buffer.truncate(written);
+        buffer
+    }
+
+    /// Borrows the string's UTF-16 code units, without copying them.
+    ///
+    /// This is only supported by the Windows API of the engine.
+    #[cfg(windows)]
+    pub fn as_utf16(&self, _guard: &ContextGuard) -> &[u16] {
+        let mut data = ::std::ptr::null();
+        let mut length = 0;
+        unsafe {
+            jsassert!(JsStringToPointer(self.as_raw(), &mut data, &mut length));
+            if length == 0 { &[] } else { ::std::slice::from_raw_parts(data as *const u16, length) }
+        }
+    }
+
     is_same!(String, "Returns true if the value is a `String`.");
 }
 
 reference!(String);
 inherit!(String, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, script, value};
+
+    #[test]
+    fn utf16() {
+        test::run_with_context(|guard| {
+            let units = [0x61, 0xD83D, 0x62];
+            let string = value::String::from_utf16(guard, &units);
+            assert_eq!(string.len(), 3);
+            assert_eq!(string.to_utf16(guard), units);
+
+            let result = script::eval(guard, "'\\uD83D\\uDE00'.charCodeAt(1)").unwrap();
+            assert_eq!(result.to_integer(guard), 0xDE00);
+            let emoji = script::eval(guard, "'\\uD83D\\uDE00!'").unwrap().into_string().unwrap();
+            assert_eq!(emoji.slice_utf16(guard, 1..2), [0xDE00]);
+            assert!(value::String::new(guard, "").is_empty());
+        });
+    }
+}
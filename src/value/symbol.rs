@@ -0,0 +1,75 @@
+use boolinator::Boolinator;
+use chakracore_sys::*;
+use context::ContextGuard;
+use error::*;
+use super::Value;
+use {util, Property};
+
+/// A JavaScript symbol.
+///
+/// Symbols can be used as property keys, see `Property::from_symbol`.
+#[repr(transparent)]
+pub struct Symbol(JsValueRef);
+
+impl Symbol {
+    /// Creates a new unique symbol with a description.
+    pub fn new(guard: &ContextGuard, description: &str) -> Self {
+        let description = super::String::new(guard, description);
+        let mut value = JsValueRef::new();
+        unsafe {
+            jsassert!(JsCreateSymbol(description.as_raw(), &mut value));
+            Self::from_raw(value)
+        }
+    }
+
+    /// Returns a well-known symbol (e.g `iterator` for `Symbol.iterator`).
+    pub fn well_known(guard: &ContextGuard, name: &str) -> Result<Self> {
+        util::jsfunc(guard, "Symbol")
+            .expect("retrieving Symbol function")
+            .get(guard, &Property::new(guard, name))
+            .into_symbol()
+            .ok_or_else(|| format!("'Symbol.{}' is not a well-known symbol", name).into())
+    }
+
+    /// Returns the symbol associated with a property identifier, if any.
+    pub fn from_property(_guard: &ContextGuard, property: &Property) -> Option<Self> {
+        let mut value = JsValueRef::new();
+        unsafe {
+            (JsGetSymbolFromPropertyId(property.as_raw(), &mut value) == JsErrorCode::NoError)
+                .as_some_from(|| Self::from_raw(value))
+        }
+    }
+
+    is_same!(Symbol, "Returns true if the value is a `Symbol`.");
+}
+
+reference!(Symbol);
+inherit!(Symbol, Value);
+
+#[cfg(test)]
+mod tests {
+    use {test, value, script, Property};
+
+    #[test]
+    fn symbol_properties() {
+        test::run_with_context(|guard| {
+            let symbol = value::Symbol::new(guard, "foo");
+            let property = Property::from_symbol(guard, &symbol);
+            assert!(property.is_symbol(guard));
+            assert_eq!(property.to_string(guard), "Symbol(foo)");
+            assert!(value::Symbol::from_property(guard, &property).unwrap().strict_equals(guard, &symbol));
+            assert!(value::Symbol::from_property(guard, &Property::new(guard, "foo")).is_none());
+
+            let object = value::Object::new(guard);
+            object.set(guard, &property, &value::Number::new(guard, 5));
+            assert_eq!(object.get(guard, &property).to_integer(guard), 5);
+            assert!(!object.has(guard, &Property::new(guard, "foo")));
+            assert_eq!(object.get_own_property_symbols(guard).len(guard), 1);
+
+            let iterator = value::Symbol::well_known(guard, "iterator").unwrap();
+            let iterable = script::eval(guard, "[1, 2]").unwrap().into_object().unwrap();
+            assert!(iterable.get(guard, &Property::from_symbol(guard, &iterator)).is_function());
+            assert!(value::Symbol::well_known(guard, "foo").is_err());
+        });
+    }
+}
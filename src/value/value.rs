@@ -2,7 +2,8 @@ use std::{fmt, mem};
 use chakracore_sys::*;
 use context::{Context, ContextGuard};
 use error::*;
-use {util, value};
+use util::jstry;
+use value;
 
 macro_rules! downcast {
     ($predicate:ident, $predicate_doc:expr, $target:ident) => {
@@ -27,6 +28,19 @@ macro_rules! downcast {
     };
 }
 
+macro_rules! refcast {
+    ($name:ident, $name_doc:expr, $predicate:ident, $result:ident) => {
+        #[doc=$name_doc]
+        pub fn $name(&self) -> Option<&super::$result> {
+            if self.$predicate() {
+                Some(unsafe { &*(self as *const Value as *const super::$result) })
+            } else {
+                None
+            }
+        }
+    }
+}
+
 macro_rules! nativecast {
     ($name:ident, $name_doc:expr, $result:ident, $into:ident, $represent:ident, $native:ident) => {
         #[doc=$name_doc]
@@ -66,13 +80,17 @@ macro_rules! representation {
 /// created with).
 ///
 /// Do not get intimidated by all the conversion methods. They are very easy to
-/// grok — there are three different types:
+/// grok — there are four different types:
 ///
 /// > `into_*`
 /// >> These do not modify any data. They only check the type of the
 /// underlying value. If the value is the designated type (e.g `Object`), the
 /// underlying pointer is copied and returned wrapped as the specific type.
 ///
+/// > `as_*`
+/// >> These are the borrowing equivalents of `into_*`, returning a reference
+/// to the value as the specific type (so it does not need to be cloned).
+///
 /// > `*_representation`
 /// >> These create a new value, by casting to a specific type using JavaScript
 /// semantics. For example; calling `number_representation` on an `Object`
@@ -85,8 +103,41 @@ macro_rules! representation {
 /// [*_representation()] -> value()`. A call to `*_representation` is only
 /// performed if required (i.e a string is not redundantly converted to a
 /// string).
+#[repr(transparent)]
 pub struct Value(JsValueRef);
 
+/// The type of a JavaScript value, distinguishing built-in object types.
+///
+/// In contrast to `JsValueType`, this tells objects such as dates and maps
+/// apart from plain objects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Undefined,
+    Null,
+    Number,
+    String,
+    Boolean,
+    Symbol,
+    /// Only produced if the engine supports BigInts.
+    BigInt,
+    /// Any object not covered by the other types.
+    Object,
+    Function,
+    Error,
+    Array,
+    ArrayBuffer,
+    /// Only produced if the engine has shared array buffers enabled.
+    SharedArrayBuffer,
+    TypedArray,
+    DataView,
+    Date,
+    RegExp,
+    Map,
+    Set,
+    Promise,
+    External,
+}
+
 impl Value {
     // Transforms a value to another custom type
     downcast!(is_undefined,
@@ -103,6 +154,11 @@ impl Value {
               into_string,
               "Represent the value as a `String`. Does not affect the underlying value.",
               String);
+    downcast!(is_symbol,
+              "Returns true if this value is a `Symbol`.",
+              into_symbol,
+              "Represent the value as a `Symbol`. Does not affect the underlying value.",
+              Symbol);
     downcast!(is_boolean,
               "Returns true if this value is a `Boolean`.",
               into_boolean,
@@ -123,6 +179,11 @@ impl Value {
               into_function,
               "Represent the value as a `Function`. Does not affect the underlying value.",
               Function);
+    downcast!(is_error,
+              "Returns true if this value is an `Error`.",
+              into_error,
+              "Represent the value as an `Error`. Does not affect the underlying value.",
+              Error);
     downcast!(is_array,
               "Returns true if this value is an `Array`.",
               into_array,
@@ -133,12 +194,31 @@ impl Value {
               into_array_buffer,
               "Represent the value as an `ArrayBuffer`. Does not affect the underlying value.",
               ArrayBuffer);
+    downcast!(is_data_view,
+              "Returns true if this value is a `DataView`.",
+              into_data_view,
+              "Represent the value as a `DataView`. Does not affect the underlying value.",
+              DataView);
     downcast!(is_promise,
               "Returns true if this value is a `Promise`.",
               into_promise,
               "Represent the value as a `Promise`. Does not affect the underlying value.",
               Promise);
 
+    // Borrows a value as another custom type
+    refcast!(as_number, "Borrows the value as a `Number`, if it is one.", is_number, Number);
+    refcast!(as_string, "Borrows the value as a `String`, if it is one.", is_string, String);
+    refcast!(as_symbol, "Borrows the value as a `Symbol`, if it is one.", is_symbol, Symbol);
+    refcast!(as_boolean, "Borrows the value as a `Boolean`, if it is one.", is_boolean, Boolean);
+    refcast!(as_object, "Borrows the value as an `Object`, if it is one.", is_object, Object);
+    refcast!(as_external, "Borrows the value as an `External`, if it is one.", is_external, External);
+    refcast!(as_function, "Borrows the value as a `Function`, if it is one.", is_function, Function);
+    refcast!(as_error, "Borrows the value as an `Error`, if it is one.", is_error, Error);
+    refcast!(as_array, "Borrows the value as an `Array`, if it is one.", is_array, Array);
+    refcast!(as_array_buffer, "Borrows the value as an `ArrayBuffer`, if it is one.", is_array_buffer, ArrayBuffer);
+    refcast!(as_data_view, "Borrows the value as a `DataView`, if it is one.", is_data_view, DataView);
+    refcast!(as_promise, "Borrows the value as a `Promise`, if it is one.", is_promise, Promise);
+
     // Converts a value to a native type
     nativecast!(to_string,
                 "Converts the value to a native string, containing the value's string representation.",
@@ -165,20 +245,38 @@ impl Value {
                 boolean_representation,
                 value);
 
+    /// Converts the value to a native string, returning an error if the
+    /// conversion throws (e.g an object's `toString` method).
+    pub fn try_to_string(&self, _guard: &ContextGuard) -> Result<String> {
+        if let Some(string) = self.as_string() {
+            return Ok(string.value());
+        }
+
+        let mut value = JsValueRef::new();
+        jstry(unsafe { JsConvertValueToString(self.as_raw(), &mut value) })?;
+        Ok(unsafe { value::String::from_raw(value) }.value())
+    }
+
+    /// Converts the value to a native double, returning an error if the
+    /// conversion throws (e.g an object's `valueOf` method).
+    pub fn try_to_double(&self, _guard: &ContextGuard) -> Result<f64> {
+        if let Some(number) = self.as_number() {
+            return Ok(number.value_double());
+        }
+
+        let mut value = JsValueRef::new();
+        jstry(unsafe { JsConvertValueToNumber(self.as_raw(), &mut value) })?;
+        Ok(unsafe { value::Number::from_raw(value) }.value_double())
+    }
+
     /// Converts the value to a native string, containing the value's JSON representation.
     pub fn to_json(&self, guard: &ContextGuard) -> Result<String> {
-        // TODO: Use native functionality when implemented
-        let stringify = util::jsfunc(guard, "JSON.stringify")
-            .expect("retrieving JSON.stringify function");
-        stringify.call(guard, &[self]).map(|v| v.to_string(guard))
+        guard.json()?.stringify.call(guard, &[self]).map(|v| v.to_string(guard))
     }
 
     /// Parses JSON and returns it represented as a JavaScript value.
     pub fn from_json(guard: &ContextGuard, json: &str) -> Result<Value> {
-        let parse = util::jsfunc(guard, "JSON.parse")
-            .expect("retrieving JSON.parse function");
-        let json = value::String::new(guard, json);
-        parse.call(guard, &[&json])
+        guard.json_parse(json)
     }
 
     // Casts a value to the JavaScript expression of another type
@@ -199,6 +297,41 @@ impl Value {
                     String,
                     JsConvertValueToString);
 
+    /// Returns true if this value is a `Date` (of the guard's context).
+    ///
+    /// Unlike the other type checks, this requires a guard since ChakraCore
+    /// does not distinguish dates from other objects.
+    pub fn is_date(&self, guard: &ContextGuard) -> bool {
+        value::Date::is_same(guard, self)
+    }
+
+    /// Exports the value as a context independent, deep copy.
+    ///
+    /// The exported value is immutable, and can be shared between threads.
+    /// Functions, external objects and cyclic structures cannot be exported.
+    pub fn export(&self, guard: &ContextGuard) -> Result<value::ExportedValue> {
+        value::ExportedValue::read(guard, self)
+    }
+
+    /// Converts the value to a native type, without JavaScript semantics.
+    ///
+    /// This is equivalent to `T::from_js`, but reads better when unpacking
+    /// arguments, since the type can often be inferred.
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let value = js::script::eval(&guard, "20 + 22").unwrap();
+    /// let number: i32 = value.convert(&guard).unwrap();
+    /// assert_eq!(number, 42);
+    /// assert!(value.convert::<String>(&guard).is_err());
+    /// ```
+    pub fn convert<T: value::FromJs>(&self, guard: &ContextGuard) -> Result<T> {
+        T::from_js(guard, self)
+    }
+
     /// Returns the type of the value. This method should be used with
     /// consideration. It does not keep track of custom types, such as
     /// `External`. It only returns the runtime's definition of a type.
@@ -208,6 +341,60 @@ impl Value {
         value_type
     }
 
+    /// Returns the type of the value, including built-in object types (of the
+    /// guard's context).
+    ///
+    /// ```rust
+    /// # use chakracore as js;
+    /// # use js::value::ValueType;
+    /// # let runtime = js::Runtime::new().unwrap();
+    /// # let context = js::Context::new(&runtime).unwrap();
+    /// # let guard = context.make_current().unwrap();
+    /// let result = js::script::eval(&guard, "[1, new Map(), /a/, {}]").unwrap();
+    /// let types = result.as_array().unwrap().iter(&guard)
+    ///     .map(|value| value.value_type(&guard))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(types, [ValueType::Number, ValueType::Map, ValueType::RegExp, ValueType::Object]);
+    /// ```
+    pub fn value_type(&self, guard: &ContextGuard) -> ValueType {
+        match self.get_type() {
+            JsValueType::Undefined => ValueType::Undefined,
+            JsValueType::Null => ValueType::Null,
+            JsValueType::Number => ValueType::Number,
+            JsValueType::String => ValueType::String,
+            JsValueType::Boolean => ValueType::Boolean,
+            JsValueType::Symbol => ValueType::Symbol,
+            JsValueType::Function => ValueType::Function,
+            JsValueType::Error => ValueType::Error,
+            JsValueType::Array => ValueType::Array,
+            JsValueType::ArrayBuffer => ValueType::ArrayBuffer,
+            JsValueType::TypedArray => ValueType::TypedArray,
+            JsValueType::DataView => ValueType::DataView,
+            JsValueType::Object => {
+                if self.is_external() {
+                    ValueType::External
+                } else if value::BigInt::is_same(guard, self) {
+                    // The engine does not have a dedicated type for BigInts
+                    ValueType::BigInt
+                } else if value::SharedArrayBuffer::is_same(guard, self) {
+                    ValueType::SharedArrayBuffer
+                } else if self.is_date(guard) {
+                    ValueType::Date
+                } else if value::RegExp::is_same(guard, self) {
+                    ValueType::RegExp
+                } else if value::Map::is_same(guard, self) {
+                    ValueType::Map
+                } else if value::Set::is_same(guard, self) {
+                    ValueType::Set
+                } else if self.is_promise() {
+                    ValueType::Promise
+                } else {
+                    ValueType::Object
+                }
+            },
+        }
+    }
+
     /// Compare two values for equality (`==`).
     pub fn equals(&self, _guard: &ContextGuard, that: &Value) -> bool {
         let mut result = false;
@@ -251,7 +438,24 @@ reference!(Value);
 
 #[cfg(test)]
 mod tests {
-    use {test, value, Property};
+    use {test, script, value, Property};
+
+    #[test]
+    fn value_type() {
+        use value::ValueType::*;
+        test::run_with_context(|guard| {
+            let values = script::eval(guard, "[undefined, null, 1, 'a', true, Symbol(), {}, () => 1, new Error(),
+                                               [], new ArrayBuffer(1), new Uint8Array(1), new DataView(new ArrayBuffer(1)),
+                                               new Date(), /a/, new Map(), new Set(), Promise.resolve()]").unwrap();
+            let types = values.as_array().unwrap().iter(guard).map(|value| value.value_type(guard)).collect::<Vec<_>>();
+            assert_eq!(types, [Undefined, Null, Number, String, Boolean, Symbol, Object, Function, Error, Array,
+                               ArrayBuffer, TypedArray, DataView, Date, RegExp, Map, Set, Promise]);
+
+            let external = value::External::new(guard, Box::new(1));
+            assert_eq!(external.value_type(guard), External);
+            assert!(values.as_object().is_some() && values.as_function().is_none());
+        });
+    }
 
     #[test]
     fn json_conversion() {
@@ -0,0 +1,153 @@
+//! Small web-platform utilities: `atob`, `btoa` and `structuredClone`.
+//!
+//! ```rust
+//! # use chakracore as js;
+//! # let runtime = js::Runtime::new().unwrap();
+//! # let context = js::Context::new(&runtime).unwrap();
+//! # let guard = context.make_current().unwrap();
+//! js::web::install(&guard).unwrap();
+//! let result = js::script::eval(&guard, "atob(btoa('foo'))").unwrap();
+//! assert_eq!(result.to_string(&guard), "foo");
+//! ```
+use context::ContextGuard;
+use error::*;
+use util::named_error;
+use value::{self, Value};
+use Property;
+
+/// The base64 alphabet.
+const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Installs `atob`, `btoa` and `structuredClone` in the guard's context.
+///
+/// The clone uses the semantics of `Value::export`, so functions, external
+/// objects and cyclic structures throw a `DataCloneError`.
+pub fn install(guard: &ContextGuard) -> Result<()> {
+    let global = guard.global();
+    let function = |name: &str, callback: Box<value::function::FunctionCallback>| {
+        let function = value::Function::with_name(guard, name, callback);
+        global.set(guard, &Property::new(guard, name), &function);
+    };
+
+    function("atob", Box::new(|guard, info| {
+        let data = required_argument(guard, &info.arguments)?.to_string(guard);
+        let bytes = decode(&data).ok_or_else(|| {
+            named_error(guard, "InvalidCharacterError", "The string to be decoded is not correctly encoded")
+        })?;
+        let binary = bytes.into_iter().map(|byte| byte as char).collect::<String>();
+        Ok(value::String::new(guard, &binary).into())
+    }));
+
+    function("btoa", Box::new(|guard, info| {
+        let data = required_argument(guard, &info.arguments)?.to_string(guard);
+        let bytes = data.chars().map(|character| {
+            if (character as u32) < 0x100 { Some(character as u8) } else { None }
+        }).collect::<Option<Vec<_>>>().ok_or_else(|| {
+            named_error(guard, "InvalidCharacterError", "The string to be encoded contains characters outside of the Latin1 range")
+        })?;
+        Ok(value::String::new(guard, &encode(&bytes)).into())
+    }));
+
+    function("structuredClone", Box::new(|guard, info| {
+        let value = required_argument(guard, &info.arguments)?;
+        value.export(guard)
+            .map(|exported| exported.write(guard))
+            .map_err(|error| named_error(guard, "DataCloneError", &error.to_string()))
+    }));
+    Ok(())
+}
+
+/// Encodes bytes as padded base64.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, &byte)| {
+            bits | (byte as u32) << (16 - index * 8)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3f] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Decodes base64, ignoring ASCII whitespace and optional padding.
+///
+/// Returns `None` if the input is not valid base64.
+pub(crate) fn decode(data: &str) -> Option<Vec<u8>> {
+    let mut data = data.bytes()
+        .filter(|byte| ![b' ', b'\t', b'\n', b'\x0c', b'\r'].contains(byte))
+        .collect::<Vec<_>>();
+
+    if data.len() % 4 == 0 {
+        for _ in 0..2 {
+            if data.last() == Some(&b'=') {
+                data.pop();
+            }
+        }
+    }
+
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut bits = 0u32;
+        for (index, byte) in chunk.iter().enumerate() {
+            let sextet = ALPHABET.iter().position(|character| character == byte)?;
+            bits |= (sextet as u32) << (18 - index * 6);
+        }
+        for index in 0..chunk.len() - 1 {
+            output.push((bits >> (16 - index * 8)) as u8);
+        }
+    }
+    Some(output)
+}
+
+/// Returns the first argument, or throws a `TypeError` if it is missing.
+fn required_argument(guard: &ContextGuard, arguments: &[Value]) -> ::std::result::Result<Value, Value> {
+    arguments.get(0).cloned().ok_or_else(|| {
+        value::Error::type_error(guard, "1 argument required, but only 0 present").into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {test, script};
+
+    #[test]
+    fn base64() {
+        let cases: &[(&[u8], &str)] = &[(b"", ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foob", "Zm9vYg==")];
+        for &(bytes, encoded) in cases {
+            assert_eq!(super::encode(bytes), encoded);
+            assert_eq!(super::decode(encoded).unwrap(), bytes);
+        }
+        assert_eq!(super::decode(" Zm9 vYg ").unwrap(), b"foob");
+        assert!(super::decode("Zm9vY").is_none());
+        assert!(super::decode("Zm9v!").is_none());
+    }
+
+    #[test]
+    fn globals() {
+        test::run_with_context(|guard| {
+            super::install(guard).unwrap();
+            let result = script::eval(guard, "
+                var original = { list: [1, 'two', null], bytes: new Uint8Array([1, 2]) };
+                var clone = structuredClone(original);
+                var errors = [];
+                try { atob('*'); } catch (ex) { errors.push(ex.name); }
+                try { btoa('\\u2603'); } catch (ex) { errors.push(ex.name); }
+                try { structuredClone(function () {}); } catch (ex) { errors.push(ex.name); }
+                [btoa('\\xff\\x00'), atob('/wA=') === '\\xff\\x00', clone !== original, clone.list, clone.bytes[1], errors]
+            ").unwrap();
+            assert_eq!(result.to_json(guard).unwrap(), concat!(
+                r#"["/wA=",true,true,[1,"two",null],2,"#,
+                r#"["InvalidCharacterError","InvalidCharacterError","DataCloneError"]]"#));
+        });
+    }
+}